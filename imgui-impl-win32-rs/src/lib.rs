@@ -135,13 +135,15 @@ impl Win32Impl {
     }
 }
 
-/// Call this function in WndProc
+/// Call this function in WndProc.
+/// Returns true if imgui wants the message: a mouse message while `io.WantCaptureMouse`
+/// or a keyboard message while `io.WantCaptureKeyboard`, the application can skip those.
 pub unsafe fn imgui_win32_window_proc(
     window: HWND,
     msg: UINT,
     wparam: WPARAM,
     lparam: LPARAM,
-) -> Result<(), Win32ImplError> {
+) -> Result<bool, Win32ImplError> {
     let io = match igGetIO().as_mut() {
         Some(io) => io,
         None => return Err(Win32ImplError::NullIO),
@@ -237,10 +239,16 @@ pub unsafe fn imgui_win32_window_proc(
         // currently no gamepad support
         WM_DEVICECHANGE => {}
 
-        _ => return Ok(()),
+        _ => {}
     };
 
-    Ok(())
+    // the flags are from the last frame
+    let captured = match msg {
+        WM_MOUSEFIRST..=WM_MOUSELAST => io.WantCaptureMouse,
+        WM_KEYFIRST..=WM_KEYLAST => io.WantCaptureKeyboard,
+        _ => false,
+    };
+    Ok(captured)
 }
 
 unsafe fn update_cursor() -> bool {
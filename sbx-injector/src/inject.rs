@@ -0,0 +1,208 @@
+use crate::process::to_wide;
+use anyhow::{anyhow, Result};
+use nameof::name_of;
+use sbx_tool_core::memory::{ExternalProcessMemory, GameMemory, Protection};
+use sbx_tool_core::utility::pe::PeImage;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::{event, Level};
+use winapi::shared::minwindef::{DWORD, LPVOID};
+use winapi::shared::winerror::WAIT_TIMEOUT;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::libloaderapi::{GetModuleHandleW, GetProcAddress};
+use winapi::um::memoryapi::{VirtualAllocEx, VirtualFreeEx};
+use winapi::um::minwinbase::LPTHREAD_START_ROUTINE;
+use winapi::um::processthreadsapi::{CreateRemoteThread, GetExitCodeThread};
+use winapi::um::synchapi::WaitForSingleObject;
+use winapi::um::winbase::WAIT_OBJECT_0;
+use winapi::um::winnt::{MEM_COMMIT, MEM_RELEASE, MEM_RESERVE, PROCESS_CREATE_THREAD, SYNCHRONIZE};
+
+/// Rights the injector needs on top of reading and writing
+pub const INJECT_ACCESS: DWORD = PROCESS_CREATE_THREAD | SYNCHRONIZE;
+
+const REMOTE_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Memory allocated in the target, freed on drop
+pub struct RemoteAllocation<'a> {
+    memory: &'a ExternalProcessMemory,
+    pub address: usize,
+}
+
+impl<'a> RemoteAllocation<'a> {
+    pub fn new(
+        memory: &'a ExternalProcessMemory,
+        size: usize,
+        protection: Protection,
+    ) -> Result<Self> {
+        let address = unsafe {
+            VirtualAllocEx(
+                memory.handle(),
+                std::ptr::null_mut(),
+                size,
+                MEM_COMMIT | MEM_RESERVE,
+                protection.0,
+            )
+        } as usize;
+        if address == 0 {
+            return Err(anyhow!(
+                "{} failed for {:x} bytes",
+                name_of!(VirtualAllocEx),
+                size
+            ));
+        }
+        Ok(RemoteAllocation { memory, address })
+    }
+}
+
+impl Drop for RemoteAllocation<'_> {
+    fn drop(&mut self) {
+        unsafe { VirtualFreeEx(self.memory.handle(), self.address as LPVOID, 0, MEM_RELEASE) };
+    }
+}
+
+/// Run `function(parameter)` on a new thread in the target and return the thread's exit code.
+/// `function` has to be stdcall with one argument, like `LPTHREAD_START_ROUTINE`.
+pub fn call_remote(
+    memory: &ExternalProcessMemory,
+    function: usize,
+    parameter: usize,
+) -> Result<u32> {
+    let start: LPTHREAD_START_ROUTINE = unsafe { std::mem::transmute(function) };
+    let thread = unsafe {
+        CreateRemoteThread(
+            memory.handle(),
+            std::ptr::null_mut(),
+            0,
+            start,
+            parameter as LPVOID,
+            0,
+            std::ptr::null_mut(),
+        )
+    };
+    if thread.is_null() {
+        return Err(anyhow!(
+            "{} failed at {:x}",
+            name_of!(CreateRemoteThread),
+            function
+        ));
+    }
+    let wait = unsafe { WaitForSingleObject(thread, REMOTE_CALL_TIMEOUT.as_millis() as u32) };
+    let mut exit_code = DWORD::default();
+    let ok = unsafe { GetExitCodeThread(thread, &mut exit_code) };
+    unsafe { CloseHandle(thread) };
+    match wait {
+        WAIT_OBJECT_0 if ok != 0 => Ok(exit_code),
+        WAIT_OBJECT_0 => Err(anyhow!("{} failed", name_of!(GetExitCodeThread))),
+        WAIT_TIMEOUT => Err(anyhow!("remote thread at {:x} timed out", function)),
+        _ => Err(anyhow!("{} failed", name_of!(WaitForSingleObject))),
+    }
+}
+
+/// Address of a kernel32 export in the target.
+/// kernel32 is mapped at the same address in every process of a session(and bitness).
+fn kernel32_proc_address(symbol: &str) -> Result<usize> {
+    let kernel32 = unsafe { GetModuleHandleW(to_wide("kernel32.dll").as_ptr()) };
+    let symbol_c = std::ffi::CString::new(symbol)?;
+    let address = unsafe { GetProcAddress(kernel32, symbol_c.as_ptr()) } as usize;
+    if address == 0 {
+        return Err(anyhow!("kernel32!{} not found", symbol));
+    }
+    Ok(address)
+}
+
+/// `LoadLibraryW` in the target, returns the module base.
+/// Works on a suspended process too, the remote thread initializes the process first.
+pub fn load_library(memory: &ExternalProcessMemory, path: &Path) -> Result<usize> {
+    let path_wide = to_wide(path);
+    let bytes: Vec<u8> = path_wide.iter().flat_map(|c| c.to_le_bytes()).collect();
+    let buffer = RemoteAllocation::new(memory, bytes.len(), Protection::READWRITE)?;
+    memory.write_bytes(buffer.address, &bytes)?;
+
+    let module = call_remote(
+        memory,
+        kernel32_proc_address("LoadLibraryW")?,
+        buffer.address,
+    )?;
+    if module == 0 {
+        return Err(anyhow!(
+            "LoadLibraryW failed in the target for {} (missing dependency or DllMain returned FALSE)",
+            path.display()
+        ));
+    }
+    //HMODULE is 32 bit in the target
+    Ok(module as usize)
+}
+
+pub fn free_library(memory: &ExternalProcessMemory, module: usize) -> Result<()> {
+    if call_remote(memory, kernel32_proc_address("FreeLibrary")?, module)? == 0 {
+        return Err(anyhow!("FreeLibrary failed in the target for {:x}", module));
+    }
+    Ok(())
+}
+
+/// Copy of a module loaded in the target
+fn read_remote_image(memory: &ExternalProcessMemory, base: usize) -> Result<Vec<u8>> {
+    let mut headers = vec![0u8; 0x1000];
+    memory.read_bytes(base, &mut headers)?;
+    let size = PeImage::parse_mapped(&headers)?
+        .optional_header
+        .size_of_image as usize;
+    let mut image = vec![0u8; size];
+    memory.read_bytes(base, &mut image)?;
+    Ok(image)
+}
+
+/// Ask an injected dll to unload itself through its unload export and wait until it is gone.
+/// `force` falls back to FreeLibrary from outside, the dll reverts what it can in DllMain.
+pub fn eject(
+    memory: &ExternalProcessMemory,
+    module_name: &str,
+    force: bool,
+    timeout: Duration,
+) -> Result<()> {
+    let base = memory.module_address(module_name)?;
+    event!(Level::INFO, "{} is loaded at {:x}", module_name, base);
+
+    let image = read_remote_image(memory, base)?;
+    let export = PeImage::parse_mapped(&image)?
+        .export_by_name(sbx_tool_core::utility::ipc::UNLOAD_EXPORT)?;
+    match export {
+        Some(export) => {
+            call_remote(memory, base + export.rva as usize, 0)?;
+        }
+        None if force => {
+            event!(
+                Level::WARN,
+                "{} has no {} export, calling FreeLibrary",
+                module_name,
+                sbx_tool_core::utility::ipc::UNLOAD_EXPORT
+            );
+            free_library(memory, base)?;
+        }
+        None => {
+            return Err(anyhow!(
+                "{} has no {} export (an older build?), use --force to FreeLibrary it",
+                module_name,
+                sbx_tool_core::utility::ipc::UNLOAD_EXPORT
+            ))
+        }
+    }
+
+    //the dll unloads on its own thread
+    let start = Instant::now();
+    while memory.module_address(module_name).is_ok() {
+        if start.elapsed() > timeout {
+            if force {
+                event!(Level::WARN, "Timed out, calling FreeLibrary");
+                return free_library(memory, base);
+            }
+            return Err(anyhow!(
+                "{} is still loaded after {:?}, use --force to FreeLibrary it",
+                module_name,
+                timeout
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    Ok(())
+}
@@ -0,0 +1,334 @@
+mod inject;
+mod process;
+
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand};
+use process::SuspendedProcess;
+use sbx_offset::signature::CodeImage;
+use sbx_offset::{OffsetTable, Selection};
+use sbx_tool_core::memory::ExternalProcessMemory;
+use sbx_tool_core::utility::ipc::ReadyEvent;
+use sbx_tool_core::utility::pe::{PeImage, IMAGE_REL_BASED_HIGHLOW};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{event, Level};
+
+const DLL_NAME: &str = "sbx_tool_dll.dll";
+
+/// Inject sbx-tool into Twinkle Crusaders -Starlit Brave Xtream!!-
+#[derive(Parser)]
+#[clap(version)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Inject into a running game
+    Inject {
+        #[clap(flatten)]
+        target: TargetArgs,
+        #[clap(flatten)]
+        inject: InjectArgs,
+    },
+    /// Start the game suspended and inject before its first frame
+    Launch {
+        /// Game executable
+        #[clap(parse(from_os_str))]
+        exe: PathBuf,
+        #[clap(flatten)]
+        inject: InjectArgs,
+        /// Arguments for the game, after --
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+    /// Unload a previously injected dll
+    Eject {
+        #[clap(flatten)]
+        target: TargetArgs,
+        /// Module name of the injected dll
+        #[clap(long, default_value = DLL_NAME)]
+        module: String,
+        /// FreeLibrary the dll if it does not unload itself
+        #[clap(long)]
+        force: bool,
+        /// Seconds to wait for the dll to unload
+        #[clap(long, default_value = "10")]
+        timeout: u64,
+    },
+    /// Print the fingerprint and offset signatures of a game executable,
+    /// to paste into sbx-offset.toml
+    Signatures {
+        /// Game executable
+        #[clap(parse(from_os_str))]
+        exe: PathBuf,
+        /// Name of the offset table to generate from, for an executable no fingerprint matches
+        #[clap(long)]
+        build: Option<String>,
+    },
+}
+
+/// How to find the game process
+#[derive(Args)]
+struct TargetArgs {
+    /// Process id of the game
+    #[clap(long, conflicts_with_all = &["process", "window"])]
+    pid: Option<u32>,
+    /// Executable name of the game, e.g. game.exe
+    #[clap(long, conflicts_with = "window")]
+    process: Option<String>,
+    /// Exact window title of the game
+    #[clap(long)]
+    window: Option<String>,
+}
+
+impl TargetArgs {
+    fn resolve(&self) -> Result<u32> {
+        match (self.pid, &self.process, &self.window) {
+            (Some(pid), _, _) => Ok(pid),
+            (None, Some(name), _) => process::find_by_name(name),
+            (None, None, Some(title)) => process::find_by_window(title),
+            (None, None, None) => Err(anyhow!("specify --pid, --process or --window")),
+        }
+    }
+}
+
+#[derive(Args)]
+struct InjectArgs {
+    /// Dll to inject, sbx_tool_dll.dll next to the injector by default
+    #[clap(long, parse(from_os_str))]
+    dll: Option<PathBuf>,
+    /// Inject into unknown game builds.
+    /// The dll still refuses to attach to an unknown build, it shows why and does nothing.
+    /// `allow_unverified` in sbx-offset.toml makes it use a table anyway
+    #[clap(long)]
+    force: bool,
+    /// Seconds to wait for the tool to report ready
+    #[clap(long, default_value = "60")]
+    timeout: u64,
+}
+
+impl InjectArgs {
+    /// Absolute, LoadLibraryW runs in the game's working directory
+    fn dll_path(&self) -> Result<PathBuf> {
+        let path = match &self.dll {
+            Some(p) => p.clone(),
+            None => std::env::current_exe()?
+                .parent()
+                .ok_or_else(|| anyhow!("injector has no parent directory"))?
+                .join(DLL_NAME),
+        };
+        let path = std::env::current_dir()?.join(path);
+        if !path.is_file() {
+            return Err(anyhow!("dll not found at {}", path.display()));
+        }
+        Ok(path)
+    }
+}
+
+fn main() {
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Command::Inject { target, inject } => inject_running(&target, &inject),
+        Command::Launch { exe, inject, args } => launch(&exe, &inject, &args),
+        Command::Eject {
+            target,
+            module,
+            force,
+            timeout,
+        } => eject(&target, &module, force, Duration::from_secs(timeout)),
+        Command::Signatures { exe, build } => print_signatures(&exe, build.as_deref()),
+    };
+    if let Err(e) = result {
+        event!(Level::ERROR, "{:#}", e);
+        std::process::exit(1);
+    }
+}
+
+fn inject_running(target: &TargetArgs, args: &InjectArgs) -> Result<()> {
+    let pid = target.resolve()?;
+    let memory = ExternalProcessMemory::open_with_access(pid, inject::INJECT_ACCESS)?;
+    let exe = process::image_path(memory.handle())?;
+    event!(Level::INFO, "Target {} (pid {})", exe.display(), pid);
+
+    let dll = args.dll_path()?;
+    verify_build(&exe, &dll, args.force)?;
+    let module_name = dll.file_name().unwrap_or_default().to_string_lossy();
+    if let Ok(base) = memory.module_address(&module_name) {
+        return Err(anyhow!(
+            "{} is already loaded at {:x}, eject it first",
+            module_name,
+            base
+        ));
+    }
+
+    let ready = ReadyEvent::create(pid)?;
+    inject_dll(&memory, &dll, args)?;
+    wait_ready(&ready, &memory, Duration::from_secs(args.timeout))
+}
+
+fn launch(exe: &Path, args: &InjectArgs, game_args: &[String]) -> Result<()> {
+    let exe = std::env::current_dir()?.join(exe);
+    let dll = args.dll_path()?;
+    verify_build(&exe, &dll, args.force)?;
+
+    //terminated on drop until resumed
+    let game = SuspendedProcess::launch(&exe, game_args)?;
+    event!(
+        Level::INFO,
+        "Launched {} suspended (pid {})",
+        exe.display(),
+        game.pid
+    );
+    let memory = ExternalProcessMemory::open_with_access(game.pid, inject::INJECT_ACCESS)?;
+    let ready = ReadyEvent::create(game.pid)?;
+    inject_dll(&memory, &dll, args)?;
+    game.resume()?;
+    wait_ready(&ready, &memory, Duration::from_secs(args.timeout))
+}
+
+fn eject(target: &TargetArgs, module: &str, force: bool, timeout: Duration) -> Result<()> {
+    let pid = target.resolve()?;
+    let memory = ExternalProcessMemory::open_with_access(pid, inject::INJECT_ACCESS)?;
+    inject::eject(&memory, module, force, timeout)?;
+    event!(Level::INFO, "Ejected {} from pid {}", module, pid);
+    Ok(())
+}
+
+/// Same lookup the dll does on attach, so a mismatch is reported before injecting
+fn verify_build(exe: &Path, dll: &Path, force: bool) -> Result<()> {
+    let fingerprint = sbx_offset::BuildFingerprint::from_file(exe)?;
+    let mut registry = sbx_offset::OffsetRegistry::embedded()?;
+    if let Some(dir) = dll.parent() {
+        let override_path = dir.join(sbx_offset::OVERRIDE_FILE_NAME);
+        if override_path.exists() {
+            event!(Level::INFO, "Loading offsets from {:?}", override_path);
+            registry.load_override_file(&override_path)?;
+        }
+    }
+
+    match registry.select(&fingerprint) {
+        Some(Selection::Matched(table)) => {
+            event!(Level::INFO, "Game build: {}", table.name);
+            Ok(())
+        }
+        Some(Selection::Unverified(table)) => {
+            event!(
+                Level::WARN,
+                "Game build is not verified, the dll will use the {} offsets: {}",
+                table.name,
+                fingerprint
+            );
+            Ok(())
+        }
+        None if force => {
+            event!(
+                Level::WARN,
+                "Unknown game build, injecting anyway: {}",
+                fingerprint
+            );
+            Ok(())
+        }
+        None => Err(anyhow!(
+            "Unknown game build {}. Add this to {} next to the dll to register it: {}",
+            exe.display(),
+            sbx_offset::OVERRIDE_FILE_NAME,
+            fingerprint
+        )),
+    }
+}
+
+/// Signatures are generated with the offsets of the build's table,
+/// so they are only as right as the table is for this executable
+fn print_signatures(exe: &Path, build: Option<&str>) -> Result<()> {
+    let bytes = std::fs::read(exe)?;
+    let fingerprint = sbx_offset::BuildFingerprint::from_bytes(&bytes)?;
+    let mut registry = sbx_offset::OffsetRegistry::embedded()?;
+    let override_path = std::env::current_exe()?.with_file_name(sbx_offset::OVERRIDE_FILE_NAME);
+    if override_path.exists() {
+        event!(Level::INFO, "Loading offsets from {:?}", override_path);
+        registry.load_override_file(&override_path)?;
+    }
+    let table = match build {
+        Some(name) => registry
+            .get(name)
+            .ok_or_else(|| anyhow!("no offset table named {:?}", name))?,
+        None => registry
+            .select(&fingerprint)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Unknown game build {}, pick a table with --build",
+                    fingerprint
+                )
+            })?
+            .table(),
+    };
+
+    let image = PeImage::parse_file(&bytes)?;
+    let text = image
+        .section_by_name(".text")
+        .ok_or_else(|| anyhow!(".text section not found"))?;
+    let relocations = image
+        .relocations()?
+        .into_iter()
+        .flat_map(|b| b.relocations)
+        .filter(|r| r.kind == IMAGE_REL_BASED_HIGHLOW)
+        .map(|r| r.rva as usize)
+        .collect::<HashSet<_>>();
+    let code = CodeImage {
+        data: image.section_data(text)?,
+        rva: text.virtual_address as usize,
+        image_base: image.optional_header.image_base as usize,
+        relocations: &relocations,
+    };
+
+    println!("[[build]]\nname = \"{}\"\n{}", table.name, fingerprint);
+    for name in OffsetTable::offset_names() {
+        let rva = table.offset(name).unwrap();
+        match code.generate(rva) {
+            Ok(signature) => println!(
+                "\n[build.signatures.{}]\n{}",
+                name,
+                signature.to_string().trim_end()
+            ),
+            Err(e) => println!("\n# {}: {}", name, e),
+        }
+    }
+    Ok(())
+}
+
+fn inject_dll(memory: &ExternalProcessMemory, dll: &Path, args: &InjectArgs) -> Result<()> {
+    let base = inject::load_library(memory, dll)?;
+    event!(Level::INFO, "Injected {} at {:x}", dll.display(), base);
+    Ok(())
+}
+
+/// The dll signals once its hooks are installed
+fn wait_ready(ready: &ReadyEvent, memory: &ExternalProcessMemory, timeout: Duration) -> Result<()> {
+    event!(Level::INFO, "Waiting for sbx-tool...");
+    let start = Instant::now();
+    loop {
+        if ready.wait(Duration::from_millis(250))? {
+            event!(Level::INFO, "sbx-tool is ready");
+            return Ok(());
+        }
+        if let Some(code) = process::exit_code(memory.handle())? {
+            return Err(anyhow!(
+                "the game exited with code {:#x} before sbx-tool was ready",
+                code
+            ));
+        }
+        if start.elapsed() > timeout {
+            return Err(anyhow!(
+                "sbx-tool did not report ready within {:?}, the debug dll shows why in its console",
+                timeout
+            ));
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{anyhow, Result};
+use nameof::name_of;
+use std::ffi::OsString;
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use std::path::{Path, PathBuf};
+use winapi::shared::minwindef::{DWORD, FALSE};
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::minwinbase::STILL_ACTIVE;
+use winapi::um::processthreadsapi::{
+    CreateProcessW, GetExitCodeProcess, ResumeThread, TerminateProcess, PROCESS_INFORMATION,
+    STARTUPINFOW,
+};
+use winapi::um::tlhelp32::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+use winapi::um::winbase::{QueryFullProcessImageNameW, CREATE_SUSPENDED};
+use winapi::um::winnt::HANDLE;
+use winapi::um::winuser::{FindWindowW, GetWindowThreadProcessId};
+
+pub fn to_wide<S: AsRef<std::ffi::OsStr>>(s: S) -> Vec<u16> {
+    s.as_ref().encode_wide().chain(std::iter::once(0)).collect()
+}
+
+fn from_wide(buffer: &[u16]) -> String {
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    String::from_utf16_lossy(&buffer[..len])
+}
+
+/// Closes the handle on drop
+pub struct OwnedHandle(HANDLE);
+
+impl OwnedHandle {
+    pub fn get(&self) -> HANDLE {
+        self.0
+    }
+}
+
+impl Drop for OwnedHandle {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessEntry {
+    pub pid: u32,
+    pub exe_name: String,
+}
+
+pub fn processes() -> Result<Vec<ProcessEntry>> {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+    if snapshot == INVALID_HANDLE_VALUE {
+        return Err(anyhow!("{} failed", name_of!(CreateToolhelp32Snapshot)));
+    }
+    let snapshot = OwnedHandle(snapshot);
+    let mut entry: PROCESSENTRY32W = unsafe { std::mem::zeroed() };
+    entry.dwSize = std::mem::size_of::<PROCESSENTRY32W>() as DWORD;
+    let mut entries = Vec::new();
+    let mut ok = unsafe { Process32FirstW(snapshot.get(), &mut entry) };
+    while ok != 0 {
+        entries.push(ProcessEntry {
+            pid: entry.th32ProcessID,
+            exe_name: from_wide(&entry.szExeFile),
+        });
+        ok = unsafe { Process32NextW(snapshot.get(), &mut entry) };
+    }
+    Ok(entries)
+}
+
+/// Exactly one process has to match, case insensitive
+pub fn find_by_name(exe_name: &str) -> Result<u32> {
+    let matches: Vec<u32> = processes()?
+        .into_iter()
+        .filter(|p| p.exe_name.eq_ignore_ascii_case(exe_name))
+        .map(|p| p.pid)
+        .collect();
+    match matches.as_slice() {
+        [] => Err(anyhow!("no process named {}", exe_name)),
+        [pid] => Ok(*pid),
+        pids => Err(anyhow!(
+            "{} processes named {} (pids {:?}), use --pid",
+            pids.len(),
+            exe_name,
+            pids
+        )),
+    }
+}
+
+/// By the exact window title
+pub fn find_by_window(title: &str) -> Result<u32> {
+    let title = to_wide(title);
+    let hwnd = unsafe { FindWindowW(std::ptr::null(), title.as_ptr()) };
+    if hwnd.is_null() {
+        return Err(anyhow!(
+            "no window titled {}",
+            String::from_utf16_lossy(&title[..title.len() - 1])
+        ));
+    }
+    let mut pid = DWORD::default();
+    unsafe { GetWindowThreadProcessId(hwnd, &mut pid) };
+    Ok(pid)
+}
+
+/// Full path of the main executable
+pub fn image_path(process: HANDLE) -> Result<PathBuf> {
+    let mut buffer = vec![0u16; 1024];
+    let mut len = buffer.len() as DWORD;
+    let ok = unsafe { QueryFullProcessImageNameW(process, 0, buffer.as_mut_ptr(), &mut len) };
+    if ok == 0 {
+        return Err(anyhow!("{} failed", name_of!(QueryFullProcessImageNameW)));
+    }
+    Ok(OsString::from_wide(&buffer[..len as usize]).into())
+}
+
+/// None while the process is running
+pub fn exit_code(process: HANDLE) -> Result<Option<u32>> {
+    let mut code = DWORD::default();
+    if unsafe { GetExitCodeProcess(process, &mut code) } == 0 {
+        return Err(anyhow!("{} failed", name_of!(GetExitCodeProcess)));
+    }
+    if code == STILL_ACTIVE {
+        return Ok(None);
+    }
+    Ok(Some(code))
+}
+
+/// Created with CREATE_SUSPENDED, the main thread has not run yet
+pub struct SuspendedProcess {
+    pub pid: u32,
+    process: OwnedHandle,
+    main_thread: OwnedHandle,
+    resumed: bool,
+}
+
+impl SuspendedProcess {
+    /// Started in the exe's directory, games tend to load data relative to it
+    pub fn launch(exe: &Path, args: &[String]) -> Result<Self> {
+        let mut command_line = format!("\"{}\"", exe.display());
+        for arg in args {
+            command_line.push(' ');
+            command_line.push_str(arg);
+        }
+        let mut command_line = to_wide(command_line);
+        let exe_wide = to_wide(exe);
+        let directory = exe.parent().map(to_wide);
+
+        let mut startup_info: STARTUPINFOW = unsafe { std::mem::zeroed() };
+        startup_info.cb = std::mem::size_of::<STARTUPINFOW>() as DWORD;
+        let mut info: PROCESS_INFORMATION = unsafe { std::mem::zeroed() };
+        let ok = unsafe {
+            CreateProcessW(
+                exe_wide.as_ptr(),
+                command_line.as_mut_ptr(),
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                FALSE,
+                CREATE_SUSPENDED,
+                std::ptr::null_mut(),
+                directory.as_ref().map_or(std::ptr::null(), |d| d.as_ptr()),
+                &mut startup_info,
+                &mut info,
+            )
+        };
+        if ok == 0 {
+            return Err(anyhow!(
+                "{} failed for {}",
+                name_of!(CreateProcessW),
+                exe.display()
+            ));
+        }
+        Ok(SuspendedProcess {
+            pid: info.dwProcessId,
+            process: OwnedHandle(info.hProcess),
+            main_thread: OwnedHandle(info.hThread),
+            resumed: false,
+        })
+    }
+
+    pub fn resume(mut self) -> Result<()> {
+        if unsafe { ResumeThread(self.main_thread.get()) } == DWORD::MAX {
+            return Err(anyhow!("{} failed", name_of!(ResumeThread)));
+        }
+        self.resumed = true;
+        Ok(())
+    }
+}
+
+/// Don't leave a suspended game behind when injecting failed
+impl Drop for SuspendedProcess {
+    fn drop(&mut self) {
+        if !self.resumed {
+            unsafe { TerminateProcess(self.process.get(), 1) };
+        }
+    }
+}
@@ -1,18 +1,28 @@
-/*battle */
-pub const BATTLE_MAIN_LOOP_FIRST_SWITCH_OFFSET: usize = 0xC548e;
+use serde::Deserialize;
 
-pub const BATTLE_MAIN_LOOP_FIRST_SWITCH_FLAG_OFFSET: usize = 0x1c3370;
+#[derive(Debug, Clone, Deserialize)]
+pub struct BattleOffsets {
+    pub battle_main_loop_first_switch_offset: usize,
+    pub battle_main_loop_first_switch_flag_offset: usize,
+    pub battle_context_offset: usize,
+    pub battle_stun_context_offset: usize,
+    pub battle_attack_level_context_offset: usize,
+    pub battle_unk_context: usize,
+    pub hpcap_1_offset: usize,
+    pub hpcap_2_offset: usize,
+    pub excap_1_offset: usize,
+    pub excap_2_offset: usize,
+}
 
-pub const BATTLE_CONTEXT_OFFSET: usize = 0x438B28;
-
-pub const BATTLE_STUN_CONTEXT_OFFSET: usize = 0x4389A0;
-
-pub const BATTLE_ATTACK_LEVEL_CONTEXT_OFFSET: usize = 0x438DD8;
-
-pub const BATTLE_UNK_CONTEXT: usize = 0x4402A0;
-
-pub const HPCAP_1_OFFSET: usize = 0xBF2A2;
-pub const HPCAP_2_OFFSET: usize = 0xBF2B4;
-
-pub const EXCAP_1_OFFSET: usize = 0xC391C;
-pub const EXCAP_2_OFFSET: usize = 0xC392B;
+offset_names!(BattleOffsets {
+    battle_main_loop_first_switch_offset,
+    battle_main_loop_first_switch_flag_offset,
+    battle_context_offset,
+    battle_stun_context_offset,
+    battle_attack_level_context_offset,
+    battle_unk_context,
+    hpcap_1_offset,
+    hpcap_2_offset,
+    excap_1_offset,
+    excap_2_offset,
+});
@@ -1,4 +1,14 @@
-/*CSS */
-pub const VS_CPU_CSS_CONTEXT_OFFSET: usize = 0x442af8;
-pub const ADD_CHARACTER_COST_TO_PARTY_COST_OFFSET: usize = 0xe6ba0;
-pub const VS_CPU_CSS_INIT_CONTEXT_CONSTANTS_OFFSET: usize = 0xe4650;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CSSOffsets {
+    pub vs_cpu_css_context_offset: usize,
+    pub add_character_cost_to_party_cost_offset: usize,
+    pub vs_cpu_css_init_context_constants_offset: usize,
+}
+
+offset_names!(CSSOffsets {
+    vs_cpu_css_context_offset,
+    add_character_cost_to_party_cost_offset,
+    vs_cpu_css_init_context_constants_offset,
+});
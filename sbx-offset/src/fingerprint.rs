@@ -0,0 +1,130 @@
+use anyhow::{anyhow, Result};
+use sbx_tool_common::utility::pe::PeImage;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::Path;
+
+/// Identifies a game executable on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildFingerprint {
+    pub size: u64,
+    /// `TimeDateStamp` of the PE file header
+    pub timestamp: u32,
+    /// lowercase hex
+    pub sha256: String,
+}
+
+impl BuildFingerprint {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let bytes = std::fs::read(path.as_ref())?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let timestamp = PeImage::parse_file(bytes)
+            .map_err(|e| anyhow!("not a PE file: {}", e))?
+            .file_header
+            .time_date_stamp;
+        let sha256 = Sha256::digest(bytes)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>();
+        Ok(BuildFingerprint {
+            size: bytes.len() as u64,
+            timestamp,
+            sha256,
+        })
+    }
+}
+
+/// Printed in the offset file format so it can be pasted into an override file.
+impl fmt::Display for BuildFingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fingerprint = {{ size = {}, timestamp = 0x{:x}, sha256 = \"{}\" }}",
+            self.size, self.timestamp, self.sha256
+        )
+    }
+}
+
+/// Fingerprint fields an offset table was written for.
+/// Every field that is set has to match, a table with no fields set never matches.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct KnownFingerprint {
+    pub size: Option<u64>,
+    pub timestamp: Option<u32>,
+    pub sha256: Option<String>,
+}
+
+impl KnownFingerprint {
+    pub fn is_empty(&self) -> bool {
+        self.size.is_none() && self.timestamp.is_none() && self.sha256.is_none()
+    }
+
+    pub fn matches(&self, fingerprint: &BuildFingerprint) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        if let Some(size) = self.size {
+            if size != fingerprint.size {
+                return false;
+            }
+        }
+        if let Some(timestamp) = self.timestamp {
+            if timestamp != fingerprint.timestamp {
+                return false;
+            }
+        }
+        if let Some(sha256) = &self.sha256 {
+            if !sha256.eq_ignore_ascii_case(&fingerprint.sha256) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TINY: &[u8] = include_bytes!("../../sbx-tool-common/tests/fixtures/tiny.dll");
+
+    #[test]
+    fn fingerprint_of_a_pe_file() {
+        let fingerprint = BuildFingerprint::from_bytes(TINY).unwrap();
+        assert_eq!(fingerprint.size, TINY.len() as u64);
+        assert_eq!(fingerprint.timestamp, 0xb5fc90f8);
+        assert_eq!(fingerprint.sha256.len(), 64);
+        assert!(BuildFingerprint::from_bytes(&TINY[..0x80]).is_err());
+        assert!(BuildFingerprint::from_bytes(b"MZ").is_err());
+    }
+
+    #[test]
+    fn known_fingerprint_matches_the_fields_it_sets() {
+        let fingerprint = BuildFingerprint::from_bytes(TINY).unwrap();
+        let known = |size, timestamp, sha256: Option<&str>| KnownFingerprint {
+            size,
+            timestamp,
+            sha256: sha256.map(str::to_string),
+        };
+        assert!(!known(None, None, None).matches(&fingerprint));
+        assert!(known(Some(TINY.len() as u64), None, None).matches(&fingerprint));
+        assert!(known(None, Some(0xb5fc90f8), None).matches(&fingerprint));
+        assert!(!known(None, Some(0xb5fc90f8), Some("00")).matches(&fingerprint));
+        let upper = fingerprint.sha256.to_uppercase();
+        assert!(
+            known(Some(TINY.len() as u64), Some(0xb5fc90f8), Some(&upper)).matches(&fingerprint)
+        );
+    }
+
+    #[test]
+    fn display_is_offset_file_syntax() {
+        let fingerprint = BuildFingerprint::from_bytes(TINY).unwrap();
+        let line: toml::Value = toml::from_str(&fingerprint.to_string()).unwrap();
+        let known: KnownFingerprint = line["fingerprint"].clone().try_into().unwrap();
+        assert!(known.matches(&fingerprint));
+    }
+}
@@ -1,13 +1,177 @@
-#![feature(once_cell)]
 #![allow(non_upper_case_globals)]
-use std::lazy::SyncOnceCell;
+use anyhow::{anyhow, Result};
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// `NAMES`, `offset` and `offset_mut` of an offsets struct from the one list of its fields.
+/// `nested` structs are searched when a name is not one of the fields.
+macro_rules! offset_names {
+    ($ty:ident { $($field:ident),* $(,)? } $(nested { $($nested:ident),* })?) => {
+        impl $ty {
+            /// Offset names as written in the offset file
+            pub const NAMES: &'static [&'static str] = &[$(stringify!($field)),*];
+
+            /// Look up an offset by its field name
+            pub fn offset(&self, name: &str) -> Option<usize> {
+                match name {
+                    $(stringify!($field) => Some(self.$field),)*
+                    _ => None$($(.or_else(|| self.$nested.offset(name)))*)?,
+                }
+            }
+
+            pub fn offset_mut(&mut self, name: &str) -> Option<&mut usize> {
+                match name {
+                    $(stringify!($field) => Some(&mut self.$field),)*
+                    _ => None$($(.or_else(|| self.$nested.offset_mut(name)))*)?,
+                }
+            }
+        }
+    };
+}
+
 pub mod battle;
 pub mod css;
+pub mod fingerprint;
+pub mod registry;
+pub mod signature;
+
+pub use fingerprint::{BuildFingerprint, KnownFingerprint};
+pub use registry::{OffsetRegistry, Selection};
+pub use sbx_tool_common::utility::patch_def::parse_hex_bytes;
+use sbx_tool_common::utility::patch_registry::OffsetLookup;
+pub use signature::{OperandKind, SignatureDef};
+
+/// File name of the user-supplied offset file, looked up next to the dll.
+pub const OVERRIDE_FILE_NAME: &str = "sbx-offset.toml";
+
+/// SBX offsets of one game build
+#[derive(Debug, Clone, Deserialize)]
+pub struct OffsetTable {
+    /// game version, e.g. "1.19"
+    pub name: String,
+    #[serde(default)]
+    pub fingerprint: KnownFingerprint,
+    /// Used for executables no fingerprint matches, the offsets may be wrong for them.
+    /// Off unless set, e.g. in `OVERRIDE_FILE_NAME`.
+    #[serde(default)]
+    pub allow_unverified: bool,
+    /*main*/
+    pub main_loop_inner_offset: usize,
+    pub game_loop_inner_offset: usize,
+    pub ui_loop_inner_offset: usize,
+    pub ui_loop_switch_flag_offset: usize,
+    pub battle: battle::BattleOffsets,
+    pub css: css::CSSOffsets,
+    /// Optional signatures keyed by offset name, e.g. `battle_context_offset`
+    #[serde(default)]
+    pub signatures: HashMap<String, SignatureDef>,
+    /// Bytes a patch expects at an offset before overwriting it, keyed by offset name.
+    /// Hex, e.g. `"8B 45 08"`.
+    #[serde(default)]
+    pub original_bytes: HashMap<String, String>,
+}
+
+offset_names!(OffsetTable {
+    main_loop_inner_offset,
+    game_loop_inner_offset,
+    ui_loop_inner_offset,
+    ui_loop_switch_flag_offset,
+} nested { battle, css });
+
+impl OffsetTable {
+    /// Every offset name of a table, nested ones included
+    pub fn offset_names() -> impl Iterator<Item = &'static str> {
+        Self::NAMES
+            .iter()
+            .chain(battle::BattleOffsets::NAMES)
+            .chain(css::CSSOffsets::NAMES)
+            .copied()
+    }
+
+    /// Parsed `original_bytes` entry, None if the build does not declare one
+    pub fn original_bytes(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        let hex = match self.original_bytes.get(name) {
+            Some(h) => h,
+            None => return Ok(None),
+        };
+        parse_hex_bytes(hex)
+            .map(Some)
+            .map_err(|e| anyhow!("original_bytes.{}: {}", name, e))
+    }
+}
+
+impl OffsetLookup for OffsetTable {
+    fn offset(&self, name: &str) -> Option<usize> {
+        OffsetTable::offset(self, name)
+    }
+
+    fn original_bytes(&self, name: &str) -> Result<Option<Vec<u8>>> {
+        OffsetTable::original_bytes(self, name)
+    }
+}
+
+static OFFSET_TABLE: OnceCell<OffsetTable> = OnceCell::new();
+
+/// Set the offset table of the running game build.
+/// Must be called once before any hook is installed.
+pub fn init(table: OffsetTable) -> Result<()> {
+    OFFSET_TABLE
+        .set(table)
+        .map_err(|t| anyhow!("offset table is already initialized ({})", t.name))
+}
+
+/// Offset table of the running game build
+pub fn get() -> &'static OffsetTable {
+    OFFSET_TABLE
+        .get()
+        .expect("offset table is not initialized. call sbx_offset::init first")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_names_cover_the_offset_file() {
+        let file: toml::Value = toml::from_str(include_str!("../data/offsets.toml")).unwrap();
+        let build = &file["build"][0];
+        let mut names = Vec::new();
+        for (key, value) in build.as_table().unwrap() {
+            match value {
+                toml::Value::Integer(_) => names.push(key.clone()),
+                toml::Value::Table(t) if key == "battle" || key == "css" => {
+                    names.extend(t.keys().cloned())
+                }
+                _ => {}
+            }
+        }
+        names.sort();
+        let mut known = OffsetTable::offset_names().collect::<Vec<_>>();
+        known.sort_unstable();
+        assert_eq!(names, known);
+    }
 
-//SBX offsets
-/*main*/
-pub const MAIN_LOOP_INNER_OFFSET: usize = 0x61F13;
-pub const GAME_LOOP_INNER_OFFSET: usize = 0x61f00;
-pub const UI_LOOP_INNER_OFFSET: usize = 0x18888;
+    #[test]
+    fn offset_and_offset_mut_agree() {
+        let mut table = OffsetRegistry::embedded().unwrap().builds()[0].clone();
+        for name in OffsetTable::offset_names() {
+            let offset = table.offset(name).unwrap();
+            assert_eq!(table.offset_mut(name).copied(), Some(offset), "{}", name);
+        }
+        *table.offset_mut("hpcap_2_offset").unwrap() = 0x1234;
+        assert_eq!(table.battle.hpcap_2_offset, 0x1234);
+        assert_eq!(table.offset("hpcap_2_offset"), Some(0x1234));
+        assert_eq!(table.offset("signatures"), None);
+    }
 
-pub const UI_LOOP_SWITCH_FLAG_OFFSET: usize = 0x1E5EE0;
+    #[test]
+    fn embedded_patches_resolve() {
+        use sbx_tool_common::memory::MockMemory;
+        use sbx_tool_common::utility::patch_registry::PatchRegistry;
+        let registry = OffsetRegistry::embedded().unwrap();
+        let memory = std::sync::Arc::new(MockMemory::new());
+        let patches = PatchRegistry::load(memory, 0x40_0000, &registry.builds()[0], None).unwrap();
+        assert!(!patches.is_empty());
+    }
+}
@@ -0,0 +1,286 @@
+use crate::fingerprint::BuildFingerprint;
+use crate::OffsetTable;
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::path::Path;
+use toml::value::Table;
+use toml::Value;
+
+/// Offset tables shipped with the tool.
+const EMBEDDED_OFFSETS: &str = include_str!("../data/offsets.toml");
+
+#[derive(Deserialize)]
+struct OffsetFile {
+    #[serde(default, rename = "build")]
+    builds: Vec<Table>,
+}
+
+/// Known game builds and their offset tables
+#[derive(Debug, Clone, Default)]
+pub struct OffsetRegistry {
+    builds: Vec<OffsetTable>,
+    //as written in the files, so an override can replace single offsets
+    raw: Vec<Table>,
+}
+
+/// Table picked for a game executable
+#[derive(Debug, Clone, Copy)]
+pub enum Selection<'a> {
+    /// The table's fingerprint matches the executable
+    Matched(&'a OffsetTable),
+    /// No fingerprint matches and this is the only table with `allow_unverified`,
+    /// used as is since the executable can't be verified against it
+    Unverified(&'a OffsetTable),
+}
+
+impl<'a> Selection<'a> {
+    pub fn table(&self) -> &'a OffsetTable {
+        match self {
+            Selection::Matched(t) | Selection::Unverified(t) => t,
+        }
+    }
+}
+
+impl OffsetRegistry {
+    pub fn embedded() -> Result<Self> {
+        Self::from_toml(EMBEDDED_OFFSETS)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self> {
+        let mut registry = OffsetRegistry::default();
+        registry.merge_toml(s)?;
+        Ok(registry)
+    }
+
+    /// Merge tables from a user-supplied file, see [`OffsetRegistry::merge_toml`]
+    pub fn load_override_file<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let s = std::fs::read_to_string(path.as_ref())?;
+        self.merge_toml(&s)
+            .map_err(|e| anyhow!("{}: {}", path.as_ref().display(), e))
+    }
+
+    /// Merge tables from an offset file.
+    /// A table with the same name as an already registered one only replaces the values it sets,
+    /// e.g. a single offset or the fingerprint. Other tables are added and have to be complete.
+    pub fn merge_toml(&mut self, s: &str) -> Result<()> {
+        let file: OffsetFile = toml::from_str(s)?;
+        for table in file.builds {
+            let name = match table.get("name") {
+                Some(Value::String(name)) => name.clone(),
+                _ => return Err(anyhow!("a build has no name")),
+            };
+            match self.builds.iter().position(|t| t.name == name) {
+                Some(i) => {
+                    let mut raw = self.raw[i].clone();
+                    merge_table(&mut raw, table);
+                    self.builds[i] = parse_table(&name, raw.clone())?;
+                    self.raw[i] = raw;
+                }
+                None => {
+                    self.builds.push(parse_table(&name, table.clone())?);
+                    self.raw.push(table);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn find(&self, fingerprint: &BuildFingerprint) -> Option<&OffsetTable> {
+        self.builds
+            .iter()
+            .find(|t| t.fingerprint.matches(fingerprint))
+    }
+
+    /// Table for a game executable.
+    /// Falls back to the only table with `allow_unverified` when no fingerprint matches.
+    pub fn select(&self, fingerprint: &BuildFingerprint) -> Option<Selection<'_>> {
+        if let Some(table) = self.find(fingerprint) {
+            return Some(Selection::Matched(table));
+        }
+        let mut unverified = self.builds.iter().filter(|t| t.allow_unverified);
+        match (unverified.next(), unverified.next()) {
+            (Some(table), None) => Some(Selection::Unverified(table)),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OffsetTable> {
+        self.builds.iter().find(|t| t.name == name)
+    }
+
+    pub fn builds(&self) -> &[OffsetTable] {
+        &self.builds
+    }
+}
+
+fn parse_table(name: &str, table: Table) -> Result<OffsetTable> {
+    Value::Table(table)
+        .try_into()
+        .map_err(|e| anyhow!("build {}: {}", name, e))
+}
+
+fn merge_table(base: &mut Table, other: Table) {
+    for (key, value) in other {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(other)) => merge_table(base, other),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: &str = r#"
+        [[build]]
+        name = "1.0"
+        main_loop_inner_offset = 0x10
+        game_loop_inner_offset = 0x20
+        ui_loop_inner_offset = 0x30
+        ui_loop_switch_flag_offset = 0x40
+
+        [build.battle]
+        battle_main_loop_first_switch_offset = 0x100
+        battle_main_loop_first_switch_flag_offset = 0x101
+        battle_context_offset = 0x102
+        battle_stun_context_offset = 0x103
+        battle_attack_level_context_offset = 0x104
+        battle_unk_context = 0x105
+        hpcap_1_offset = 0x106
+        hpcap_2_offset = 0x107
+        excap_1_offset = 0x108
+        excap_2_offset = 0x109
+
+        [build.css]
+        vs_cpu_css_context_offset = 0x200
+        add_character_cost_to_party_cost_offset = 0x201
+        vs_cpu_css_init_context_constants_offset = 0x202
+    "#;
+
+    fn fingerprint(timestamp: u32) -> BuildFingerprint {
+        BuildFingerprint {
+            size: 0x1000,
+            timestamp,
+            sha256: "ab".repeat(32),
+        }
+    }
+
+    fn with_name(name: &str, fingerprint: &str) -> String {
+        TABLE.replace(
+            "name = \"1.0\"",
+            &format!("name = \"{}\"\n{}", name, fingerprint),
+        )
+    }
+
+    #[test]
+    fn embedded_tables_parse() {
+        let registry = OffsetRegistry::embedded().unwrap();
+        let table = registry.get("1.19").unwrap();
+        assert_eq!(table.battle.battle_context_offset, 0x438B28);
+    }
+
+    //the 1.19 fingerprint isn't captured yet, it is only used when asked for
+    #[test]
+    fn embedded_table_is_selected_on_opt_in() {
+        let mut registry = OffsetRegistry::embedded().unwrap();
+        assert!(registry.select(&fingerprint(1)).is_none());
+
+        registry
+            .merge_toml("[[build]]\nname = \"1.19\"\nallow_unverified = true")
+            .unwrap();
+        match registry.select(&fingerprint(1)) {
+            Some(Selection::Unverified(t)) => assert_eq!(t.name, "1.19"),
+            s => panic!("{:?}", s),
+        }
+    }
+
+    #[test]
+    fn select_prefers_matching_fingerprint() {
+        let mut registry =
+            OffsetRegistry::from_toml(&with_name("1.0", "allow_unverified = true")).unwrap();
+        registry
+            .merge_toml(&with_name("1.1", "fingerprint = { timestamp = 7 }"))
+            .unwrap();
+
+        match registry.select(&fingerprint(7)) {
+            Some(Selection::Matched(t)) => assert_eq!(t.name, "1.1"),
+            s => panic!("{:?}", s),
+        }
+        match registry.select(&fingerprint(8)) {
+            Some(Selection::Unverified(t)) => assert_eq!(t.name, "1.0"),
+            s => panic!("{:?}", s),
+        }
+    }
+
+    #[test]
+    fn select_refuses_unknown_builds() {
+        //no fingerprint is not the same as any fingerprint
+        let registry = OffsetRegistry::from_toml(TABLE).unwrap();
+        assert!(registry.select(&fingerprint(1)).is_none());
+
+        let registry =
+            OffsetRegistry::from_toml(&with_name("1.1", "fingerprint = { timestamp = 7 }"))
+                .unwrap();
+        assert!(registry.select(&fingerprint(8)).is_none());
+
+        let mut registry =
+            OffsetRegistry::from_toml(&with_name("1.0", "allow_unverified = true")).unwrap();
+        registry
+            .merge_toml(&with_name("1.1", "allow_unverified = true"))
+            .unwrap();
+        assert!(registry.select(&fingerprint(1)).is_none());
+    }
+
+    #[test]
+    fn override_merges_single_offsets() {
+        let mut registry = OffsetRegistry::from_toml(TABLE).unwrap();
+        registry
+            .merge_toml(
+                r#"
+                [[build]]
+                name = "1.0"
+                fingerprint = { size = 0x1000 }
+                ui_loop_inner_offset = 0x31
+
+                [build.battle]
+                battle_context_offset = 0x1102
+                "#,
+            )
+            .unwrap();
+
+        assert_eq!(registry.builds().len(), 1);
+        let table = registry.get("1.0").unwrap();
+        assert_eq!(table.ui_loop_inner_offset, 0x31);
+        assert_eq!(table.main_loop_inner_offset, 0x10);
+        assert_eq!(table.battle.battle_context_offset, 0x1102);
+        assert_eq!(table.battle.hpcap_1_offset, 0x106);
+        assert_eq!(table.css.vs_cpu_css_context_offset, 0x200);
+        assert!(table.fingerprint.matches(&fingerprint(1)));
+    }
+
+    #[test]
+    fn override_adds_new_builds() {
+        let mut registry = OffsetRegistry::from_toml(TABLE).unwrap();
+        registry.merge_toml(&with_name("1.1", "")).unwrap();
+        assert_eq!(registry.builds().len(), 2);
+
+        //a new build has to be complete
+        let err = registry
+            .merge_toml("[[build]]\nname = \"1.2\"\nmain_loop_inner_offset = 1")
+            .unwrap_err();
+        assert!(err.to_string().contains("1.2"), "{}", err);
+        assert!(registry.get("1.2").is_none());
+    }
+
+    #[test]
+    fn bad_override_keeps_the_table() {
+        let mut registry = OffsetRegistry::from_toml(TABLE).unwrap();
+        assert!(registry
+            .merge_toml("[[build]]\nname = \"1.0\"\nmain_loop_inner_offset = \"x\"")
+            .is_err());
+        assert_eq!(registry.get("1.0").unwrap().main_loop_inner_offset, 0x10);
+    }
+}
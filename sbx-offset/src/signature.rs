@@ -0,0 +1,530 @@
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt;
+
+/// Longest pattern [`CodeImage::generate`] tries before giving up
+pub const MAX_GENERATED_PATTERN_LENGTH: usize = 64;
+/// References to a global [`CodeImage::generate`] tries a signature at
+const MAX_REFERENCES: usize = 32;
+
+/// Byte signature that locates an offset in the game's code,
+/// so the offset survives minor game updates.
+///
+/// `pattern` is IDA-style, e.g. `8B 0D ?? ?? ?? ?? 85 C9`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct SignatureDef {
+    pub pattern: String,
+    /// Added to the match address before the operand is read
+    #[serde(default)]
+    pub offset: isize,
+    #[serde(default)]
+    pub operand: OperandKind,
+}
+
+/// Printed in the offset file format, below a `[build.signatures.<offset name>]` header
+impl fmt::Display for SignatureDef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "pattern = \"{}\"", self.pattern)?;
+        if self.offset != 0 {
+            writeln!(f, "offset = {}", self.offset)?;
+        }
+        match self.operand {
+            OperandKind::None => Ok(()),
+            OperandKind::Absolute => writeln!(f, "operand = \"absolute\""),
+            OperandKind::Relative => writeln!(f, "operand = \"relative\""),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OperandKind {
+    /// The offset is the (match + offset) address itself
+    None,
+    /// Read an absolute address at (match + offset), e.g. `mov ecx, [addr]`
+    Absolute,
+    /// Read a rel32 at (match + offset) and follow it, e.g. `call rel32`
+    Relative,
+}
+
+#[allow(clippy::derivable_impls)]
+impl Default for OperandKind {
+    fn default() -> Self {
+        OperandKind::None
+    }
+}
+
+/// IDA-style byte pattern, `None` is a wildcard
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    bytes: Vec<Option<u8>>,
+}
+
+impl Pattern {
+    /// Parse `8B 0D ?? ?? ?? ?? 85 C9`. `?` and `??` are wildcards.
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let bytes = pattern
+            .split_whitespace()
+            .map(|token| match token {
+                "?" | "??" => Ok(None),
+                _ => u8::from_str_radix(token, 16)
+                    .map(Some)
+                    .map_err(|_| anyhow!("invalid byte '{}' in pattern '{}'", token, pattern)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::from_bytes(bytes).map_err(|e| anyhow!("{} '{}'", e, pattern))
+    }
+
+    fn from_bytes(bytes: Vec<Option<u8>>) -> Result<Self> {
+        if bytes.is_empty() {
+            return Err(anyhow!("empty pattern"));
+        }
+        if bytes.iter().all(|b| b.is_none()) {
+            return Err(anyhow!("only wildcards in pattern"));
+        }
+        Ok(Pattern { bytes })
+    }
+
+    pub fn matches_at(&self, data: &[u8], at: usize) -> bool {
+        let window = match at
+            .checked_add(self.bytes.len())
+            .and_then(|end| data.get(at..end))
+        {
+            Some(w) => w,
+            None => return false,
+        };
+        self.bytes
+            .iter()
+            .zip(window)
+            .all(|(p, b)| p.is_none() || *p == Some(*b))
+    }
+
+    /// Offsets in `data` where the pattern matches
+    pub fn find_iter<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        //first non-wildcard byte is used as an anchor
+        let (anchor_index, anchor) = self
+            .bytes
+            .iter()
+            .enumerate()
+            .find_map(|(i, b)| b.map(|b| (i, b)))
+            .unwrap(); //parse rejects all-wildcard patterns
+        let last = (data.len() + 1).saturating_sub(self.bytes.len());
+        (0..last).filter(move |&at| data[at + anchor_index] == anchor && self.matches_at(data, at))
+    }
+
+    pub fn find(&self, data: &[u8]) -> Option<usize> {
+        self.find_iter(data).next()
+    }
+
+    pub fn find_all(&self, data: &[u8]) -> Vec<usize> {
+        self.find_iter(data).collect()
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, b) in self.bytes.iter().enumerate() {
+            if i != 0 {
+                f.write_str(" ")?;
+            }
+            match b {
+                Some(b) => write!(f, "{:02X}", b)?,
+                None => f.write_str("??")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pattern plus the way to turn a match into an offset
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub pattern: Pattern,
+    pub offset: isize,
+    pub operand: OperandKind,
+}
+
+impl Signature {
+    pub fn from_def(def: &SignatureDef) -> Result<Self> {
+        Ok(Signature {
+            pattern: Pattern::parse(&def.pattern)?,
+            offset: def.offset,
+            operand: def.operand,
+        })
+    }
+
+    /// Resolve the signature to an rva.
+    ///
+    /// `data` is a copy of (or a view into) the image starting at `data_rva`,
+    /// `image_base` is the base address absolute operands are relative to.
+    /// The pattern has to match exactly once.
+    pub fn resolve(&self, data: &[u8], data_rva: usize, image_base: usize) -> Result<usize> {
+        let mut matches = self.pattern.find_iter(data);
+        let found = matches
+            .next()
+            .ok_or_else(|| anyhow!("signature not found"))?;
+        let more = matches.count();
+        if more != 0 {
+            return Err(anyhow!("signature is ambiguous ({} matches)", more + 1));
+        }
+
+        let at = (found as isize)
+            .checked_add(self.offset)
+            .filter(|at| *at >= 0)
+            .ok_or_else(|| anyhow!("signature offset is out of bounds"))? as usize;
+        let read_u32 = || -> Result<u32> {
+            let bytes = at
+                .checked_add(4)
+                .and_then(|end| data.get(at..end))
+                .ok_or_else(|| anyhow!("signature operand is out of bounds"))?;
+            Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        let out_of_range = || anyhow!("signature resolves outside the address space");
+
+        match self.operand {
+            OperandKind::None => data_rva.checked_add(at).ok_or_else(out_of_range),
+            OperandKind::Absolute => {
+                let address = read_u32()? as usize;
+                address.checked_sub(image_base).ok_or_else(|| {
+                    anyhow!("absolute operand {:x} is below the image base", address)
+                })
+            }
+            OperandKind::Relative => {
+                //rel32 is relative to the end of the instruction, the operand is assumed to be the last part of it
+                let rel = read_u32()? as i32;
+                let next = data_rva.checked_add(at + 4).ok_or_else(out_of_range)?;
+                if rel >= 0 {
+                    next.checked_add(rel as usize)
+                } else {
+                    next.checked_sub(rel.unsigned_abs() as usize)
+                }
+                .ok_or_else(out_of_range)
+            }
+        }
+    }
+}
+
+/// Code to generate signatures from, e.g. the .text section of the game executable
+pub struct CodeImage<'a> {
+    pub data: &'a [u8],
+    /// rva of `data[0]`
+    pub rva: usize,
+    /// Image base absolute addresses in `data` are relative to
+    pub image_base: usize,
+    /// rvas of the absolute addresses the loader relocates, they are wildcards in a signature.
+    /// Without relocations the addresses are part of the pattern.
+    pub relocations: &'a HashSet<usize>,
+}
+
+impl<'a> CodeImage<'a> {
+    /// Shortest signature that finds `rva` in this image.
+    ///
+    /// An rva inside the code is matched by the bytes starting at it,
+    /// any other rva by an instruction that has it as an absolute operand.
+    pub fn generate(&self, rva: usize) -> Result<SignatureDef> {
+        let def = match rva.checked_sub(self.rva).filter(|at| *at < self.data.len()) {
+            Some(at) => self
+                .unique_length(at, 1)
+                .map(|len| self.signature_def(at, len, 0, OperandKind::None)),
+            None => self.generate_reference(rva),
+        }
+        .ok_or_else(|| anyhow!("no unique signature for {:x}", rva))?;
+
+        //the pattern is checked against the image it was made from
+        let resolved = Signature::from_def(&def)?.resolve(self.data, self.rva, self.image_base)?;
+        if resolved != rva {
+            return Err(anyhow!(
+                "signature for {:x} resolves to {:x}",
+                rva,
+                resolved
+            ));
+        }
+        Ok(def)
+    }
+
+    fn generate_reference(&self, rva: usize) -> Option<SignatureDef> {
+        let address = u32::try_from(self.image_base.checked_add(rva)?).ok()?;
+        let mut best: Option<(usize, usize, usize)> = None;
+        for operand in self.references(address).into_iter().take(MAX_REFERENCES) {
+            //the opcode and modrm before the operand, up to the longest x86 prefix + opcode
+            for before in 1..=std::cmp::min(operand, 8) {
+                if matches!(best, Some((_, _, best_len)) if best_len <= before + 4) {
+                    break;
+                }
+                let start = operand - before;
+                if let Some(len) = self.unique_length(start, before + 4) {
+                    match best {
+                        Some((_, _, best_len)) if best_len <= len => {}
+                        _ => best = Some((start, before, len)),
+                    }
+                }
+            }
+        }
+        best.map(|(start, before, len)| {
+            self.signature_def(start, len, before as isize, OperandKind::Absolute)
+        })
+    }
+
+    /// Positions of the dwords that hold `address`
+    fn references(&self, address: u32) -> Vec<usize> {
+        let address = address.to_le_bytes();
+        self.data
+            .windows(4)
+            .enumerate()
+            .filter(|(_, dword)| *dword == address)
+            .map(|(at, _)| at)
+            .collect()
+    }
+
+    fn is_relocated(&self, at: usize) -> bool {
+        let rva = self.rva + at;
+        (rva.saturating_sub(3)..=rva).any(|r| self.relocations.contains(&r))
+    }
+
+    fn pattern_byte(&self, at: usize) -> Option<u8> {
+        if self.is_relocated(at) {
+            None
+        } else {
+            Some(self.data[at])
+        }
+    }
+
+    /// Shortest length, at least `min_len`, for which the pattern at `start` matches only there
+    fn unique_length(&self, start: usize, min_len: usize) -> Option<usize> {
+        let end = std::cmp::min(start + MAX_GENERATED_PATTERN_LENGTH, self.data.len());
+        if start + min_len > end {
+            return None;
+        }
+        let pattern = (start..end)
+            .map(|at| self.pattern_byte(at))
+            .collect::<Vec<_>>();
+        let byte_matches = |candidate: usize, i: usize| match pattern[i] {
+            Some(b) => self.data.get(candidate + i) == Some(&b),
+            None => candidate + i < self.data.len(),
+        };
+
+        let mut len = min_len;
+        //the first fixed byte narrows the candidates down before the rest is compared
+        let anchor = match (0..len).find(|&i| pattern[i].is_some()) {
+            Some(i) => i,
+            None => {
+                len = pattern.iter().position(|b| b.is_some())? + 1;
+                len - 1
+            }
+        };
+        let mut candidates = (0..=self.data.len() - len)
+            .filter(|&c| byte_matches(c, anchor) && (0..len).all(|i| byte_matches(c, i)))
+            .collect::<Vec<_>>();
+        loop {
+            if candidates.len() == 1 {
+                return Some(len);
+            }
+            if len == pattern.len() {
+                return None;
+            }
+            candidates.retain(|&c| byte_matches(c, len));
+            len += 1;
+        }
+    }
+
+    fn signature_def(
+        &self,
+        start: usize,
+        len: usize,
+        offset: isize,
+        operand: OperandKind,
+    ) -> SignatureDef {
+        let pattern = Pattern {
+            bytes: (start..start + len)
+                .map(|at| self.pattern_byte(at))
+                .collect(),
+        };
+        SignatureDef {
+            pattern: pattern.to_string(),
+            offset,
+            operand,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(pattern: &str, offset: isize, operand: OperandKind) -> Signature {
+        Signature::from_def(&SignatureDef {
+            pattern: pattern.to_string(),
+            offset,
+            operand,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn parse_pattern() {
+        let pattern = Pattern::parse("8B 0D ?? ? 85 c9").unwrap();
+        assert_eq!(
+            pattern.bytes,
+            vec![Some(0x8b), Some(0x0d), None, None, Some(0x85), Some(0xc9)]
+        );
+        assert_eq!(pattern.to_string(), "8B 0D ?? ?? 85 C9");
+        assert_eq!(Pattern::parse(&pattern.to_string()).unwrap(), pattern);
+    }
+
+    #[test]
+    fn parse_rejects_bad_patterns() {
+        assert!(Pattern::parse("").is_err());
+        assert!(Pattern::parse("?? ??").is_err());
+        assert!(Pattern::parse("8B 0X").is_err());
+        assert!(Pattern::parse("8B 100").is_err());
+        assert!(Pattern::parse("8B0D").is_err());
+    }
+
+    #[test]
+    fn wildcards_match_any_byte() {
+        let data = [
+            0x90, 0x8b, 0x0d, 0x11, 0x22, 0x85, 0xc9, 0x8b, 0x0d, 0x33, 0x44, 0x85, 0xc0,
+        ];
+        let pattern = Pattern::parse("8B 0D ?? ?? 85").unwrap();
+        assert_eq!(pattern.find_all(&data), vec![1, 7]);
+        assert_eq!(
+            Pattern::parse("8B 0D ?? ?? 85 C9").unwrap().find_all(&data),
+            vec![1]
+        );
+        //leading wildcards and a match that ends with the data
+        assert_eq!(
+            Pattern::parse("?? 85 C0").unwrap().find_all(&data),
+            vec![10]
+        );
+        assert_eq!(Pattern::parse("85 C0 ??").unwrap().find(&data), None);
+        assert_eq!(Pattern::parse("C0").unwrap().find(&[]), None);
+    }
+
+    #[test]
+    fn resolve_none_absolute_and_relative() {
+        let data = [
+            0x55, // push ebp
+            0x8b, 0x0d, 0x28, 0x8b, 0x83, 0x00, // mov ecx,[0x838b28]
+            0xe8, 0xf0, 0xff, 0xff, 0xff, // call -0x10
+            0xc3,
+        ];
+        let text_rva = 0x1000;
+        let image_base = 0x400000;
+
+        let none = signature("8B 0D", 0, OperandKind::None);
+        assert_eq!(none.resolve(&data, text_rva, image_base).unwrap(), 0x1001);
+
+        let absolute = signature("8B 0D ?? ?? ?? ??", 2, OperandKind::Absolute);
+        assert_eq!(
+            absolute.resolve(&data, text_rva, image_base).unwrap(),
+            0x438b28
+        );
+
+        //call at 0x1007, next instruction at 0x100c
+        let relative = signature("E8 ?? ?? ?? ?? C3", 1, OperandKind::Relative);
+        assert_eq!(
+            relative.resolve(&data, text_rva, image_base).unwrap(),
+            0xffc
+        );
+    }
+
+    #[test]
+    fn resolve_errors() {
+        let data = [0x8b, 0x0d, 0x00, 0x10, 0x00, 0x00, 0x8b, 0x0d];
+        let image_base = 0x400000;
+        let err = |pattern, offset, operand| {
+            signature(pattern, offset, operand)
+                .resolve(&data, 0x1000, image_base)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(err("8B 0D", 0, OperandKind::None).contains("ambiguous (2 matches)"));
+        assert!(err("8B 0E", 0, OperandKind::None).contains("not found"));
+        assert!(err("00 10", -3, OperandKind::None).contains("out of bounds"));
+        assert!(err("00 10", isize::MAX, OperandKind::None).contains("out of bounds"));
+        assert!(err("00 00 8B", 1, OperandKind::Absolute).contains("out of bounds"));
+        assert!(err("8B 0D 00", 2, OperandKind::Absolute).contains("below the image base"));
+        //rel32 from rva 0 that points before the image
+        let relative = signature("8B 0D 00", 2, OperandKind::Relative);
+        assert!(relative
+            .resolve(&[0x8b, 0x0d, 0x00, 0x00, 0x00, 0x80], 0, 0)
+            .is_err());
+    }
+
+    #[test]
+    fn display_def() {
+        let def = SignatureDef {
+            pattern: "8B 0D ?? ?? ?? ??".to_string(),
+            offset: 2,
+            operand: OperandKind::Absolute,
+        };
+        let text = format!("[signatures.x]\n{}", def);
+        let parsed: toml::Value = toml::from_str(&text).unwrap();
+        let parsed: SignatureDef = parsed["signatures"]["x"].clone().try_into().unwrap();
+        assert_eq!(parsed, def);
+    }
+
+    //two functions that load the same global, one of them also at the start of a site
+    const CODE: [u8; 32] = [
+        0x55, 0x8b, 0xec, 0x8b, 0x0d, 0x28, 0x8b, 0x83, 0x00, 0x85, 0xc9, 0x74, 0x02, 0x5d, 0xc3,
+        0xcc, //
+        0x55, 0x8b, 0xec, 0xa1, 0x28, 0x8b, 0x83, 0x00, 0x85, 0xc0, 0x74, 0x02, 0x5d, 0xc3, 0xcc,
+        0xcc,
+    ];
+
+    #[test]
+    fn generate_code_and_data_signatures() {
+        let relocations = [0x1005, 0x1014].into_iter().collect::<HashSet<_>>();
+        let image = CodeImage {
+            data: &CODE,
+            rva: 0x1000,
+            image_base: 0x400000,
+            relocations: &relocations,
+        };
+
+        //site at the second function, "55 8B EC" alone is not unique
+        let site = image.generate(0x1010).unwrap();
+        assert_eq!(site.pattern, "55 8B EC A1");
+        assert_eq!(site.operand, OperandKind::None);
+
+        //global loaded by both functions, the first of the shortest unique ones is used
+        let global = image.generate(0x438b28).unwrap();
+        assert_eq!(
+            global,
+            SignatureDef {
+                pattern: "0D ?? ?? ?? ??".to_string(),
+                offset: 1,
+                operand: OperandKind::Absolute,
+            }
+        );
+    }
+
+    #[test]
+    fn generate_fails_without_unique_signature() {
+        let relocations = HashSet::new();
+        let image = CodeImage {
+            data: &[0xcc; 16],
+            rva: 0x1000,
+            image_base: 0x400000,
+            relocations: &relocations,
+        };
+        assert!(image.generate(0x1004).is_err());
+        //not referenced from the code
+        assert!(image.generate(0x438b28).is_err());
+    }
+
+    #[test]
+    fn generate_without_relocations() {
+        let relocations = HashSet::new();
+        let image = CodeImage {
+            data: &CODE,
+            rva: 0x1000,
+            image_base: 0x400000,
+            relocations: &relocations,
+        };
+        let global = image.generate(0x438b28).unwrap();
+        assert_eq!(global.pattern, "0D 28 8B 83 00");
+        assert_eq!(global.offset, 1);
+    }
+}
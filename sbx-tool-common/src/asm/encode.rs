@@ -0,0 +1,492 @@
+use super::operand::{Expr, Mem, Operand, Reg};
+use anyhow::{anyhow, Result};
+
+/// Where a branch goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Target {
+    /// Label of the code, plus an addend
+    Label(String, i64),
+    /// Displacement from the end of the instruction
+    Relative(i64),
+    Absolute(usize),
+}
+
+/// A branch displacement to fill in once labels are known.
+/// The operand is the last thing in the instruction.
+#[derive(Debug, Clone)]
+pub(super) struct Branch {
+    pub at: usize,
+    /// 1 or 4
+    pub size: usize,
+    pub target: Target,
+}
+
+/// Bytes of the code being assembled
+#[derive(Default)]
+pub(super) struct Encoder {
+    pub bytes: Vec<u8>,
+    pub branches: Vec<Branch>,
+}
+
+const ALU: [&str; 8] = ["add", "or", "adc", "sbb", "and", "sub", "xor", "cmp"];
+const SHIFTS: [(&str, u8); 8] = [
+    ("rol", 0),
+    ("ror", 1),
+    ("rcl", 2),
+    ("rcr", 3),
+    ("shl", 4),
+    ("sal", 4),
+    ("shr", 5),
+    ("sar", 7),
+];
+const UNARY: [(&str, u8); 5] = [("not", 2), ("neg", 3), ("mul", 4), ("div", 6), ("idiv", 7)];
+const CONDITIONS: [(&str, u8); 30] = [
+    ("o", 0),
+    ("no", 1),
+    ("b", 2),
+    ("c", 2),
+    ("nae", 2),
+    ("ae", 3),
+    ("nb", 3),
+    ("nc", 3),
+    ("e", 4),
+    ("z", 4),
+    ("ne", 5),
+    ("nz", 5),
+    ("be", 6),
+    ("na", 6),
+    ("a", 7),
+    ("nbe", 7),
+    ("s", 8),
+    ("ns", 9),
+    ("p", 10),
+    ("pe", 10),
+    ("np", 11),
+    ("po", 11),
+    ("l", 12),
+    ("nge", 12),
+    ("ge", 13),
+    ("nl", 13),
+    ("le", 14),
+    ("ng", 14),
+    ("g", 15),
+    ("nle", 15),
+];
+const NO_OPERANDS: [(&str, u8); 8] = [
+    ("nop", 0x90),
+    ("ret", 0xC3),
+    ("int3", 0xCC),
+    ("pushad", 0x60),
+    ("popad", 0x61),
+    ("pushfd", 0x9C),
+    ("popfd", 0x9D),
+    ("cdq", 0x99),
+];
+
+impl Encoder {
+    /// Encode one instruction. `short` is set for `jmp short`/`jcc short`.
+    pub fn instruction(&mut self, mnemonic: &str, short: bool, operands: &[Operand]) -> Result<()> {
+        let mnemonic = mnemonic.to_ascii_lowercase();
+        let m = mnemonic.as_str();
+        if short && !(m == "jmp" || condition(m).is_some()) {
+            return Err(anyhow!("'short' only goes with jmp and jcc"));
+        }
+
+        if let Some((_, opcode)) = NO_OPERANDS.iter().find(|(n, _)| *n == m) {
+            if m == "ret" && operands.len() == 1 {
+                let value = imm(&operands[0])?;
+                self.bytes.push(0xC2);
+                self.immediate(value, 2)?;
+                return Ok(());
+            }
+            expect_count(operands, 0)?;
+            self.bytes.push(*opcode);
+            return Ok(());
+        }
+        if let Some(op) = ALU.iter().position(|n| *n == m) {
+            expect_count(operands, 2)?;
+            return self.alu(op as u8, &operands[0], &operands[1]);
+        }
+        if let Some((_, ext)) = SHIFTS.iter().find(|(n, _)| *n == m) {
+            expect_count(operands, 2)?;
+            return self.shift(*ext, &operands[0], &operands[1]);
+        }
+        if let Some((_, ext)) = UNARY.iter().find(|(n, _)| *n == m) {
+            expect_count(operands, 1)?;
+            let size = rm_size(&operands[0], None)?;
+            self.prefix(size);
+            self.bytes.push(if size == 1 { 0xF6 } else { 0xF7 });
+            return self.modrm(*ext, &operands[0]);
+        }
+        if let Some(cc) = condition(m) {
+            expect_count(operands, 1)?;
+            let target = branch_target(&operands[0])?;
+            match short {
+                true => self.bytes.push(0x70 + cc),
+                false => self.bytes.extend_from_slice(&[0x0F, 0x80 + cc]),
+            }
+            self.branch(if short { 1 } else { 4 }, target);
+            return Ok(());
+        }
+
+        match (m, operands) {
+            ("mov", [dst, src]) => self.mov(dst, src),
+            ("test", [rm, Operand::Reg(r)]) => {
+                rm_size(rm, Some(r.size))?;
+                self.prefix(r.size);
+                self.bytes.push(if r.size == 1 { 0x84 } else { 0x85 });
+                self.modrm(r.num, rm)
+            }
+            ("test", [rm, Operand::Imm(e)]) => {
+                let size = rm_size(rm, None)?;
+                self.prefix(size);
+                match rm {
+                    Operand::Reg(Reg { num: 0, .. }) => {
+                        self.bytes.push(if size == 1 { 0xA8 } else { 0xA9 })
+                    }
+                    _ => {
+                        self.bytes.push(if size == 1 { 0xF6 } else { 0xF7 });
+                        self.modrm(0, rm)?;
+                    }
+                }
+                self.immediate(e.absolute()?, size)
+            }
+            ("xchg", [rm, Operand::Reg(r)]) => {
+                rm_size(rm, Some(r.size))?;
+                self.prefix(r.size);
+                self.bytes.push(if r.size == 1 { 0x86 } else { 0x87 });
+                self.modrm(r.num, rm)
+            }
+            ("lea", [Operand::Reg(r), mem @ Operand::Mem(_)]) if r.size == 4 => {
+                self.bytes.push(0x8D);
+                self.modrm(r.num, mem)
+            }
+            ("inc" | "dec", [rm]) => {
+                let ext = if m == "inc" { 0 } else { 1 };
+                match rm {
+                    Operand::Reg(r) if r.size == 4 => self.bytes.push(0x40 + ext * 8 + r.num),
+                    _ => {
+                        let size = rm_size(rm, None)?;
+                        self.prefix(size);
+                        self.bytes.push(if size == 1 { 0xFE } else { 0xFF });
+                        self.modrm(ext, rm)?;
+                    }
+                }
+                Ok(())
+            }
+            ("imul", [rm]) => {
+                let size = rm_size(rm, None)?;
+                self.prefix(size);
+                self.bytes.push(if size == 1 { 0xF6 } else { 0xF7 });
+                self.modrm(5, rm)
+            }
+            ("imul", [Operand::Reg(r), rm]) if r.size != 1 => {
+                rm_size(rm, Some(r.size))?;
+                self.prefix(r.size);
+                self.bytes.extend_from_slice(&[0x0F, 0xAF]);
+                self.modrm(r.num, rm)
+            }
+            ("imul", [Operand::Reg(r), rm, Operand::Imm(e)]) if r.size != 1 => {
+                rm_size(rm, Some(r.size))?;
+                let value = e.absolute()?;
+                self.prefix(r.size);
+                let short = fits_i8(value);
+                self.bytes.push(if short { 0x6B } else { 0x69 });
+                self.modrm(r.num, rm)?;
+                self.immediate(value, if short { 1 } else { r.size })
+            }
+            ("movzx" | "movsx", [Operand::Reg(r), rm]) if r.size != 1 => {
+                let size = rm_size(rm, None)?;
+                if size >= r.size {
+                    return Err(anyhow!("{} needs a smaller source", m));
+                }
+                let opcode = match (m, size) {
+                    ("movzx", 1) => 0xB6,
+                    ("movzx", _) => 0xB7,
+                    (_, 1) => 0xBE,
+                    _ => 0xBF,
+                };
+                self.prefix(r.size);
+                self.bytes.extend_from_slice(&[0x0F, opcode]);
+                self.modrm(r.num, rm)
+            }
+            ("push", [Operand::Reg(r)]) if r.size == 4 => {
+                self.bytes.push(0x50 + r.num);
+                Ok(())
+            }
+            ("push", [Operand::Imm(e)]) => {
+                let value = e.absolute()?;
+                let short = fits_i8(value);
+                self.bytes.push(if short { 0x6A } else { 0x68 });
+                self.immediate(value, if short { 1 } else { 4 })
+            }
+            ("push", [mem @ Operand::Mem(_)]) => {
+                rm_size(mem, Some(4))?;
+                self.bytes.push(0xFF);
+                self.modrm(6, mem)
+            }
+            ("pop", [Operand::Reg(r)]) if r.size == 4 => {
+                self.bytes.push(0x58 + r.num);
+                Ok(())
+            }
+            ("pop", [mem @ Operand::Mem(_)]) => {
+                rm_size(mem, Some(4))?;
+                self.bytes.push(0x8F);
+                self.modrm(0, mem)
+            }
+            ("jmp" | "call", [target @ Operand::Imm(_)]) => {
+                if m == "call" && short {
+                    return Err(anyhow!("there is no short call"));
+                }
+                let target = branch_target(target)?;
+                self.bytes.push(match (m, short) {
+                    ("call", _) => 0xE8,
+                    (_, true) => 0xEB,
+                    _ => 0xE9,
+                });
+                self.branch(if short { 1 } else { 4 }, target);
+                Ok(())
+            }
+            ("jmp" | "call", [rm]) => {
+                rm_size(rm, Some(4))?;
+                self.bytes.push(0xFF);
+                self.modrm(if m == "call" { 2 } else { 4 }, rm)
+            }
+            _ => Err(anyhow!(
+                "unsupported instruction '{}' with {} operand(s)",
+                m,
+                operands.len()
+            )),
+        }
+    }
+
+    fn alu(&mut self, op: u8, dst: &Operand, src: &Operand) -> Result<()> {
+        match (dst, src) {
+            (rm, Operand::Reg(r)) => {
+                rm_size(rm, Some(r.size))?;
+                self.prefix(r.size);
+                self.bytes.push(op * 8 + if r.size == 1 { 0 } else { 1 });
+                self.modrm(r.num, rm)
+            }
+            (Operand::Reg(r), mem @ Operand::Mem(_)) => {
+                rm_size(mem, Some(r.size))?;
+                self.prefix(r.size);
+                self.bytes.push(op * 8 + if r.size == 1 { 2 } else { 3 });
+                self.modrm(r.num, mem)
+            }
+            (rm, Operand::Imm(e)) => {
+                let size = rm_size(rm, None)?;
+                let value = e.absolute()?;
+                self.prefix(size);
+                let accumulator = matches!(rm, Operand::Reg(Reg { num: 0, .. }));
+                if size == 1 {
+                    match accumulator {
+                        true => self.bytes.push(op * 8 + 4),
+                        false => {
+                            self.bytes.push(0x80);
+                            self.modrm(op, rm)?;
+                        }
+                    }
+                    return self.immediate(value, 1);
+                }
+                if fits_i8(value) {
+                    self.bytes.push(0x83);
+                    self.modrm(op, rm)?;
+                    return self.immediate(value, 1);
+                }
+                match accumulator {
+                    true => self.bytes.push(op * 8 + 5),
+                    false => {
+                        self.bytes.push(0x81);
+                        self.modrm(op, rm)?;
+                    }
+                }
+                self.immediate(value, size)
+            }
+            _ => Err(anyhow!("bad operands")),
+        }
+    }
+
+    fn shift(&mut self, ext: u8, rm: &Operand, count: &Operand) -> Result<()> {
+        let size = rm_size(rm, None)?;
+        self.prefix(size);
+        let byte = size == 1;
+        match count {
+            Operand::Reg(Reg { size: 1, num: 1 }) => {
+                self.bytes.push(if byte { 0xD2 } else { 0xD3 });
+                self.modrm(ext, rm)
+            }
+            Operand::Imm(e) if e.absolute()? == 1 => {
+                self.bytes.push(if byte { 0xD0 } else { 0xD1 });
+                self.modrm(ext, rm)
+            }
+            Operand::Imm(e) => {
+                self.bytes.push(if byte { 0xC0 } else { 0xC1 });
+                self.modrm(ext, rm)?;
+                self.immediate(e.absolute()?, 1)
+            }
+            _ => Err(anyhow!("shift count is an immediate or cl")),
+        }
+    }
+
+    fn mov(&mut self, dst: &Operand, src: &Operand) -> Result<()> {
+        match (dst, src) {
+            (rm, Operand::Reg(r)) => {
+                rm_size(rm, Some(r.size))?;
+                self.prefix(r.size);
+                self.bytes.push(if r.size == 1 { 0x88 } else { 0x89 });
+                self.modrm(r.num, rm)
+            }
+            (Operand::Reg(r), mem @ Operand::Mem(_)) => {
+                rm_size(mem, Some(r.size))?;
+                self.prefix(r.size);
+                self.bytes.push(if r.size == 1 { 0x8A } else { 0x8B });
+                self.modrm(r.num, mem)
+            }
+            (Operand::Reg(r), Operand::Imm(e)) => {
+                self.prefix(r.size);
+                self.bytes
+                    .push(if r.size == 1 { 0xB0 } else { 0xB8 } + r.num);
+                self.immediate(e.absolute()?, r.size)
+            }
+            (mem @ Operand::Mem(_), Operand::Imm(e)) => {
+                let size = rm_size(mem, None)?;
+                self.prefix(size);
+                self.bytes.push(if size == 1 { 0xC6 } else { 0xC7 });
+                self.modrm(0, mem)?;
+                self.immediate(e.absolute()?, size)
+            }
+            _ => Err(anyhow!("bad operands")),
+        }
+    }
+
+    fn prefix(&mut self, size: u8) {
+        if size == 2 {
+            self.bytes.push(0x66);
+        }
+    }
+
+    fn branch(&mut self, size: usize, target: Target) {
+        self.branches.push(Branch {
+            at: self.bytes.len(),
+            size,
+            target,
+        });
+        self.bytes.resize(self.bytes.len() + size, 0);
+    }
+
+    /// `size` bytes, little endian. Signed or unsigned values that fit are taken.
+    pub fn immediate(&mut self, value: i64, size: u8) -> Result<()> {
+        let bits = size as u32 * 8;
+        let min = -(1i64 << (bits - 1));
+        let max = (1i64 << bits) - 1;
+        if value < min || value > max {
+            return Err(anyhow!("{:#x} doesn't fit in {} byte(s)", value, size));
+        }
+        self.bytes
+            .extend_from_slice(&value.to_le_bytes()[..size as usize]);
+        Ok(())
+    }
+
+    /// ModRM(+SIB+displacement) with `reg` in the reg field
+    fn modrm(&mut self, reg: u8, rm: &Operand) -> Result<()> {
+        let mem = match rm {
+            Operand::Reg(r) => {
+                self.bytes.push(0xC0 | reg << 3 | r.num);
+                return Ok(());
+            }
+            Operand::Mem(mem) => mem,
+            Operand::Imm(_) => return Err(anyhow!("expected a register or memory operand")),
+        };
+        let disp = mem.disp;
+        if !(i32::MIN as i64..=u32::MAX as i64).contains(&disp) {
+            return Err(anyhow!("displacement {:#x} is out of range", disp));
+        }
+        let Mem { base, index, .. } = mem;
+        let (md, disp_size) = match base {
+            None => (0, 4),
+            //[ebp] has no mod 00 form
+            Some(5) if disp == 0 => (1, 1),
+            Some(_) if disp == 0 => (0, 0),
+            Some(_) if fits_i8(disp) => (1, 1),
+            Some(_) => (2, 4),
+        };
+        match (base, index) {
+            (Some(base), None) if *base != 4 => self.bytes.push(md << 6 | reg << 3 | base),
+            (None, None) => self.bytes.push(reg << 3 | 5),
+            _ => {
+                let (index, scale) = index.unwrap_or((4, 1));
+                let scale_bits = match scale {
+                    1 => 0,
+                    2 => 1,
+                    4 => 2,
+                    _ => 3,
+                };
+                self.bytes.push(md << 6 | reg << 3 | 4);
+                self.bytes
+                    .push(scale_bits << 6 | index << 3 | base.unwrap_or(5));
+            }
+        }
+        self.bytes
+            .extend_from_slice(&(disp as i32).to_le_bytes()[..disp_size]);
+        Ok(())
+    }
+}
+
+fn condition(mnemonic: &str) -> Option<u8> {
+    let cc = mnemonic.strip_prefix('j')?;
+    CONDITIONS.iter().find(|(n, _)| *n == cc).map(|(_, c)| *c)
+}
+
+fn branch_target(operand: &Operand) -> Result<Target> {
+    match operand {
+        Operand::Imm(Expr {
+            label: Some(label),
+            value,
+            ..
+        }) => Ok(Target::Label(label.clone(), *value)),
+        Operand::Imm(Expr {
+            relative: true,
+            value,
+            ..
+        }) => Ok(Target::Relative(*value)),
+        Operand::Imm(Expr { value, .. }) => Ok(Target::Absolute(*value as usize)),
+        _ => Err(anyhow!("expected a label, an address or +displacement")),
+    }
+}
+
+/// Size of a register/memory operand, checked against `expected`
+fn rm_size(rm: &Operand, expected: Option<u8>) -> Result<u8> {
+    let size = match rm {
+        Operand::Reg(r) => Some(r.size),
+        Operand::Mem(m) => m.size,
+        Operand::Imm(_) => return Err(anyhow!("expected a register or memory operand")),
+    };
+    match (size, expected) {
+        (Some(a), Some(b)) if a != b => Err(anyhow!("operand size mismatch")),
+        (Some(size), _) | (None, Some(size)) => Ok(size),
+        (None, None) => Err(anyhow!("operand size unknown, add byte/word/dword ptr")),
+    }
+}
+
+fn imm(operand: &Operand) -> Result<i64> {
+    match operand {
+        Operand::Imm(e) => e.absolute(),
+        _ => Err(anyhow!("expected an immediate")),
+    }
+}
+
+fn expect_count(operands: &[Operand], count: usize) -> Result<()> {
+    if operands.len() != count {
+        return Err(anyhow!(
+            "expected {} operand(s), got {}",
+            count,
+            operands.len()
+        ));
+    }
+    Ok(())
+}
+
+fn fits_i8(value: i64) -> bool {
+    (i8::MIN as i64..=i8::MAX as i64).contains(&value)
+}
@@ -0,0 +1,502 @@
+//! Small x86-32 assembler for patch definitions and code caves.
+//! Intel syntax, one statement per line or separated by `;`, `//` comments.
+//!
+//! mov eax, dword ptr [esi+0x10]; cmp eax, 9999
+//! jle short done
+//! mov eax, 9999
+//! done: call sbx+0xBF2A2
+//!
+//! Numbers are decimal, `0x` or `h` for hex. `+N`/`-N` as a jump target is a displacement
+//! from the end of the instruction(`jmp short +5`). Other names come from `symbols`,
+//! e.g. `sbx` for the game module. `db`/`dd` emit data.
+mod encode;
+mod operand;
+
+use anyhow::{anyhow, Result};
+use encode::{Encoder, Target};
+use operand::{is_identifier, parse_expr, parse_operand, split_operands};
+use std::collections::HashMap;
+
+/// Position independent machine code
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Assembled {
+    pub bytes: Vec<u8>,
+    /// rel32 operands of jumps and calls out of the code(offset of the operand, absolute target)
+    pub rel32: Vec<(usize, usize)>,
+}
+
+impl Assembled {
+    /// The bytes for the code placed at `origin`
+    pub fn at(&self, origin: usize) -> Vec<u8> {
+        let mut bytes = self.bytes.clone();
+        for (at, target) in &self.rel32 {
+            let next = origin + at + 4;
+            bytes[*at..at + 4].copy_from_slice(&(target.wrapping_sub(next) as u32).to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// Assemble `source`. `symbols` resolves names that aren't registers or labels to addresses.
+pub fn assemble(source: &str, symbols: &dyn Fn(&str) -> Option<usize>) -> Result<Assembled> {
+    let mut encoder = Encoder::default();
+    let mut labels: HashMap<String, usize> = HashMap::new();
+    //line of each branch, for errors
+    let mut branch_lines = Vec::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.split("//").next().unwrap_or("");
+        for statement in line.split(';') {
+            statement_into(statement, symbols, &mut encoder, &mut labels)
+                .map_err(|e| anyhow!("line {}: {}", line_number, e))?;
+            branch_lines.resize(encoder.branches.len(), line_number);
+        }
+    }
+
+    let mut assembled = Assembled {
+        bytes: encoder.bytes,
+        rel32: Vec::new(),
+    };
+    for (branch, line_number) in encoder.branches.iter().zip(branch_lines) {
+        let end = branch.at + branch.size;
+        let displacement = match &branch.target {
+            Target::Label(label, addend) => {
+                let offset = labels
+                    .get(label)
+                    .ok_or_else(|| anyhow!("line {}: unknown label '{}'", line_number, label))?;
+                *offset as i64 + addend - end as i64
+            }
+            Target::Relative(displacement) => *displacement,
+            Target::Absolute(target) if branch.size == 4 => {
+                assembled.rel32.push((branch.at, *target));
+                continue;
+            }
+            Target::Absolute(target) => {
+                return Err(anyhow!(
+                    "line {}: a short jump can't reach {:x} outside the code",
+                    line_number,
+                    target
+                ))
+            }
+        };
+        let bytes = &mut assembled.bytes[branch.at..end];
+        if branch.size == 1 {
+            if !(i8::MIN as i64..=i8::MAX as i64).contains(&displacement) {
+                return Err(anyhow!(
+                    "line {}: short jump out of range ({})",
+                    line_number,
+                    displacement
+                ));
+            }
+            bytes[0] = displacement as i8 as u8;
+        } else {
+            bytes.copy_from_slice(&(displacement as i32).to_le_bytes());
+        }
+    }
+    Ok(assembled)
+}
+
+/// An address like `sbx+0x1234`, names come from `symbols`
+pub fn evaluate(expr: &str, symbols: &dyn Fn(&str) -> Option<usize>) -> Result<usize> {
+    let expr = parse_expr(expr, symbols)?;
+    if let Some(name) = &expr.label {
+        return Err(anyhow!("unknown symbol '{}'", name));
+    }
+    usize::try_from(expr.value).map_err(|_| anyhow!("{} is not an address", expr.value))
+}
+
+fn statement_into(
+    statement: &str,
+    symbols: &dyn Fn(&str) -> Option<usize>,
+    encoder: &mut Encoder,
+    labels: &mut HashMap<String, usize>,
+) -> Result<()> {
+    let mut statement = statement.trim();
+    //`label:`, maybe followed by an instruction
+    if let Some((label, rest)) = statement.split_once(':') {
+        let label = label.trim();
+        if is_identifier(label) {
+            if labels
+                .insert(label.to_owned(), encoder.bytes.len())
+                .is_some()
+            {
+                return Err(anyhow!("label '{}' is defined twice", label));
+            }
+            statement = rest.trim();
+        }
+    }
+    if statement.is_empty() {
+        return Ok(());
+    }
+
+    let (mnemonic, rest) = match statement.split_once(char::is_whitespace) {
+        Some((m, rest)) => (m, rest.trim()),
+        None => (statement, ""),
+    };
+    let lower = mnemonic.to_ascii_lowercase();
+    if lower == "db" || lower == "dd" {
+        let size = if lower == "db" { 1 } else { 4 };
+        for value in split_operands(rest) {
+            let value = parse_expr(value, symbols)?.absolute()?;
+            encoder.immediate(value, size)?;
+        }
+        return Ok(());
+    }
+
+    let (short, rest) = match rest.split_once(char::is_whitespace) {
+        Some((keyword, target)) if keyword.eq_ignore_ascii_case("short") => (true, target),
+        Some((keyword, target)) if keyword.eq_ignore_ascii_case("near") => (false, target),
+        _ => (false, rest),
+    };
+    let operands = split_operands(rest)
+        .into_iter()
+        .map(|o| parse_operand(o, symbols))
+        .collect::<Result<Vec<_>>>()?;
+    encoder.instruction(mnemonic, short, &operands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODULE: usize = 0x40_0000;
+
+    fn symbols(name: &str) -> Option<usize> {
+        (name == "sbx").then_some(MODULE)
+    }
+
+    fn bytes(source: &str) -> Vec<u8> {
+        let assembled = assemble(source, &symbols).unwrap();
+        assert!(assembled.rel32.is_empty(), "{}", source);
+        assembled.bytes
+    }
+
+    /// Assemble each line on its own and compare to its bytes
+    fn check(cases: &[(&str, &[u8])]) {
+        for (source, expected) in cases {
+            assert_eq!(bytes(source), *expected, "{}", source);
+        }
+    }
+
+    fn error(source: &str) -> String {
+        assemble(source, &symbols).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn no_operands() {
+        check(&[
+            ("nop", &[0x90]),
+            ("ret", &[0xC3]),
+            ("ret 8", &[0xC2, 0x08, 0x00]),
+            ("int3", &[0xCC]),
+            ("pushad", &[0x60]),
+            ("popad", &[0x61]),
+            ("pushfd", &[0x9C]),
+            ("popfd", &[0x9D]),
+            ("cdq", &[0x99]),
+        ]);
+    }
+
+    #[test]
+    fn mov() {
+        check(&[
+            ("mov eax, ebx", &[0x89, 0xD8]),
+            ("mov [ebp], ecx", &[0x89, 0x4D, 0x00]),
+            ("mov al, byte ptr [esi]", &[0x8A, 0x06]),
+            ("mov eax, dword ptr [esi+0x10]", &[0x8B, 0x46, 0x10]),
+            ("mov ax, 0x1234", &[0x66, 0xB8, 0x34, 0x12]),
+            ("mov ecx, 9999", &[0xB9, 0x0F, 0x27, 0x00, 0x00]),
+            ("mov bh, 1", &[0xB7, 0x01]),
+            ("MOV EAX, 1", &[0xB8, 0x01, 0x00, 0x00, 0x00]),
+            (
+                "mov dword ptr [esp+4], 1",
+                &[0xC7, 0x44, 0x24, 0x04, 0x01, 0x00, 0x00, 0x00],
+            ),
+            (
+                "mov byte ptr [eax+ecx*4+0x100], 0xFF",
+                &[0xC6, 0x84, 0x88, 0x00, 0x01, 0x00, 0x00, 0xFF],
+            ),
+            ("mov word ptr [ecx], 0x10", &[0x66, 0xC7, 0x01, 0x10, 0x00]),
+        ]);
+    }
+
+    #[test]
+    fn addressing() {
+        check(&[
+            (
+                "mov eax, [0x12345678]",
+                &[0x8B, 0x05, 0x78, 0x56, 0x34, 0x12],
+            ),
+            ("mov eax, [sbx+0x10]", &[0x8B, 0x05, 0x10, 0x00, 0x40, 0x00]),
+            ("mov edx, [ebx+eax]", &[0x8B, 0x14, 0x03]),
+            // [ebp] has no mod 00 form
+            ("mov eax, [ebp+ebx*2]", &[0x8B, 0x44, 0x5D, 0x00]),
+            // no base is a disp32
+            (
+                "mov eax, [ecx*4+0x10]",
+                &[0x8B, 0x04, 0x8D, 0x10, 0x00, 0x00, 0x00],
+            ),
+            ("mov eax, [2*ecx]", &[0x8B, 0x04, 0x4D, 0, 0, 0, 0]),
+            // esp is moved to the base
+            ("mov ecx, [eax+esp]", &[0x8B, 0x0C, 0x04]),
+            ("mov ecx, [esp]", &[0x8B, 0x0C, 0x24]),
+            ("mov ecx, [esi-4]", &[0x8B, 0x4E, 0xFC]),
+            ("mov ecx, [esi+0x80]", &[0x8B, 0x8E, 0x80, 0x00, 0x00, 0x00]),
+            ("lea eax, [esi+ecx*2+8]", &[0x8D, 0x44, 0x4E, 0x08]),
+            ("lea edi, [eax+eax*8]", &[0x8D, 0x3C, 0xC0]),
+        ]);
+    }
+
+    #[test]
+    fn alu() {
+        check(&[
+            ("add eax, ecx", &[0x01, 0xC8]),
+            ("xor eax, eax", &[0x31, 0xC0]),
+            ("sbb ebx, ebx", &[0x19, 0xDB]),
+            ("sub ecx, [esi+8]", &[0x2B, 0x4E, 0x08]),
+            ("or byte ptr [ebx], 1", &[0x80, 0x0B, 0x01]),
+            ("and al, 0x0F", &[0x24, 0x0F]),
+            ("adc edx, 0", &[0x83, 0xD2, 0x00]),
+            ("add ax, -1", &[0x66, 0x83, 0xC0, 0xFF]),
+            ("cmp dword ptr [esi+0x10], 5", &[0x83, 0x7E, 0x10, 0x05]),
+            ("cmp eax, 9999", &[0x3D, 0x0F, 0x27, 0x00, 0x00]),
+            ("cmp ecx, 9999", &[0x81, 0xF9, 0x0F, 0x27, 0x00, 0x00]),
+            ("sub ax, 0x200", &[0x66, 0x2D, 0x00, 0x02]),
+        ]);
+    }
+
+    #[test]
+    fn shifts_and_unary() {
+        check(&[
+            ("shl eax, 1", &[0xD1, 0xE0]),
+            ("sal eax, 1", &[0xD1, 0xE0]),
+            ("shr ecx, 4", &[0xC1, 0xE9, 0x04]),
+            ("sar edx, cl", &[0xD3, 0xFA]),
+            ("rol al, 3", &[0xC0, 0xC0, 0x03]),
+            ("ror byte ptr [eax], cl", &[0xD2, 0x08]),
+            ("rcl bx, 1", &[0x66, 0xD1, 0xD3]),
+            ("rcr ecx, 2", &[0xC1, 0xD9, 0x02]),
+            ("not eax", &[0xF7, 0xD0]),
+            ("neg ecx", &[0xF7, 0xD9]),
+            ("mul dword ptr [esi]", &[0xF7, 0x26]),
+            ("div bl", &[0xF6, 0xF3]),
+            ("idiv ecx", &[0xF7, 0xF9]),
+        ]);
+    }
+
+    #[test]
+    fn other_instructions() {
+        check(&[
+            ("test eax, eax", &[0x85, 0xC0]),
+            ("test al, 1", &[0xA8, 0x01]),
+            ("test ecx, 0x100", &[0xF7, 0xC1, 0x00, 0x01, 0x00, 0x00]),
+            ("test byte ptr [esi], 0x80", &[0xF6, 0x06, 0x80]),
+            ("xchg eax, ecx", &[0x87, 0xC8]),
+            ("inc eax", &[0x40]),
+            ("dec edi", &[0x4F]),
+            ("inc ax", &[0x66, 0xFF, 0xC0]),
+            ("inc byte ptr [eax]", &[0xFE, 0x00]),
+            ("dec word ptr [ecx]", &[0x66, 0xFF, 0x09]),
+            ("imul ecx", &[0xF7, 0xE9]),
+            ("imul eax, ecx", &[0x0F, 0xAF, 0xC1]),
+            ("imul eax, ecx, 10", &[0x6B, 0xC1, 0x0A]),
+            (
+                "imul edx, [esi], 1000",
+                &[0x69, 0x16, 0xE8, 0x03, 0x00, 0x00],
+            ),
+            ("movzx eax, byte ptr [esi]", &[0x0F, 0xB6, 0x06]),
+            ("movzx eax, ax", &[0x0F, 0xB7, 0xC0]),
+            ("movsx ecx, ax", &[0x0F, 0xBF, 0xC8]),
+            ("movsx ax, bl", &[0x66, 0x0F, 0xBE, 0xC3]),
+            ("push eax", &[0x50]),
+            ("push 1", &[0x6A, 0x01]),
+            ("push 10h", &[0x6A, 0x10]),
+            ("push 0x1000", &[0x68, 0x00, 0x10, 0x00, 0x00]),
+            ("push dword ptr [esp+8]", &[0xFF, 0x74, 0x24, 0x08]),
+            ("pop ecx", &[0x59]),
+            ("pop dword ptr [eax]", &[0x8F, 0x00]),
+            ("call eax", &[0xFF, 0xD0]),
+            ("jmp dword ptr [ebx+4]", &[0xFF, 0x63, 0x04]),
+            ("call [0x401000]", &[0xFF, 0x15, 0x00, 0x10, 0x40, 0x00]),
+        ]);
+    }
+
+    #[test]
+    fn data_and_layout() {
+        check(&[
+            (
+                "db 1, 0xFF, -1; dd 0x12345678, sbx",
+                &[
+                    0x01, 0xFF, 0xFF, 0x78, 0x56, 0x34, 0x12, 0x00, 0x00, 0x40, 0x00,
+                ],
+            ),
+            (
+                "nop // comment\n// only a comment\n\n nop ;; nop",
+                &[0x90; 3],
+            ),
+        ]);
+    }
+
+    #[test]
+    fn relative_branches() {
+        check(&[
+            ("jmp short +5", &[0xEB, 0x05]),
+            ("jne short -2", &[0x75, 0xFE]),
+            ("jmp -5", &[0xE9, 0xFB, 0xFF, 0xFF, 0xFF]),
+            ("jmp near +0x10", &[0xE9, 0x10, 0x00, 0x00, 0x00]),
+            ("jz +0", &[0x0F, 0x84, 0x00, 0x00, 0x00, 0x00]),
+            ("call +0", &[0xE8, 0x00, 0x00, 0x00, 0x00]),
+        ]);
+        // every condition code, short and near
+        let conditions = [
+            "jo", "jno", "jb", "jae", "je", "jne", "jbe", "ja", "js", "jns", "jp", "jnp", "jl",
+            "jge", "jle", "jg",
+        ];
+        for (cc, mnemonic) in conditions.iter().enumerate() {
+            let cc = cc as u8;
+            check(&[
+                (&format!("{} short +1", mnemonic), &[0x70 + cc, 0x01]),
+                (
+                    &format!("{} +1", mnemonic),
+                    &[0x0F, 0x80 + cc, 0x01, 0x00, 0x00, 0x00],
+                ),
+            ]);
+        }
+        // aliases
+        check(&[
+            ("jc short +0", &[0x72, 0x00]),
+            ("jnae short +0", &[0x72, 0x00]),
+            ("jnc short +0", &[0x73, 0x00]),
+            ("jz short +0", &[0x74, 0x00]),
+            ("jnz short +0", &[0x75, 0x00]),
+            ("jna short +0", &[0x76, 0x00]),
+            ("jnbe short +0", &[0x77, 0x00]),
+            ("jpe short +0", &[0x7A, 0x00]),
+            ("jpo short +0", &[0x7B, 0x00]),
+            ("jnge short +0", &[0x7C, 0x00]),
+            ("jnl short +0", &[0x7D, 0x00]),
+            ("jng short +0", &[0x7E, 0x00]),
+            ("jnle short +0", &[0x7F, 0x00]),
+        ]);
+    }
+
+    #[test]
+    fn labels() {
+        // forward, rel8
+        check(&[(
+            "jle short done\nmov eax, 9999\ndone: ret",
+            &[0x7E, 0x05, 0xB8, 0x0F, 0x27, 0x00, 0x00, 0xC3],
+        )]);
+        // backward, rel8 and rel32
+        check(&[(
+            "top: dec ecx; jnz short top; jnz top",
+            &[0x49, 0x75, 0xFD, 0x0F, 0x85, 0xF7, 0xFF, 0xFF, 0xFF],
+        )]);
+        // addend, call and a label alone on its line
+        check(&[
+            (
+                "jmp short done+1\ndone:\nnop; nop",
+                &[0xEB, 0x01, 0x90, 0x90],
+            ),
+            (
+                "call f; ret; f: ret",
+                &[0xE8, 0x01, 0x00, 0x00, 0x00, 0xC3, 0xC3],
+            ),
+        ]);
+        // the edges of rel8
+        let forward = format!("jmp short end\n{}end:", "nop\n".repeat(127));
+        assert_eq!(bytes(&forward)[..2], [0xEB, 0x7F]);
+        let backward = format!("start:\n{}jmp short start", "nop\n".repeat(126));
+        assert_eq!(bytes(&backward)[126..], [0xEB, 0x80]);
+    }
+
+    #[test]
+    fn absolute_branches() {
+        let assembled = assemble("jmp sbx+0x100\ncall 0x401000\nje sbx", &symbols).unwrap();
+        assert_eq!(
+            assembled.bytes,
+            [0xE9, 0, 0, 0, 0, 0xE8, 0, 0, 0, 0, 0x0F, 0x84, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            assembled.rel32,
+            [(1, MODULE + 0x100), (6, 0x40_1000), (12, MODULE)]
+        );
+        // rel32 is from the end of each instruction at the origin
+        assert_eq!(
+            assembled.at(MODULE),
+            [
+                0xE9, 0xFB, 0x00, 0x00, 0x00, 0xE8, 0xF6, 0x0F, 0x00, 0x00, 0x0F, 0x84, 0xF0, 0xFF,
+                0xFF, 0xFF
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("jmp short +200", "line 1: short jump out of range (200)"),
+            (
+                "jmp short sbx",
+                "line 1: a short jump can't reach 400000 outside the code",
+            ),
+            ("nop\njmp nowhere", "line 2: unknown label 'nowhere'"),
+            ("a: nop\na: nop", "line 2: label 'a' is defined twice"),
+            (
+                "call short +5",
+                "line 1: 'short' only goes with jmp and jcc",
+            ),
+            (
+                "mov [eax], 1",
+                "line 1: operand size unknown, add byte/word/dword ptr",
+            ),
+            ("mov al, ecx", "line 1: operand size mismatch"),
+            ("mov al, 0x100", "line 1: 0x100 doesn't fit in 1 byte(s)"),
+            (
+                "cpuid",
+                "line 1: unsupported instruction 'cpuid' with 0 operand(s)",
+            ),
+            ("nop eax", "line 1: expected 0 operand(s), got 1"),
+            ("mov eax, [esp*2]", "line 1: esp can't be an index"),
+            ("lea eax, [ecx*3]", "line 1: bad scaled index 'ecx*3'"),
+            (
+                "lea eax, [eax+ebx+ecx]",
+                "line 1: too many registers in '[eax+ebx+ecx]'",
+            ),
+            ("mov eax, [ax]", "line 1: bad address register 'ax'"),
+            (
+                "mov eax, done\ndone:",
+                "line 1: label 'done' can only be a jump or call target",
+            ),
+            ("mov eax, [foo]", "line 1: unknown symbol 'foo'"),
+            ("mov eax, [ecx", "line 1: missing ']' in '[ecx'"),
+            (
+                "mov eax, dword ptr ecx",
+                "line 1: 'dword ptr ecx': a size only goes with a memory operand",
+            ),
+            ("shl eax, ebx", "line 1: shift count is an immediate or cl"),
+            ("movzx eax, ecx", "line 1: movzx needs a smaller source"),
+            ("mov eax, 1 +", "line 1: bad expression '1 +'"),
+            ("push 0xZZ", "line 1: bad number '0xZZ'"),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(error(source), expected, "{}", source);
+        }
+        let too_far = format!("jmp short end\n{}end:", "nop\n".repeat(128));
+        assert_eq!(error(&too_far), "line 1: short jump out of range (128)");
+    }
+
+    #[test]
+    fn evaluate_addresses() {
+        assert_eq!(evaluate("sbx+0x1234", &symbols).unwrap(), MODULE + 0x1234);
+        assert_eq!(evaluate("0x10 + 16", &symbols).unwrap(), 0x20);
+        assert_eq!(
+            evaluate("nope", &symbols).unwrap_err().to_string(),
+            "unknown symbol 'nope'"
+        );
+        assert_eq!(
+            evaluate("0x10-0x20", &symbols).unwrap_err().to_string(),
+            "-16 is not an address"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use anyhow::{anyhow, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Reg {
+    /// bytes, 1 2 or 4
+    pub size: u8,
+    pub num: u8,
+}
+
+const REGS_32: [&str; 8] = ["eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi"];
+const REGS_16: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+const REGS_8: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+
+pub(super) fn parse_reg(s: &str) -> Option<Reg> {
+    let s = s.to_ascii_lowercase();
+    [(4, REGS_32), (2, REGS_16), (1, REGS_8)]
+        .iter()
+        .find_map(|(size, names)| {
+            names.iter().position(|n| *n == s).map(|num| Reg {
+                size: *size,
+                num: num as u8,
+            })
+        })
+}
+
+/// A value, maybe relative to a label of the code being assembled
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Expr {
+    pub label: Option<String>,
+    pub value: i64,
+    /// Written as `+N`/`-N`, a branch displacement
+    pub relative: bool,
+}
+
+impl Expr {
+    /// The value, labels are only known relative to the code
+    pub fn absolute(&self) -> Result<i64> {
+        match &self.label {
+            Some(label) => Err(anyhow!(
+                "label '{}' can only be a jump or call target",
+                label
+            )),
+            None => Ok(self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Mem {
+    /// bytes, from `byte/word/dword ptr`
+    pub size: Option<u8>,
+    pub base: Option<u8>,
+    /// (register, scale)
+    pub index: Option<(u8, u8)>,
+    pub disp: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum Operand {
+    Reg(Reg),
+    Mem(Mem),
+    Imm(Expr),
+}
+
+/// Split operands on commas outside of brackets
+pub(super) fn split_operands(s: &str) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            ',' if depth == 0 => {
+                operands.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = s[start..].trim();
+    if !last.is_empty() || !operands.is_empty() {
+        operands.push(last);
+    }
+    operands
+}
+
+pub(super) fn parse_operand(s: &str, symbols: &dyn Fn(&str) -> Option<usize>) -> Result<Operand> {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+    let mut size = None;
+    let mut rest = s;
+    for (name, bytes) in [("byte", 1), ("word", 2), ("dword", 4)] {
+        if let Some(after) = lower.strip_prefix(name) {
+            if after.starts_with(char::is_whitespace) || after.starts_with('[') {
+                let after = after.trim_start();
+                let after = after.strip_prefix("ptr").unwrap_or(after);
+                size = Some(bytes);
+                rest = s[s.len() - after.len()..].trim();
+                break;
+            }
+        }
+    }
+
+    if let Some(inner) = rest.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or_else(|| anyhow!("missing ']' in '{}'", s))?;
+        let mut mem = parse_mem(inner, symbols)?;
+        mem.size = size;
+        return Ok(Operand::Mem(mem));
+    }
+    if size.is_some() {
+        return Err(anyhow!("'{}': a size only goes with a memory operand", s));
+    }
+    if let Some(reg) = parse_reg(rest) {
+        return Ok(Operand::Reg(reg));
+    }
+    Ok(Operand::Imm(parse_expr(rest, symbols)?))
+}
+
+/// `base + index*scale + disp`, in any order
+fn parse_mem(s: &str, symbols: &dyn Fn(&str) -> Option<usize>) -> Result<Mem> {
+    let mut mem = Mem {
+        size: None,
+        base: None,
+        index: None,
+        disp: 0,
+    };
+    for (negative, term) in terms(s)? {
+        let scaled = term.split_once('*').map(|(a, b)| (a.trim(), b.trim()));
+        let (reg, scale) = match scaled {
+            Some((a, b)) => match (parse_reg(a), parse_reg(b)) {
+                (Some(r), None) => (Some(r), Some(parse_number(b)?)),
+                (None, Some(r)) => (Some(r), Some(parse_number(a)?)),
+                _ => return Err(anyhow!("bad scaled index '{}'", term)),
+            },
+            None => (parse_reg(term), None),
+        };
+        let reg = match reg {
+            Some(reg) => reg,
+            None => {
+                let value = term_value(term, symbols)?
+                    .ok_or_else(|| anyhow!("unknown symbol '{}'", term))?;
+                mem.disp += if negative { -value } else { value };
+                continue;
+            }
+        };
+        if reg.size != 4 || negative {
+            return Err(anyhow!("bad address register '{}'", term));
+        }
+        match scale {
+            Some(scale) => {
+                if !matches!(scale, 1 | 2 | 4 | 8) || mem.index.is_some() {
+                    return Err(anyhow!("bad scaled index '{}'", term));
+                }
+                mem.index = Some((reg.num, scale as u8));
+            }
+            None if mem.base.is_none() => mem.base = Some(reg.num),
+            None if mem.index.is_none() => mem.index = Some((reg.num, 1)),
+            None => return Err(anyhow!("too many registers in '[{}]'", s)),
+        }
+    }
+    if let Some((4, _)) = mem.index {
+        //esp can't be an index, but it can be the base
+        match (mem.base, mem.index) {
+            (Some(base), Some((4, 1))) if base != 4 => {
+                mem.index = Some((base, 1));
+                mem.base = Some(4);
+            }
+            _ => return Err(anyhow!("esp can't be an index")),
+        }
+    }
+    Ok(mem)
+}
+
+/// `a + b - c`, a leading sign makes it relative
+pub(super) fn parse_expr(s: &str, symbols: &dyn Fn(&str) -> Option<usize>) -> Result<Expr> {
+    let s = s.trim();
+    let mut expr = Expr {
+        label: None,
+        value: 0,
+        relative: s.starts_with('+') || s.starts_with('-'),
+    };
+    for (negative, term) in terms(s)? {
+        match term_value(term, symbols)? {
+            Some(value) => expr.value += if negative { -value } else { value },
+            None if negative || expr.label.is_some() || !is_identifier(term) => {
+                return Err(anyhow!("unknown symbol '{}'", term))
+            }
+            None => expr.label = Some(term.to_owned()),
+        }
+    }
+    Ok(expr)
+}
+
+/// Terms of a sum with their sign
+fn terms(s: &str) -> Result<Vec<(bool, &str)>> {
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        if c == '+' || c == '-' {
+            let term = s[start..i].trim();
+            if !term.is_empty() {
+                terms.push((negative, term));
+            } else if i != 0 && !s[..i].trim().is_empty() {
+                return Err(anyhow!("bad expression '{}'", s));
+            }
+            negative = c == '-';
+            start = i + 1;
+        }
+    }
+    let term = s[start..].trim();
+    if term.is_empty() {
+        return Err(anyhow!("bad expression '{}'", s));
+    }
+    terms.push((negative, term));
+    Ok(terms)
+}
+
+/// A number or a symbol, None for a name that may be a label
+fn term_value(term: &str, symbols: &dyn Fn(&str) -> Option<usize>) -> Result<Option<i64>> {
+    if term.starts_with(|c: char| c.is_ascii_digit()) {
+        return Ok(Some(parse_number(term)?));
+    }
+    Ok(symbols(term).map(|v| v as i64))
+}
+
+/// `123`, `0x7B` or `7Bh`
+pub(super) fn parse_number(s: &str) -> Result<i64> {
+    let lower = s.trim().to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(hex) = lower.strip_suffix('h') {
+        i64::from_str_radix(hex, 16)
+    } else {
+        lower.parse::<i64>()
+    };
+    parsed.map_err(|_| anyhow!("bad number '{}'", s))
+}
+
+pub(super) fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
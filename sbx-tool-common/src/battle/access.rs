@@ -0,0 +1,306 @@
+use super::snapshot::{BattleSnapshot, BattleState, PlayerSnapshot};
+use super::{BattleContext, PlayerClass, PlayerSubParamExClass, Side};
+use crate::memory::pointer::{PointerPath, PointerPathError};
+use crate::memory::{GameMemory, GameMemoryExt};
+use anyhow::Result;
+use memoffset::offset_of;
+
+/// Absolute addresses of the battle globals, from the build's offset table
+#[derive(Debug, Clone, Copy)]
+pub struct BattleAddresses {
+    pub context: usize,
+    pub state_flag: usize,
+}
+
+/// Reads and writes `BattleContext` and the player classes it points to through `GameMemory`
+pub struct BattleAccess<'a> {
+    memory: &'a dyn GameMemory,
+    addresses: BattleAddresses,
+}
+
+impl<'a> BattleAccess<'a> {
+    pub fn new(memory: &'a dyn GameMemory, addresses: BattleAddresses) -> Self {
+        BattleAccess { memory, addresses }
+    }
+
+    pub fn context(&self) -> Result<BattleContext> {
+        self.memory.read(self.addresses.context)
+    }
+
+    fn player_path(&self, side: Side) -> PointerPath {
+        let field = match side {
+            Side::Player => offset_of!(BattleContext, player1_ptr),
+            Side::CPU => offset_of!(BattleContext, player2_ptr),
+        };
+        PointerPath::absolute(self.addresses.context + field, &[0])
+    }
+
+    fn sub_param_ex_path(&self, side: Side) -> PointerPath {
+        let field = match side {
+            Side::Player => offset_of!(BattleContext, player1_sub_param_ptr),
+            Side::CPU => offset_of!(BattleContext, player2_sub_param_ptr),
+        };
+        PointerPath::absolute(self.addresses.context + field, &[0])
+    }
+
+    /// Check every pointer the battle values are read through.
+    /// Pointers are null while not in battle.
+    pub fn validate(&self) -> Result<(), PointerPathError> {
+        for side in [Side::Player, Side::CPU] {
+            self.player_path(side).resolve_readable(
+                self.memory,
+                0,
+                std::mem::size_of::<PlayerClass>(),
+            )?;
+            self.sub_param_ex_path(side).resolve_readable(
+                self.memory,
+                0,
+                std::mem::size_of::<PlayerSubParamExClass>(),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn is_in_battle(&self) -> bool {
+        self.validate().is_ok()
+    }
+
+    pub fn player_address(&self, side: Side) -> Result<usize> {
+        let address = self.player_path(side).resolve_readable(
+            self.memory,
+            0,
+            std::mem::size_of::<PlayerClass>(),
+        )?;
+        Ok(address)
+    }
+
+    pub fn sub_param_ex_address(&self, side: Side) -> Result<usize> {
+        let address = self.sub_param_ex_path(side).resolve_readable(
+            self.memory,
+            0,
+            std::mem::size_of::<PlayerSubParamExClass>(),
+        )?;
+        Ok(address)
+    }
+
+    /// Battle loop switch case
+    pub fn state(&self) -> Result<BattleState> {
+        let case: u32 = self.memory.read(self.addresses.state_flag)?;
+        Ok(BattleState::from_case(case))
+    }
+
+    /// Read everything at once.
+    /// Fails if not in battle.
+    pub fn snapshot(&self) -> Result<BattleSnapshot> {
+        self.validate()?;
+        let context = self.context()?;
+        let player_snapshot = |side: Side| -> Result<PlayerSnapshot> {
+            let (rush_count, score) = match side {
+                Side::Player => (context.player1_rush_count, context.player1_score),
+                Side::CPU => (context.player2_rush_count, context.player2_score),
+            };
+            Ok(PlayerSnapshot::new(
+                &self.player(side)?,
+                &self.sub_param_ex(side)?,
+                rush_count,
+                score,
+            ))
+        };
+        Ok(BattleSnapshot {
+            state: self.state()?,
+            player: player_snapshot(Side::Player)?,
+            cpu: player_snapshot(Side::CPU)?,
+        })
+    }
+
+    pub fn player(&self, side: Side) -> Result<PlayerClass> {
+        self.memory.read(self.player_address(side)?)
+    }
+
+    pub fn sub_param_ex(&self, side: Side) -> Result<PlayerSubParamExClass> {
+        self.memory.read(self.sub_param_ex_address(side)?)
+    }
+
+    pub fn hp(&self, side: Side) -> Result<u32> {
+        Ok(self.player(side)?.current_hp)
+    }
+
+    /// Also updates the hp bar so it moves smoothly
+    pub fn set_hp(&self, side: Side, value: u32) -> Result<()> {
+        let player = self.player_address(side)?;
+        self.memory
+            .write(player + offset_of!(PlayerClass, current_hp), value)?;
+        self.memory
+            .write(player + offset_of!(PlayerClass, graphic_hp_end), value)
+    }
+
+    pub fn ex(&self, side: Side) -> Result<i32> {
+        Ok(self.sub_param_ex(side)?.current_ex)
+    }
+
+    /// Also updates the ex gauge so it moves smoothly
+    pub fn set_ex(&self, side: Side, value: i32) -> Result<()> {
+        let sub_params = self.sub_param_ex_address(side)?;
+        self.memory.write(
+            sub_params + offset_of!(PlayerSubParamExClass, current_ex),
+            value,
+        )?;
+        //avoid crash
+        self.memory.write(
+            sub_params + offset_of!(PlayerSubParamExClass, graphic_ex_end),
+            std::cmp::max(value, 0),
+        )
+    }
+
+    pub fn rush_count(&self, side: Side) -> Result<u32> {
+        let c = self.context()?;
+        Ok(match side {
+            Side::Player => c.player1_rush_count,
+            Side::CPU => c.player2_rush_count,
+        })
+    }
+
+    pub fn set_rush_count(&self, side: Side, value: u32) -> Result<()> {
+        let offset = match side {
+            Side::Player => offset_of!(BattleContext, player1_rush_count),
+            Side::CPU => offset_of!(BattleContext, player2_rush_count),
+        };
+        self.memory.write(self.addresses.context + offset, value)
+    }
+
+    pub fn score(&self, side: Side) -> Result<u32> {
+        let c = self.context()?;
+        Ok(match side {
+            Side::Player => c.player1_score,
+            Side::CPU => c.player2_score,
+        })
+    }
+
+    pub fn set_score(&self, side: Side, value: u32) -> Result<()> {
+        let offset = match side {
+            Side::Player => offset_of!(BattleContext, player1_score),
+            Side::CPU => offset_of!(BattleContext, player2_score),
+        };
+        self.memory.write(self.addresses.context + offset, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battle::tests::{battle_memory, enter_battle, ex_address, player_address};
+
+    #[test]
+    fn null_pointers_are_not_a_battle() {
+        let (memory, addresses) = battle_memory();
+        let battle = BattleAccess::new(&memory, addresses);
+        assert!(!battle.is_in_battle());
+        let err = battle.validate().unwrap_err();
+        assert_eq!(err.hop(), 0);
+        assert!(battle.snapshot().is_err());
+        assert!(battle.hp(Side::Player).is_err());
+        assert!(battle.set_ex(Side::CPU, 1).is_err());
+
+        //one side is not enough
+        memory
+            .write(
+                addresses.context + offset_of!(BattleContext, player1_ptr),
+                player_address(Side::Player),
+            )
+            .unwrap();
+        assert!(!battle.is_in_battle());
+        assert!(battle.hp(Side::Player).is_ok());
+        assert!(battle.hp(Side::CPU).is_err());
+    }
+
+    #[test]
+    fn gauges() {
+        let (memory, addresses) = battle_memory();
+        enter_battle(&memory);
+        let battle = BattleAccess::new(&memory, addresses);
+        assert!(battle.is_in_battle());
+        assert_eq!(
+            battle.player_address(Side::CPU).unwrap(),
+            player_address(Side::CPU)
+        );
+        assert_eq!(
+            battle.sub_param_ex_address(Side::Player).unwrap(),
+            ex_address(Side::Player)
+        );
+
+        battle.set_hp(Side::CPU, 1234).unwrap();
+        assert_eq!(battle.hp(Side::CPU).unwrap(), 1234);
+        assert_eq!(battle.hp(Side::Player).unwrap(), 0);
+        let cpu = battle.player(Side::CPU).unwrap();
+        assert_eq!((cpu.current_hp, cpu.graphic_hp_end), (1234, 1234));
+
+        battle.set_ex(Side::Player, 300).unwrap();
+        assert_eq!(battle.ex(Side::Player).unwrap(), 300);
+        let ex = battle.sub_param_ex(Side::Player).unwrap();
+        assert_eq!((ex.current_ex, ex.graphic_ex_end), (300, 300));
+        //the gauge is never drawn below 0
+        battle.set_ex(Side::Player, -5).unwrap();
+        let ex = battle.sub_param_ex(Side::Player).unwrap();
+        assert_eq!((ex.current_ex, ex.graphic_ex_end), (-5, 0));
+    }
+
+    #[test]
+    fn context_values() {
+        let (memory, addresses) = battle_memory();
+        let battle = BattleAccess::new(&memory, addresses);
+        //read from the context, no battle needed
+        battle.set_rush_count(Side::Player, 3).unwrap();
+        battle.set_score(Side::CPU, 7000).unwrap();
+        assert_eq!(battle.rush_count(Side::Player).unwrap(), 3);
+        assert_eq!(battle.rush_count(Side::CPU).unwrap(), 0);
+        assert_eq!(battle.score(Side::CPU).unwrap(), 7000);
+        let context = battle.context().unwrap();
+        assert_eq!(context.player1_rush_count, 3);
+        assert_eq!(context.player2_score, 7000);
+
+        memory.write(addresses.state_flag, 15u32).unwrap();
+        assert_eq!(battle.state().unwrap(), BattleState::EndResult);
+    }
+
+    #[test]
+    fn snapshot() {
+        let (memory, addresses) = battle_memory();
+        enter_battle(&memory);
+        memory.write(addresses.state_flag, 13u32).unwrap();
+        memory
+            .write(
+                player_address(Side::Player),
+                [0u32, 0, 1000, 400, 410, 420, 430],
+            )
+            .unwrap();
+        memory
+            .write(ex_address(Side::CPU), [0i32, 0, 600, 250, 240, 230])
+            .unwrap();
+        let battle = BattleAccess::new(&memory, addresses);
+        battle.set_rush_count(Side::CPU, 2).unwrap();
+        battle.set_score(Side::Player, 100).unwrap();
+
+        let snapshot = battle.snapshot().unwrap();
+        assert_eq!(snapshot.state, BattleState::Attack);
+        assert_eq!(
+            snapshot.player,
+            PlayerSnapshot {
+                initial_hp: 1000,
+                current_hp: 400,
+                graphic_hp_end: 410,
+                graphic_hp_start: 420,
+                graphic_hp_bar: 430,
+                current_ex: 0,
+                max_ex: 0,
+                graphic_ex_start: 0,
+                graphic_ex_end: 0,
+                rush_count: 0,
+                score: 100,
+            }
+        );
+        let cpu = snapshot.side(Side::CPU);
+        assert_eq!((cpu.max_ex, cpu.current_ex), (600, 250));
+        assert_eq!((cpu.graphic_ex_start, cpu.graphic_ex_end), (240, 230));
+        assert_eq!((cpu.current_hp, cpu.rush_count), (0, 2));
+    }
+}
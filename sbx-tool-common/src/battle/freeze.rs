@@ -0,0 +1,237 @@
+use super::{BattleAccess, BattleSnapshot, Side};
+use crate::config::FreezeSettings;
+
+/// A gauge and the value it is held at while frozen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Held<T> {
+    frozen: bool,
+    value: T,
+}
+
+impl<T: Copy> Held<T> {
+    /// The value the gauge is held at, if it is frozen
+    fn held(&self) -> Option<T> {
+        if self.frozen {
+            Some(self.value)
+        } else {
+            None
+        }
+    }
+
+    /// At `value`, at the gauge's `current` value if None
+    fn set(&mut self, enable: bool, value: Option<T>, current: Option<T>) {
+        self.frozen = enable;
+        if let Some(value) = value.or(current) {
+            self.value = value;
+        }
+    }
+}
+
+/// Gauges held by the freeze thread, written every tick while in battle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Freezer {
+    player_hp: Held<u32>,
+    player_ex: Held<i32>,
+    cpu_hp: Held<u32>,
+    cpu_ex: Held<i32>,
+}
+
+impl Freezer {
+    pub const fn new() -> Self {
+        Freezer {
+            player_hp: Held {
+                frozen: false,
+                value: 0x77777777,
+            },
+            player_ex: Held {
+                frozen: false,
+                value: 300,
+            },
+            cpu_hp: Held {
+                frozen: false,
+                value: 0x77777777,
+            },
+            cpu_ex: Held {
+                frozen: false,
+                value: 300,
+            },
+        }
+    }
+
+    fn hp_mut(&mut self, side: Side) -> &mut Held<u32> {
+        match side {
+            Side::Player => &mut self.player_hp,
+            Side::CPU => &mut self.cpu_hp,
+        }
+    }
+
+    fn ex_mut(&mut self, side: Side) -> &mut Held<i32> {
+        match side {
+            Side::Player => &mut self.player_ex,
+            Side::CPU => &mut self.cpu_ex,
+        }
+    }
+
+    /// Hold hp at `value`, at the hp in `snapshot` if None.
+    /// Without both the last value is kept.
+    pub fn freeze_hp(
+        &mut self,
+        side: Side,
+        enable: bool,
+        value: Option<u32>,
+        snapshot: Option<&BattleSnapshot>,
+    ) {
+        let current = snapshot.map(|s| s.side(side).current_hp);
+        self.hp_mut(side).set(enable, value, current);
+    }
+
+    /// Hold ex at `value`, at the ex in `snapshot` if None.
+    /// Without both the last value is kept.
+    pub fn freeze_ex(
+        &mut self,
+        side: Side,
+        enable: bool,
+        value: Option<i32>,
+        snapshot: Option<&BattleSnapshot>,
+    ) {
+        let current = snapshot.map(|s| s.side(side).current_ex);
+        self.ex_mut(side).set(enable, value, current);
+    }
+
+    /// Hp was set, a frozen gauge is frozen at the new value
+    pub fn hp_changed(&mut self, side: Side, value: u32) {
+        self.hp_mut(side).value = value;
+    }
+
+    /// Ex was set, a frozen gauge is frozen at the new value
+    pub fn ex_changed(&mut self, side: Side, value: i32) {
+        self.ex_mut(side).value = value;
+    }
+
+    /// For the UI and the profile, values are only kept for frozen gauges
+    pub fn settings(&self) -> FreezeSettings {
+        FreezeSettings {
+            player_hp: self.player_hp.frozen,
+            player_ex: self.player_ex.frozen,
+            cpu_hp: self.cpu_hp.frozen,
+            cpu_ex: self.cpu_ex.frozen,
+            player_hp_value: self.player_hp.held(),
+            player_ex_value: self.player_ex.held(),
+            cpu_hp_value: self.cpu_hp.held(),
+            cpu_ex_value: self.cpu_ex.held(),
+        }
+    }
+
+    /// Write the frozen gauges.
+    /// Errors are ignored, the battle pointers can go away between two ticks.
+    pub fn apply(&self, battle: &BattleAccess) {
+        for side in [Side::Player, Side::CPU] {
+            let (hp, ex) = match side {
+                Side::Player => (self.player_hp, self.player_ex),
+                Side::CPU => (self.cpu_hp, self.cpu_ex),
+            };
+            if let Some(value) = hp.held() {
+                let _ = battle.set_hp(side, value);
+            }
+            if let Some(value) = ex.held() {
+                let _ = battle.set_ex(side, value);
+            }
+        }
+    }
+}
+
+impl Default for Freezer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battle::tests::{battle_memory, enter_battle};
+    use crate::battle::BattleState;
+    use crate::memory::GameMemoryExt;
+
+    fn snapshot(player_hp: u32, cpu_ex: i32) -> BattleSnapshot {
+        let (memory, addresses) = battle_memory();
+        enter_battle(&memory);
+        let battle = BattleAccess::new(&memory, addresses);
+        battle.set_hp(Side::Player, player_hp).unwrap();
+        battle.set_ex(Side::CPU, cpu_ex).unwrap();
+        battle.snapshot().unwrap()
+    }
+
+    #[test]
+    fn nothing_frozen_at_first() {
+        assert_eq!(Freezer::new().settings(), FreezeSettings::default());
+    }
+
+    #[test]
+    fn freeze_values() {
+        let current = snapshot(500, 100);
+        let mut freezer = Freezer::new();
+
+        //at the current value
+        freezer.freeze_hp(Side::Player, true, None, Some(&current));
+        freezer.freeze_ex(Side::CPU, true, None, Some(&current));
+        //a given value wins
+        freezer.freeze_hp(Side::CPU, true, Some(42), Some(&current));
+        let settings = freezer.settings();
+        assert!(settings.player_hp && settings.cpu_ex && settings.cpu_hp && !settings.player_ex);
+        assert_eq!(settings.player_hp_value, Some(500));
+        assert_eq!(settings.cpu_ex_value, Some(100));
+        assert_eq!(settings.cpu_hp_value, Some(42));
+        assert_eq!(settings.player_ex_value, None);
+
+        //out of battle the last value is kept
+        freezer.freeze_hp(Side::Player, false, None, None);
+        freezer.freeze_hp(Side::Player, true, None, None);
+        assert_eq!(freezer.settings().player_hp_value, Some(500));
+
+        freezer.freeze_ex(Side::CPU, false, None, Some(&current));
+        let settings = freezer.settings();
+        assert!(!settings.cpu_ex);
+        assert_eq!(settings.cpu_ex_value, None);
+    }
+
+    #[test]
+    fn changed_values_are_held() {
+        let mut freezer = Freezer::new();
+        freezer.freeze_ex(Side::Player, true, Some(10), None);
+        freezer.ex_changed(Side::Player, 20);
+        assert_eq!(freezer.settings().player_ex_value, Some(20));
+
+        //an unfrozen gauge is frozen at the last set value
+        freezer.hp_changed(Side::CPU, 77);
+        assert_eq!(freezer.settings().cpu_hp_value, None);
+        freezer.freeze_hp(Side::CPU, true, None, None);
+        assert_eq!(freezer.settings().cpu_hp_value, Some(77));
+    }
+
+    #[test]
+    fn apply_writes_frozen_gauges() {
+        let (memory, addresses) = battle_memory();
+        enter_battle(&memory);
+        memory.write(addresses.state_flag, 13u32).unwrap();
+        let battle = BattleAccess::new(&memory, addresses);
+        battle.set_hp(Side::Player, 100).unwrap();
+        battle.set_hp(Side::CPU, 200).unwrap();
+        battle.set_ex(Side::CPU, 50).unwrap();
+
+        let mut freezer = Freezer::new();
+        freezer.freeze_hp(Side::Player, true, Some(999), None);
+        freezer.freeze_ex(Side::CPU, true, Some(-1), None);
+        freezer.apply(&battle);
+        let snapshot = battle.snapshot().unwrap();
+        assert_eq!(snapshot.state, BattleState::Attack);
+        assert_eq!(snapshot.player.current_hp, 999);
+        assert_eq!(snapshot.cpu.current_hp, 200);
+        assert_eq!(snapshot.cpu.current_ex, -1);
+        assert_eq!(snapshot.player.current_ex, 0);
+
+        //the pointers going away is not an error
+        memory.write(addresses.context, 0usize).unwrap();
+        freezer.apply(&battle);
+    }
+}
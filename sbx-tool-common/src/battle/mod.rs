@@ -0,0 +1,253 @@
+//! Battle structures of the game and the logic on top of `GameMemory`.
+//! sbx-tool-core's battle module re-exports them next to the battle loop hook.
+pub mod access;
+pub mod freeze;
+pub mod phase;
+pub mod snapshot;
+use crate::reflect::game_struct;
+pub use access::{BattleAccess, BattleAddresses};
+pub use freeze::Freezer;
+pub use phase::{decide_winner, BattleEvent, BattlePhase};
+use phf::{phf_map, Map};
+use serde::Serialize;
+pub use snapshot::{BattleSnapshot, BattleState, PlayerSnapshot};
+
+/// Left(player) or right(cpu) side of a battle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum Side {
+    Player,
+    CPU,
+}
+
+game_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    #[size(0x3c)]
+    pub struct BattleContext {
+        #[offset(0x0)]
+        pub player1_ptr: *mut PlayerClass,
+        #[offset(0x4)]
+        pub player2_ptr: *mut PlayerClass,
+        #[offset(0x8)]
+        pub player1_rush_count: u32,
+        #[offset(0xc)]
+        pub player2_rush_count: u32,
+        unk_10: usize,
+        unk_14: usize,
+        unk_18: usize,
+        unk_1c: usize,
+        unk_20: usize,
+        unk_24: usize,
+        unk_28: usize,
+        #[offset(0x2c)]
+        pub player1_sub_param_ptr: *mut PlayerSubParamExClass,
+        #[offset(0x30)]
+        pub player2_sub_param_ptr: *mut PlayerSubParamExClass,
+        #[offset(0x34)]
+        pub player1_score: u32,
+        #[offset(0x38)]
+        pub player2_score: u32,
+    }
+}
+
+game_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    #[size(0x1c)]
+    pub struct PlayerClass {
+        unk_0: u32,
+        unk_4: u32,
+        #[offset(0x8)]
+        pub initial_hp: u32,
+        #[offset(0xc)]
+        pub current_hp: u32,
+        #[offset(0x10)]
+        pub graphic_hp_end: u32,
+        pub graphic_hp_start: u32,
+        pub graphic_hp_bar: u32,
+    }
+}
+
+game_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    #[size(0x18)]
+    pub struct PlayerSubParamExClass {
+        unk_0: u32,
+        unk_4: u32,
+        #[offset(0x8)]
+        pub max_ex: u32,
+        #[offset(0xc)]
+        pub current_ex: i32,
+        #[offset(0x10)]
+        pub graphic_ex_start: i32,
+        #[offset(0x14)]
+        pub graphic_ex_end: i32,
+    }
+}
+
+game_struct! {
+    /// not reachable yet, the pointer path from `PlayerClass` is still unknown.
+    /// Stun stars stay out of `BattleSnapshot` until it is verified in game.
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    #[size(0x10)]
+    pub struct PlayerSubParamStunClass {
+        unk_0: u32,
+        unk_4: u32,
+        #[offset(0x8)]
+        pub max_stunstar_count: u32,
+        #[offset(0xc)]
+        pub current_stunstar_count: u32,
+        // mb_bgm:[u8] //+38 not sure
+    }
+}
+
+game_struct! {
+    /// incomplete
+    /// still not sure what are those
+    /// pointers sometimes suddenly 'freed' by client
+    /// sbxmodule.ext + 0x4402A0
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    #[size(0xc)]
+    pub struct UnkContext {
+        #[offset(0x0)]
+        pub sub_context_ptr: *mut UnkContextSub,
+        unk_4: usize,
+        unk_8: usize,
+    }
+}
+
+game_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    #[size(0x38)]
+    pub struct UnkContextSub {
+        unk_0: u32,
+        unk_4: u32,
+        unk_8: u32,
+        unk_c: u32,
+        unk_10: u32,
+        unk_14: u32,
+        unk_18: u32,
+        unk_1c: u32,
+        unk_20: u32,
+        unk_24: u32,
+        unk_28: u32,
+        unk_2c: u32,
+        #[offset(0x30)]
+        pub character_ptr: *mut CharacterStatus,
+        unk_34: u32,
+        //38 files
+    }
+}
+
+game_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    #[size(0x30)]
+    pub struct CharacterStatus {
+        unk_0: u32,
+        unk_4: u32,
+        unk_8: u32,
+        unk_c: u32,
+        unk_10: u32,
+        unk_14: u32,
+        unk_18: u32,
+        /// left 0
+        #[offset(0x1c)]
+        pub position: u32,
+        unk_20: u32,
+        unk_24: u32,
+        unk_28: u32,
+        unk_2c: u32,
+    }
+}
+
+static BATTLE_MAIN_LOOP_FIRST_SWITCH_CASE_NAME_MAP: Map<u32, &'static str> = phf_map! {
+    0u32 => "BATTLE_INITIALIZE",
+    1u32 => "BATTLE_LOADING",
+    6u32 => "BATTLE_STARTDASH",//is there an official name for this?
+    8u32 => "BATTLE_FRAME_DRAWING",
+    10u32 => "BATTLE_PLAYER_WAITING",
+    11u32 => "BATTLE_RUMBLE_LEADER_SELECT",
+    13u32 => "BATTLE_ATTACK",
+    15u32 => "BATTLE_END_RESULT",
+    19u32 => "BATTLE_ASK_RETRY"
+};
+
+fn get_battle_main_loop_first_switch_case_name(case: u32) -> &'static str {
+    match BATTLE_MAIN_LOOP_FIRST_SWITCH_CASE_NAME_MAP.get(&case) {
+        Some(n) => n,
+        None => "Unknown",
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::memory::{GameMemoryExt, MockMemory, Protection};
+    use memoffset::offset_of;
+
+    pub(crate) const CONTEXT: usize = 0x1000;
+    pub(crate) const STATE_FLAG: usize = 0x2000;
+    /// PlayerClass and PlayerSubParamExClass of each side, 0x40 apart
+    pub(crate) const PLAYERS: usize = 0x3000;
+
+    pub(crate) fn player_address(side: Side) -> usize {
+        match side {
+            Side::Player => PLAYERS,
+            Side::CPU => PLAYERS + 0x40,
+        }
+    }
+
+    pub(crate) fn ex_address(side: Side) -> usize {
+        player_address(side) + 0x20
+    }
+
+    /// Context, state flag and classes of both sides, the context's pointers are null
+    pub(crate) fn battle_memory() -> (MockMemory, BattleAddresses) {
+        let memory = MockMemory::new();
+        memory.map(CONTEXT, 0x100, Protection::READWRITE);
+        memory.map(STATE_FLAG, 4, Protection::READWRITE);
+        memory.map(PLAYERS, 0x100, Protection::READWRITE);
+        let addresses = BattleAddresses {
+            context: CONTEXT,
+            state_flag: STATE_FLAG,
+        };
+        (memory, addresses)
+    }
+
+    /// Point the context at both sides
+    pub(crate) fn enter_battle(memory: &MockMemory) {
+        let fields = [
+            (Side::Player, offset_of!(BattleContext, player1_ptr)),
+            (Side::CPU, offset_of!(BattleContext, player2_ptr)),
+        ];
+        let ex_fields = [
+            (
+                Side::Player,
+                offset_of!(BattleContext, player1_sub_param_ptr),
+            ),
+            (Side::CPU, offset_of!(BattleContext, player2_sub_param_ptr)),
+        ];
+        for (side, field) in fields {
+            memory.write(CONTEXT + field, player_address(side)).unwrap();
+        }
+        for (side, field) in ex_fields {
+            memory.write(CONTEXT + field, ex_address(side)).unwrap();
+        }
+    }
+
+    #[test]
+    fn state_names() {
+        assert_eq!(BattleState::from_case(13), BattleState::Attack);
+        assert_eq!(BattleState::Attack.case(), 13);
+        assert_eq!(BattleState::Attack.name(), "BATTLE_ATTACK");
+        assert_eq!(BattleState::from_case(6).name(), "BATTLE_STARTDASH");
+        assert_eq!(BattleState::from_case(2), BattleState::Unknown(2));
+        assert_eq!(BattleState::Unknown(2).case(), 2);
+        assert_eq!(BattleState::Unknown(2).name(), "Unknown");
+    }
+}
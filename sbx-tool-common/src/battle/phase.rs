@@ -0,0 +1,195 @@
+use super::snapshot::{BattleSnapshot, BattleState};
+use super::Side;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum BattleEvent {
+    /// BATTLE_INITIALIZE entered
+    BattleStarted,
+    /// BATTLE_LOADING left
+    LoadingFinished,
+    /// BATTLE_STARTDASH entered, once per round
+    RoundStart,
+    /// BATTLE_ATTACK entered
+    AttackPhase,
+    /// BATTLE_END_RESULT entered, or a new battle started without one.
+    /// `winner` is None if it could not be decided.
+    BattleEnded { winner: Option<Side> },
+    /// BATTLE_ASK_RETRY entered
+    RetryPrompt,
+}
+
+/// The side with hp left, None if both or neither have hp
+pub fn decide_winner(snapshot: &BattleSnapshot) -> Option<Side> {
+    match (snapshot.player.current_hp, snapshot.cpu.current_hp) {
+        (0, 0) => None,
+        (_, 0) => Some(Side::Player),
+        (0, _) => Some(Side::CPU),
+        _ => None,
+    }
+}
+
+/// Turns battle loop switch case changes into `BattleEvent`s
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BattlePhase {
+    state: Option<BattleState>,
+    in_battle: bool,
+}
+
+impl BattlePhase {
+    pub const fn new() -> Self {
+        BattlePhase {
+            state: None,
+            in_battle: false,
+        }
+    }
+
+    /// Last seen state, None before the first battle
+    pub fn state(&self) -> Option<BattleState> {
+        self.state
+    }
+
+    /// Between BattleStarted and BattleEnded
+    pub fn in_battle(&self) -> bool {
+        self.in_battle
+    }
+
+    /// Feed the current switch case.
+    /// `winner` is only called when the battle ends.
+    pub fn update<F>(&mut self, state: BattleState, winner: F) -> Vec<BattleEvent>
+    where
+        F: FnOnce() -> Option<Side>,
+    {
+        let mut events = Vec::new();
+        let prev = self.state;
+        if prev == Some(state) {
+            return events;
+        }
+        self.state = Some(state);
+
+        if prev == Some(BattleState::Loading) {
+            events.push(BattleEvent::LoadingFinished);
+        }
+        match state {
+            BattleState::Initialize => {
+                if self.in_battle {
+                    //quit or restarted before the result screen
+                    events.push(BattleEvent::BattleEnded { winner: None });
+                }
+                self.in_battle = true;
+                events.push(BattleEvent::BattleStarted);
+            }
+            BattleState::StartDash => events.push(BattleEvent::RoundStart),
+            BattleState::Attack => events.push(BattleEvent::AttackPhase),
+            BattleState::EndResult if self.in_battle => {
+                self.in_battle = false;
+                events.push(BattleEvent::BattleEnded { winner: winner() });
+            }
+            BattleState::AskRetry => events.push(BattleEvent::RetryPrompt),
+            _ => {}
+        }
+        events
+    }
+}
+
+impl Default for BattlePhase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::battle::tests::{battle_memory, enter_battle};
+    use crate::battle::BattleAccess;
+    use BattleEvent::*;
+    use BattleState::*;
+
+    fn no_winner() -> Option<Side> {
+        panic!("winner asked outside the result screen")
+    }
+
+    fn snapshot(player_hp: u32, cpu_hp: u32) -> BattleSnapshot {
+        let (memory, addresses) = battle_memory();
+        enter_battle(&memory);
+        let battle = BattleAccess::new(&memory, addresses);
+        battle.set_hp(Side::Player, player_hp).unwrap();
+        battle.set_hp(Side::CPU, cpu_hp).unwrap();
+        battle.snapshot().unwrap()
+    }
+
+    #[test]
+    fn battle_with_result_screen() {
+        let mut phase = BattlePhase::new();
+        assert_eq!(phase.state(), None);
+        assert!(!phase.in_battle());
+
+        assert_eq!(phase.update(Initialize, no_winner), [BattleStarted]);
+        assert!(phase.in_battle());
+        assert_eq!(phase.update(Loading, no_winner), []);
+        assert_eq!(
+            phase.update(StartDash, no_winner),
+            [LoadingFinished, RoundStart]
+        );
+        assert_eq!(phase.update(Attack, no_winner), [AttackPhase]);
+        assert_eq!(phase.update(StartDash, no_winner), [RoundStart]);
+        assert_eq!(phase.update(Attack, no_winner), [AttackPhase]);
+        assert_eq!(
+            phase.update(EndResult, || Some(Side::CPU)),
+            [BattleEnded {
+                winner: Some(Side::CPU)
+            }]
+        );
+        assert!(!phase.in_battle());
+        assert_eq!(phase.update(AskRetry, no_winner), [RetryPrompt]);
+        assert_eq!(phase.state(), Some(AskRetry));
+    }
+
+    #[test]
+    fn battle_without_result_screen() {
+        let mut phase = BattlePhase::new();
+        phase.update(Initialize, no_winner);
+        phase.update(Attack, no_winner);
+        //restarted from the pause menu, the winner is not asked
+        assert_eq!(
+            phase.update(Initialize, no_winner),
+            [BattleEnded { winner: None }, BattleStarted]
+        );
+        assert!(phase.in_battle());
+    }
+
+    #[test]
+    fn result_screen_outside_battle() {
+        let mut phase = BattlePhase::new();
+        assert_eq!(phase.update(EndResult, no_winner), []);
+        assert!(!phase.in_battle());
+    }
+
+    #[test]
+    fn repeated_states() {
+        let mut phase = BattlePhase::new();
+        assert_eq!(phase.update(Initialize, no_winner), [BattleStarted]);
+        assert_eq!(phase.update(Initialize, no_winner), []);
+        phase.update(Loading, no_winner);
+        assert_eq!(phase.update(Loading, no_winner), []);
+        assert_eq!(
+            phase.update(Attack, no_winner),
+            [LoadingFinished, AttackPhase]
+        );
+        assert_eq!(phase.update(Attack, no_winner), []);
+        assert_eq!(phase.update(Unknown(3), no_winner), []);
+        assert_eq!(phase.update(Unknown(3), no_winner), []);
+        phase.update(EndResult, || None);
+        assert_eq!(phase.update(EndResult, no_winner), []);
+    }
+
+    #[test]
+    fn winner() {
+        assert_eq!(decide_winner(&snapshot(100, 0)), Some(Side::Player));
+        assert_eq!(decide_winner(&snapshot(0, 100)), Some(Side::CPU));
+        assert_eq!(decide_winner(&snapshot(0, 0)), None);
+        //time over, not decided by hp
+        assert_eq!(decide_winner(&snapshot(100, 200)), None);
+    }
+}
@@ -0,0 +1,112 @@
+use super::{
+    get_battle_main_loop_first_switch_case_name, PlayerClass, PlayerSubParamExClass, Side,
+};
+use serde::Serialize;
+
+/// Battle main loop first switch case
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub enum BattleState {
+    Initialize,
+    Loading,
+    StartDash,
+    FrameDrawing,
+    PlayerWaiting,
+    RumbleLeaderSelect,
+    Attack,
+    EndResult,
+    AskRetry,
+    Unknown(u32),
+}
+
+impl BattleState {
+    pub fn from_case(case: u32) -> Self {
+        match case {
+            0 => BattleState::Initialize,
+            1 => BattleState::Loading,
+            6 => BattleState::StartDash,
+            8 => BattleState::FrameDrawing,
+            10 => BattleState::PlayerWaiting,
+            11 => BattleState::RumbleLeaderSelect,
+            13 => BattleState::Attack,
+            15 => BattleState::EndResult,
+            19 => BattleState::AskRetry,
+            _ => BattleState::Unknown(case),
+        }
+    }
+
+    pub fn case(&self) -> u32 {
+        match self {
+            BattleState::Initialize => 0,
+            BattleState::Loading => 1,
+            BattleState::StartDash => 6,
+            BattleState::FrameDrawing => 8,
+            BattleState::PlayerWaiting => 10,
+            BattleState::RumbleLeaderSelect => 11,
+            BattleState::Attack => 13,
+            BattleState::EndResult => 15,
+            BattleState::AskRetry => 19,
+            BattleState::Unknown(case) => *case,
+        }
+    }
+
+    /// Same name as the battle loop hook logs
+    pub fn name(&self) -> &'static str {
+        get_battle_main_loop_first_switch_case_name(self.case())
+    }
+}
+
+/// No stun stars, see `PlayerSubParamStunClass`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PlayerSnapshot {
+    pub initial_hp: u32,
+    pub current_hp: u32,
+    pub graphic_hp_end: u32,
+    pub graphic_hp_start: u32,
+    pub graphic_hp_bar: u32,
+    pub current_ex: i32,
+    pub max_ex: u32,
+    pub graphic_ex_start: i32,
+    pub graphic_ex_end: i32,
+    pub rush_count: u32,
+    pub score: u32,
+}
+
+impl PlayerSnapshot {
+    pub(crate) fn new(
+        player: &PlayerClass,
+        ex: &PlayerSubParamExClass,
+        rush_count: u32,
+        score: u32,
+    ) -> Self {
+        PlayerSnapshot {
+            initial_hp: player.initial_hp,
+            current_hp: player.current_hp,
+            graphic_hp_end: player.graphic_hp_end,
+            graphic_hp_start: player.graphic_hp_start,
+            graphic_hp_bar: player.graphic_hp_bar,
+            current_ex: ex.current_ex,
+            max_ex: ex.max_ex,
+            graphic_ex_start: ex.graphic_ex_start,
+            graphic_ex_end: ex.graphic_ex_end,
+            rush_count,
+            score,
+        }
+    }
+}
+
+/// Both players read at once
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BattleSnapshot {
+    pub state: BattleState,
+    pub player: PlayerSnapshot,
+    pub cpu: PlayerSnapshot,
+}
+
+impl BattleSnapshot {
+    pub fn side(&self, side: Side) -> &PlayerSnapshot {
+        match side {
+            Side::Player => &self.player,
+            Side::CPU => &self.cpu,
+        }
+    }
+}
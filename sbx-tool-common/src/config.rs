@@ -0,0 +1,554 @@
+//! Tool settings that survive a restart, in named profiles.
+//! The file is read leniently, anything unknown or invalid is reported in `issues`
+//! and left at its default, so a broken config never stops the tool from attaching.
+use crate::hotkey::Hotkeys;
+use crate::input::InputMode;
+use crate::style::DEFAULT_STYLE;
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml::value::Table;
+use toml::Value;
+
+/// File name of the config, looked up next to the dll
+pub const CONFIG_FILE_NAME: &str = "sbx-tool.toml";
+
+/// Directory in `%APPDATA%` used when the dll's directory is unknown
+pub const APPDATA_DIR_NAME: &str = "sbx-tool";
+
+pub const DEFAULT_PROFILE: &str = "casual";
+
+/// Levels `log_level` takes, quietest first
+pub const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// Freeze checkboxes of the Battle tab, on or off when the profile is applied.
+/// A frozen gauge is held at its value, at the gauge's value when frozen if None.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct FreezeSettings {
+    pub player_hp: bool,
+    pub player_ex: bool,
+    pub cpu_hp: bool,
+    pub cpu_ex: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub player_hp_value: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub player_ex_value: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_hp_value: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu_ex_value: Option<i32>,
+}
+
+/// Where the tool window is
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct UiLayout {
+    pub window_size: [f32; 2],
+    /// imgui places it if None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_position: Option<[f32; 2]>,
+    pub collapsed: bool,
+}
+
+impl Default for UiLayout {
+    fn default() -> Self {
+        UiLayout {
+            window_size: [200.0, 400.0],
+            window_position: None,
+            collapsed: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Profile {
+    /// One of `LOG_LEVELS`
+    pub log_level: String,
+    /// Name of the style preset
+    pub style: String,
+    /// Names of the patches enabled, the others are disabled
+    pub patches: Vec<String>,
+    /// Which input the game gets while the tool window is shown
+    pub input_mode: InputMode,
+    pub freeze: FreezeSettings,
+    pub ui: UiLayout,
+    /// Action name -> key chord, see `Hotkeys::from_config`
+    pub hotkeys: BTreeMap<String, String>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            log_level: "info".to_owned(),
+            style: DEFAULT_STYLE.to_owned(),
+            patches: Vec::new(),
+            input_mode: InputMode::default(),
+            freeze: FreezeSettings::default(),
+            ui: UiLayout::default(),
+            hotkeys: Hotkeys::defaults().to_config(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Config {
+    /// Name of the profile applied at attach
+    pub profile: String,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Default for Config {
+    /// practice, casual and debug
+    fn default() -> Self {
+        let mut profiles = BTreeMap::new();
+        profiles.insert(
+            "practice".to_owned(),
+            Profile {
+                freeze: FreezeSettings {
+                    player_hp: true,
+                    cpu_hp: true,
+                    ..FreezeSettings::default()
+                },
+                ..Profile::default()
+            },
+        );
+        profiles.insert(DEFAULT_PROFILE.to_owned(), Profile::default());
+        profiles.insert(
+            "debug".to_owned(),
+            Profile {
+                log_level: "trace".to_owned(),
+                ui: UiLayout {
+                    window_size: [600.0, 700.0],
+                    ..UiLayout::default()
+                },
+                ..Profile::default()
+            },
+        );
+        Config {
+            profile: DEFAULT_PROFILE.to_owned(),
+            profiles,
+        }
+    }
+}
+
+/// A config read with what was wrong in it
+#[derive(Debug, Clone)]
+pub struct ConfigLoad {
+    pub config: Config,
+    pub issues: Vec<String>,
+}
+
+impl Config {
+    /// The active profile, the default one if it is missing
+    pub fn active(&self) -> Profile {
+        self.profiles
+            .get(&self.profile)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn profile_names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+
+    /// Read the config at `path`, the defaults if there is no file.
+    /// Only an unreadable file is an error, see `parse`.
+    pub fn load(path: &Path) -> Result<ConfigLoad> {
+        if !path.exists() {
+            return Ok(ConfigLoad {
+                config: Config::default(),
+                issues: Vec::new(),
+            });
+        }
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("failed to read {:?}: {}", path, e))?;
+        Ok(Config::parse(&s))
+    }
+
+    /// Parse a config file. Unknown keys and invalid values are skipped with an issue,
+    /// a file that isn't TOML gives the default config.
+    pub fn parse(s: &str) -> ConfigLoad {
+        let mut issues = Vec::new();
+        let table = match s.parse::<Value>() {
+            Ok(Value::Table(table)) => table,
+            Ok(_) => Table::new(),
+            Err(e) => {
+                issues.push(format!("not a valid config, using the defaults: {}", e));
+                return ConfigLoad {
+                    config: Config::default(),
+                    issues,
+                };
+            }
+        };
+
+        let defaults = Config::default();
+        let mut root = Section::new("", table, &mut issues);
+        let profile = root.take("profile");
+        let profiles = root.take_table("profiles");
+        root.finish();
+
+        let mut config = Config {
+            profile: profile.unwrap_or(defaults.profile),
+            profiles: BTreeMap::new(),
+        };
+        match profiles {
+            Some(profiles) => {
+                for (name, value) in profiles {
+                    let path = format!("profiles.{}", name);
+                    match value {
+                        Value::Table(table) => {
+                            let profile = parse_profile(Section::new(&path, table, &mut issues));
+                            config.profiles.insert(name, profile);
+                        }
+                        _ => issues.push(format!("{} is not a table", path)),
+                    }
+                }
+            }
+            None => config.profiles = defaults.profiles,
+        }
+        if !config.profiles.contains_key(&config.profile) {
+            issues.push(format!(
+                "profile '{}' does not exist, using the defaults",
+                config.profile
+            ));
+        }
+        ConfigLoad { config, issues }
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.to_toml()?)
+            .map_err(|e| anyhow!("failed to write {:?}: {}", path, e))
+    }
+}
+
+fn parse_profile(mut section: Section) -> Profile {
+    let defaults = Profile::default();
+    let log_level = section
+        .take::<String>("log_level")
+        .map(|l| l.to_lowercase())
+        .filter(|l| {
+            let known = LOG_LEVELS.contains(&l.as_str());
+            if !known {
+                let path = section.key_path("log_level");
+                section.issues.push(format!(
+                    "{} '{}' is not one of {}",
+                    path,
+                    l,
+                    LOG_LEVELS.join(", ")
+                ));
+            }
+            known
+        })
+        .unwrap_or(defaults.log_level);
+    let style = section.take("style").unwrap_or(defaults.style);
+    let patches = section.take("patches").unwrap_or_default();
+    let input_mode = section.take("input_mode").unwrap_or_default();
+    //an empty table is no hotkeys, a missing one the defaults
+    let hotkeys = section.take("hotkeys").unwrap_or(defaults.hotkeys);
+    for issue in Hotkeys::from_config(&hotkeys).1 {
+        let path = section.key_path(&issue);
+        section.issues.push(path);
+    }
+
+    let freeze = match section.take_section("freeze") {
+        Some(mut freeze) => {
+            let settings = FreezeSettings {
+                player_hp: freeze.take("player_hp").unwrap_or_default(),
+                player_ex: freeze.take("player_ex").unwrap_or_default(),
+                cpu_hp: freeze.take("cpu_hp").unwrap_or_default(),
+                cpu_ex: freeze.take("cpu_ex").unwrap_or_default(),
+                player_hp_value: freeze.take("player_hp_value"),
+                player_ex_value: freeze.take("player_ex_value"),
+                cpu_hp_value: freeze.take("cpu_hp_value"),
+                cpu_ex_value: freeze.take("cpu_ex_value"),
+            };
+            freeze.finish();
+            settings
+        }
+        None => defaults.freeze,
+    };
+    let ui = match section.take_section("ui") {
+        Some(mut ui) => {
+            let layout = UiLayout {
+                window_size: ui.take("window_size").unwrap_or(defaults.ui.window_size),
+                window_position: ui.take("window_position"),
+                collapsed: ui.take("collapsed").unwrap_or_default(),
+            };
+            ui.finish();
+            layout
+        }
+        None => defaults.ui,
+    };
+    section.finish();
+    Profile {
+        log_level,
+        style,
+        patches,
+        input_mode,
+        freeze,
+        ui,
+        hotkeys,
+    }
+}
+
+/// A table keys are taken out of, whatever is left over is unknown
+struct Section<'a> {
+    path: String,
+    table: Table,
+    issues: &'a mut Vec<String>,
+}
+
+impl<'a> Section<'a> {
+    fn new(path: &str, table: Table, issues: &'a mut Vec<String>) -> Self {
+        Section {
+            path: path.to_owned(),
+            table,
+            issues,
+        }
+    }
+
+    fn key_path(&self, key: &str) -> String {
+        if self.path.is_empty() {
+            return key.to_owned();
+        }
+        format!("{}.{}", self.path, key)
+    }
+
+    /// The value of `key`, None if it is missing or invalid
+    fn take<T: DeserializeOwned>(&mut self, key: &str) -> Option<T> {
+        let value = self.table.remove(key)?;
+        match value.try_into() {
+            Ok(v) => Some(v),
+            Err(e) => {
+                let path = self.key_path(key);
+                self.issues.push(format!("{} is invalid: {}", path, e));
+                None
+            }
+        }
+    }
+
+    fn take_table(&mut self, key: &str) -> Option<Table> {
+        match self.table.remove(key)? {
+            Value::Table(table) => Some(table),
+            _ => {
+                let path = self.key_path(key);
+                self.issues.push(format!("{} is not a table", path));
+                None
+            }
+        }
+    }
+
+    fn take_section(&mut self, key: &str) -> Option<Section<'_>> {
+        let table = self.take_table(key)?;
+        let path = self.key_path(key);
+        Some(Section::new(&path, table, self.issues))
+    }
+
+    fn finish(self) {
+        for key in self.table.keys() {
+            let path = self.key_path(key);
+            self.issues.push(format!("unknown key {}", path));
+        }
+    }
+}
+
+/// Where the config is, next to the dll or in `%APPDATA%` if the dll has no directory
+pub fn config_path(dll_directory: Option<&Path>) -> Option<PathBuf> {
+    match dll_directory {
+        Some(dir) => Some(dir.join(CONFIG_FILE_NAME)),
+        None => {
+            let appdata = std::env::var_os("APPDATA")?;
+            Some(
+                PathBuf::from(appdata)
+                    .join(APPDATA_DIR_NAME)
+                    .join(CONFIG_FILE_NAME),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each issue starts with its expected prefix, in order
+    fn assert_issues(issues: &[String], expected: &[&str]) {
+        assert_eq!(issues.len(), expected.len(), "{:#?}", issues);
+        for (issue, prefix) in issues.iter().zip(expected) {
+            assert!(issue.starts_with(prefix), "'{}' is not '{}'", issue, prefix);
+        }
+    }
+
+    #[test]
+    fn full_profile() {
+        let load = Config::parse(
+            r#"
+            profile = "training"
+
+            [profiles.training]
+            log_level = "DEBUG"
+            style = "Light"
+            patches = ["Ignore Party Cost"]
+            input_mode = "ui_focus"
+            hotkeys = { toggle_ui = "F1", freeze_cpu_hp = "Ctrl+F2" }
+
+            [profiles.training.freeze]
+            player_hp = true
+            cpu_ex = true
+            player_hp_value = 5000
+            cpu_ex_value = -30
+
+            [profiles.training.ui]
+            window_size = [300.0, 500.0]
+            window_position = [10.0, 20.0]
+            collapsed = true
+            "#,
+        );
+        assert_issues(&load.issues, &[]);
+        let config = load.config;
+        assert_eq!(config.profile, "training");
+        assert_eq!(config.profile_names(), ["training"]);
+        let profile = config.active();
+        assert_eq!(profile.log_level, "debug");
+        assert_eq!(profile.style, "Light");
+        assert_eq!(profile.patches, ["Ignore Party Cost"]);
+        assert_eq!(profile.input_mode, InputMode::UiFocus);
+        assert_eq!(profile.hotkeys["freeze_cpu_hp"], "Ctrl+F2");
+        assert_eq!(
+            profile.freeze,
+            FreezeSettings {
+                player_hp: true,
+                cpu_ex: true,
+                player_hp_value: Some(5000),
+                cpu_ex_value: Some(-30),
+                ..FreezeSettings::default()
+            }
+        );
+        assert_eq!(
+            profile.ui,
+            UiLayout {
+                window_size: [300.0, 500.0],
+                window_position: Some([10.0, 20.0]),
+                collapsed: true,
+            }
+        );
+    }
+
+    #[test]
+    fn missing_keys_are_defaults() {
+        let load = Config::parse("[profiles.empty]");
+        assert_issues(&load.issues, &["profile 'casual' does not exist"]);
+        assert_eq!(load.config.profiles["empty"], Profile::default());
+        // an active profile that doesn't exist is the default one
+        assert_eq!(load.config.active(), Profile::default());
+
+        // no profiles at all are the built-in ones
+        let load = Config::parse("");
+        assert_issues(&load.issues, &[]);
+        assert_eq!(load.config, Config::default());
+    }
+
+    #[test]
+    fn issues_are_reported_with_their_path() {
+        let load = Config::parse(
+            r#"
+            profile = "a"
+            colour = "red"
+
+            [profiles.a]
+            log_level = "loud"
+            input_mode = "sideways"
+            extra = 1
+            hotkeys = { toggle_ui = "Insert", bogus = "F1", eject = "Insert" }
+            ui = 5
+
+            [profiles.a.freeze]
+            player_hp = "yes"
+            cpu_hp = true
+            cpu_hp_value = -1
+            speed = 2
+            "#,
+        );
+        assert_issues(
+            &load.issues,
+            &[
+                "unknown key colour",
+                "profiles.a.log_level 'loud' is not one of off, error, warn, info, debug, trace",
+                "profiles.a.input_mode is invalid: ",
+                "profiles.a.hotkeys.bogus: unknown action 'bogus'",
+                "profiles.a.hotkeys.toggle_ui: Insert is already bound to Eject",
+                "profiles.a.freeze.player_hp is invalid: ",
+                "profiles.a.freeze.cpu_hp_value is invalid: ",
+                "unknown key profiles.a.freeze.speed",
+                "profiles.a.ui is not a table",
+                "unknown key profiles.a.extra",
+            ],
+        );
+        // what was invalid is left at its default
+        let profile = load.config.active();
+        assert_eq!(profile.log_level, "info");
+        assert_eq!(profile.input_mode, InputMode::default());
+        assert_eq!(
+            profile.freeze,
+            FreezeSettings {
+                cpu_hp: true,
+                ..FreezeSettings::default()
+            }
+        );
+        assert_eq!(profile.ui, UiLayout::default());
+    }
+
+    #[test]
+    fn broken_files() {
+        let load = Config::parse("profile = ");
+        assert_issues(&load.issues, &["not a valid config, using the defaults: "]);
+        assert_eq!(load.config, Config::default());
+
+        // profiles that aren't a table are the built-in ones
+        let load = Config::parse("profiles = 1");
+        assert_issues(&load.issues, &["profiles is not a table"]);
+        assert_eq!(load.config, Config::default());
+
+        let load = Config::parse("[profiles]\ncasual = 3");
+        assert_issues(
+            &load.issues,
+            &[
+                "profiles.casual is not a table",
+                "profile 'casual' does not exist",
+            ],
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("sbx-tool-config-{}", std::process::id()));
+        let path = dir.join(CONFIG_FILE_NAME);
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // no file is the defaults
+        let load = Config::load(&path).unwrap();
+        assert_issues(&load.issues, &[]);
+        assert_eq!(load.config, Config::default());
+
+        let mut config = Config::default();
+        let practice = config.profiles.get_mut("practice").unwrap();
+        practice.freeze.player_hp_value = Some(1234);
+        practice.patches = vec!["a".to_owned()];
+        practice.ui.window_position = Some([1.0, 2.0]);
+        config.profile = "practice".to_owned();
+        config.save(&path).unwrap();
+
+        let load = Config::load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_issues(&load.issues, &[]);
+        assert_eq!(load.config, config);
+    }
+}
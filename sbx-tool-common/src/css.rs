@@ -0,0 +1,36 @@
+//! Character select screen structures, sbx-tool-core's css module re-exports them.
+use crate::reflect::game_struct;
+
+game_struct! {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    #[size(0x3c)]
+    pub struct CSSContext {
+        /// 2 for player, 4 for cpu
+        #[offset(0x0)]
+        pub css_current_party: u32,
+        #[offset(0x4)]
+        pub player_party_hp: u32,
+        #[offset(0x8)]
+        pub cpu_party_hp: u32,
+        #[offset(0xc)]
+        graphic_player_party_hp: u32,
+        #[offset(0x10)]
+        graphic_cpu_party_hp: u32,
+        #[offset(0x14)]
+        pub player_party_ex: u32,
+        pub cpu_party_ex: u32,
+        graphic_player_party_ex: u32,
+        graphic_cpu_party_ex: u32,
+        #[offset(0x24)]
+        pub player_party_cost: u32,
+        pub cpu_party_cost: u32,
+        graphic_player_party_cost: u32,
+        graphic_cpu_party_cost: u32,
+        #[offset(0x34)]
+        pub max_party_cost: u32,
+        #[offset(0x38)]
+        pub max_party_member: u32,
+        // current_party_character_count: u32, //2e20
+    }
+}
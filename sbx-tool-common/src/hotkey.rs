@@ -0,0 +1,668 @@
+//! Key chords bound to named actions.
+//! The dll feeds key presses from its WndProc hook, nothing here touches Windows,
+//! keys are plain virtual key codes.
+use crate::battle::Side;
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+const VK_SHIFT: u32 = 0x10;
+const VK_CONTROL: u32 = 0x11;
+const VK_MENU: u32 = 0x12;
+const VK_ESCAPE: u32 = 0x1B;
+
+/// Keys named other than by their character, letters and digits are their own names
+const KEY_NAMES: &[(&str, u32)] = &[
+    ("Backspace", 0x08),
+    ("Tab", 0x09),
+    ("Enter", 0x0D),
+    ("Pause", 0x13),
+    ("Escape", VK_ESCAPE),
+    ("Space", 0x20),
+    ("PageUp", 0x21),
+    ("PageDown", 0x22),
+    ("End", 0x23),
+    ("Home", 0x24),
+    ("Left", 0x25),
+    ("Up", 0x26),
+    ("Right", 0x27),
+    ("Down", 0x28),
+    ("Insert", 0x2D),
+    ("Delete", 0x2E),
+    ("NumpadMultiply", 0x6A),
+    ("NumpadAdd", 0x6B),
+    ("NumpadSubtract", 0x6D),
+    ("NumpadDecimal", 0x6E),
+    ("NumpadDivide", 0x6F),
+    ("ScrollLock", 0x91),
+];
+
+/// Shift, Ctrl, Alt and Win, left, right or either. They only modify a chord.
+pub fn is_modifier_key(key: u32) -> bool {
+    matches!(
+        key,
+        VK_SHIFT | VK_CONTROL | VK_MENU | 0x5B | 0x5C | 0xA0..=0xA5
+    )
+}
+
+/// Name of a virtual key, None if it can't be bound
+pub fn key_name(key: u32) -> Option<String> {
+    if let Some((name, _)) = KEY_NAMES.iter().find(|(_, k)| *k == key) {
+        return Some((*name).to_owned());
+    }
+    match key {
+        //'0'..'9' and 'A'..'Z' are their ascii codes
+        0x30..=0x39 | 0x41..=0x5A => Some(char::from(key as u8).to_string()),
+        0x60..=0x69 => Some(format!("Numpad{}", key - 0x60)),
+        0x70..=0x87 => Some(format!("F{}", key - 0x70 + 1)),
+        _ => None,
+    }
+}
+
+/// Virtual key of a name from `key_name`, case insensitive
+pub fn parse_key(name: &str) -> Option<u32> {
+    if let Some((_, key)) = KEY_NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+        return Some(*key);
+    }
+    let upper = name.to_ascii_uppercase();
+    let mut chars = upper.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_uppercase() || c.is_ascii_digit() {
+            return Some(c as u32);
+        }
+    }
+    if let Some(n) = upper.strip_prefix("NUMPAD") {
+        return match n.parse::<u32>() {
+            Ok(n) if n <= 9 => Some(0x60 + n),
+            _ => None,
+        };
+    }
+    if let Some(n) = upper.strip_prefix('F') {
+        return match n.parse::<u32>() {
+            Ok(n) if (1..=24).contains(&n) => Some(0x70 + n - 1),
+            _ => None,
+        };
+    }
+    None
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+/// A key with the modifiers held, e.g. `Ctrl+Shift+F1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub modifiers: Modifiers,
+    /// Virtual key code, never a modifier key
+    pub key: u32,
+}
+
+impl KeyChord {
+    pub fn new(key: u32, modifiers: Modifiers) -> Self {
+        KeyChord { modifiers, key }
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.ctrl {
+            f.write_str("Ctrl+")?;
+        }
+        if self.modifiers.shift {
+            f.write_str("Shift+")?;
+        }
+        if self.modifiers.alt {
+            f.write_str("Alt+")?;
+        }
+        match key_name(self.key) {
+            Some(name) => f.write_str(&name),
+            None => write!(f, "0x{:02X}", self.key),
+        }
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = anyhow::Error;
+
+    /// Modifiers then the key, joined by `+`. Case and spaces don't matter.
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts: Vec<&str> = s.split('+').map(|p| p.trim()).collect();
+        let key_part = parts.pop().unwrap_or_default();
+        let mut modifiers = Modifiers::default();
+        for part in parts {
+            let modifier = match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut modifiers.ctrl,
+                "shift" => &mut modifiers.shift,
+                "alt" => &mut modifiers.alt,
+                _ => return Err(anyhow!("'{}' in '{}' is not Ctrl, Shift or Alt", part, s)),
+            };
+            *modifier = true;
+        }
+        let key = parse_key(key_part).ok_or_else(|| anyhow!("'{}' is not a key", key_part))?;
+        Ok(KeyChord { modifiers, key })
+    }
+}
+
+/// A battle gauge
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Gauge {
+    Hp,
+    Ex,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Show or hide the tool window
+    ToggleUi,
+    ToggleFreeze(Side, Gauge),
+    /// Hp to the initial hp, ex to the max
+    Refill(Side, Gauge),
+    /// Switch a patch of the `PatchRegistry` by name
+    TogglePatch(String),
+    /// Remember hp, ex, rush counts and scores of both sides
+    SaveBattleState,
+    /// Write back what `SaveBattleState` remembered
+    LoadBattleState,
+    /// Revert everything and unload the dll
+    Eject,
+}
+
+const SIDES: [(Side, &str, &str); 2] = [
+    (Side::Player, "player", "Player"),
+    (Side::CPU, "cpu", "CPU"),
+];
+const GAUGES: [(Gauge, &str, &str); 2] = [(Gauge::Hp, "hp", "HP"), (Gauge::Ex, "ex", "EX")];
+const PATCH_PREFIX: &str = "patch:";
+
+impl Action {
+    /// Every action but the patch toggles, which depend on the registered patches
+    pub fn fixed() -> Vec<Action> {
+        let mut actions = vec![Action::ToggleUi];
+        for (side, _, _) in SIDES {
+            for (gauge, _, _) in GAUGES {
+                actions.push(Action::ToggleFreeze(side, gauge));
+            }
+        }
+        for (side, _, _) in SIDES {
+            for (gauge, _, _) in GAUGES {
+                actions.push(Action::Refill(side, gauge));
+            }
+        }
+        actions.extend([
+            Action::SaveBattleState,
+            Action::LoadBattleState,
+            Action::Eject,
+        ]);
+        actions
+    }
+
+    /// Name in the config, e.g. `freeze_player_hp` or `patch:Ignore Party Cost`
+    pub fn name(&self) -> String {
+        let side_gauge = |side: Side, gauge: Gauge| {
+            let side = SIDES.iter().find(|(s, _, _)| *s == side).unwrap().1;
+            let gauge = GAUGES.iter().find(|(g, _, _)| *g == gauge).unwrap().1;
+            format!("{}_{}", side, gauge)
+        };
+        match self {
+            Action::ToggleUi => "toggle_ui".to_owned(),
+            Action::ToggleFreeze(side, gauge) => format!("freeze_{}", side_gauge(*side, *gauge)),
+            Action::Refill(side, gauge) => format!("refill_{}", side_gauge(*side, *gauge)),
+            Action::TogglePatch(name) => format!("{}{}", PATCH_PREFIX, name),
+            Action::SaveBattleState => "save_battle_state".to_owned(),
+            Action::LoadBattleState => "load_battle_state".to_owned(),
+            Action::Eject => "eject".to_owned(),
+        }
+    }
+
+    /// Name in the ui
+    pub fn label(&self) -> String {
+        let side_gauge = |side: Side, gauge: Gauge| {
+            let side = SIDES.iter().find(|(s, _, _)| *s == side).unwrap().2;
+            let gauge = GAUGES.iter().find(|(g, _, _)| *g == gauge).unwrap().2;
+            format!("{} {}", side, gauge)
+        };
+        match self {
+            Action::ToggleUi => "Toggle UI".to_owned(),
+            Action::ToggleFreeze(side, gauge) => format!("Freeze {}", side_gauge(*side, *gauge)),
+            Action::Refill(side, gauge) => format!("Refill {}", side_gauge(*side, *gauge)),
+            Action::TogglePatch(name) => format!("Patch {}", name),
+            Action::SaveBattleState => "Save Battle State".to_owned(),
+            Action::LoadBattleState => "Load Battle State".to_owned(),
+            Action::Eject => "Eject".to_owned(),
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(patch) = s.strip_prefix(PATCH_PREFIX) {
+            return Ok(Action::TogglePatch(patch.to_owned()));
+        }
+        Action::fixed()
+            .into_iter()
+            .find(|a| a.name() == s)
+            .ok_or_else(|| anyhow!("unknown action '{}'", s))
+    }
+}
+
+/// Which chord runs which action, at most one chord per action and one action per chord
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Hotkeys {
+    bindings: Vec<(Action, KeyChord)>,
+}
+
+impl Hotkeys {
+    /// Bindings of a new profile. Ctrl+End ejected before hotkeys were rebindable.
+    pub fn defaults() -> Self {
+        let mut hotkeys = Hotkeys::default();
+        let ctrl = Modifiers {
+            ctrl: true,
+            ..Modifiers::default()
+        };
+        hotkeys.bindings = vec![
+            (Action::ToggleUi, KeyChord::new(0x2D, Modifiers::default())),
+            (Action::Eject, KeyChord::new(0x23, ctrl)),
+        ];
+        hotkeys
+    }
+
+    /// Bindings from a profile's `hotkeys` table, with what was skipped.
+    /// A chord bound twice keeps the first action in name order.
+    pub fn from_config(map: &BTreeMap<String, String>) -> (Self, Vec<String>) {
+        let mut hotkeys = Hotkeys::default();
+        let mut issues = Vec::new();
+        for (action, chord) in map {
+            let result = action
+                .parse::<Action>()
+                .and_then(|action| Ok((action, chord.parse::<KeyChord>()?)))
+                .and_then(|(action, chord)| hotkeys.bind(action, chord));
+            if let Err(e) = result {
+                issues.push(format!("hotkeys.{}: {}", action, e));
+            }
+        }
+        (hotkeys, issues)
+    }
+
+    pub fn to_config(&self) -> BTreeMap<String, String> {
+        self.bindings
+            .iter()
+            .map(|(action, chord)| (action.name(), chord.to_string()))
+            .collect()
+    }
+
+    pub fn bindings(&self) -> &[(Action, KeyChord)] {
+        &self.bindings
+    }
+
+    pub fn chord(&self, action: &Action) -> Option<KeyChord> {
+        self.bindings
+            .iter()
+            .find(|(a, _)| a == action)
+            .map(|(_, c)| *c)
+    }
+
+    pub fn action(&self, chord: &KeyChord) -> Option<&Action> {
+        self.bindings
+            .iter()
+            .find(|(_, c)| c == chord)
+            .map(|(a, _)| a)
+    }
+
+    /// Bind `chord` to `action`, replacing the action's chord.
+    /// Fails if another action has the chord, unbind that first.
+    pub fn bind(&mut self, action: Action, chord: KeyChord) -> Result<()> {
+        if is_modifier_key(chord.key) {
+            return Err(anyhow!("a modifier key alone can't be bound"));
+        }
+        if let Some(other) = self.action(&chord) {
+            if *other != action {
+                return Err(anyhow!("{} is already bound to {}", chord, other.label()));
+            }
+        }
+        self.unbind(&action);
+        self.bindings.push((action, chord));
+        Ok(())
+    }
+
+    /// false if the action had no chord
+    pub fn unbind(&mut self, action: &Action) -> bool {
+        let len = self.bindings.len();
+        self.bindings.retain(|(a, _)| a != action);
+        self.bindings.len() != len
+    }
+}
+
+/// What a key press did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HotkeyEvent {
+    Triggered(Action),
+    /// The chord pressed while capturing for the action, not bound yet
+    Captured(Action, KeyChord),
+    /// Escape pressed while capturing
+    CaptureCancelled,
+}
+
+/// Turns key presses into actions.
+/// A key fires once until it is released, so auto repeat and a press reported
+/// by more than one source(window messages and raw input) don't run an action twice.
+#[derive(Debug, Default)]
+pub struct HotkeyDispatcher {
+    pressed: HashSet<u32>,
+    capturing: Option<Action>,
+}
+
+impl HotkeyDispatcher {
+    /// The next chord pressed is captured for `action` instead of triggering anything
+    pub fn capture(&mut self, action: Action) {
+        self.capturing = Some(action);
+    }
+
+    pub fn capturing(&self) -> Option<&Action> {
+        self.capturing.as_ref()
+    }
+
+    pub fn cancel_capture(&mut self) {
+        self.capturing = None;
+    }
+
+    pub fn key_down(
+        &mut self,
+        hotkeys: &Hotkeys,
+        key: u32,
+        modifiers: Modifiers,
+    ) -> Option<HotkeyEvent> {
+        if !self.pressed.insert(key) || is_modifier_key(key) {
+            return None;
+        }
+        let chord = KeyChord::new(key, modifiers);
+        if let Some(action) = self.capturing.take() {
+            if key == VK_ESCAPE && modifiers == Modifiers::default() {
+                return Some(HotkeyEvent::CaptureCancelled);
+            }
+            if key_name(key).is_none() {
+                //keep waiting for a key that has a name in the config
+                self.capturing = Some(action);
+                return None;
+            }
+            return Some(HotkeyEvent::Captured(action, chord));
+        }
+        hotkeys.action(&chord).cloned().map(HotkeyEvent::Triggered)
+    }
+
+    pub fn key_up(&mut self, key: u32) {
+        self.pressed.remove(&key);
+    }
+
+    /// Forget the held keys, their releases won't be seen(e.g. the window lost focus)
+    pub fn reset(&mut self) {
+        self.pressed.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const F1: u32 = 0x70;
+    const INSERT: u32 = 0x2D;
+    const END: u32 = 0x23;
+
+    fn ctrl() -> Modifiers {
+        Modifiers {
+            ctrl: true,
+            ..Modifiers::default()
+        }
+    }
+
+    fn chord(s: &str) -> KeyChord {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn key_names() {
+        for key in 0..=0xFF {
+            if let Some(name) = key_name(key) {
+                assert!(!is_modifier_key(key), "{}", name);
+                assert_eq!(parse_key(&name), Some(key), "{}", name);
+                assert_eq!(parse_key(&name.to_lowercase()), Some(key), "{}", name);
+            }
+        }
+        assert_eq!(key_name(0x41).as_deref(), Some("A"));
+        assert_eq!(key_name(0x39).as_deref(), Some("9"));
+        assert_eq!(key_name(0x63).as_deref(), Some("Numpad3"));
+        assert_eq!(key_name(0x87).as_deref(), Some("F24"));
+        assert_eq!(key_name(0x10), None);
+        assert_eq!(parse_key("F25"), None);
+        assert_eq!(parse_key("Numpad10"), None);
+        assert_eq!(parse_key("Shift"), None);
+        assert_eq!(parse_key(""), None);
+    }
+
+    #[test]
+    fn chord_parse_and_format() {
+        let all = Modifiers {
+            ctrl: true,
+            shift: true,
+            alt: true,
+        };
+        assert_eq!(chord("Ctrl+Shift+Alt+F1"), KeyChord::new(F1, all));
+        // modifiers are written in one order
+        assert_eq!(
+            chord(" alt + SHIFT+control+f1 ").to_string(),
+            "Ctrl+Shift+Alt+F1"
+        );
+        assert_eq!(chord("Ctrl+End"), KeyChord::new(END, ctrl()));
+        assert_eq!(chord("insert").to_string(), "Insert");
+        for s in [
+            "A",
+            "Ctrl+9",
+            "Shift+Numpad0",
+            "Alt+PageDown",
+            "Ctrl+Shift+Space",
+        ] {
+            assert_eq!(chord(s).to_string(), s);
+        }
+        // a key without a name is still shown
+        assert_eq!(KeyChord::new(0xBA, ctrl()).to_string(), "Ctrl+0xBA");
+
+        let error = |s: &str| s.parse::<KeyChord>().unwrap_err().to_string();
+        assert_eq!(
+            error("Meta+A"),
+            "'Meta' in 'Meta+A' is not Ctrl, Shift or Alt"
+        );
+        assert_eq!(error("Ctrl+"), "'' is not a key");
+        assert_eq!(error("Ctrl+Banana"), "'Banana' is not a key");
+    }
+
+    #[test]
+    fn action_names() {
+        for action in Action::fixed() {
+            assert_eq!(action.name().parse::<Action>().unwrap(), action);
+        }
+        assert_eq!(
+            Action::ToggleFreeze(Side::CPU, Gauge::Ex).name(),
+            "freeze_cpu_ex"
+        );
+        assert_eq!(
+            Action::Refill(Side::Player, Gauge::Hp).label(),
+            "Refill Player HP"
+        );
+        assert_eq!(
+            "patch:Ignore Party Cost".parse::<Action>().unwrap(),
+            Action::TogglePatch("Ignore Party Cost".to_owned())
+        );
+        assert_eq!(
+            "fly".parse::<Action>().unwrap_err().to_string(),
+            "unknown action 'fly'"
+        );
+    }
+
+    #[test]
+    fn bind() {
+        let mut hotkeys = Hotkeys::defaults();
+        assert_eq!(hotkeys.action(&chord("Ctrl+End")), Some(&Action::Eject));
+
+        // a chord has one action
+        let error = hotkeys
+            .bind(Action::ToggleUi, chord("Ctrl+End"))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Ctrl+End is already bound to Eject");
+        assert_eq!(hotkeys, Hotkeys::defaults());
+
+        // an action has one chord, binding again replaces it
+        hotkeys.bind(Action::ToggleUi, chord("F1")).unwrap();
+        assert_eq!(hotkeys.chord(&Action::ToggleUi), Some(chord("F1")));
+        assert_eq!(hotkeys.action(&chord("Insert")), None);
+        assert_eq!(hotkeys.bindings().len(), 2);
+        // the same binding again is fine
+        hotkeys.bind(Action::ToggleUi, chord("F1")).unwrap();
+        assert_eq!(hotkeys.bindings().len(), 2);
+
+        let error = hotkeys
+            .bind(Action::SaveBattleState, KeyChord::new(0xA0, ctrl()))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "a modifier key alone can't be bound");
+
+        assert!(hotkeys.unbind(&Action::Eject));
+        assert!(!hotkeys.unbind(&Action::Eject));
+        hotkeys
+            .bind(Action::SaveBattleState, chord("Ctrl+End"))
+            .unwrap();
+        assert_eq!(
+            hotkeys.action(&chord("Ctrl+End")),
+            Some(&Action::SaveBattleState)
+        );
+    }
+
+    #[test]
+    fn from_config() {
+        let defaults = Hotkeys::defaults().to_config();
+        let (hotkeys, issues) = Hotkeys::from_config(&defaults);
+        assert_eq!(hotkeys.to_config(), defaults);
+        assert!(issues.is_empty());
+
+        let map: BTreeMap<String, String> = [
+            ("toggle_ui", "F1"),
+            ("eject", "F1"),
+            ("fly", "F2"),
+            ("refill_cpu_hp", "Ctrl+Nope"),
+            ("patch:Ignore Party Cost", "Shift+F3"),
+        ]
+        .iter()
+        .map(|(a, c)| (a.to_string(), c.to_string()))
+        .collect();
+        let (hotkeys, issues) = Hotkeys::from_config(&map);
+        // a chord bound twice keeps the first action in name order
+        assert_eq!(
+            issues,
+            [
+                "hotkeys.fly: unknown action 'fly'",
+                "hotkeys.refill_cpu_hp: 'Nope' is not a key",
+                "hotkeys.toggle_ui: F1 is already bound to Eject",
+            ]
+        );
+        assert_eq!(hotkeys.action(&chord("F1")), Some(&Action::Eject));
+        assert_eq!(
+            hotkeys.action(&chord("Shift+F3")),
+            Some(&Action::TogglePatch("Ignore Party Cost".to_owned()))
+        );
+        assert_eq!(hotkeys.bindings().len(), 2);
+    }
+
+    #[test]
+    fn dispatch_once_per_press() {
+        let hotkeys = Hotkeys::defaults();
+        let mut dispatcher = HotkeyDispatcher::default();
+        let toggle = Some(HotkeyEvent::Triggered(Action::ToggleUi));
+
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, INSERT, Modifiers::default()),
+            toggle
+        );
+        // auto repeat, and the same press from raw input after WM_KEYDOWN
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, INSERT, Modifiers::default()),
+            None
+        );
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, INSERT, Modifiers::default()),
+            None
+        );
+        dispatcher.key_up(INSERT);
+        // both sources report the release too
+        dispatcher.key_up(INSERT);
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, INSERT, Modifiers::default()),
+            toggle
+        );
+
+        // a lost release is forgotten with reset
+        dispatcher.reset();
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, INSERT, Modifiers::default()),
+            toggle
+        );
+
+        // modifiers are only part of a chord
+        assert_eq!(dispatcher.key_down(&hotkeys, VK_CONTROL, ctrl()), None);
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, END, ctrl()),
+            Some(HotkeyEvent::Triggered(Action::Eject))
+        );
+        // unbound chords do nothing
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, F1, Modifiers::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn capture() {
+        let hotkeys = Hotkeys::defaults();
+        let mut dispatcher = HotkeyDispatcher::default();
+        let action = Action::Refill(Side::Player, Gauge::Ex);
+
+        // a bound chord is captured instead of triggered
+        dispatcher.capture(action.clone());
+        assert_eq!(dispatcher.capturing(), Some(&action));
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, VK_SHIFT, Modifiers::default()),
+            None
+        );
+        // a key without a name keeps waiting
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, 0xFF, Modifiers::default()),
+            None
+        );
+        assert_eq!(dispatcher.capturing(), Some(&action));
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, END, ctrl()),
+            Some(HotkeyEvent::Captured(action.clone(), chord("Ctrl+End")))
+        );
+        assert_eq!(dispatcher.capturing(), None);
+
+        // escape cancels, ctrl+escape is a chord
+        dispatcher.capture(action.clone());
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, VK_ESCAPE, Modifiers::default()),
+            Some(HotkeyEvent::CaptureCancelled)
+        );
+        dispatcher.key_up(VK_ESCAPE);
+        dispatcher.capture(action.clone());
+        assert_eq!(
+            dispatcher.key_down(&hotkeys, VK_ESCAPE, ctrl()),
+            Some(HotkeyEvent::Captured(action.clone(), chord("Ctrl+Escape")))
+        );
+
+        dispatcher.capture(action);
+        dispatcher.cancel_capture();
+        assert_eq!(dispatcher.capturing(), None);
+    }
+}
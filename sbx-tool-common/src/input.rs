@@ -0,0 +1,107 @@
+//! What the game gets of the mouse and keyboard while the tool window is shown.
+//! The dll sorts window messages into `InputMessage`s, the routing itself doesn't need Windows.
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputMode {
+    /// The game gets what imgui doesn't use, e.g. not the keys typed into an input box
+    #[default]
+    Capture,
+    /// The game gets no input while the window is shown
+    UiFocus,
+    /// The game gets everything, like before the routing existed
+    Passthrough,
+}
+
+impl InputMode {
+    pub const ALL: [InputMode; 3] = [
+        InputMode::Capture,
+        InputMode::UiFocus,
+        InputMode::Passthrough,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            InputMode::Capture => "Capture",
+            InputMode::UiFocus => "UI Focus",
+            InputMode::Passthrough => "Passthrough",
+        }
+    }
+}
+
+/// A mouse or keyboard window message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputMessage {
+    /// Button down, move or wheel
+    Mouse,
+    MouseRelease,
+    /// Key down or a character
+    Key,
+    KeyRelease,
+}
+
+/// Should the game get `message`. `captured` is imgui wanting it,
+/// `ui_shown` the tool window being shown and not collapsed.
+pub fn forward_to_game(
+    mode: InputMode,
+    message: InputMessage,
+    captured: bool,
+    ui_shown: bool,
+) -> bool {
+    //the game may have seen the press before the tool took the input, don't leave it held
+    if matches!(
+        message,
+        InputMessage::MouseRelease | InputMessage::KeyRelease
+    ) {
+        return true;
+    }
+    match mode {
+        InputMode::Capture => !captured,
+        InputMode::UiFocus => !ui_shown && !captured,
+        InputMode::Passthrough => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use InputMessage::*;
+    use InputMode::*;
+
+    /// (captured, ui_shown) of the columns below
+    const STATES: [(bool, bool); 4] = [(false, false), (false, true), (true, false), (true, true)];
+
+    #[test]
+    fn forward_to_game_table() {
+        #[rustfmt::skip]
+        let table = [
+            //                            free, shown, captured, both
+            (Capture, Mouse,             [true, true,  false,    false]),
+            (Capture, Key,               [true, true,  false,    false]),
+            (Capture, MouseRelease,      [true, true,  true,     true]),
+            (Capture, KeyRelease,        [true, true,  true,     true]),
+            (UiFocus, Mouse,             [true, false, false,    false]),
+            (UiFocus, Key,               [true, false, false,    false]),
+            (UiFocus, MouseRelease,      [true, true,  true,     true]),
+            (UiFocus, KeyRelease,        [true, true,  true,     true]),
+            (Passthrough, Mouse,         [true, true,  true,     true]),
+            (Passthrough, Key,           [true, true,  true,     true]),
+            (Passthrough, MouseRelease,  [true, true,  true,     true]),
+            (Passthrough, KeyRelease,    [true, true,  true,     true]),
+        ];
+        for (mode, message, expected) in table {
+            for ((captured, ui_shown), forward) in STATES.into_iter().zip(expected) {
+                assert_eq!(
+                    forward_to_game(mode, message, captured, ui_shown),
+                    forward,
+                    "{:?} {:?} captured: {} ui shown: {}",
+                    mode,
+                    message,
+                    captured,
+                    ui_shown
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+//! Compile time layout checks of the reversed game structures
+
+/// Check the field offsets and the size of a `#[repr(C)]` struct at compile time.
+/// Fields are listed in declaration order, all of them, `#[offset(..)]` and `#[size(..)]`
+/// are optional. A wrong one fails the build.
+///
+/// ```
+/// #[repr(C)]
+/// struct Status {
+///     hp: u32,
+///     flag: u8,
+///     ex: u32,
+/// }
+/// sbx_tool_common::assert_layout!(#[size(0xc)] Status { hp: u32, flag: u8, #[offset(0x8)] ex: u32 });
+/// ```
+///
+/// A field added without fixing the padding moves the ones after it:
+///
+/// ```compile_fail
+/// #[repr(C)]
+/// struct Status {
+///     hp: u32,
+///     flag: u8,
+///     ex: u32,
+/// }
+/// sbx_tool_common::assert_layout!(Status { hp: u32, flag: u8, #[offset(0x5)] ex: u32 });
+/// ```
+///
+/// ```compile_fail
+/// #[repr(C)]
+/// struct Status {
+///     hp: u32,
+///     flag: u8,
+/// }
+/// sbx_tool_common::assert_layout!(#[size(0x5)] Status { hp: u32, flag: u8 });
+/// ```
+#[macro_export]
+macro_rules! assert_layout {
+    (
+        $(#[size($size:literal)])?
+        $name:ident {
+            $($(#[offset($offset:literal)])? $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        const _: () = {
+            let mut offset = 0usize;
+            let mut align = 1usize;
+            $(
+                let field_align = std::mem::align_of::<$ty>();
+                offset = (offset + field_align - 1) / field_align * field_align;
+                $(assert!(
+                    offset == $offset,
+                    concat!(stringify!($name), "::", stringify!($field), " is not at ", stringify!($offset))
+                );)?
+                offset += std::mem::size_of::<$ty>();
+                if field_align > align {
+                    align = field_align;
+                }
+            )*
+            let size = (offset + align - 1) / align * align;
+            assert!(
+                size == std::mem::size_of::<$name>(),
+                concat!(stringify!($name), " is not laid out like #[repr(C)]")
+            );
+            $(assert!(
+                size == $size,
+                concat!(stringify!($name), " is not ", stringify!($size), " bytes")
+            );)?
+        };
+    };
+}
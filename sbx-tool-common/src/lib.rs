@@ -0,0 +1,14 @@
+//! Parts of sbx-tool that don't use windows, the game is only reached through `GameMemory`.
+//! sbx-tool-core re-exports them under the same paths.
+pub mod asm;
+pub mod battle;
+pub mod config;
+pub mod css;
+pub mod hotkey;
+pub mod input;
+mod layout;
+pub mod memory;
+pub mod reflect;
+pub mod scene;
+pub mod style;
+pub mod utility;
@@ -0,0 +1,258 @@
+use super::{GameMemory, Protection, Region};
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use std::collections::BTreeMap;
+
+struct MockRegion {
+    bytes: Vec<u8>,
+    protection: Protection,
+}
+
+/// In-memory fake of the game's address space.
+/// Accessing unmapped or protected memory returns an error instead of crashing,
+/// so the logic on top of `GameMemory` can be tested without the game.
+#[derive(Default)]
+pub struct MockMemory {
+    regions: Mutex<BTreeMap<usize, MockRegion>>,
+}
+
+impl MockMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Map a zeroed region
+    pub fn map(&self, base: usize, size: usize, protection: Protection) {
+        self.map_bytes(base, &vec![0; size], protection);
+    }
+
+    /// Map a region initialized with `bytes`
+    pub fn map_bytes(&self, base: usize, bytes: &[u8], protection: Protection) {
+        self.regions.lock().insert(
+            base,
+            MockRegion {
+                bytes: bytes.to_vec(),
+                protection,
+            },
+        );
+    }
+
+    pub fn unmap(&self, base: usize) {
+        self.regions.lock().remove(&base);
+    }
+
+    /// Run `f` on the region containing [address, address + size)
+    fn with_region<R>(
+        &self,
+        address: usize,
+        size: usize,
+        f: impl FnOnce(&mut MockRegion, usize) -> Result<R>,
+    ) -> Result<R> {
+        let mut regions = self.regions.lock();
+        let (base, region) = regions
+            .range_mut(..=address)
+            .next_back()
+            .ok_or_else(|| anyhow!("access violation at {:x} (unmapped)", address))?;
+        let offset = address - base;
+        if !matches!(offset.checked_add(size), Some(end) if end <= region.bytes.len()) {
+            return Err(anyhow!(
+                "access violation at {:x} (unmapped)",
+                address.wrapping_add(size)
+            ));
+        }
+        f(region, offset)
+    }
+}
+
+impl GameMemory for MockMemory {
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<()> {
+        self.with_region(address, buffer.len(), |region, offset| {
+            if !region.protection.is_readable() {
+                return Err(anyhow!("access violation at {:x} (read)", address));
+            }
+            buffer.copy_from_slice(&region.bytes[offset..offset + buffer.len()]);
+            Ok(())
+        })
+    }
+
+    fn write_bytes(&self, address: usize, bytes: &[u8]) -> Result<()> {
+        self.with_region(address, bytes.len(), |region, offset| {
+            if !region.protection.is_writable() {
+                return Err(anyhow!("access violation at {:x} (write)", address));
+            }
+            region.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+            Ok(())
+        })
+    }
+
+    fn query(&self, address: usize) -> Result<Region> {
+        let regions = self.regions.lock();
+        match regions.range(..=address).next_back() {
+            Some((base, region)) if address < base + region.bytes.len() => Ok(Region {
+                base: *base,
+                size: region.bytes.len(),
+                committed: true,
+                protection: region.protection,
+            }),
+            _ => Ok(Region {
+                base: address,
+                size: 0,
+                committed: false,
+                protection: Protection::NOACCESS,
+            }),
+        }
+    }
+
+    /// Protection is per region, like a real page
+    fn protect(&self, address: usize, size: usize, protection: Protection) -> Result<Protection> {
+        self.with_region(address, size, |region, _| {
+            let old = region.protection;
+            region.protection = protection;
+            Ok(old)
+        })
+    }
+
+    /// Anywhere is after the last region, 64k aligned like VirtualAlloc
+    fn allocate(
+        &self,
+        address: Option<usize>,
+        size: usize,
+        protection: Protection,
+    ) -> Result<usize> {
+        const GRANULARITY: usize = 0x10000;
+        let base = {
+            let regions = self.regions.lock();
+            let overlaps = |base: usize| {
+                regions
+                    .iter()
+                    .any(|(b, r)| *b < base + size && base < b + r.bytes.len())
+            };
+            match address {
+                Some(base) if overlaps(base) => {
+                    return Err(anyhow!("{:x} is already in use", base))
+                }
+                Some(base) => base,
+                None => {
+                    let end = regions
+                        .iter()
+                        .map(|(b, r)| b + r.bytes.len())
+                        .max()
+                        .unwrap_or(GRANULARITY);
+                    end + (GRANULARITY - end % GRANULARITY) % GRANULARITY
+                }
+            }
+        };
+        self.map(base, size, protection);
+        Ok(base)
+    }
+
+    fn free(&self, address: usize) -> Result<()> {
+        match self.regions.lock().remove(&address) {
+            Some(_) => Ok(()),
+            None => Err(anyhow!("{:x} is not allocated", address)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::GameMemoryExt;
+
+    const BASE: usize = 0x40_0000;
+
+    fn memory() -> MockMemory {
+        let memory = MockMemory::new();
+        memory.map(BASE, 0x100, Protection::READWRITE);
+        memory
+    }
+
+    #[test]
+    fn read_write() {
+        let memory = memory();
+        memory.write_bytes(BASE + 0x10, &[1, 2, 3, 4]).unwrap();
+        assert_eq!(memory.read_vec(BASE + 0x10, 4).unwrap(), [1, 2, 3, 4]);
+        assert_eq!(memory.read::<u32>(BASE + 0x10).unwrap(), 0x0403_0201);
+
+        memory.write::<f32>(BASE + 0x20, 1.5).unwrap();
+        assert_eq!(memory.read::<f32>(BASE + 0x20).unwrap(), 1.5);
+        // untouched bytes stay zeroed
+        assert_eq!(memory.read_ptr(BASE).unwrap(), 0);
+    }
+
+    #[test]
+    fn map_bytes_initializes() {
+        let memory = MockMemory::new();
+        memory.map_bytes(BASE, &[0xaa, 0xbb], Protection::READONLY);
+        assert_eq!(memory.read_vec(BASE, 2).unwrap(), [0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn region_bounds() {
+        let memory = memory();
+        // the last byte is mapped, one past it is not
+        assert!(memory.read::<u8>(BASE + 0xff).is_ok());
+        assert!(memory.read::<u8>(BASE + 0x100).is_err());
+        // an access straddling the end fails as a whole and writes nothing
+        assert!(memory.read::<u32>(BASE + 0xfe).is_err());
+        assert!(memory.write_bytes(BASE + 0xfe, &[1, 2, 3, 4]).is_err());
+        assert_eq!(memory.read_vec(BASE + 0xfe, 2).unwrap(), [0, 0]);
+
+        assert!(memory.is_readable(BASE, 0x100));
+        assert!(!memory.is_readable(BASE, 0x101));
+        assert!(!memory.is_readable(BASE - 1, 1));
+    }
+
+    #[test]
+    fn unmapped_access_errors() {
+        let memory = memory();
+        let error = memory.read::<u32>(BASE - 4).unwrap_err();
+        assert!(error.to_string().contains("unmapped"), "{}", error);
+        assert!(memory.write::<u32>(0, 1).is_err());
+        assert!(memory.protect(0x1000, 4, Protection::READWRITE).is_err());
+
+        let region = memory.query(0x1000).unwrap();
+        assert!(!region.committed);
+        assert!(!region.is_readable());
+
+        memory.unmap(BASE);
+        assert!(memory.read::<u8>(BASE).is_err());
+    }
+
+    #[test]
+    fn protection_is_enforced() {
+        let memory = MockMemory::new();
+        memory.map(BASE, 0x10, Protection::EXECUTE_READ);
+        assert!(memory.read::<u32>(BASE).is_ok());
+        assert!(memory.write::<u32>(BASE, 1).is_err());
+
+        // patch_bytes unprotects and restores the old protection
+        memory.patch_bytes(BASE, &[0x90]).unwrap();
+        assert_eq!(memory.read::<u8>(BASE).unwrap(), 0x90);
+        assert_eq!(
+            memory.query(BASE).unwrap().protection,
+            Protection::EXECUTE_READ
+        );
+
+        memory.protect(BASE, 1, Protection::NOACCESS).unwrap();
+        assert!(memory.read::<u8>(BASE).is_err());
+        assert!(!memory.is_readable(BASE, 1));
+    }
+
+    #[test]
+    fn allocate_and_free() {
+        let memory = memory();
+        assert!(memory
+            .allocate(Some(BASE + 0x80), 0x10, Protection::READWRITE)
+            .is_err());
+
+        let anywhere = memory.allocate(None, 0x20, Protection::READWRITE).unwrap();
+        assert_eq!(anywhere % 0x10000, 0);
+        assert!(anywhere >= BASE + 0x100);
+        memory.write::<u32>(anywhere, 7).unwrap();
+
+        memory.free(anywhere).unwrap();
+        assert!(memory.read::<u32>(anywhere).is_err());
+        assert!(memory.free(anywhere).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+pub mod mock;
+pub mod pointer;
+pub mod viewer;
+pub mod watch;
+use anyhow::{anyhow, Result};
+pub use mock::MockMemory;
+pub use pointer::{PathBase, PointerPath, PointerPathError};
+pub use viewer::{Interpretation, MemoryView};
+pub use watch::{WatchEntry, WatchType};
+
+/// PAGE_* protection flags
+/// Defined here so backends that don't touch winapi(mock) can use them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Protection(pub u32);
+
+impl Protection {
+    pub const NOACCESS: Protection = Protection(0x01);
+    pub const READONLY: Protection = Protection(0x02);
+    pub const READWRITE: Protection = Protection(0x04);
+    pub const WRITECOPY: Protection = Protection(0x08);
+    pub const EXECUTE: Protection = Protection(0x10);
+    pub const EXECUTE_READ: Protection = Protection(0x20);
+    pub const EXECUTE_READWRITE: Protection = Protection(0x40);
+    pub const EXECUTE_WRITECOPY: Protection = Protection(0x80);
+    pub const GUARD: u32 = 0x100;
+
+    fn base(self) -> u32 {
+        self.0 & 0xff
+    }
+
+    pub fn is_readable(self) -> bool {
+        self.0 & Self::GUARD == 0 && self.base() & !(Self::NOACCESS.0 | Self::EXECUTE.0) != 0
+    }
+
+    pub fn is_writable(self) -> bool {
+        self.0 & Self::GUARD == 0
+            && self.base()
+                & (Self::READWRITE.0
+                    | Self::WRITECOPY.0
+                    | Self::EXECUTE_READWRITE.0
+                    | Self::EXECUTE_WRITECOPY.0)
+                != 0
+    }
+
+    pub fn is_executable(self) -> bool {
+        self.base()
+            & (Self::EXECUTE.0
+                | Self::EXECUTE_READ.0
+                | Self::EXECUTE_READWRITE.0
+                | Self::EXECUTE_WRITECOPY.0)
+            != 0
+    }
+}
+
+/// Result of a `VirtualQuery`-style query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub base: usize,
+    pub size: usize,
+    pub committed: bool,
+    pub protection: Protection,
+}
+
+impl Region {
+    /// Ranges that wrap around the address space are never contained
+    pub fn contains(&self, address: usize, size: usize) -> bool {
+        match (address.checked_add(size), self.base.checked_add(self.size)) {
+            (Some(end), Some(region_end)) => address >= self.base && end <= region_end,
+            _ => false,
+        }
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.committed && self.protection.is_readable()
+    }
+
+    pub fn is_writable(&self) -> bool {
+        self.committed && self.protection.is_writable()
+    }
+}
+
+/// Access to the game's memory.
+/// In-process(dll), another process(external trainer) or a mock(no game needed).
+pub trait GameMemory: Send + Sync {
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<()>;
+
+    /// Does not touch the protection, use `patch_bytes` for code
+    fn write_bytes(&self, address: usize, bytes: &[u8]) -> Result<()>;
+
+    fn query(&self, address: usize) -> Result<Region>;
+
+    /// Returns the old protection
+    fn protect(&self, address: usize, size: usize, protection: Protection) -> Result<Protection>;
+
+    /// Commit `size` bytes exactly at `address`, or anywhere if None.
+    /// Returns the base of the allocation.
+    fn allocate(
+        &self,
+        address: Option<usize>,
+        size: usize,
+        protection: Protection,
+    ) -> Result<usize>;
+
+    /// Release an allocation by its base
+    fn free(&self, address: usize) -> Result<()>;
+
+    /// Write to code the game may be running.
+    /// All `patches` go in at once. Backends that can suspend the other threads do so,
+    /// wait until none of them is inside a patched range and flush the instruction cache.
+    fn patch_code(&self, patches: &[(usize, &[u8])]) -> Result<()> {
+        for (address, bytes) in patches {
+            self.patch_bytes(*address, bytes)?;
+        }
+        Ok(())
+    }
+}
+
+/// Typed helpers for every `GameMemory`, trait objects included
+pub trait GameMemoryExt: GameMemory {
+    /// Read a value of `T`.
+    /// `T` should be a plain old data type(integers, floats, pointers and `#[repr(C)]` structs of them).
+    fn read<T: Copy>(&self, address: usize) -> Result<T> {
+        let mut value = std::mem::MaybeUninit::<T>::uninit();
+        let buffer = unsafe {
+            std::slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, std::mem::size_of::<T>())
+        };
+        self.read_bytes(address, buffer)?;
+        Ok(unsafe { value.assume_init() })
+    }
+
+    fn write<T: Copy>(&self, address: usize, value: T) -> Result<()> {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(&value as *const T as *const u8, std::mem::size_of::<T>())
+        };
+        self.write_bytes(address, bytes)
+    }
+
+    fn read_vec(&self, address: usize, size: usize) -> Result<Vec<u8>> {
+        let mut bytes = vec![0; size];
+        self.read_bytes(address, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Read a pointer sized value
+    fn read_ptr(&self, address: usize) -> Result<usize> {
+        self.read::<usize>(address)
+    }
+
+    /// Write to a protected(e.g. code) page and restore the protection
+    fn patch_bytes(&self, address: usize, bytes: &[u8]) -> Result<()> {
+        let old_protection = self.protect(address, bytes.len(), Protection::EXECUTE_READWRITE)?;
+        let result = self.write_bytes(address, bytes);
+        self.protect(address, bytes.len(), old_protection)?;
+        result
+    }
+
+    fn is_readable(&self, address: usize, size: usize) -> bool {
+        match self.query(address) {
+            Ok(region) => region.is_readable() && region.contains(address, size),
+            Err(_) => false,
+        }
+    }
+
+    fn is_writable(&self, address: usize, size: usize) -> bool {
+        match self.query(address) {
+            Ok(region) => region.is_writable() && region.contains(address, size),
+            Err(_) => false,
+        }
+    }
+}
+
+impl<M: GameMemory + ?Sized> GameMemoryExt for M {}
+
+/// Backends check addresses with this before touching them
+pub fn null_check(address: usize) -> Result<()> {
+    if address == 0 {
+        return Err(anyhow!("null pointer access"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_contains() {
+        let region = Region {
+            base: 0x1000,
+            size: 0x100,
+            committed: true,
+            protection: Protection::READWRITE,
+        };
+        assert!(region.contains(0x1000, 0x100));
+        assert!(region.contains(0x10ff, 1));
+        assert!(!region.contains(0x10ff, 2));
+        assert!(!region.contains(0xfff, 1));
+        assert!(!region.contains(usize::MAX, 2));
+
+        let top = Region {
+            base: usize::MAX - 0xff,
+            ..region
+        };
+        assert!(!top.contains(usize::MAX - 0xff, 0x10));
+    }
+}
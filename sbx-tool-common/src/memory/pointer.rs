@@ -0,0 +1,309 @@
+use super::{GameMemory, GameMemoryExt};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Where a pointer path starts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathBase {
+    /// rva in the game module
+    Module(usize),
+    Absolute(usize),
+}
+
+/// Cheat Engine style pointer path.
+/// `[[base] + offsets[0]] + offsets[1] ...`, the last offset is added without dereferencing.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PointerPath {
+    pub base: PathBase,
+    pub offsets: Vec<isize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PointerPathError {
+    #[error("hop {hop}: {address:#x} is not readable")]
+    Unreadable { hop: usize, address: usize },
+    #[error("hop {hop}: [{address:#x}] is null")]
+    Null { hop: usize, address: usize },
+    #[error("hop {hop}: {pointer:#x} {offset:+#x} is out of the address space")]
+    InvalidPointer {
+        hop: usize,
+        pointer: usize,
+        offset: isize,
+    },
+    #[error("hop {hop}: failed to read {address:#x} ({reason})")]
+    ReadFailed {
+        hop: usize,
+        address: usize,
+        reason: String,
+    },
+}
+
+impl PointerPathError {
+    /// Index of the hop that failed, 0 is the base
+    pub fn hop(&self) -> usize {
+        match self {
+            PointerPathError::Unreadable { hop, .. }
+            | PointerPathError::Null { hop, .. }
+            | PointerPathError::InvalidPointer { hop, .. }
+            | PointerPathError::ReadFailed { hop, .. } => *hop,
+        }
+    }
+}
+
+impl PointerPath {
+    pub fn new(base: PathBase, offsets: &[isize]) -> Self {
+        PointerPath {
+            base,
+            offsets: offsets.to_vec(),
+        }
+    }
+
+    pub fn module(rva: usize, offsets: &[isize]) -> Self {
+        Self::new(PathBase::Module(rva), offsets)
+    }
+
+    pub fn absolute(address: usize, offsets: &[isize]) -> Self {
+        Self::new(PathBase::Absolute(address), offsets)
+    }
+
+    /// Resolve to the final address.
+    /// Every dereferenced address is checked to be readable before it is read.
+    pub fn resolve(
+        &self,
+        memory: &dyn GameMemory,
+        module_address: usize,
+    ) -> Result<usize, PointerPathError> {
+        let mut address = match self.base {
+            PathBase::Module(rva) => {
+                module_address
+                    .checked_add(rva)
+                    .ok_or(PointerPathError::InvalidPointer {
+                        hop: 0,
+                        pointer: module_address,
+                        offset: rva as isize,
+                    })?
+            }
+            PathBase::Absolute(address) => address,
+        };
+        for (hop, offset) in self.offsets.iter().enumerate() {
+            if !memory.is_readable(address, std::mem::size_of::<usize>()) {
+                return Err(PointerPathError::Unreadable { hop, address });
+            }
+            let pointer = memory
+                .read_ptr(address)
+                .map_err(|e| PointerPathError::ReadFailed {
+                    hop,
+                    address,
+                    reason: e.to_string(),
+                })?;
+            if pointer == 0 {
+                return Err(PointerPathError::Null { hop, address });
+            }
+            address = add_offset(pointer, *offset).ok_or(PointerPathError::InvalidPointer {
+                hop,
+                pointer,
+                offset: *offset,
+            })?;
+        }
+        Ok(address)
+    }
+
+    /// Resolve and check `size` bytes at the final address are readable
+    pub fn resolve_readable(
+        &self,
+        memory: &dyn GameMemory,
+        module_address: usize,
+        size: usize,
+    ) -> Result<usize, PointerPathError> {
+        let address = self.resolve(memory, module_address)?;
+        if !memory.is_readable(address, size) {
+            return Err(PointerPathError::Unreadable {
+                hop: self.offsets.len(),
+                address,
+            });
+        }
+        Ok(address)
+    }
+
+    /// Resolve and read a `T` at the final address
+    pub fn read<T: Copy>(
+        &self,
+        memory: &dyn GameMemory,
+        module_address: usize,
+    ) -> Result<T, PointerPathError> {
+        let address = self.resolve_readable(memory, module_address, std::mem::size_of::<T>())?;
+        memory
+            .read(address)
+            .map_err(|e| PointerPathError::ReadFailed {
+                hop: self.offsets.len(),
+                address,
+                reason: e.to_string(),
+            })
+    }
+}
+
+/// `address + offset`, None if it leaves the address space
+fn add_offset(address: usize, offset: isize) -> Option<usize> {
+    if offset < 0 {
+        address.checked_sub(offset.unsigned_abs())
+    } else {
+        address.checked_add(offset as usize)
+    }
+}
+
+/// `module+438B28, 0, C` or `1A2B3C, -4`, numbers are hex
+impl FromStr for PointerPath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_hex = |token: &str| -> anyhow::Result<isize> {
+            let token = token.trim();
+            let (negative, digits) = match token.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, token),
+            };
+            let digits = digits.trim_start_matches("0x").trim_start_matches("0X");
+            let value = isize::from_str_radix(digits, 16)
+                .map_err(|_| anyhow::anyhow!("invalid hex number '{}'", token))?;
+            Ok(if negative { -value } else { value })
+        };
+
+        let mut tokens = s.split(',');
+        let base = tokens.next().unwrap_or_default().trim();
+        if base.is_empty() {
+            return Err(anyhow::anyhow!("empty pointer path"));
+        }
+        //"module.exe"+rva
+        let base = match base.rsplit_once('+') {
+            Some((_, rva)) => PathBase::Module(parse_hex(rva)? as usize),
+            None => PathBase::Absolute(parse_hex(base)? as usize),
+        };
+        let offsets = tokens.map(parse_hex).collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(PointerPath { base, offsets })
+    }
+}
+
+impl fmt::Display for PointerPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.base {
+            PathBase::Module(rva) => write!(f, "module+{:X}", rva)?,
+            PathBase::Absolute(address) => write!(f, "{:X}", address)?,
+        }
+        for offset in &self.offsets {
+            if *offset < 0 {
+                write!(f, ", -{:X}", -offset)?;
+            } else {
+                write!(f, ", {:X}", offset)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{MockMemory, Protection};
+
+    const MODULE: usize = 0x40_0000;
+    const HEAP: usize = 0x100_0000;
+
+    /// module+10 -> HEAP, [HEAP + 8] -> HEAP + 0x40
+    fn memory() -> MockMemory {
+        let memory = MockMemory::new();
+        memory.map(MODULE, 0x100, Protection::READWRITE);
+        memory.map(HEAP, 0x100, Protection::READWRITE);
+        memory.write(MODULE + 0x10, HEAP).unwrap();
+        memory.write(HEAP + 0x8, HEAP + 0x40).unwrap();
+        memory.write::<u32>(HEAP + 0x44, 1234).unwrap();
+        memory
+    }
+
+    #[test]
+    fn good_chain() {
+        let memory = memory();
+        let path = PointerPath::module(0x10, &[0x8, 0x4]);
+        assert_eq!(path.resolve(&memory, MODULE), Ok(HEAP + 0x44));
+        assert_eq!(path.read::<u32>(&memory, MODULE), Ok(1234));
+        // negative offsets walk backwards
+        let path = PointerPath::absolute(MODULE + 0x10, &[0x8, -0x38]);
+        assert_eq!(path.resolve(&memory, 0), Ok(HEAP + 0x8));
+        // no offsets is the base itself
+        assert_eq!(
+            PointerPath::module(0x10, &[]).resolve(&memory, MODULE),
+            Ok(MODULE + 0x10)
+        );
+    }
+
+    #[test]
+    fn null_link() {
+        let memory = memory();
+        let path = PointerPath::module(0x10, &[0x20, 0]);
+        assert_eq!(
+            path.resolve(&memory, MODULE),
+            Err(PointerPathError::Null {
+                hop: 1,
+                address: HEAP + 0x20
+            })
+        );
+    }
+
+    #[test]
+    fn unmapped_link() {
+        let memory = memory();
+        memory.write(HEAP + 0x8, 0x2000_0000usize).unwrap();
+        let path = PointerPath::module(0x10, &[0x8, 0, 0]);
+        let error = path.resolve(&memory, MODULE).unwrap_err();
+        assert_eq!(
+            error,
+            PointerPathError::Unreadable {
+                hop: 2,
+                address: 0x2000_0000
+            }
+        );
+        assert_eq!(error.hop(), 2);
+
+        // the final address is only checked by resolve_readable
+        let path = PointerPath::module(0x10, &[0x8, 0]);
+        assert_eq!(path.resolve(&memory, MODULE), Ok(0x2000_0000));
+        assert!(path.resolve_readable(&memory, MODULE, 4).is_err());
+    }
+
+    #[test]
+    fn overflow() {
+        let memory = memory();
+        memory.write(HEAP, usize::MAX - 4).unwrap();
+        let path = PointerPath::absolute(HEAP, &[0x10]);
+        assert_eq!(
+            path.resolve(&memory, 0),
+            Err(PointerPathError::InvalidPointer {
+                hop: 0,
+                pointer: usize::MAX - 4,
+                offset: 0x10
+            })
+        );
+
+        let path = PointerPath::module(0x10, &[-0x100_0001]);
+        assert!(matches!(
+            path.resolve(&memory, MODULE),
+            Err(PointerPathError::InvalidPointer { hop: 0, .. })
+        ));
+
+        let path = PointerPath::module(0x10, &[0]);
+        assert!(matches!(
+            path.resolve(&memory, usize::MAX),
+            Err(PointerPathError::InvalidPointer { hop: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let path: PointerPath = "game.exe+438B28, 0, -C".parse().unwrap();
+        assert_eq!(path, PointerPath::module(0x438b28, &[0, -0xc]));
+        assert_eq!(path.to_string(), "module+438B28, 0, -C");
+        assert_eq!(path.to_string().parse::<PointerPath>().unwrap(), path);
+        assert!("".parse::<PointerPath>().is_err());
+        assert!("12, xyz".parse::<PointerPath>().is_err());
+    }
+}
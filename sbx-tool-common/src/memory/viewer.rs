@@ -0,0 +1,417 @@
+use super::watch::format_value;
+use super::{GameMemory, GameMemoryExt, PointerPath, PointerPathError, WatchType};
+use anyhow::{anyhow, Result};
+use std::time::{Duration, Instant};
+
+/// How long a changed byte stays highlighted
+pub const CHANGE_HIGHLIGHT: Duration = Duration::from_millis(1500);
+const PAGE_SIZE: usize = 0x1000;
+
+/// How the selected bytes are read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpretation {
+    U8,
+    U16,
+    U32,
+    I32,
+    F32,
+    Pointer,
+}
+
+impl Interpretation {
+    pub const ALL: [Interpretation; 6] = [
+        Interpretation::U8,
+        Interpretation::U16,
+        Interpretation::U32,
+        Interpretation::I32,
+        Interpretation::F32,
+        Interpretation::Pointer,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Interpretation::U8 => "u8",
+            Interpretation::U16 => "u16",
+            Interpretation::U32 => "u32",
+            Interpretation::I32 => "i32",
+            Interpretation::F32 => "f32",
+            Interpretation::Pointer => "pointer",
+        }
+    }
+
+    pub fn size(self) -> usize {
+        self.watch_type().size()
+    }
+
+    fn watch_type(self) -> WatchType {
+        match self {
+            Interpretation::U8 => WatchType::Int {
+                size: 1,
+                signed: false,
+            },
+            Interpretation::U16 => WatchType::Int {
+                size: 2,
+                signed: false,
+            },
+            Interpretation::U32 => WatchType::Int {
+                size: 4,
+                signed: false,
+            },
+            Interpretation::I32 => WatchType::Int {
+                size: 4,
+                signed: true,
+            },
+            Interpretation::F32 => WatchType::Float,
+            Interpretation::Pointer => WatchType::Int {
+                size: std::mem::size_of::<usize>(),
+                signed: false,
+            },
+        }
+    }
+
+    /// `bytes` has at least `size()` bytes
+    pub fn format(self, bytes: &[u8]) -> String {
+        let bytes = &bytes[..self.size()];
+        format_value(bytes, self.watch_type(), self == Interpretation::Pointer)
+    }
+
+    /// The bytes of a value typed by the user. Integers can be `0x` hex, pointers are always hex.
+    pub fn parse(self, s: &str) -> Result<Vec<u8>> {
+        let s = s.trim();
+        if self == Interpretation::F32 {
+            let value: f32 = s.parse().map_err(|_| anyhow!("'{}' is not a number", s))?;
+            return Ok(value.to_le_bytes().to_vec());
+        }
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let parsed = match digits
+            .strip_prefix("0x")
+            .or_else(|| digits.strip_prefix("0X"))
+        {
+            Some(hex) => i128::from_str_radix(hex, 16),
+            None if self == Interpretation::Pointer => i128::from_str_radix(digits, 16),
+            None => digits.parse::<i128>(),
+        };
+        let value = parsed.map_err(|_| anyhow!("'{}' is not a number", s))?;
+        let value = if negative { -value } else { value };
+
+        //negative values of unsigned types wrap like in C
+        let bits = self.size() * 8;
+        let (min, max) = match self {
+            Interpretation::I32 => (i32::MIN as i128, i32::MAX as i128),
+            _ => (-(1i128 << (bits - 1)), (1i128 << bits) - 1),
+        };
+        if value < min || value > max {
+            return Err(anyhow!("{} doesn't fit in {}", s, self.name()));
+        }
+        Ok(value.to_le_bytes()[..self.size()].to_vec())
+    }
+}
+
+/// Read what is readable of `[address, address + size)`, None for the rest
+pub fn read_partial(memory: &dyn GameMemory, address: usize, size: usize) -> Vec<Option<u8>> {
+    let mut bytes = vec![None; size];
+    let end = address.saturating_add(size);
+    let mut at = address;
+    while at < end {
+        let region = memory
+            .query(at)
+            .ok()
+            .filter(|r| r.base.saturating_add(r.size) > at);
+        //unknown or free, try the next page
+        let region_end = match region {
+            Some(r) => r.base.saturating_add(r.size),
+            None => (at & !(PAGE_SIZE - 1)).saturating_add(PAGE_SIZE),
+        };
+        let region_end = std::cmp::min(region_end, end);
+        if matches!(region, Some(r) if r.is_readable()) {
+            if let Ok(read) = memory.read_vec(at, region_end - at) {
+                for (i, b) in read.into_iter().enumerate() {
+                    bytes[at - address + i] = Some(b);
+                }
+            }
+        }
+        at = region_end;
+    }
+    bytes
+}
+
+/// Write anywhere in one committed region.
+/// Code goes through `patch_code`, other read only pages are unprotected for the write.
+pub fn write_protected(memory: &dyn GameMemory, address: usize, bytes: &[u8]) -> Result<()> {
+    let region = memory.query(address)?;
+    if !region.committed || !region.contains(address, bytes.len()) {
+        return Err(anyhow!(
+            "{:x}..{:x} is not in one committed region",
+            address,
+            address + bytes.len()
+        ));
+    }
+    if region.protection.is_executable() {
+        return memory.patch_code(&[(address, bytes)]);
+    }
+    if region.is_writable() {
+        return memory.write_bytes(address, bytes);
+    }
+    memory.patch_bytes(address, bytes)
+}
+
+/// Live bytes at a pointer path for the hex viewer, re-read every frame
+pub struct MemoryView {
+    pub path: PointerPath,
+    /// Added to the resolved address, to look around it
+    pub scroll: isize,
+    pub size: usize,
+    /// Offset of the selected byte in the view
+    pub selected: Option<usize>,
+    address: Option<usize>,
+    bytes: Vec<Option<u8>>,
+    changed_at: Vec<Option<Instant>>,
+}
+
+impl MemoryView {
+    pub fn new(path: PointerPath, size: usize) -> Self {
+        MemoryView {
+            path,
+            scroll: 0,
+            size,
+            selected: None,
+            address: None,
+            bytes: Vec::new(),
+            changed_at: Vec::new(),
+        }
+    }
+
+    /// Show another path from its start
+    pub fn set_path(&mut self, path: PointerPath) {
+        self.path = path;
+        self.scroll = 0;
+        self.selected = None;
+    }
+
+    /// Resolve the path and read, bytes that differ from the last refresh are marked changed.
+    /// The path can break at any time(e.g. leaving a battle), then nothing is shown.
+    pub fn refresh(
+        &mut self,
+        memory: &dyn GameMemory,
+        module_address: usize,
+    ) -> Result<(), PointerPathError> {
+        let address = match self.path.resolve(memory, module_address) {
+            Ok(a) => (a as isize).wrapping_add(self.scroll) as usize,
+            Err(e) => {
+                self.address = None;
+                self.bytes.clear();
+                return Err(e);
+            }
+        };
+        let bytes = read_partial(memory, address, self.size);
+        let now = Instant::now();
+        if self.address != Some(address) || self.bytes.len() != bytes.len() {
+            //another place, nothing to compare with
+            self.changed_at = vec![None; bytes.len()];
+        } else {
+            for (i, (old, new)) in self.bytes.iter().zip(&bytes).enumerate() {
+                if old.is_some() && new.is_some() && old != new {
+                    self.changed_at[i] = Some(now);
+                }
+            }
+        }
+        self.address = Some(address);
+        self.bytes = bytes;
+        Ok(())
+    }
+
+    /// Address of the first byte, None if the path didn't resolve
+    pub fn address(&self) -> Option<usize> {
+        self.address
+    }
+
+    pub fn bytes(&self) -> &[Option<u8>] {
+        &self.bytes
+    }
+
+    /// The byte at `offset` changed less than `CHANGE_HIGHLIGHT` ago
+    pub fn is_changed(&self, offset: usize) -> bool {
+        match self.changed_at.get(offset) {
+            Some(Some(at)) => at.elapsed() < CHANGE_HIGHLIGHT,
+            _ => false,
+        }
+    }
+
+    /// `size` bytes from the selection, if they are all in the view and readable
+    pub fn selection(&self, size: usize) -> Option<Vec<u8>> {
+        let start = self.selected?;
+        self.bytes
+            .get(start..start + size)?
+            .iter()
+            .copied()
+            .collect()
+    }
+
+    /// Address of the selected byte
+    pub fn selected_address(&self) -> Option<usize> {
+        Some(self.address? + self.selected?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{MockMemory, Protection};
+
+    const POINTER: usize = 0x40_0000;
+    const DATA: usize = 0x50_0000;
+
+    /// `[POINTER] + 0x10`, pointing at the 0x20 bytes of data
+    fn memory() -> MockMemory {
+        let memory = MockMemory::new();
+        memory.map(POINTER, 0x100, Protection::READWRITE);
+        memory.map(DATA, 0x40, Protection::READWRITE);
+        memory.write::<usize>(POINTER, DATA - 0x10).unwrap();
+        memory
+    }
+
+    fn view() -> MemoryView {
+        MemoryView::new(PointerPath::absolute(POINTER, &[0x10]), 0x20)
+    }
+
+    #[test]
+    fn parse_format_round_trip() {
+        use Interpretation::*;
+        let cases = [
+            (U8, "0"),
+            (U8, "255"),
+            (U16, "65535"),
+            (U32, "4294967295"),
+            (I32, "-2147483648"),
+            (I32, "2147483647"),
+            (I32, "-1"),
+            (F32, "1.5"),
+            (F32, "-0.25"),
+            (Pointer, "401000"),
+        ];
+        for (interpretation, s) in cases {
+            let bytes = interpretation.parse(s).unwrap();
+            assert_eq!(bytes.len(), interpretation.size(), "{}", s);
+            assert_eq!(interpretation.format(&bytes), s);
+        }
+        // hex, negative unsigned values wrap and whitespace is ignored
+        assert_eq!(U16.parse("0x1234").unwrap(), [0x34, 0x12]);
+        assert_eq!(U32.parse(" 0XFF ").unwrap(), [0xFF, 0, 0, 0]);
+        assert_eq!(U8.parse("-1").unwrap(), [0xFF]);
+        assert_eq!(I32.parse("-0x10").unwrap(), (-16i32).to_le_bytes());
+        assert_eq!(Pointer.parse("0x10").unwrap(), Pointer.parse("10").unwrap());
+        // extra bytes are not read
+        assert_eq!(U8.format(&[7, 1, 2, 3]), "7");
+    }
+
+    #[test]
+    fn parse_range_errors() {
+        use Interpretation::*;
+        let errors = [
+            (U8, "256"),
+            (U8, "-129"),
+            (U16, "0x10000"),
+            (U32, "4294967296"),
+            (I32, "2147483648"),
+            (I32, "-2147483649"),
+            (U8, ""),
+            (U8, "ff"),
+            (F32, "one"),
+            (Pointer, "xyz"),
+        ];
+        for (interpretation, s) in errors {
+            assert!(
+                interpretation.parse(s).is_err(),
+                "{} {}",
+                interpretation.name(),
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn read_partial_marks_unreadable_bytes() {
+        let memory = memory();
+        memory.write_bytes(DATA + 0x3e, &[1, 2]).unwrap();
+        let bytes = read_partial(&memory, DATA + 0x3e, 4);
+        assert_eq!(bytes, [Some(1), Some(2), None, None]);
+    }
+
+    #[test]
+    fn changed_bytes_are_highlighted() {
+        let memory = memory();
+        let mut view = view();
+        view.refresh(&memory, 0).unwrap();
+        assert_eq!(view.address(), Some(DATA));
+        assert_eq!(view.bytes().len(), 0x20);
+        assert!(!view.is_changed(4));
+
+        memory.write::<u8>(DATA + 4, 0xAA).unwrap();
+        view.refresh(&memory, 0).unwrap();
+        assert!(view.is_changed(4));
+        assert!(!view.is_changed(3));
+        assert!(!view.is_changed(5));
+        // stays highlighted while it keeps its value
+        view.refresh(&memory, 0).unwrap();
+        assert!(view.is_changed(4));
+        assert!(!view.is_changed(0x100));
+    }
+
+    #[test]
+    fn moving_the_view_drops_the_highlight() {
+        let memory = memory();
+        let mut view = view();
+        view.refresh(&memory, 0).unwrap();
+        memory.write::<u8>(DATA + 4, 0xAA).unwrap();
+        view.refresh(&memory, 0).unwrap();
+
+        // the path now points somewhere else, nothing to compare with
+        memory.write::<usize>(POINTER, DATA - 0xc).unwrap();
+        view.refresh(&memory, 0).unwrap();
+        assert_eq!(view.address(), Some(DATA + 4));
+        assert!((0..0x20).all(|i| !view.is_changed(i)));
+
+        view.scroll = 0x10;
+        view.refresh(&memory, 0).unwrap();
+        assert_eq!(view.address(), Some(DATA + 0x14));
+        assert!((0..0x20).all(|i| !view.is_changed(i)));
+    }
+
+    #[test]
+    fn broken_path_clears_the_view() {
+        let memory = memory();
+        let mut view = view();
+        view.refresh(&memory, 0).unwrap();
+        memory.write::<usize>(POINTER, 0).unwrap();
+        assert!(matches!(
+            view.refresh(&memory, 0),
+            Err(PointerPathError::Null { hop: 0, .. })
+        ));
+        assert_eq!(view.address(), None);
+        assert!(view.bytes().is_empty());
+        assert!(!view.is_changed(0));
+    }
+
+    #[test]
+    fn selection() {
+        let memory = memory();
+        memory.write::<u32>(DATA + 8, 0x1234_5678).unwrap();
+        let mut view = view();
+        assert_eq!(view.selection(1), None);
+        view.refresh(&memory, 0).unwrap();
+        view.selected = Some(8);
+        assert_eq!(view.selected_address(), Some(DATA + 8));
+        let selection = view.selection(4).unwrap();
+        assert_eq!(Interpretation::U32.format(&selection), "305419896");
+        // past the end of the view
+        view.selected = Some(0x1e);
+        assert_eq!(view.selection(4), None);
+
+        view.set_path(PointerPath::absolute(DATA, &[]));
+        assert_eq!(view.selected, None);
+        assert_eq!(view.scroll, 0);
+    }
+}
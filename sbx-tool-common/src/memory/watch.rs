@@ -0,0 +1,84 @@
+use super::{GameMemory, GameMemoryExt, PointerPath};
+use anyhow::Result;
+
+/// How a watched value is read and shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchType {
+    Int {
+        size: usize,
+        signed: bool,
+    },
+    Float,
+    Double,
+    /// ascii, up to `length` bytes
+    String {
+        length: usize,
+    },
+    Bytes {
+        length: usize,
+    },
+}
+
+impl WatchType {
+    pub fn size(&self) -> usize {
+        match self {
+            WatchType::Int { size, .. } => *size,
+            WatchType::Float => 4,
+            WatchType::Double => 8,
+            WatchType::String { length } | WatchType::Bytes { length } => *length,
+        }
+    }
+}
+
+/// A named value at a pointer path, shown by the memory viewer
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchEntry {
+    pub name: String,
+    pub path: PointerPath,
+    pub value_type: WatchType,
+    /// show integers in hex
+    pub hex: bool,
+}
+
+impl WatchEntry {
+    /// Resolve the path and format the value
+    pub fn read_display(&self, memory: &dyn GameMemory, module_address: usize) -> Result<String> {
+        let address = self
+            .path
+            .resolve_readable(memory, module_address, self.value_type.size())?;
+        let bytes = memory.read_vec(address, self.value_type.size())?;
+        Ok(format_value(&bytes, self.value_type, self.hex))
+    }
+}
+
+/// `bytes` has `value_type.size()` bytes
+pub fn format_value(bytes: &[u8], value_type: WatchType, hex: bool) -> String {
+    let mut le = [0u8; 8];
+    let n = std::cmp::min(bytes.len(), 8);
+    le[..n].copy_from_slice(&bytes[..n]);
+    match value_type {
+        WatchType::Int { size, signed } => {
+            let value = u64::from_le_bytes(le);
+            if hex {
+                return format!("{:X}", value);
+            }
+            if !signed {
+                return value.to_string();
+            }
+            //sign extend from `size` bytes
+            let shift = 64 - size * 8;
+            (((value << shift) as i64) >> shift).to_string()
+        }
+        WatchType::Float => f32::from_le_bytes([le[0], le[1], le[2], le[3]]).to_string(),
+        WatchType::Double => f64::from_le_bytes(le).to_string(),
+        WatchType::String { .. } => {
+            let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..len]).into_owned()
+        }
+        WatchType::Bytes { .. } => bytes
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
@@ -0,0 +1,476 @@
+//! Field descriptions of the reversed game structures, for the struct inspector.
+//! Structures are declared with `game_struct!`, so the description can't drift from the struct.
+use crate::battle::{
+    BattleContext, CharacterStatus, PlayerClass, PlayerSubParamExClass, PlayerSubParamStunClass,
+    UnkContext, UnkContextSub,
+};
+use crate::css::CSSContext;
+use crate::memory::{GameMemory, Interpretation, MemoryView, PointerPath};
+use std::collections::HashMap;
+
+/// How a field is read
+#[derive(Debug, Clone, Copy)]
+pub enum FieldKind {
+    Value(Interpretation),
+    /// Pointer to another described struct
+    Pointer(fn() -> &'static StructInfo),
+}
+
+/// Types a `game_struct!` field can have
+pub trait FieldType {
+    fn kind() -> FieldKind;
+}
+
+macro_rules! value_field_type {
+    ($($ty:ty => $interpretation:ident),* $(,)?) => {
+        $(impl FieldType for $ty {
+            fn kind() -> FieldKind {
+                FieldKind::Value(Interpretation::$interpretation)
+            }
+        })*
+    };
+}
+
+value_field_type! {
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    i32 => I32,
+    f32 => F32,
+    //unknown pointer sized fields, hex reads better
+    usize => Pointer,
+}
+
+impl<T: GameStruct> FieldType for *mut T {
+    fn kind() -> FieldKind {
+        FieldKind::Pointer(T::info)
+    }
+}
+
+impl<T: GameStruct> FieldType for *const T {
+    fn kind() -> FieldKind {
+        FieldKind::Pointer(T::info)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    pub kind: FieldKind,
+    /// Doc comment of the field
+    pub comment: String,
+}
+
+impl FieldInfo {
+    /// Reversed, not an `unk_*` placeholder
+    pub fn is_known(&self) -> bool {
+        !self.name.starts_with("unk_")
+    }
+
+    pub fn type_name(&self) -> String {
+        match self.kind {
+            FieldKind::Value(interpretation) => interpretation.name().to_owned(),
+            FieldKind::Pointer(target) => format!("{}*", target().name),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StructInfo {
+    pub name: &'static str,
+    pub size: usize,
+    pub fields: Vec<FieldInfo>,
+}
+
+/// A `#[repr(C)]` game structure with a field description
+pub trait GameStruct {
+    fn info() -> &'static StructInfo;
+}
+
+/// Doc comment lines to text
+pub fn doc_text(lines: &[&str]) -> String {
+    lines
+        .iter()
+        .map(|l| l.trim())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Declare a reversed structure and its `GameStruct` description.
+/// Fields take `///` comments, they show up in the inspector.
+///
+/// `#[offset(0x2c)]` on a field and `#[size(0x3c)]` on the struct are checked at compile time
+/// for the 32bit game with `assert_layout!`, a field added without fixing
+/// the padding fails the build. The checks lay the fields out like `#[repr(C)]`, which every
+/// game struct is.
+macro_rules! game_struct {
+    //`#[size(..)]` is ours, other attributes go to the struct
+    (@attrs [$($attrs:tt)*] [$($size:tt)*] #[size($s:literal)] $($rest:tt)*) => {
+        $crate::reflect::game_struct!(@attrs [$($attrs)*] [$s] $($rest)*);
+    };
+    (@attrs [$($attrs:tt)*] [$($size:tt)*] #[$meta:meta] $($rest:tt)*) => {
+        $crate::reflect::game_struct!(@attrs [$($attrs)* #[$meta]] [$($size)*] $($rest)*);
+    };
+    (
+        @attrs [$($attrs:tt)*] [$($size:literal)?]
+        $vis:vis struct $name:ident {
+            $(
+                $(#[doc = $doc:literal])*
+                $(#[offset($offset:literal)])?
+                $field_vis:vis $field:ident : $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $($attrs)*
+        $vis struct $name {
+            $(
+                $(#[doc = $doc])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        #[cfg(target_pointer_width = "32")]
+        $crate::assert_layout!(
+            $(#[size($size)])?
+            $name { $($(#[offset($offset)])? $field: $ty),* }
+        );
+
+        impl $crate::reflect::GameStruct for $name {
+            fn info() -> &'static $crate::reflect::StructInfo {
+                static INFO: once_cell::sync::OnceCell<$crate::reflect::StructInfo> =
+                    once_cell::sync::OnceCell::new();
+                INFO.get_or_init(|| $crate::reflect::StructInfo {
+                    name: stringify!($name),
+                    size: std::mem::size_of::<$name>(),
+                    fields: vec![$(
+                        $crate::reflect::FieldInfo {
+                            name: stringify!($field),
+                            offset: memoffset::offset_of!($name, $field),
+                            size: std::mem::size_of::<$ty>(),
+                            kind: <$ty as $crate::reflect::FieldType>::kind(),
+                            comment: $crate::reflect::doc_text(&[$($doc),*]),
+                        }
+                    ),*],
+                })
+            }
+        }
+    };
+    (@attrs $($rest:tt)*) => {
+        compile_error!("game_struct! expects one struct with named fields");
+    };
+    ($($input:tt)*) => {
+        $crate::reflect::game_struct!(@attrs [] [] $($input)*);
+    };
+}
+pub(crate) use game_struct;
+
+/// Every described structure
+pub fn game_structs() -> Vec<&'static StructInfo> {
+    vec![
+        BattleContext::info(),
+        PlayerClass::info(),
+        PlayerSubParamExClass::info(),
+        PlayerSubParamStunClass::info(),
+        UnkContext::info(),
+        UnkContextSub::info(),
+        CharacterStatus::info(),
+        CSSContext::info(),
+    ]
+}
+
+/// A field read from the game
+pub struct FieldValue {
+    pub info: &'static FieldInfo,
+    /// None if unreadable
+    pub bytes: Option<Vec<u8>>,
+    /// Changed lately, see `CHANGE_HIGHLIGHT`
+    pub changed: bool,
+}
+
+impl FieldValue {
+    pub fn display(&self) -> String {
+        let bytes = match &self.bytes {
+            Some(b) => b,
+            None => return "??".to_owned(),
+        };
+        match self.info.kind {
+            FieldKind::Value(interpretation) => interpretation.format(bytes),
+            FieldKind::Pointer(_) => Interpretation::Pointer.format(bytes),
+        }
+    }
+
+    /// Where a non null pointer field points
+    pub fn pointer(&self) -> Option<(usize, &'static StructInfo)> {
+        let target = match self.info.kind {
+            FieldKind::Pointer(target) => target,
+            FieldKind::Value(_) => return None,
+        };
+        let mut pointer = [0u8; std::mem::size_of::<usize>()];
+        pointer.copy_from_slice(self.bytes.as_ref()?);
+        match usize::from_le_bytes(pointer) {
+            0 => None,
+            address => Some((address, target())),
+        }
+    }
+}
+
+/// Live field values of described structures.
+/// Every struct shown keeps a `MemoryView`, so changes between frames are known.
+#[derive(Default)]
+pub struct StructInspector {
+    /// (address, struct name) -> (view, read since the last `end_frame`)
+    views: HashMap<(usize, &'static str), (MemoryView, bool)>,
+}
+
+impl StructInspector {
+    pub fn read(
+        &mut self,
+        memory: &dyn GameMemory,
+        info: &'static StructInfo,
+        address: usize,
+    ) -> Vec<FieldValue> {
+        let (view, used) = self.views.entry((address, info.name)).or_insert_with(|| {
+            (
+                MemoryView::new(PointerPath::absolute(address, &[]), info.size),
+                false,
+            )
+        });
+        *used = true;
+        //an absolute path without offsets always resolves
+        let _ = view.refresh(memory, 0);
+        info.fields
+            .iter()
+            .map(|field| {
+                let range = field.offset..field.offset + field.size;
+                FieldValue {
+                    info: field,
+                    bytes: view
+                        .bytes()
+                        .get(range.clone())
+                        .and_then(|b| b.iter().copied().collect()),
+                    changed: range.into_iter().any(|i| view.is_changed(i)),
+                }
+            })
+            .collect()
+    }
+
+    /// Forget the structs that weren't read since the last call
+    pub fn end_frame(&mut self) {
+        self.views.retain(|_, (_, used)| std::mem::take(used));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{GameMemoryExt, MockMemory, Protection};
+
+    /// Reversed fields and their offsets
+    type Offsets = &'static [(&'static str, usize)];
+
+    /// (struct, size, field count, offsets) in the 32bit game
+    const LAYOUTS: &[(&str, usize, usize, Offsets)] = &[
+        (
+            "BattleContext",
+            0x3c,
+            15,
+            &[
+                ("player1_ptr", 0x0),
+                ("player2_ptr", 0x4),
+                ("player1_rush_count", 0x8),
+                ("player2_rush_count", 0xc),
+                ("player1_sub_param_ptr", 0x2c),
+                ("player2_sub_param_ptr", 0x30),
+                ("player1_score", 0x34),
+                ("player2_score", 0x38),
+            ],
+        ),
+        (
+            "PlayerClass",
+            0x1c,
+            7,
+            &[
+                ("initial_hp", 0x8),
+                ("current_hp", 0xc),
+                ("graphic_hp_end", 0x10),
+                ("graphic_hp_start", 0x14),
+                ("graphic_hp_bar", 0x18),
+            ],
+        ),
+        (
+            "PlayerSubParamExClass",
+            0x18,
+            6,
+            &[
+                ("max_ex", 0x8),
+                ("current_ex", 0xc),
+                ("graphic_ex_start", 0x10),
+                ("graphic_ex_end", 0x14),
+            ],
+        ),
+        (
+            "PlayerSubParamStunClass",
+            0x10,
+            4,
+            &[("max_stunstar_count", 0x8), ("current_stunstar_count", 0xc)],
+        ),
+        ("UnkContext", 0xc, 3, &[("sub_context_ptr", 0x0)]),
+        ("UnkContextSub", 0x38, 14, &[("character_ptr", 0x30)]),
+        ("CharacterStatus", 0x30, 12, &[("position", 0x1c)]),
+        (
+            "CSSContext",
+            0x3c,
+            15,
+            &[
+                ("css_current_party", 0x0),
+                ("player_party_hp", 0x4),
+                ("cpu_party_hp", 0x8),
+                ("graphic_player_party_hp", 0xc),
+                ("graphic_cpu_party_hp", 0x10),
+                ("player_party_ex", 0x14),
+                ("cpu_party_ex", 0x18),
+                ("graphic_player_party_ex", 0x1c),
+                ("graphic_cpu_party_ex", 0x20),
+                ("player_party_cost", 0x24),
+                ("cpu_party_cost", 0x28),
+                ("graphic_player_party_cost", 0x2c),
+                ("graphic_cpu_party_cost", 0x30),
+                ("max_party_cost", 0x34),
+                ("max_party_member", 0x38),
+            ],
+        ),
+    ];
+
+    fn struct_info(name: &str) -> &'static StructInfo {
+        game_structs()
+            .into_iter()
+            .find(|s| s.name == name)
+            .unwrap_or_else(|| panic!("no struct {}", name))
+    }
+
+    fn field(info: &'static StructInfo, name: &str) -> &'static FieldInfo {
+        info.fields
+            .iter()
+            .find(|f| f.name == name)
+            .unwrap_or_else(|| panic!("no field {}::{}", info.name, name))
+    }
+
+    /// Pointer sized fields move on 64bit, structs without them are laid out the same
+    fn has_pointers(info: &StructInfo) -> bool {
+        info.fields.iter().any(|f| {
+            matches!(
+                f.kind,
+                FieldKind::Pointer(_) | FieldKind::Value(Interpretation::Pointer)
+            )
+        })
+    }
+
+    #[test]
+    fn layouts() {
+        assert_eq!(game_structs().len(), LAYOUTS.len());
+        for (name, size, count, fields) in LAYOUTS {
+            let info = struct_info(name);
+            assert_eq!(info.fields.len(), *count, "{}", name);
+            if cfg!(target_pointer_width = "64") && has_pointers(info) {
+                continue;
+            }
+            assert_eq!(info.size, *size, "{}", name);
+            for (field_name, offset) in *fields {
+                let field = field(info, field_name);
+                assert_eq!(field.offset, *offset, "{}::{}", name, field_name);
+                assert!(field.is_known());
+            }
+        }
+    }
+
+    #[test]
+    fn field_lists() {
+        let names: Vec<&str> = game_structs().iter().map(|s| s.name).collect();
+        for info in game_structs() {
+            let mut end = 0;
+            for field in &info.fields {
+                // declaration order, nothing overlaps and everything is inside the struct
+                assert!(field.offset >= end, "{}::{}", info.name, field.name);
+                end = field.offset + field.size;
+                let size = match field.kind {
+                    FieldKind::Value(interpretation) => interpretation.size(),
+                    FieldKind::Pointer(target) => {
+                        assert!(names.contains(&target().name));
+                        std::mem::size_of::<usize>()
+                    }
+                };
+                assert_eq!(field.size, size, "{}::{}", info.name, field.name);
+                let count = info.fields.iter().filter(|f| f.name == field.name).count();
+                assert_eq!(count, 1);
+            }
+            assert!(end <= info.size, "{}", info.name);
+            assert_eq!(names.iter().filter(|n| **n == info.name).count(), 1);
+        }
+    }
+
+    #[test]
+    fn field_kinds_and_comments() {
+        let context = struct_info("BattleContext");
+        assert_eq!(field(context, "player1_ptr").type_name(), "PlayerClass*");
+        assert_eq!(
+            field(context, "player2_sub_param_ptr").type_name(),
+            "PlayerSubParamExClass*"
+        );
+        assert_eq!(field(context, "player1_score").type_name(), "u32");
+        let unknown = field(context, "unk_10");
+        assert!(!unknown.is_known());
+        assert_eq!(unknown.type_name(), "pointer");
+
+        let ex = struct_info("PlayerSubParamExClass");
+        assert_eq!(field(ex, "current_ex").type_name(), "i32");
+
+        let status = struct_info("CharacterStatus");
+        assert_eq!(field(status, "position").comment, "left 0");
+        assert_eq!(field(status, "unk_0").comment, "");
+        let css = struct_info("CSSContext");
+        assert_eq!(
+            field(css, "css_current_party").comment,
+            "2 for player, 4 for cpu"
+        );
+    }
+
+    #[test]
+    fn inspector_reads_fields() {
+        const CONTEXT: usize = 0x1000;
+        const PLAYER: usize = 0x2000;
+        let memory = MockMemory::new();
+        memory.map(CONTEXT, 0x100, Protection::READWRITE);
+        let context = BattleContext::info();
+        let offset = |name| field(context, name).offset;
+        memory
+            .write(CONTEXT + offset("player1_ptr"), PLAYER)
+            .unwrap();
+        memory
+            .write(CONTEXT + offset("player1_score"), 3u32)
+            .unwrap();
+
+        let mut inspector = StructInspector::default();
+        let values = inspector.read(&memory, context, CONTEXT);
+        assert_eq!(values.len(), context.fields.len());
+        let value = |values: &[FieldValue], name| {
+            let i = context.fields.iter().position(|f| f.name == name).unwrap();
+            (values[i].display(), values[i].changed, values[i].pointer())
+        };
+        let (display, changed, _) = value(&values, "player1_score");
+        assert_eq!((display.as_str(), changed), ("3", false));
+        let (_, _, pointer) = value(&values, "player1_ptr");
+        let (address, target) = pointer.unwrap();
+        assert_eq!((address, target.name), (PLAYER, "PlayerClass"));
+        assert!(value(&values, "player2_ptr").2.is_none());
+
+        memory
+            .write(CONTEXT + offset("player1_score"), 4u32)
+            .unwrap();
+        let values = inspector.read(&memory, context, CONTEXT);
+        let (display, changed, _) = value(&values, "player1_score");
+        assert_eq!((display.as_str(), changed), ("4", true));
+        assert!(!value(&values, "player2_score").1);
+    }
+}
@@ -0,0 +1,262 @@
+//! Scenes of the ui main loop and what is applied in them.
+//! The tracker instance and the scene events live in sbx-tool-core next to the ui loop hook.
+use anyhow::{anyhow, Result};
+use phf::{phf_map, Map};
+use std::time::Instant;
+
+pub static UI_MAIN_LOOP_FIRST_SWITCH_CASE_NAME_MAP: Map<u32, &'static str> = phf_map! {
+    23u32 => "CONFIG",
+    24u32 => "SAVE_LOAD",
+    26u32 => "ESCAPE",
+    95u32 => "BRAVE_MODE_SSS",
+    96u32 => "BRAVE_MODE_CSS",
+    97u32 => "VS_CPU_MODE_CSS",
+    98u32 => "VS_CPU_MODE_SSS",
+    99u32 => "BATTLE",
+};
+
+/// UI main loop first switch case
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scene {
+    Config,
+    SaveLoad,
+    Escape,
+    BraveModeSSS,
+    BraveModeCSS,
+    VsCpuModeCSS,
+    VsCpuModeSSS,
+    Battle,
+    Unknown(u32),
+}
+
+impl Scene {
+    pub fn from_case(case: u32) -> Self {
+        match case {
+            23 => Scene::Config,
+            24 => Scene::SaveLoad,
+            26 => Scene::Escape,
+            95 => Scene::BraveModeSSS,
+            96 => Scene::BraveModeCSS,
+            97 => Scene::VsCpuModeCSS,
+            98 => Scene::VsCpuModeSSS,
+            99 => Scene::Battle,
+            _ => Scene::Unknown(case),
+        }
+    }
+
+    pub fn case(&self) -> u32 {
+        match self {
+            Scene::Config => 23,
+            Scene::SaveLoad => 24,
+            Scene::Escape => 26,
+            Scene::BraveModeSSS => 95,
+            Scene::BraveModeCSS => 96,
+            Scene::VsCpuModeCSS => 97,
+            Scene::VsCpuModeSSS => 98,
+            Scene::Battle => 99,
+            Scene::Unknown(case) => *case,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match UI_MAIN_LOOP_FIRST_SWITCH_CASE_NAME_MAP.get(&self.case()) {
+            Some(n) => n,
+            None => "Unknown",
+        }
+    }
+
+    /// Inverse of `name`, None for names not in the case map
+    pub fn from_name(name: &str) -> Option<Self> {
+        UI_MAIN_LOOP_FIRST_SWITCH_CASE_NAME_MAP
+            .entries()
+            .find(|(_, n)| **n == name)
+            .map(|(case, _)| Scene::from_case(*case))
+    }
+}
+
+/// Scenes a patch or hook is applied in, every scene if empty
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SceneGate {
+    scenes: Vec<Scene>,
+}
+
+impl SceneGate {
+    pub fn new(scenes: &[Scene]) -> Self {
+        SceneGate {
+            scenes: scenes.to_vec(),
+        }
+    }
+
+    /// From `Scene::name`s, e.g. the `scenes` of a patch definition
+    pub fn from_names(names: &[String]) -> Result<Self> {
+        let scenes = names
+            .iter()
+            .map(|n| Scene::from_name(n).ok_or_else(|| anyhow!("unknown scene '{}'", n)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SceneGate { scenes })
+    }
+
+    pub fn scenes(&self) -> &[Scene] {
+        &self.scenes
+    }
+
+    /// Ungated things are allowed before the first scene is known too
+    pub fn allows(&self, scene: Option<Scene>) -> bool {
+        self.scenes.is_empty() || matches!(scene, Some(s) if self.scenes.contains(&s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SceneEvent {
+    Enter(Scene),
+    Exit(Scene),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SceneTransition {
+    pub from: Option<Scene>,
+    pub to: Scene,
+    pub at: Instant,
+}
+
+/// Tracks the current scene and the last `HISTORY_LENGTH` transitions
+#[derive(Debug, Clone)]
+pub struct SceneTracker {
+    current: Option<Scene>,
+    history: Vec<SceneTransition>,
+}
+
+impl SceneTracker {
+    pub const HISTORY_LENGTH: usize = 64;
+
+    pub const fn new() -> Self {
+        SceneTracker {
+            current: None,
+            history: Vec::new(),
+        }
+    }
+
+    /// None until the ui loop hook ran once
+    pub fn current(&self) -> Option<Scene> {
+        self.current
+    }
+
+    /// Oldest first
+    pub fn history(&self) -> &[SceneTransition] {
+        &self.history
+    }
+
+    /// Feed the current scene, returns exit and enter events if it changed
+    pub fn update(&mut self, scene: Scene) -> Vec<SceneEvent> {
+        let prev = self.current;
+        if prev == Some(scene) {
+            return Vec::new();
+        }
+        self.current = Some(scene);
+
+        if self.history.len() == Self::HISTORY_LENGTH {
+            self.history.remove(0);
+        }
+        self.history.push(SceneTransition {
+            from: prev,
+            to: scene,
+            at: Instant::now(),
+        });
+
+        let mut events = Vec::with_capacity(2);
+        if let Some(prev) = prev {
+            events.push(SceneEvent::Exit(prev));
+        }
+        events.push(SceneEvent::Enter(scene));
+        events
+    }
+}
+
+impl Default for SceneTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for case in UI_MAIN_LOOP_FIRST_SWITCH_CASE_NAME_MAP.keys() {
+            let scene = Scene::from_case(*case);
+            assert_eq!(scene.case(), *case);
+            assert_eq!(Scene::from_name(scene.name()), Some(scene));
+        }
+        assert_eq!(Scene::from_case(5), Scene::Unknown(5));
+        assert_eq!(Scene::Unknown(5).name(), "Unknown");
+        assert_eq!(Scene::from_name("Unknown"), None);
+    }
+
+    #[test]
+    fn gate() {
+        let every = SceneGate::default();
+        assert!(every.allows(None));
+        assert!(every.allows(Some(Scene::Config)));
+
+        let gate = SceneGate::from_names(&["BATTLE".into(), "VS_CPU_MODE_CSS".into()]).unwrap();
+        assert_eq!(gate, SceneGate::new(&[Scene::Battle, Scene::VsCpuModeCSS]));
+        assert_eq!(gate.scenes(), [Scene::Battle, Scene::VsCpuModeCSS]);
+        assert!(gate.allows(Some(Scene::Battle)));
+        assert!(gate.allows(Some(Scene::VsCpuModeCSS)));
+        assert!(!gate.allows(Some(Scene::VsCpuModeSSS)));
+        assert!(!gate.allows(Some(Scene::Unknown(99))));
+        //gated things wait for the first scene
+        assert!(!gate.allows(None));
+
+        assert!(SceneGate::from_names(&["BATLE".into()]).is_err());
+    }
+
+    #[test]
+    fn tracker_update() {
+        let mut tracker = SceneTracker::new();
+        assert_eq!(tracker.current(), None);
+
+        assert_eq!(
+            tracker.update(Scene::VsCpuModeCSS),
+            [SceneEvent::Enter(Scene::VsCpuModeCSS)]
+        );
+        assert_eq!(tracker.update(Scene::VsCpuModeCSS), []);
+        assert_eq!(
+            tracker.update(Scene::Battle),
+            [
+                SceneEvent::Exit(Scene::VsCpuModeCSS),
+                SceneEvent::Enter(Scene::Battle)
+            ]
+        );
+        assert_eq!(tracker.current(), Some(Scene::Battle));
+
+        let history: Vec<_> = tracker.history().iter().map(|t| (t.from, t.to)).collect();
+        assert_eq!(
+            history,
+            [
+                (None, Scene::VsCpuModeCSS),
+                (Some(Scene::VsCpuModeCSS), Scene::Battle)
+            ]
+        );
+        assert!(tracker.history()[0].at <= tracker.history()[1].at);
+    }
+
+    #[test]
+    fn tracker_history_is_capped() {
+        let mut tracker = SceneTracker::new();
+        for case in 0..SceneTracker::HISTORY_LENGTH as u32 + 10 {
+            tracker.update(Scene::Unknown(case));
+        }
+        let history = tracker.history();
+        assert_eq!(history.len(), SceneTracker::HISTORY_LENGTH);
+        //oldest dropped first
+        assert_eq!(history[0].to, Scene::Unknown(10));
+        assert_eq!(history[0].from, Some(Scene::Unknown(9)));
+        assert_eq!(
+            history.last().unwrap().to,
+            Scene::Unknown(SceneTracker::HISTORY_LENGTH as u32 + 9)
+        );
+    }
+}
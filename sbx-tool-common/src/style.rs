@@ -0,0 +1,302 @@
+//! imgui style presets in TOML.
+//! A preset starts from one of imgui's color sets and overrides `imgui::Style` fields by name
+//! and colors by `StyleColor` name, so a hand written one only lists what it changes.
+//! Nothing here depends on imgui, the dll converts presets to and from `imgui::Style`.
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{event, Level};
+
+/// Themes shipped with the tool
+const EMBEDDED_STYLES: &str = include_str!("../data/styles.toml");
+
+/// File name of the user style presets, looked up next to the dll.
+/// Saved styles go there.
+pub const STYLE_FILE_NAME: &str = "sbx-styles.toml";
+
+pub const DEFAULT_STYLE: &str = "Dark";
+
+/// imgui's built-in color sets
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StyleBase {
+    #[default]
+    Dark,
+    Light,
+    Classic,
+}
+
+/// A style field, by its type in `imgui::Style`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StyleValue {
+    Bool(bool),
+    Float(f64),
+    Vec2([f64; 2]),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StylePreset {
+    pub name: String,
+    #[serde(default)]
+    pub base: StyleBase,
+    /// `io.font_global_scale`
+    #[serde(default = "default_font_scale")]
+    pub font_scale: f64,
+    /// `imgui::Style` field name -> value, e.g. `window_rounding = 4.0`
+    #[serde(default)]
+    pub vars: BTreeMap<String, StyleValue>,
+    /// `StyleColor` name -> RGBA, e.g. `WindowBg = [0.1, 0.1, 0.1, 0.9]`
+    #[serde(default)]
+    pub colors: BTreeMap<String, [f64; 4]>,
+}
+
+fn default_font_scale() -> f64 {
+    1.0
+}
+
+impl StylePreset {
+    /// imgui's style with the colors of `base`
+    pub fn new(name: &str, base: StyleBase) -> Self {
+        StylePreset {
+            name: name.to_owned(),
+            base,
+            font_scale: default_font_scale(),
+            vars: BTreeMap::new(),
+            colors: BTreeMap::new(),
+        }
+    }
+
+    /// This preset alone in the style file format, for sharing
+    pub fn to_toml(&self) -> Result<String> {
+        to_style_file(std::slice::from_ref(self))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct StyleFile {
+    #[serde(default, rename = "style")]
+    styles: Vec<StylePreset>,
+}
+
+/// Parse presets from a style file
+pub fn parse_style_file(s: &str) -> Result<Vec<StylePreset>> {
+    let file: StyleFile = toml::from_str(s)?;
+    Ok(file.styles)
+}
+
+pub fn to_style_file(styles: &[StylePreset]) -> Result<String> {
+    Ok(toml::to_string(&StyleFile {
+        styles: styles.to_vec(),
+    })?)
+}
+
+/// Named style presets, the built-in themes first
+pub struct StyleRegistry {
+    presets: Vec<StylePreset>,
+    /// Where `save` writes, None if there is no place for it
+    user_file: Option<PathBuf>,
+}
+
+impl StyleRegistry {
+    /// imgui's color sets, the embedded themes, then `STYLE_FILE_NAME` in `dir` if it exists.
+    /// A preset with the same name as an earlier one replaces it.
+    pub fn load(dir: Option<&Path>) -> Result<Self> {
+        let mut presets = vec![
+            StylePreset::new(DEFAULT_STYLE, StyleBase::Dark),
+            StylePreset::new("Light", StyleBase::Light),
+            StylePreset::new("Classic", StyleBase::Classic),
+        ];
+        merge_presets(&mut presets, parse_style_file(EMBEDDED_STYLES)?);
+
+        let user_file = dir.map(|d| d.join(STYLE_FILE_NAME));
+        if let Some(path) = &user_file {
+            if path.exists() {
+                event!(Level::INFO, "Loading styles from {:?}", path);
+                let s = std::fs::read_to_string(path)?;
+                merge_presets(
+                    &mut presets,
+                    parse_style_file(&s).map_err(|e| anyhow!("{:?}: {}", path, e))?,
+                );
+            }
+        }
+        Ok(StyleRegistry { presets, user_file })
+    }
+
+    /// Load again, for presets edited by hand
+    pub fn reload(&mut self) -> Result<()> {
+        let dir = self.user_file.as_deref().and_then(Path::parent);
+        *self = StyleRegistry::load(dir)?;
+        Ok(())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.presets.iter().map(|p| p.name.clone()).collect()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&StylePreset> {
+        self.presets.iter().find(|p| p.name == name)
+    }
+
+    /// Add or replace `preset` and write it to the user style file.
+    /// Other presets in the file are kept as they are.
+    pub fn save(&mut self, preset: StylePreset) -> Result<()> {
+        let path = self
+            .user_file
+            .as_ref()
+            .ok_or_else(|| anyhow!("no place for {}", STYLE_FILE_NAME))?;
+        let mut saved = Vec::new();
+        if path.exists() {
+            saved = parse_style_file(&std::fs::read_to_string(path)?)?;
+        }
+        merge_presets(&mut saved, vec![preset.clone()]);
+        std::fs::write(path, to_style_file(&saved)?)
+            .map_err(|e| anyhow!("failed to write {:?}: {}", path, e))?;
+        event!(Level::INFO, "Saved style '{}' to {:?}", preset.name, path);
+        self.insert(preset);
+        Ok(())
+    }
+
+    /// Add or replace `preset` until the tool is unloaded
+    pub fn insert(&mut self, preset: StylePreset) {
+        merge_presets(&mut self.presets, vec![preset]);
+    }
+}
+
+fn merge_presets(presets: &mut Vec<StylePreset>, new_presets: Vec<StylePreset>) {
+    for preset in new_presets {
+        match presets.iter_mut().find(|p| p.name == preset.name) {
+            Some(p) => *p = preset,
+            None => presets.push(preset),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUILT_IN: [&str; 7] = [
+        "Dark",
+        "Light",
+        "Classic",
+        "Rounded",
+        "Compact",
+        "Sakura",
+        "High Contrast",
+    ];
+
+    fn preset() -> StylePreset {
+        let mut preset = StylePreset::new("Mine", StyleBase::Light);
+        preset.font_scale = 1.25;
+        preset
+            .vars
+            .insert("window_rounding".to_owned(), StyleValue::Float(4.5));
+        preset
+            .vars
+            .insert("frame_padding".to_owned(), StyleValue::Vec2([4.0, 3.0]));
+        preset
+            .vars
+            .insert("anti_aliased_lines".to_owned(), StyleValue::Bool(false));
+        preset
+            .colors
+            .insert("WindowBg".to_owned(), [0.1, 0.2, 0.3, 0.9]);
+        preset
+    }
+
+    #[test]
+    fn defaults() {
+        let presets = parse_style_file("[[style]]\nname = \"Plain\"").unwrap();
+        assert_eq!(presets, [StylePreset::new("Plain", StyleBase::Dark)]);
+        assert_eq!(presets[0].font_scale, 1.0);
+        assert!(presets[0].vars.is_empty() && presets[0].colors.is_empty());
+        assert!(parse_style_file("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn value_types() {
+        let presets = parse_style_file(
+            "[[style]]\nname = \"x\"\nbase = \"classic\"\n\
+             [style.vars]\na = true\nb = 2.5\nc = [1.0, 2.0]\nd = 3",
+        )
+        .unwrap();
+        let preset = &presets[0];
+        assert_eq!(preset.base, StyleBase::Classic);
+        assert_eq!(preset.vars["a"], StyleValue::Bool(true));
+        assert_eq!(preset.vars["b"], StyleValue::Float(2.5));
+        assert_eq!(preset.vars["c"], StyleValue::Vec2([1.0, 2.0]));
+        // integers are read as floats
+        assert_eq!(preset.vars["d"], StyleValue::Float(3.0));
+    }
+
+    #[test]
+    fn invalid_presets() {
+        assert!(parse_style_file("[[style]]\nbase = \"dark\"").is_err());
+        assert!(parse_style_file("[[style]]\nname = \"x\"\nbase = \"blue\"").is_err());
+        assert!(parse_style_file("[[style]]\nname = \"x\"\n[style.vars]\na = \"big\"").is_err());
+        assert!(parse_style_file("[[style]]\nname = \"x\"\n[style.colors]\nText = [1.0]").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let toml = preset().to_toml().unwrap();
+        assert_eq!(parse_style_file(&toml).unwrap(), [preset()]);
+
+        let styles = vec![preset(), StylePreset::new("Other", StyleBase::Dark)];
+        let s = to_style_file(&styles).unwrap();
+        assert_eq!(parse_style_file(&s).unwrap(), styles);
+    }
+
+    #[test]
+    fn embedded_styles() {
+        let mut registry = StyleRegistry::load(None).unwrap();
+        assert_eq!(registry.names(), BUILT_IN);
+        assert_eq!(registry.get(DEFAULT_STYLE).unwrap().base, StyleBase::Dark);
+        assert_eq!(registry.get("Light").unwrap().base, StyleBase::Light);
+        assert_eq!(registry.get("Compact").unwrap().font_scale, 0.9);
+        assert!(registry.get("Mine").is_none());
+
+        // nowhere to save, but it can be used until unloaded
+        assert!(registry.save(preset()).is_err());
+        registry.insert(preset());
+        assert_eq!(registry.get("Mine"), Some(&preset()));
+    }
+
+    #[test]
+    fn user_styles() {
+        let dir = std::env::temp_dir().join(format!("sbx-tool-style-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(STYLE_FILE_NAME);
+
+        // same name replaces a built-in one in place, new ones go last
+        let rounded = StylePreset::new("Rounded", StyleBase::Light);
+        let other = StylePreset::new("Other", StyleBase::Classic);
+        std::fs::write(
+            &path,
+            to_style_file(&[rounded.clone(), other.clone()]).unwrap(),
+        )
+        .unwrap();
+        let mut registry = StyleRegistry::load(Some(&dir)).unwrap();
+        let mut names = BUILT_IN.to_vec();
+        names.push("Other");
+        assert_eq!(registry.names(), names);
+        assert_eq!(registry.get("Rounded"), Some(&rounded));
+
+        // saving keeps the other presets of the file
+        registry.save(preset()).unwrap();
+        assert_eq!(registry.get("Mine"), Some(&preset()));
+        let saved = parse_style_file(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved, [rounded, other, preset()]);
+
+        registry.reload().unwrap();
+        names.push("Mine");
+        assert_eq!(registry.names(), names);
+
+        std::fs::write(&path, "[[style]]\nname = 3").unwrap();
+        let error = StyleRegistry::load(Some(&dir)).err().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(error.to_string().contains(STYLE_FILE_NAME));
+    }
+}
@@ -0,0 +1,755 @@
+//! Cheat Engine `.CT` import.
+//! Plain address/pointer entries become `WatchEntry`s, Auto Assembler scripts that only
+//! write bytes into the game exe become `PatchDef`s.
+//! Nothing here touches the process, so it runs anywhere.
+use crate::memory::{PathBase, PointerPath, WatchEntry, WatchType};
+use crate::utility::patch_def::{OffsetRef, PatchDef, SiteDef};
+use anyhow::{anyhow, Result};
+use roxmltree::{Document, Node};
+
+/// File name of the cheat table imported along the patch file, looked up next to the dll.
+pub const CHEAT_TABLE_FILE_NAME: &str = "sbx-table.CT";
+
+/// Category of imported patches that aren't in a group
+pub const DEFAULT_CATEGORY: &str = "Cheat Table";
+
+/// Something in the table that was not imported, or not imported as is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportIssue {
+    /// Description of the cheat entry
+    pub entry: String,
+    /// Line in the Auto Assembler script
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for ImportIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "'{}' line {}: {}", self.entry, line, self.message),
+            None => write!(f, "'{}': {}", self.entry, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CheatTableImport {
+    pub watches: Vec<WatchEntry>,
+    pub patches: Vec<PatchDef>,
+    pub issues: Vec<ImportIssue>,
+}
+
+/// Watches of `CHEAT_TABLE_FILE_NAME` in `dir`, none if there is no table.
+/// Import issues are left to `PatchRegistry::load` in sbx-tool-core, which reads the same file.
+pub fn load_watches(dir: &std::path::Path) -> Result<Vec<WatchEntry>> {
+    let path = dir.join(CHEAT_TABLE_FILE_NAME);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Ok(import_cheat_table(&std::fs::read_to_string(&path)?)?.watches)
+}
+
+/// Import a `.CT` file.
+/// Any `*.exe` module is taken as the game module, offsets are RVAs in it.
+/// Errors only if the file is not a cheat table at all, the rest ends up in `issues`.
+pub fn import_cheat_table(xml: &str) -> Result<CheatTableImport> {
+    let document = Document::parse(xml)?;
+    let root = document.root_element();
+    if root.tag_name().name() != "CheatTable" {
+        return Err(anyhow!(
+            "not a cheat table (root element is <{}>)",
+            root.tag_name().name()
+        ));
+    }
+
+    let mut import = CheatTableImport::default();
+    for child in root.children().filter(Node::is_element) {
+        match child.tag_name().name() {
+            "CheatEntries" => import_entries(child, None, &mut import),
+            "LuaScript" if !text(child).trim().is_empty() => import.issues.push(ImportIssue {
+                entry: "<table>".to_owned(),
+                line: None,
+                message: "the table's Lua script is not supported".to_owned(),
+            }),
+            "UserdefinedSymbols" if child.children().any(|n| n.is_element()) => {
+                import.issues.push(ImportIssue {
+                    entry: "<table>".to_owned(),
+                    line: None,
+                    message: "user defined symbols are not supported".to_owned(),
+                })
+            }
+            _ => {}
+        }
+    }
+    Ok(import)
+}
+
+/// `<CheatEntries>`, recursing into groups.
+/// `group` is the description of the enclosing entry, used as the patch category.
+fn import_entries(entries: Node, group: Option<&str>, import: &mut CheatTableImport) {
+    for entry in entries.children().filter(|n| n.has_tag_name("CheatEntry")) {
+        let description = child_text(entry, "Description")
+            .map(|d| d.trim().trim_matches('"').to_owned())
+            .unwrap_or_default();
+        let description = match description.is_empty() {
+            true => format!("Entry {}", child_text(entry, "ID").unwrap_or("?").trim()),
+            false => description,
+        };
+        let is_group = is_set(entry, "GroupHeader");
+        let variable_type = child_text(entry, "VariableType").map(str::trim);
+
+        if variable_type == Some("Auto Assembler Script") {
+            let script = child_text(entry, "AssemblerScript").unwrap_or("");
+            import_script(&description, script, group, import);
+        } else if !is_group {
+            match watch_from_entry(entry, &description, variable_type) {
+                Ok(watch) => import.watches.push(watch),
+                Err(message) => import.issues.push(ImportIssue {
+                    entry: description.clone(),
+                    line: None,
+                    message,
+                }),
+            }
+        }
+
+        if let Some(children) = entry.children().find(|n| n.has_tag_name("CheatEntries")) {
+            //a plain group names its children, a script or value keeps the outer name
+            let group = match is_group {
+                true => Some(description.as_str()),
+                false => group,
+            };
+            import_entries(children, group, import);
+        }
+    }
+}
+
+fn watch_from_entry(
+    entry: Node,
+    description: &str,
+    variable_type: Option<&str>,
+) -> std::result::Result<WatchEntry, String> {
+    let signed = is_set(entry, "ShowAsSigned");
+    let length = |tag: &str| {
+        child_text(entry, tag)
+            .and_then(|l| l.trim().parse::<usize>().ok())
+            .ok_or_else(|| format!("<{}> is missing", tag))
+    };
+    let value_type = match variable_type {
+        Some("Byte") => WatchType::Int { size: 1, signed },
+        Some("2 Bytes") => WatchType::Int { size: 2, signed },
+        Some("4 Bytes") => WatchType::Int { size: 4, signed },
+        Some("8 Bytes") => WatchType::Int { size: 8, signed },
+        Some("Float") => WatchType::Float,
+        Some("Double") => WatchType::Double,
+        Some("String") => {
+            if is_set(entry, "Unicode") {
+                return Err("unicode strings are not supported".to_owned());
+            }
+            WatchType::String {
+                length: length("Length")?,
+            }
+        }
+        Some("Array of byte") => WatchType::Bytes {
+            length: length("ByteLength")?,
+        },
+        Some(other) => return Err(format!("value type '{}' is not supported", other)),
+        None => return Err("no value type".to_owned()),
+    };
+
+    let address = child_text(entry, "Address").ok_or("no address")?;
+    let base = parse_address(address)?;
+    //CE lists the offsets from the last one applied to the first
+    let mut offsets = match entry.children().find(|n| n.has_tag_name("Offsets")) {
+        Some(node) => node
+            .children()
+            .filter(|n| n.has_tag_name("Offset"))
+            .map(|n| parse_offset(text(n)))
+            .collect::<std::result::Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
+    offsets.reverse();
+
+    Ok(WatchEntry {
+        name: description.to_owned(),
+        path: PointerPath::new(base, &offsets),
+        value_type,
+        hex: is_set(entry, "ShowAsHex"),
+    })
+}
+
+/// `"game.exe"+1234`, `game.exe+1234` or an absolute `00401234`
+fn parse_address(address: &str) -> std::result::Result<PathBase, String> {
+    let address = address.trim();
+    if address.starts_with('+') || address.starts_with('-') {
+        return Err(format!(
+            "address '{}' is relative to the parent entry, not supported",
+            address
+        ));
+    }
+    let (module, rva) = match address.split_once('+') {
+        Some((module, rva)) => (module, Some(rva)),
+        None => (address, None),
+    };
+    let module = module.trim().trim_matches('"');
+    if module.to_ascii_lowercase().ends_with(".exe") {
+        let rva = match rva {
+            Some(rva) => parse_hex(rva).ok_or_else(|| format!("bad offset in '{}'", address))?,
+            None => 0,
+        };
+        return Ok(PathBase::Module(rva));
+    }
+    if rva.is_none() {
+        if let Some(absolute) = parse_hex(module) {
+            return Ok(PathBase::Absolute(absolute));
+        }
+    }
+    if module.contains('.') {
+        return Err(format!(
+            "address '{}' is not in the game exe, not supported",
+            address
+        ));
+    }
+    Err(format!(
+        "address '{}' uses a symbol or label, not supported",
+        address
+    ))
+}
+
+fn parse_offset(s: &str) -> std::result::Result<isize, String> {
+    let s = s.trim();
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, s),
+    };
+    let value = parse_hex(digits).ok_or_else(|| format!("bad offset '{}'", s))? as isize;
+    Ok(if negative { -value } else { value })
+}
+
+/// CE numbers are hex unless marked otherwise
+fn parse_hex(s: &str) -> Option<usize> {
+    let s = s.trim();
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+    if s.is_empty() {
+        return None;
+    }
+    usize::from_str_radix(s, 16).ok()
+}
+
+/// Bytes written at one address, consecutive `db`/`nop` lines append
+struct Write {
+    rva: usize,
+    bytes: Vec<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    None,
+    Enable,
+    Disable,
+}
+
+/// Import a script made only of `address:` lines, `db`, `nop` and `assert`.
+/// The whole script is skipped if anything else is in it.
+fn import_script(
+    description: &str,
+    script: &str,
+    group: Option<&str>,
+    import: &mut CheatTableImport,
+) {
+    //unsupported lines, any of them skips the script
+    let mut errors: Vec<(usize, String)> = Vec::new();
+    let mut section = Section::None;
+    let mut enable: Vec<Write> = Vec::new();
+    let mut disable: Vec<Write> = Vec::new();
+    let mut asserts: Vec<Write> = Vec::new();
+    let mut in_comment = false;
+    for (i, raw) in script.lines().enumerate() {
+        let line_number = i + 1;
+        let (line, directive) = strip_comments(raw, &mut in_comment);
+        if directive {
+            errors.push((
+                line_number,
+                "{$...} directives are not supported".to_owned(),
+            ));
+        }
+        let mut statement = line.trim();
+
+        //`address:`, maybe followed by a statement on the same line
+        if let Some((address, after)) = statement.split_once(':') {
+            if is_address_like(address) {
+                statement = after.trim();
+                match (section, parse_address(address)) {
+                    (Section::None, _) => errors.push((
+                        line_number,
+                        "code outside [ENABLE]/[DISABLE] is not supported".to_owned(),
+                    )),
+                    (Section::Enable, Ok(PathBase::Module(rva))) => enable.push(Write {
+                        rva,
+                        bytes: Vec::new(),
+                    }),
+                    (Section::Disable, Ok(PathBase::Module(rva))) => disable.push(Write {
+                        rva,
+                        bytes: Vec::new(),
+                    }),
+                    (_, Ok(PathBase::Absolute(_))) => errors.push((
+                        line_number,
+                        format!("absolute address '{}' is not supported", address.trim()),
+                    )),
+                    (_, Err(message)) => errors.push((line_number, message)),
+                }
+            }
+        }
+        if statement.is_empty() {
+            continue;
+        }
+
+        let lower = statement.to_ascii_lowercase();
+        if lower == "[enable]" {
+            section = Section::Enable;
+            continue;
+        }
+        if lower == "[disable]" {
+            section = Section::Disable;
+            continue;
+        }
+        if section == Section::None {
+            errors.push((
+                line_number,
+                "code outside [ENABLE]/[DISABLE] is not supported".to_owned(),
+            ));
+            continue;
+        }
+
+        let (keyword, arguments) = match statement.split_once(char::is_whitespace) {
+            Some((k, a)) => (k.to_ascii_lowercase(), a.trim()),
+            None => (lower.clone(), ""),
+        };
+        let bytes = match keyword.as_str() {
+            "db" => parse_db(arguments),
+            "nop" if arguments.is_empty() => Ok(vec![0x90]),
+            "nop" => parse_hex(arguments)
+                .map(|count| vec![0x90; count])
+                .ok_or_else(|| format!("bad nop count '{}'", arguments)),
+            _ if lower.starts_with("assert(") && lower.ends_with(')') => {
+                match parse_assert(&statement["assert(".len()..statement.len() - 1]) {
+                    Ok(write) => asserts.push(write),
+                    Err(message) => errors.push((line_number, message)),
+                }
+                continue;
+            }
+            _ => Err(format!(
+                "'{}' is not supported, only db/nop/assert",
+                statement
+            )),
+        };
+        let bytes = match bytes {
+            Ok(bytes) => bytes,
+            Err(message) => {
+                errors.push((line_number, message));
+                continue;
+            }
+        };
+        let writes = match section {
+            Section::Disable => &mut disable,
+            _ => &mut enable,
+        };
+        match writes.last_mut() {
+            Some(write) => write.bytes.extend(bytes),
+            None => errors.push((line_number, "bytes without an address".to_owned())),
+        }
+    }
+    if in_comment {
+        errors.push((script.lines().count(), "unterminated { comment".to_owned()));
+    }
+
+    let issue = |line: Option<usize>, message: String| ImportIssue {
+        entry: description.to_owned(),
+        line,
+        message,
+    };
+    if !errors.is_empty() {
+        let skipped = issue(
+            None,
+            "script skipped, it is not a plain byte patch".to_owned(),
+        );
+        import.issues.extend(
+            errors
+                .into_iter()
+                .map(|(line, message)| issue(Some(line), message))
+                .chain(std::iter::once(skipped)),
+        );
+        return;
+    }
+
+    let sites: Vec<SiteDef> = enable
+        .iter()
+        .filter(|w| !w.bytes.is_empty())
+        .map(|w| {
+            //what [DISABLE] restores is the original, an assert of the same length too
+            let expected = disable
+                .iter()
+                .chain(&asserts)
+                .find(|e| e.rva == w.rva && e.bytes.len() == w.bytes.len())
+                .map(|e| hex(&e.bytes));
+            SiteDef {
+                offset: OffsetRef::Rva(w.rva),
+                replacement: Some(hex(&w.bytes)),
+                asm: None,
+                expected,
+            }
+        })
+        .collect();
+    if sites.is_empty() {
+        import
+            .issues
+            .push(issue(None, "script skipped, it writes nothing".to_owned()));
+        return;
+    }
+    //imported anyway, disabling restores the backed up bytes
+    for d in &disable {
+        if !enable.iter().any(|e| e.rva == d.rva) {
+            import.issues.push(issue(
+                None,
+                format!(
+                    "[DISABLE] writes {:x} which [ENABLE] doesn't, ignored",
+                    d.rva
+                ),
+            ));
+        }
+    }
+
+    let mut name = description.to_owned();
+    let mut n = 2;
+    while import.patches.iter().any(|p| p.name == name) {
+        name = format!("{} ({})", description, n);
+        n += 1;
+    }
+    import.patches.push(PatchDef {
+        name,
+        description: "Imported from a cheat table".to_owned(),
+        category: group.unwrap_or(DEFAULT_CATEGORY).to_owned(),
+        requires: Vec::new(),
+        conflicts: Vec::new(),
+        scenes: Vec::new(),
+        sites,
+        caves: Vec::new(),
+    });
+}
+
+/// `game.exe+1234` or a label, not the start of a statement containing ':'
+fn is_address_like(s: &str) -> bool {
+    let s = s.trim();
+    !s.is_empty() && !s.contains(|c: char| c.is_whitespace() || "[](),".contains(c))
+}
+
+/// Remove `//` and `{ }` comments. Returns true as well if a `{$` directive was found.
+fn strip_comments(line: &str, in_comment: &mut bool) -> (String, bool) {
+    let mut out = String::with_capacity(line.len());
+    let mut directive = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if *in_comment {
+            if c == '}' {
+                *in_comment = false;
+            }
+            continue;
+        }
+        match c {
+            '{' => {
+                directive |= chars.peek() == Some(&'$');
+                *in_comment = true;
+            }
+            '/' if chars.peek() == Some(&'/') => break,
+            _ => out.push(c),
+        }
+    }
+    (out, directive)
+}
+
+fn parse_db(arguments: &str) -> std::result::Result<Vec<u8>, String> {
+    if arguments.is_empty() {
+        return Err("db without bytes".to_owned());
+    }
+    arguments
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|t| !t.is_empty())
+        .map(|t| match parse_hex(t) {
+            Some(b) if b <= 0xff => Ok(b as u8),
+            _ => Err(format!(
+                "'{}' in db is not a byte, only hex bytes are supported",
+                t
+            )),
+        })
+        .collect()
+}
+
+/// `address, bytes`
+fn parse_assert(arguments: &str) -> std::result::Result<Write, String> {
+    let (address, bytes) = arguments
+        .split_once(',')
+        .ok_or_else(|| format!("bad assert '{}'", arguments))?;
+    let rva = match parse_address(address)? {
+        PathBase::Module(rva) => rva,
+        PathBase::Absolute(_) => {
+            return Err(format!(
+                "absolute address '{}' is not supported",
+                address.trim()
+            ))
+        }
+    };
+    Ok(Write {
+        rva,
+        bytes: parse_db(bytes.trim())?,
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn child_text<'a>(node: Node<'a, '_>, tag: &str) -> Option<&'a str> {
+    node.children().find(|n| n.has_tag_name(tag)).map(text)
+}
+
+/// `<tag>1</tag>`
+fn is_set(node: Node, tag: &str) -> bool {
+    child_text(node, tag).map(str::trim) == Some("1")
+}
+
+fn text<'a>(node: Node<'a, '_>) -> &'a str {
+    node.text().unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPTS: &str = include_str!("../../tests/fixtures/scripts.CT");
+    const POINTERS: &str = include_str!("../../tests/fixtures/pointers.CT");
+    const GROUPS: &str = include_str!("../../tests/fixtures/groups.CT");
+    const MALFORMED: &str = include_str!("../../tests/fixtures/malformed.CT");
+
+    fn issues_of<'a>(import: &'a CheatTableImport, entry: &str) -> Vec<&'a ImportIssue> {
+        import.issues.iter().filter(|i| i.entry == entry).collect()
+    }
+
+    fn site(
+        offset: usize,
+        replacement: &str,
+        expected: Option<&str>,
+    ) -> (usize, String, Option<String>) {
+        (offset, replacement.to_owned(), expected.map(str::to_owned))
+    }
+
+    fn sites(patch: &PatchDef) -> Vec<(usize, String, Option<String>)> {
+        patch
+            .sites
+            .iter()
+            .map(|s| match s.offset {
+                OffsetRef::Rva(rva) => (rva, s.replacement.clone().unwrap(), s.expected.clone()),
+                OffsetRef::Name(ref name) => panic!("named offset {}", name),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn byte_patch_scripts() {
+        let import = import_cheat_table(SCRIPTS).unwrap();
+        assert!(import.watches.is_empty());
+        let names: Vec<&str> = import.patches.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["Ignore Party Cost", "Ignore Party Cost (2)"]);
+
+        let patch = &import.patches[0];
+        assert_eq!(patch.category, DEFAULT_CATEGORY);
+        assert!(patch.caves.is_empty() && patch.scenes.is_empty());
+        //[DISABLE] gives the expected bytes, comments and consecutive db lines are handled
+        assert_eq!(
+            sites(patch),
+            [
+                site(0x5b2a1, "90 90 90", Some("01 46 18")),
+                site(0x5b2b0, "EB 10", Some("74 10")),
+            ]
+        );
+
+        //a duplicate is renamed, its issues keep the description
+        let patch = &import.patches[1];
+        assert_eq!(sites(patch), [site(0x100, "90", Some("90"))]);
+        let issues = issues_of(&import, "Ignore Party Cost");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].message,
+            "[DISABLE] writes 200 which [ENABLE] doesn't, ignored"
+        );
+    }
+
+    #[test]
+    fn unsupported_scripts_are_skipped() {
+        let import = import_cheat_table(SCRIPTS).unwrap();
+
+        let issues = issues_of(&import, "Infinite HP");
+        let lines: Vec<Option<usize>> = issues.iter().map(|i| i.line).collect();
+        assert_eq!(lines, [Some(2), Some(4), Some(6), None]);
+        assert!(issues[0].message.contains("not supported"));
+        assert_eq!(
+            issues.last().unwrap().message,
+            "script skipped, it is not a plain byte patch"
+        );
+
+        let issues = issues_of(&import, "Lua");
+        assert!(issues
+            .iter()
+            .any(|i| i.line == Some(1) && i.message.contains("directives")));
+        assert!(issues
+            .iter()
+            .any(|i| i.line == Some(2) && i.message.contains("outside [ENABLE]")));
+        assert_eq!(issues.last().unwrap().line, None);
+
+        let issues = issues_of(&import, "Empty");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].message, "script skipped, it writes nothing");
+        assert_eq!(
+            issues[0].to_string(),
+            "'Empty': script skipped, it writes nothing"
+        );
+    }
+
+    #[test]
+    fn pointer_records() {
+        let import = import_cheat_table(POINTERS).unwrap();
+        assert!(import.patches.is_empty());
+        assert_eq!(
+            import.watches,
+            [
+                WatchEntry {
+                    name: "Player HP".to_owned(),
+                    //CE lists the last offset first
+                    path: PointerPath::module(0x438b28, &[0, 0xc]),
+                    value_type: WatchType::Int {
+                        size: 4,
+                        signed: false
+                    },
+                    hex: false,
+                },
+                WatchEntry {
+                    name: "Ex".to_owned(),
+                    path: PointerPath::module(0x4389a0, &[-8]),
+                    value_type: WatchType::Int {
+                        size: 2,
+                        signed: true
+                    },
+                    hex: true,
+                },
+                WatchEntry {
+                    name: "Timer".to_owned(),
+                    path: PointerPath::absolute(0x01a2_b3c4, &[]),
+                    value_type: WatchType::Float,
+                    hex: false,
+                },
+                WatchEntry {
+                    name: "Name".to_owned(),
+                    path: PointerPath::module(0x500, &[]),
+                    value_type: WatchType::String { length: 16 },
+                    hex: false,
+                },
+                WatchEntry {
+                    name: "Entry 14".to_owned(),
+                    path: PointerPath::module(0x600, &[]),
+                    value_type: WatchType::Bytes { length: 4 },
+                    hex: false,
+                },
+            ]
+        );
+
+        let messages: Vec<(&str, &str)> = import
+            .issues
+            .iter()
+            .map(|i| (i.entry.as_str(), i.message.as_str()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                ("Wide Name", "unicode strings are not supported"),
+                (
+                    "Child",
+                    "address '+10' is relative to the parent entry, not supported"
+                ),
+                (
+                    "D3D",
+                    "address 'd3d9.dll+1000' is not in the game exe, not supported"
+                ),
+                (
+                    "Symbol",
+                    "address 'playerbase' uses a symbol or label, not supported"
+                ),
+                ("Custom", "value type 'Custom' is not supported"),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_groups() {
+        let import = import_cheat_table(GROUPS).unwrap();
+        assert!(import.issues.is_empty(), "{:?}", import.issues);
+        let categories: Vec<(&str, &str)> = import
+            .patches
+            .iter()
+            .map(|p| (p.name.as_str(), p.category.as_str()))
+            .collect();
+        assert_eq!(
+            categories,
+            [
+                ("No Ex Cap", "Battle"),
+                //children of a script keep the enclosing group
+                ("Ex Cap Script Child", "Battle"),
+                ("No HP Cap", "HP"),
+                ("Loose", DEFAULT_CATEGORY),
+            ]
+        );
+        assert_eq!(
+            sites(&import.patches[0]),
+            [site(0x2000, "90 90", Some("7E 05"))]
+        );
+        assert_eq!(sites(&import.patches[2]), [site(0x3000, "90 90", None)]);
+
+        //group headers are not watches, values inside groups are
+        let watches: Vec<&str> = import.watches.iter().map(|w| w.name.as_str()).collect();
+        assert_eq!(watches, ["Player HP"]);
+    }
+
+    #[test]
+    fn malformed_xml() {
+        assert!(import_cheat_table(MALFORMED).is_err());
+        assert!(import_cheat_table("").is_err());
+        let error = import_cheat_table("<PatchFile/>").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "not a cheat table (root element is <PatchFile>)"
+        );
+    }
+
+    #[test]
+    fn table_level_issues() {
+        let xml = r#"<CheatTable>
+            <CheatEntries/>
+            <UserdefinedSymbols><SymbolEntry><Name>a</Name></SymbolEntry></UserdefinedSymbols>
+            <LuaScript>print(1)</LuaScript>
+        </CheatTable>"#;
+        let import = import_cheat_table(xml).unwrap();
+        let messages: Vec<&str> = import.issues.iter().map(|i| i.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "user defined symbols are not supported",
+                "the table's Lua script is not supported"
+            ]
+        );
+    }
+}
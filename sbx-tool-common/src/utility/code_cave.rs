@@ -0,0 +1,398 @@
+pub use super::patch_def::OriginalCode;
+use crate::memory::{GameMemory, GameMemoryExt, Protection};
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+
+/// `jmp rel32`
+const JMP_SIZE: usize = 5;
+/// VirtualAlloc reserves in 64k steps
+const ALLOCATION_GRANULARITY: usize = 0x10000;
+/// How far past `near` to look for a free spot before taking any address
+const NEAR_SEARCH_RANGE: usize = 0x1000_0000;
+
+/// Executable memory owned by a `CodeCave`, released on drop
+struct CaveAllocation {
+    memory: Arc<dyn GameMemory>,
+    address: usize,
+    size: usize,
+}
+
+impl Drop for CaveAllocation {
+    fn drop(&mut self) {
+        let _ = self.memory.free(self.address);
+    }
+}
+
+/// Code written to allocated memory, entered by a `jmp` at `address`.
+/// Layout: [original][code][original], then `jmp address + length`.
+/// The overwritten instructions are copied as is, so they must not be relative(jmp/call/jcc rel).
+pub struct CodeCave {
+    /// Site of the jump
+    pub address: usize,
+    /// Bytes overwritten at the site, whole instructions, at least 5
+    pub length: usize,
+    /// Original bytes at the site, None to patch whatever is there
+    pub expected: Option<Vec<u8>>,
+    pub code: Vec<u8>,
+    /// rel32 operands in `code`(offset of the operand, absolute target), e.g. a call into the game
+    pub rel32: Vec<(usize, usize)>,
+    pub original: OriginalCode,
+    allocation: Option<CaveAllocation>,
+}
+
+impl CodeCave {
+    pub fn new(address: usize, length: usize, code: &[u8]) -> Self {
+        CodeCave {
+            address,
+            length,
+            expected: None,
+            code: code.to_vec(),
+            rel32: Vec::new(),
+            original: OriginalCode::default(),
+            allocation: None,
+        }
+    }
+
+    pub fn with_expected(mut self, expected: Option<Vec<u8>>) -> Self {
+        self.expected = expected;
+        self
+    }
+
+    pub fn with_original(mut self, original: OriginalCode) -> Self {
+        self.original = original;
+        self
+    }
+
+    pub fn with_rel32(mut self, offset: usize, target: usize) -> Self {
+        self.rel32.push((offset, target));
+        self
+    }
+
+    /// Where the cave is, once `prepare` allocated it
+    pub fn cave_address(&self) -> Option<usize> {
+        self.allocation.as_ref().map(|a| a.address)
+    }
+
+    pub fn cave_size(&self) -> usize {
+        let original = match self.original {
+            OriginalCode::Omit => 0,
+            _ => self.length,
+        };
+        original + self.code.len() + JMP_SIZE
+    }
+
+    /// Definition errors, before anything is allocated
+    pub fn check(&self) -> Result<()> {
+        if self.length < JMP_SIZE {
+            return Err(anyhow!(
+                "cave at {:x} overwrites {} bytes, a jmp needs {}",
+                self.address,
+                self.length,
+                JMP_SIZE
+            ));
+        }
+        if let Some(expected) = &self.expected {
+            if expected.len() != self.length {
+                return Err(anyhow!(
+                    "cave at {:x} expects {} bytes but overwrites {}",
+                    self.address,
+                    expected.len(),
+                    self.length
+                ));
+            }
+        }
+        for (offset, _) in &self.rel32 {
+            if offset + 4 > self.code.len() {
+                return Err(anyhow!(
+                    "cave at {:x}: rel32 at +{:x} is past the code",
+                    self.address,
+                    offset
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Cave bytes for a cave at `cave_address`, `original` being the bytes at the site
+    pub fn build(&self, cave_address: usize, original: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.cave_size());
+        if self.original == OriginalCode::Before {
+            bytes.extend_from_slice(original);
+        }
+        let code_start = bytes.len();
+        bytes.extend_from_slice(&self.code);
+        for (offset, target) in &self.rel32 {
+            let at = code_start + offset;
+            let operand = rel32(cave_address + at + 4, *target);
+            bytes[at..at + 4].copy_from_slice(&operand);
+        }
+        if self.original == OriginalCode::After {
+            bytes.extend_from_slice(original);
+        }
+        let from = cave_address + bytes.len();
+        bytes.extend_from_slice(&jmp(from, self.address + self.length));
+        bytes
+    }
+
+    /// `jmp cave`, padded with nops to `length`
+    pub fn site_bytes(&self, cave_address: usize) -> Vec<u8> {
+        let mut bytes = jmp(self.address, cave_address).to_vec();
+        bytes.resize(self.length, 0x90);
+        bytes
+    }
+
+    /// Allocate the cave if needed and write it, nothing jumps there yet.
+    /// Returns the bytes for the site.
+    pub fn prepare(&mut self, memory: &Arc<dyn GameMemory>, original: &[u8]) -> Result<Vec<u8>> {
+        if self.allocation.is_none() {
+            let size = self.cave_size();
+            let address = allocate_near(memory.as_ref(), self.address, size)?;
+            self.allocation = Some(CaveAllocation {
+                memory: memory.clone(),
+                address,
+                size,
+            });
+        }
+        let allocation = self.allocation.as_ref().unwrap();
+        let bytes = self.build(allocation.address, original);
+        memory.protect(allocation.address, allocation.size, Protection::READWRITE)?;
+        memory.write_bytes(allocation.address, &bytes)?;
+        memory.protect(
+            allocation.address,
+            allocation.size,
+            Protection::EXECUTE_READ,
+        )?;
+        Ok(self.site_bytes(allocation.address))
+    }
+
+    /// Free the cave, nothing may jump there anymore
+    pub(crate) fn release(&mut self) {
+        self.allocation = None;
+    }
+
+    /// The cave as it is in memory
+    pub fn read_cave(&self, memory: &dyn GameMemory) -> Result<Option<(usize, Vec<u8>)>> {
+        match &self.allocation {
+            Some(a) => Ok(Some((a.address, memory.read_vec(a.address, a.size)?))),
+            None => Ok(None),
+        }
+    }
+}
+
+fn rel32(next_instruction: usize, target: usize) -> [u8; 4] {
+    (target.wrapping_sub(next_instruction) as u32).to_le_bytes()
+}
+
+fn jmp(from: usize, to: usize) -> [u8; JMP_SIZE] {
+    let operand = rel32(from + JMP_SIZE, to);
+    [0xE9, operand[0], operand[1], operand[2], operand[3]]
+}
+
+/// A jmp rel32 from `from` reaches `to`
+fn reachable(from: usize, to: usize) -> bool {
+    //32bit addresses wrap around
+    if std::mem::size_of::<usize>() == 4 {
+        return true;
+    }
+    //i128 since a 64bit address doesn't fit i64
+    let distance = to as i128 - (from as i128 + JMP_SIZE as i128);
+    distance >= i32::MIN as i128 && distance <= i32::MAX as i128
+}
+
+/// Executable memory a jmp at `near` can reach, the first free spot after it if there is one
+fn allocate_near(memory: &dyn GameMemory, near: usize, size: usize) -> Result<usize> {
+    let mut candidate = align_up(near);
+    while candidate < near.saturating_add(NEAR_SEARCH_RANGE) {
+        //a region that can't be queried is skipped
+        let next = match memory.query(candidate) {
+            Ok(region) => {
+                if !region.committed {
+                    let allocated =
+                        memory.allocate(Some(candidate), size, Protection::EXECUTE_READ);
+                    if let Ok(address) = allocated {
+                        return Ok(address);
+                    }
+                }
+                align_up(region.base.saturating_add(region.size.max(1)))
+            }
+            Err(_) => candidate,
+        };
+        candidate = match candidate.checked_add(ALLOCATION_GRANULARITY) {
+            Some(step) => std::cmp::max(next, step),
+            None => break, //end of the address space
+        };
+    }
+    let address = memory.allocate(None, size, Protection::EXECUTE_READ)?;
+    if !reachable(near, address) {
+        let _ = memory.free(address);
+        return Err(anyhow!(
+            "no memory for a code cave within jmp range of {:x}",
+            near
+        ));
+    }
+    Ok(address)
+}
+
+fn align_up(address: usize) -> usize {
+    address.saturating_add(ALLOCATION_GRANULARITY - 1) / ALLOCATION_GRANULARITY
+        * ALLOCATION_GRANULARITY
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{MockMemory, Region};
+
+    const SITE: usize = 0x40_1000;
+    const CAVE: usize = 0x50_0000;
+    /// mov eax,[ebp+8]; mov [ebp-4],eax
+    const ORIGINAL: [u8; 6] = [0x8B, 0x45, 0x08, 0x89, 0x45, 0xFC];
+    /// xor eax,eax
+    const CODE: [u8; 2] = [0x31, 0xC0];
+
+    /// `MockMemory` whose query fails at `broken`, refusing fixed addresses unless `fixed`
+    struct PickyMemory {
+        inner: MockMemory,
+        broken: Option<usize>,
+        fixed: bool,
+    }
+
+    impl GameMemory for PickyMemory {
+        fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<()> {
+            self.inner.read_bytes(address, buffer)
+        }
+
+        fn write_bytes(&self, address: usize, bytes: &[u8]) -> Result<()> {
+            self.inner.write_bytes(address, bytes)
+        }
+
+        fn query(&self, address: usize) -> Result<Region> {
+            if self.broken == Some(address) {
+                return Err(anyhow!("query at {:x} failed", address));
+            }
+            self.inner.query(address)
+        }
+
+        fn protect(
+            &self,
+            address: usize,
+            size: usize,
+            protection: Protection,
+        ) -> Result<Protection> {
+            self.inner.protect(address, size, protection)
+        }
+
+        fn allocate(
+            &self,
+            address: Option<usize>,
+            size: usize,
+            protection: Protection,
+        ) -> Result<usize> {
+            if address.is_some() && !self.fixed {
+                return Err(anyhow!("no fixed allocations"));
+            }
+            self.inner.allocate(address, size, protection)
+        }
+
+        fn free(&self, address: usize) -> Result<()> {
+            self.inner.free(address)
+        }
+    }
+
+    fn code_memory() -> MockMemory {
+        let memory = MockMemory::new();
+        memory.map(0x40_0000, 0x2000, Protection::EXECUTE_READ);
+        memory
+    }
+
+    #[test]
+    fn build_layouts() {
+        let cave = CodeCave::new(SITE, ORIGINAL.len(), &CODE);
+        let mut expected = CODE.to_vec();
+        expected.extend_from_slice(&ORIGINAL);
+        // jmp 0x401006 from 0x500008
+        expected.extend_from_slice(&[0xE9, 0xF9, 0x0F, 0xF0, 0xFF]);
+        assert_eq!(cave.cave_size(), 13);
+        assert_eq!(cave.build(CAVE, &ORIGINAL), expected);
+
+        let cave = cave.with_original(OriginalCode::Before);
+        let mut expected = ORIGINAL.to_vec();
+        expected.extend_from_slice(&CODE);
+        expected.extend_from_slice(&[0xE9, 0xF9, 0x0F, 0xF0, 0xFF]);
+        assert_eq!(cave.cave_size(), 13);
+        assert_eq!(cave.build(CAVE, &ORIGINAL), expected);
+
+        // the jmp back comes right after the code
+        let cave = cave.with_original(OriginalCode::Omit);
+        assert_eq!(cave.cave_size(), 7);
+        assert_eq!(
+            cave.build(CAVE, &ORIGINAL),
+            [0x31, 0xC0, 0xE9, 0xFF, 0x0F, 0xF0, 0xFF]
+        );
+    }
+
+    #[test]
+    fn build_rel32() {
+        // call 0x402000 after the original code
+        let cave = CodeCave::new(SITE, ORIGINAL.len(), &[0xE8, 0, 0, 0, 0])
+            .with_original(OriginalCode::Before)
+            .with_rel32(1, 0x40_2000);
+        let bytes = cave.build(CAVE, &ORIGINAL);
+        assert_eq!(bytes[6..11], [0xE8, 0xF5, 0x1F, 0xF0, 0xFF]);
+        assert_eq!(bytes.len(), cave.cave_size());
+    }
+
+    #[test]
+    fn site_bytes() {
+        let cave = CodeCave::new(SITE, ORIGINAL.len(), &CODE);
+        assert_eq!(cave.site_bytes(CAVE), [0xE9, 0xFB, 0xEF, 0x0F, 0x00, 0x90]);
+        let cave = CodeCave::new(SITE, 5, &CODE);
+        assert_eq!(cave.site_bytes(CAVE), [0xE9, 0xFB, 0xEF, 0x0F, 0x00]);
+    }
+
+    #[test]
+    fn check_errors() {
+        assert!(CodeCave::new(SITE, 6, &CODE).check().is_ok());
+        assert!(CodeCave::new(SITE, 4, &CODE).check().is_err());
+        let wrong_expected = CodeCave::new(SITE, 6, &CODE).with_expected(Some(vec![0x90; 5]));
+        assert!(wrong_expected.check().is_err());
+        let past_code = CodeCave::new(SITE, 6, &CODE).with_rel32(0, 0x40_2000);
+        assert!(past_code.check().is_err());
+    }
+
+    #[test]
+    fn allocate_near_takes_the_first_free_block() {
+        let memory = code_memory();
+        assert_eq!(allocate_near(&memory, SITE, 0x10).unwrap(), 0x41_0000);
+
+        // committed blocks are skipped
+        memory.map(0x42_0000, 0x2_0000, Protection::READWRITE);
+        assert_eq!(allocate_near(&memory, 0x41_8000, 0x10).unwrap(), 0x44_0000);
+    }
+
+    #[test]
+    fn allocate_near_skips_unqueryable_regions() {
+        let memory = PickyMemory {
+            inner: code_memory(),
+            broken: Some(0x41_0000),
+            fixed: true,
+        };
+        assert_eq!(allocate_near(&memory, SITE, 0x10).unwrap(), 0x42_0000);
+    }
+
+    #[test]
+    fn allocate_near_stops_at_the_end_of_the_address_space() {
+        let memory = PickyMemory {
+            inner: code_memory(),
+            broken: None,
+            fixed: false,
+        };
+        // falls back to anywhere, which only a 32bit jmp reaches from up there
+        let result = allocate_near(&memory, usize::MAX - 0x100, 0x10);
+        assert_eq!(result.is_ok(), std::mem::size_of::<usize>() == 4);
+        if result.is_err() {
+            // the unreachable allocation was freed again
+            assert!(!memory.query(0x41_0000).unwrap().committed);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use crate::memory::{GameMemory, GameMemoryExt};
+use crate::utility::mempatch::MemPatch;
+use anyhow::{anyhow, Result};
+use iced_x86::{Decoder, DecoderError, DecoderOptions, Formatter, Instruction, IntelFormatter};
+
+/// The game is 32bit
+const BITNESS: u32 = 32;
+/// x86 instructions are at most 15 bytes
+const MAX_INSTRUCTION_SIZE: usize = 15;
+/// How far back decoding starts to find instruction boundaries
+const SYNC_WINDOW: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    /// Intel syntax, `(bad)` for bytes that don't decode
+    pub text: String,
+    pub invalid: bool,
+}
+
+impl Line {
+    pub fn end(&self) -> usize {
+        self.address + self.bytes.len()
+    }
+
+    /// Shares a byte with `[address, address + size)`
+    pub fn overlaps(&self, address: usize, size: usize) -> bool {
+        self.address < address + size && address < self.end()
+    }
+}
+
+/// Decode `bytes` placed at `address`, stops before an instruction cut off by the end
+pub fn disassemble(bytes: &[u8], address: usize) -> Vec<Line> {
+    let mut decoder = Decoder::with_ip(BITNESS, bytes, address as u64, DecoderOptions::NONE);
+    let mut formatter = IntelFormatter::new();
+    formatter
+        .options_mut()
+        .set_space_after_operand_separator(true);
+    let mut instruction = Instruction::default();
+    let mut lines = Vec::new();
+    while decoder.can_decode() {
+        let offset = decoder.position();
+        decoder.decode_out(&mut instruction);
+        if decoder.last_error() == DecoderError::NoMoreBytes {
+            break;
+        }
+        let invalid = instruction.is_invalid();
+        let mut text = String::new();
+        if invalid {
+            text.push_str("(bad)");
+        } else {
+            formatter.format(&instruction, &mut text);
+        }
+        lines.push(Line {
+            address: address + offset,
+            bytes: bytes[offset..offset + instruction.len()].to_vec(),
+            text,
+            invalid,
+        });
+    }
+    lines
+}
+
+/// Read `[address - before, address + after)`, cut down to what is readable.
+/// Returns the start of what was read.
+fn read_around(
+    memory: &dyn GameMemory,
+    address: usize,
+    before: usize,
+    after: usize,
+) -> Result<(usize, Vec<u8>)> {
+    let region = memory.query(address)?;
+    if !region.is_readable() {
+        return Err(anyhow!("{:x} is not readable", address));
+    }
+    //the region before may be readable too, but one region is enough for a view
+    let start = address.saturating_sub(before).max(region.base);
+    let end = address.saturating_add(after).min(region.base + region.size);
+    Ok((start, memory.read_vec(start, end - start)?))
+}
+
+/// Whether decoding `bytes` from the start hits `target` exactly, None on a bad instruction
+fn lands_on(bytes: &[u8], target: usize) -> Option<bool> {
+    let mut decoder = Decoder::new(BITNESS, bytes, DecoderOptions::NONE);
+    let mut instruction = Instruction::default();
+    let mut position = 0;
+    while position < target {
+        decoder.decode_out(&mut instruction);
+        if instruction.is_invalid() {
+            return None;
+        }
+        position += instruction.len();
+    }
+    Some(position == target)
+}
+
+/// Of the decodes starting in `bytes[..target]`, (those landing on `target`, those that don't)
+fn boundary_votes(bytes: &[u8], target: usize) -> (usize, usize) {
+    let mut landed = 0;
+    let mut missed = 0;
+    for start in 0..target {
+        match lands_on(&bytes[start..], target - start) {
+            Some(true) => landed += 1,
+            Some(false) => missed += 1,
+            None => {}
+        }
+    }
+    (landed, missed)
+}
+
+/// Whether an instruction starts at `address`, by decoding from every byte before it.
+/// x86 decoding resynchronizes quickly, so most starts agree.
+pub fn is_instruction_boundary(memory: &dyn GameMemory, address: usize) -> Result<bool> {
+    let (start, bytes) = read_around(memory, address, SYNC_WINDOW, MAX_INSTRUCTION_SIZE)?;
+    let target = address - start;
+    match disassemble(&bytes[target..], address).first() {
+        Some(line) if !line.invalid => {}
+        _ => return Ok(false),
+    }
+    let (landed, missed) = boundary_votes(&bytes, target);
+    Ok(landed >= missed)
+}
+
+/// Error if a hook at `address` would split an instruction
+pub fn check_hook_site(memory: &dyn GameMemory, address: usize) -> Result<()> {
+    if !is_instruction_boundary(memory, address)? {
+        return Err(anyhow!(
+            "hook site {:x} is not at the start of an instruction (wrong offset for this game version?)",
+            address
+        ));
+    }
+    Ok(())
+}
+
+/// `count` instructions from `address` and the ones up to `before` bytes before it.
+/// The lines before start at the first byte decoding lands on `address` from.
+pub fn disassemble_around(
+    memory: &dyn GameMemory,
+    address: usize,
+    before: usize,
+    count: usize,
+) -> Result<Vec<Line>> {
+    let (start, bytes) = read_around(memory, address, before, count * MAX_INSTRUCTION_SIZE)?;
+    let target = address - start;
+    //the first start landing on the target gives the longest listing
+    let sync = (0..target)
+        .find(|s| lands_on(&bytes[*s..], target - s) == Some(true))
+        .unwrap_or(target);
+    let mut lines = disassemble(&bytes[sync..], start + sync);
+    let after = lines
+        .iter()
+        .position(|l| l.address >= address)
+        .unwrap_or(lines.len());
+    lines.truncate(after + count);
+    Ok(lines)
+}
+
+/// Bytes a `MemPatch` overwrites, before and after
+#[derive(Debug, Clone)]
+pub struct PatchCode {
+    pub address: usize,
+    pub original: Vec<Line>,
+    pub patched: Vec<Line>,
+    /// Code cave body, at its address once allocated or at 0
+    pub cave: Option<Vec<Line>>,
+}
+
+/// Original and patched instructions of every site and cave of `patch`
+pub fn patch_code(memory: &dyn GameMemory, patch: &MemPatch) -> Result<Vec<PatchCode>> {
+    let mut original = Vec::new();
+    for (i, (address, size)) in patch.targets().enumerate() {
+        original.push(match patch.original_bytes() {
+            Some(backups) => backups[i].clone(),
+            None => memory.read_vec(address, size)?,
+        });
+    }
+
+    let mut code = Vec::new();
+    for (site, original) in patch.sites().iter().zip(&original) {
+        code.push(PatchCode {
+            address: site.address,
+            original: disassemble(original, site.address),
+            patched: disassemble(&site.replacement, site.address),
+            cave: None,
+        });
+    }
+    for (cave, original) in patch.caves().iter().zip(&original[patch.sites().len()..]) {
+        let (patched, body) = match cave.read_cave(memory)? {
+            Some((cave_address, bytes)) => (
+                disassemble(&cave.site_bytes(cave_address), cave.address),
+                disassemble(&bytes, cave_address),
+            ),
+            //not allocated yet, the jumps out of it are off
+            None => (Vec::new(), disassemble(&cave.build(0, original), 0)),
+        };
+        code.push(PatchCode {
+            address: cave.address,
+            original: disassemble(original, cave.address),
+            patched,
+            cave: Some(body),
+        });
+    }
+    Ok(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{MockMemory, Protection};
+
+    const CODE: usize = 0x40_1000;
+    /// A small function, the call goes to 0x402000
+    const FUNCTION: [u8; 16] = [
+        0x55, // push ebp
+        0x8B, 0xEC, // mov ebp, esp
+        0x83, 0xEC, 0x08, // sub esp, 8
+        0x8B, 0x45, 0x08, // mov eax, [ebp+8]
+        0xE8, 0xF2, 0x0F, 0x00, 0x00, // call 0x402000
+        0xC9, // leave
+        0xC3, // ret
+    ];
+
+    #[test]
+    fn disassemble_function() {
+        let lines = disassemble(&FUNCTION, CODE);
+        let listing: Vec<(usize, usize, &str)> = lines
+            .iter()
+            .map(|l| (l.address - CODE, l.bytes.len(), l.text.as_str()))
+            .collect();
+        assert_eq!(
+            listing,
+            [
+                (0x0, 1, "push ebp"),
+                (0x1, 2, "mov ebp, esp"),
+                (0x3, 3, "sub esp, 8"),
+                (0x6, 3, "mov eax, [ebp+8]"),
+                (0x9, 5, "call 00402000h"),
+                (0xe, 1, "leave"),
+                (0xf, 1, "ret"),
+            ]
+        );
+        assert!(lines.iter().all(|l| !l.invalid));
+        assert_eq!(lines[4].bytes, FUNCTION[9..14]);
+    }
+
+    #[test]
+    fn bad_and_cut_off_bytes() {
+        // the call at the end is missing its operand
+        let lines = disassemble(&[0xFF, 0xFF, 0x55, 0xE8, 0x00], CODE);
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].invalid);
+        assert_eq!(lines[0].text, "(bad)");
+        assert_eq!(lines[0].bytes, [0xFF, 0xFF]);
+        assert_eq!(
+            (lines[1].address, lines[1].text.as_str()),
+            (CODE + 2, "push ebp")
+        );
+        assert!(disassemble(&[], CODE).is_empty());
+    }
+
+    #[test]
+    fn line_overlaps() {
+        let lines = disassemble(&FUNCTION, CODE);
+        let call = &lines[4];
+        assert_eq!(call.end(), CODE + 0xe);
+        assert!(call.overlaps(CODE + 0xd, 1));
+        assert!(call.overlaps(CODE, 0x10));
+        assert!(!call.overlaps(CODE + 0xe, 2));
+        assert!(!call.overlaps(CODE + 6, 3));
+    }
+
+    #[test]
+    fn instruction_boundaries() {
+        let memory = MockMemory::new();
+        memory.map_bytes(CODE, &FUNCTION, Protection::EXECUTE_READ);
+        for offset in [0x0, 0x1, 0x3, 0x6, 0x9, 0xe, 0xf] {
+            assert!(is_instruction_boundary(&memory, CODE + offset).unwrap());
+            assert!(check_hook_site(&memory, CODE + offset).is_ok());
+        }
+        // inside mov ebp, esp and the call operand
+        for offset in [0x2, 0xa] {
+            assert!(!is_instruction_boundary(&memory, CODE + offset).unwrap());
+            assert!(check_hook_site(&memory, CODE + offset).is_err());
+        }
+        assert!(is_instruction_boundary(&memory, 0x50_0000).is_err());
+    }
+
+    #[test]
+    fn disassemble_around_call() {
+        let memory = MockMemory::new();
+        memory.map_bytes(CODE, &FUNCTION, Protection::EXECUTE_READ);
+        let lines = disassemble_around(&memory, CODE + 9, 6, 2).unwrap();
+        let addresses: Vec<usize> = lines.iter().map(|l| l.address - CODE).collect();
+        assert_eq!(addresses, [0x3, 0x6, 0x9, 0xe]);
+
+        // the first byte landing on the target starts the listing, here the esp of mov ebp, esp
+        let lines = disassemble_around(&memory, CODE + 6, 4, 1).unwrap();
+        let listing: Vec<(usize, &str)> = lines
+            .iter()
+            .map(|l| (l.address - CODE, l.text.as_str()))
+            .collect();
+        assert_eq!(
+            listing,
+            [
+                (0x2, "in al, dx"),
+                (0x3, "sub esp, 8"),
+                (0x6, "mov eax, [ebp+8]")
+            ]
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use parking_lot::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+type Callback<E> = Arc<dyn Fn(&E) + Send + Sync>;
+
+/// Returned by `EventBus::subscribe`, pass it to `unsubscribe` to stop receiving events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+/// Synchronous publish/subscribe.
+/// Subscribers are called on the publishing thread (mostly the game thread from a hook), so keep them short.
+pub struct EventBus<E> {
+    next_id: AtomicU64,
+    subscribers: RwLock<Vec<(SubscriptionId, Callback<E>)>>,
+}
+
+impl<E> EventBus<E> {
+    pub fn new() -> Self {
+        EventBus {
+            next_id: AtomicU64::new(0),
+            subscribers: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn subscribe<F>(&self, callback: F) -> SubscriptionId
+    where
+        F: Fn(&E) + Send + Sync + 'static,
+    {
+        let id = SubscriptionId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.subscribers.write().push((id, Arc::new(callback)));
+        id
+    }
+
+    /// Returns false if the id was not subscribed
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut subscribers = self.subscribers.write();
+        let len = subscribers.len();
+        subscribers.retain(|(i, _)| *i != id);
+        subscribers.len() != len
+    }
+
+    pub fn publish(&self, event: &E) {
+        //clone the list so subscribers can (un)subscribe from the callback
+        let subscribers: Vec<Callback<E>> = self
+            .subscribers
+            .read()
+            .iter()
+            .map(|(_, c)| c.clone())
+            .collect();
+        for callback in subscribers {
+            callback(event);
+        }
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.read().len()
+    }
+}
+
+impl<E> Default for EventBus<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
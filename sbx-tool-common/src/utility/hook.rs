@@ -0,0 +1,631 @@
+use crate::scene::{Scene, SceneGate};
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{event, Level};
+
+/// Call counter for a hook function, call `hit` at the top of the hook
+pub struct HookStats {
+    call_count: AtomicU64,
+    last_hit_millis: AtomicU64, //since unix epoch, 0 = never
+}
+
+impl HookStats {
+    pub const fn new() -> Self {
+        HookStats {
+            call_count: AtomicU64::new(0),
+            last_hit_millis: AtomicU64::new(0),
+        }
+    }
+
+    pub fn hit(&self) {
+        self.call_count.fetch_add(1, Ordering::Relaxed);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        self.last_hit_millis.store(now, Ordering::Relaxed);
+    }
+
+    pub fn call_count(&self) -> u64 {
+        self.call_count.load(Ordering::Relaxed)
+    }
+
+    pub fn last_hit(&self) -> Option<SystemTime> {
+        match self.last_hit_millis.load(Ordering::Relaxed) {
+            0 => None,
+            millis => Some(UNIX_EPOCH + Duration::from_millis(millis)),
+        }
+    }
+}
+
+impl Default for HookStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+    Inline,
+    Detour,
+}
+
+/// Something that can be installed and removed at runtime
+pub trait HookControl: Send {
+    fn kind(&self) -> HookKind;
+    fn enable(&mut self) -> Result<()>;
+    fn disable(&mut self) -> Result<()>;
+    fn is_enabled(&self) -> bool;
+
+    /// Patched code address, if known
+    fn address(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Consumed by installing it and by removing it, like ilhook's `Hooker` and `HookPoint`
+pub trait OneShotHook {
+    type Installed: Send;
+    fn install(self) -> Result<Self::Installed>;
+    fn uninstall(installed: Self::Installed) -> Result<()>;
+}
+
+/// Inline hook, a new `OneShotHook` is made by `factory` on every enable
+pub struct InlineHook<H: OneShotHook> {
+    /// Where `factory`'s hooks hook
+    address: usize,
+    factory: Box<dyn Fn() -> Result<H> + Send>,
+    installed: Option<H::Installed>,
+}
+
+impl<H: OneShotHook> InlineHook<H> {
+    pub fn new<F>(address: usize, factory: F) -> Self
+    where
+        F: Fn() -> Result<H> + Send + 'static,
+    {
+        InlineHook {
+            address,
+            factory: Box::new(factory),
+            installed: None,
+        }
+    }
+}
+
+impl<H: OneShotHook> HookControl for InlineHook<H> {
+    fn kind(&self) -> HookKind {
+        HookKind::Inline
+    }
+
+    fn enable(&mut self) -> Result<()> {
+        if self.is_enabled() {
+            return Ok(());
+        }
+        self.installed = Some((self.factory)()?.install()?);
+        Ok(())
+    }
+
+    fn disable(&mut self) -> Result<()> {
+        if let Some(installed) = self.installed.take() {
+            H::uninstall(installed)?;
+        }
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.installed.is_some()
+    }
+
+    fn address(&self) -> Option<usize> {
+        Some(self.address)
+    }
+}
+
+/// For detours owned by someone else, e.g. winapi-mon's
+pub struct DetourFns<E, D, S> {
+    pub enable: E,
+    pub disable: D,
+    pub is_enabled: S,
+}
+
+impl<E, D, S> HookControl for DetourFns<E, D, S>
+where
+    E: FnMut() -> Result<()> + Send,
+    D: FnMut() -> Result<()> + Send,
+    S: Fn() -> bool + Send,
+{
+    fn kind(&self) -> HookKind {
+        HookKind::Detour
+    }
+
+    fn enable(&mut self) -> Result<()> {
+        (self.enable)()
+    }
+
+    fn disable(&mut self) -> Result<()> {
+        (self.disable)()
+    }
+
+    fn is_enabled(&self) -> bool {
+        (self.is_enabled)()
+    }
+}
+
+struct ManagedHook {
+    name: String,
+    control: Box<dyn HookControl>,
+    stats: Option<&'static HookStats>,
+    pinned: bool,
+    gate: SceneGate,
+    /// Switched on, installed if `gate` allows the scene
+    enabled: bool,
+}
+
+impl ManagedHook {
+    /// Install or remove to match `enabled` in `scene`
+    fn sync(&mut self, scene: Option<Scene>) -> Result<()> {
+        let install = self.enabled && self.gate.allows(scene);
+        if install == self.control.is_enabled() {
+            return Ok(());
+        }
+        if install {
+            self.control.enable()?;
+            event!(Level::INFO, "Hook '{}' installed in its scene", self.name);
+        } else {
+            self.control.disable()?;
+            event!(
+                Level::INFO,
+                "Hook '{}' removed outside its scene",
+                self.name
+            );
+        }
+        Ok(())
+    }
+}
+
+/// State of a hook for display
+#[derive(Debug, Clone)]
+pub struct HookInfo {
+    pub name: String,
+    pub kind: HookKind,
+    /// Switched on, installed or waiting for its scene
+    pub enabled: bool,
+    /// Installed in the game
+    pub installed: bool,
+    /// can not be toggled from the UI
+    pub pinned: bool,
+    pub scenes: Vec<Scene>,
+    /// Patched code address, for inline hooks
+    pub address: Option<usize>,
+    pub call_count: Option<u64>,
+    pub last_hit: Option<SystemTime>,
+}
+
+/// Named hooks, kept in registration order
+#[derive(Default)]
+pub struct HookManager {
+    hooks: Vec<ManagedHook>,
+    /// Last scene given to `set_scene`
+    scene: Option<Scene>,
+}
+
+impl HookManager {
+    pub fn new() -> Self {
+        HookManager {
+            hooks: Vec::new(),
+            scene: None,
+        }
+    }
+
+    /// Register a hook, it is left as it is (enabled or not)
+    pub fn add<C>(
+        &mut self,
+        name: &str,
+        control: C,
+        stats: Option<&'static HookStats>,
+    ) -> Result<()>
+    where
+        C: HookControl + 'static,
+    {
+        if self.find(name).is_some() {
+            return Err(anyhow!("hook '{}' is already registered", name));
+        }
+        self.hooks.push(ManagedHook {
+            name: name.to_owned(),
+            enabled: control.is_enabled(),
+            control: Box::new(control),
+            stats,
+            pinned: false,
+            gate: SceneGate::default(),
+        });
+        Ok(())
+    }
+
+    /// Register and enable
+    pub fn add_enabled<C>(
+        &mut self,
+        name: &str,
+        control: C,
+        stats: Option<&'static HookStats>,
+    ) -> Result<()>
+    where
+        C: HookControl + 'static,
+    {
+        self.add(name, control, stats)?;
+        self.enable(name)
+    }
+
+    /// Hooks the UI depends on, disabling them from the UI would lock us out
+    pub fn pin(&mut self, name: &str) -> Result<()> {
+        self.get_mut(name)?.pinned = true;
+        Ok(())
+    }
+
+    /// Only install the hook in `gate`'s scenes, e.g. detours that change one screen
+    pub fn gate(&mut self, name: &str, gate: SceneGate) -> Result<()> {
+        let scene = self.scene;
+        let hook = self.get_mut(name)?;
+        if hook.pinned {
+            return Err(anyhow!("hook '{}' is pinned", name));
+        }
+        hook.gate = gate;
+        hook.sync(scene)
+    }
+
+    /// Installed now if the current scene allows it
+    pub fn enable(&mut self, name: &str) -> Result<()> {
+        let scene = self.scene;
+        let hook = self.get_mut(name)?;
+        if hook.gate.allows(scene) {
+            hook.control.enable()?;
+            event!(Level::INFO, "Hook '{}' enabled", name);
+        } else {
+            event!(
+                Level::INFO,
+                "Hook '{}' enabled, waiting for its scene",
+                name
+            );
+        }
+        hook.enabled = true;
+        Ok(())
+    }
+
+    pub fn disable(&mut self, name: &str) -> Result<()> {
+        let hook = self.get_mut(name)?;
+        hook.control.disable()?;
+        hook.enabled = false;
+        event!(Level::INFO, "Hook '{}' disabled", name);
+        Ok(())
+    }
+
+    /// Install and remove the enabled hooks for `scene`, see `gate`.
+    /// Keeps going on errors and returns the first one.
+    pub fn set_scene(&mut self, scene: Option<Scene>) -> Result<()> {
+        self.scene = scene;
+        let mut result = Ok(());
+        for hook in &mut self.hooks {
+            if let Err(e) = hook.sync(scene) {
+                event!(Level::ERROR, "Hook '{}': {}", hook.name, e);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    pub fn switch(&mut self, name: &str, on_off: bool) -> Result<()> {
+        if on_off {
+            return self.enable(name);
+        }
+        self.disable(name)
+    }
+
+    pub fn is_enabled(&self, name: &str) -> Option<bool> {
+        self.find(name).map(|h| h.enabled)
+    }
+
+    /// Disable every hook in reverse registration order.
+    /// Keeps going on errors and returns the first one.
+    pub fn disable_all(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for hook in self.hooks.iter_mut().rev() {
+            hook.enabled = false;
+            if !hook.control.is_enabled() {
+                continue;
+            }
+            match hook.control.disable() {
+                Ok(()) => event!(Level::INFO, "Hook '{}' disabled", hook.name),
+                Err(e) => {
+                    event!(
+                        Level::ERROR,
+                        "Failed to disable hook '{}': {}",
+                        hook.name,
+                        e
+                    );
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    pub fn hooks(&self) -> Vec<HookInfo> {
+        self.hooks
+            .iter()
+            .map(|h| HookInfo {
+                name: h.name.clone(),
+                kind: h.control.kind(),
+                enabled: h.enabled,
+                installed: h.control.is_enabled(),
+                pinned: h.pinned,
+                scenes: h.gate.scenes().to_vec(),
+                address: h.control.address(),
+                call_count: h.stats.map(|s| s.call_count()),
+                last_hit: h.stats.and_then(|s| s.last_hit()),
+            })
+            .collect()
+    }
+
+    fn find(&self, name: &str) -> Option<&ManagedHook> {
+        self.hooks.iter().find(|h| h.name == name)
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut ManagedHook> {
+        self.hooks
+            .iter_mut()
+            .find(|h| h.name == name)
+            .ok_or_else(|| anyhow!("unknown hook '{}'", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parking_lot::Mutex;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    /// Installs and removes are logged to a list shared with the test
+    #[derive(Clone, Default)]
+    struct FakeHook {
+        name: &'static str,
+        installed: Arc<AtomicBool>,
+        fail: Arc<AtomicBool>,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl FakeHook {
+        fn new(name: &'static str, log: &Arc<Mutex<Vec<String>>>) -> Self {
+            FakeHook {
+                name,
+                log: log.clone(),
+                ..FakeHook::default()
+            }
+        }
+
+        fn set(&mut self, installed: bool) -> Result<()> {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(anyhow!("{} failed", self.name));
+            }
+            self.installed.store(installed, Ordering::SeqCst);
+            let action = if installed { "install" } else { "remove" };
+            self.log.lock().push(format!("{} {}", action, self.name));
+            Ok(())
+        }
+    }
+
+    impl HookControl for FakeHook {
+        fn kind(&self) -> HookKind {
+            HookKind::Detour
+        }
+
+        fn enable(&mut self) -> Result<()> {
+            self.set(true)
+        }
+
+        fn disable(&mut self) -> Result<()> {
+            self.set(false)
+        }
+
+        fn is_enabled(&self) -> bool {
+            self.installed.load(Ordering::SeqCst)
+        }
+    }
+
+    fn take_log(log: &Mutex<Vec<String>>) -> Vec<String> {
+        std::mem::take(&mut *log.lock())
+    }
+
+    #[test]
+    fn enable_and_disable() {
+        static STATS: HookStats = HookStats::new();
+        let log = Arc::default();
+        let a = FakeHook::new("a", &log);
+        let mut hooks = HookManager::new();
+        hooks.add("a", a.clone(), Some(&STATS)).unwrap();
+        hooks
+            .add_enabled("b", FakeHook::new("b", &log), None)
+            .unwrap();
+        assert!(hooks.add("a", FakeHook::new("a", &log), None).is_err());
+        assert_eq!(take_log(&log), ["install b"]);
+        assert_eq!(hooks.is_enabled("a"), Some(false));
+        assert_eq!(hooks.is_enabled("b"), Some(true));
+        assert_eq!(hooks.is_enabled("c"), None);
+
+        hooks.switch("a", true).unwrap();
+        assert!(a.is_enabled());
+        hooks.switch("a", false).unwrap();
+        assert!(!a.is_enabled());
+        assert_eq!(take_log(&log), ["install a", "remove a"]);
+        assert!(hooks.enable("c").is_err());
+
+        STATS.hit();
+        let info = hooks.hooks();
+        assert_eq!(info.len(), 2);
+        assert_eq!(info[0].name, "a");
+        assert_eq!(info[0].call_count, Some(1));
+        assert!(info[0].last_hit.is_some());
+        assert!(info[1].enabled && info[1].installed);
+        assert_eq!(info[1].call_count, None);
+    }
+
+    #[test]
+    fn disable_all_keeps_going() {
+        let log = Arc::default();
+        let mut hooks = HookManager::new();
+        let b = FakeHook::new("b", &log);
+        hooks
+            .add_enabled("a", FakeHook::new("a", &log), None)
+            .unwrap();
+        hooks.add_enabled("b", b.clone(), None).unwrap();
+        hooks
+            .add_enabled("c", FakeHook::new("c", &log), None)
+            .unwrap();
+        take_log(&log);
+
+        b.fail.store(true, Ordering::SeqCst);
+        assert!(hooks.disable_all().is_err());
+        //reverse registration order
+        assert_eq!(take_log(&log), ["remove c", "remove a"]);
+        assert!(hooks.hooks().iter().all(|h| !h.enabled));
+        assert!(b.is_enabled());
+    }
+
+    #[test]
+    fn scene_gating() {
+        let log = Arc::default();
+        let css = FakeHook::new("css", &log);
+        let mut hooks = HookManager::new();
+        hooks.add("css", css.clone(), None).unwrap();
+        hooks
+            .gate("css", SceneGate::new(&[Scene::VsCpuModeCSS]))
+            .unwrap();
+
+        //before the first scene the hook waits
+        hooks.enable("css").unwrap();
+        assert_eq!(hooks.is_enabled("css"), Some(true));
+        assert!(!css.is_enabled());
+
+        hooks.set_scene(Some(Scene::VsCpuModeCSS)).unwrap();
+        assert!(css.is_enabled());
+        hooks.set_scene(Some(Scene::Battle)).unwrap();
+        assert!(!css.is_enabled());
+        assert_eq!(hooks.is_enabled("css"), Some(true));
+
+        //switched off outside its scene, not installed when it comes back
+        hooks.disable("css").unwrap();
+        hooks.set_scene(Some(Scene::VsCpuModeCSS)).unwrap();
+        assert!(!css.is_enabled());
+        assert_eq!(take_log(&log), ["install css", "remove css", "remove css"]);
+
+        //changing the gate applies it right away
+        hooks.enable("css").unwrap();
+        hooks.gate("css", SceneGate::new(&[Scene::Battle])).unwrap();
+        assert!(!css.is_enabled());
+        assert_eq!(hooks.hooks()[0].scenes, [Scene::Battle]);
+
+        hooks
+            .add_enabled("ui", FakeHook::new("ui", &log), None)
+            .unwrap();
+        hooks.pin("ui").unwrap();
+        assert!(hooks.hooks()[1].pinned);
+        assert!(hooks.gate("ui", SceneGate::new(&[Scene::Battle])).is_err());
+    }
+
+    #[test]
+    fn set_scene_returns_the_first_error() {
+        let log = Arc::default();
+        let a = FakeHook::new("a", &log);
+        let mut hooks = HookManager::new();
+        hooks.add_enabled("a", a.clone(), None).unwrap();
+        hooks
+            .add_enabled("b", FakeHook::new("b", &log), None)
+            .unwrap();
+        for name in ["a", "b"] {
+            hooks.gate(name, SceneGate::new(&[Scene::Battle])).unwrap();
+        }
+        take_log(&log);
+
+        hooks.set_scene(Some(Scene::Battle)).unwrap();
+        a.fail.store(true, Ordering::SeqCst);
+        //b is still removed
+        assert!(hooks.set_scene(Some(Scene::Config)).is_err());
+        assert!(a.is_enabled());
+        assert_eq!(take_log(&log), ["install a", "install b", "remove b"]);
+    }
+
+    /// Hooks made by the factory, numbered in order
+    struct OneShot {
+        id: u32,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    struct Installed {
+        id: u32,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl OneShotHook for OneShot {
+        type Installed = Installed;
+
+        fn install(self) -> Result<Installed> {
+            self.log.lock().push(format!("install {}", self.id));
+            Ok(Installed {
+                id: self.id,
+                log: self.log,
+            })
+        }
+
+        fn uninstall(installed: Installed) -> Result<()> {
+            installed
+                .log
+                .lock()
+                .push(format!("remove {}", installed.id));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn inline_hooks_are_made_by_the_factory() {
+        let log: Arc<Mutex<Vec<String>>> = Arc::default();
+        let made = Arc::new(Mutex::new(0));
+        let fail = Arc::new(AtomicBool::new(false));
+        let hook = {
+            let (log, made, fail) = (log.clone(), made.clone(), fail.clone());
+            InlineHook::new(0x401000, move || {
+                if fail.load(Ordering::SeqCst) {
+                    return Err(anyhow!("bad site"));
+                }
+                let mut made = made.lock();
+                *made += 1;
+                Ok(OneShot {
+                    id: *made,
+                    log: log.clone(),
+                })
+            })
+        };
+        assert_eq!(hook.kind(), HookKind::Inline);
+        assert_eq!(hook.address(), Some(0x401000));
+
+        let mut hooks = HookManager::new();
+        hooks.add("loop", hook, None).unwrap();
+        assert_eq!(*made.lock(), 0);
+        hooks.enable("loop").unwrap();
+        hooks.enable("loop").unwrap();
+        hooks.disable("loop").unwrap();
+        hooks.disable("loop").unwrap();
+        hooks.enable("loop").unwrap();
+        assert_eq!(*made.lock(), 2);
+        assert_eq!(take_log(&log), ["install 1", "remove 1", "install 2"]);
+        assert_eq!(hooks.hooks()[0].address, Some(0x401000));
+
+        //a hook the factory can't make stays off
+        hooks.disable("loop").unwrap();
+        fail.store(true, Ordering::SeqCst);
+        assert!(hooks.enable("loop").is_err());
+        assert_eq!(hooks.is_enabled("loop"), Some(false));
+        assert!(!hooks.hooks()[0].installed);
+    }
+}
@@ -0,0 +1,493 @@
+use crate::memory::{GameMemory, GameMemoryExt};
+use crate::utility::code_cave::CodeCave;
+use anyhow::{anyhow, Result};
+use std::sync::Arc;
+use tracing::{event, Level};
+
+/// One write of a `MemPatch`
+#[derive(Debug, Clone)]
+pub struct PatchSite {
+    pub address: usize,
+    /// Bytes that have to be there before patching, None to patch whatever is there
+    pub expected: Option<Vec<u8>>,
+    pub replacement: Vec<u8>,
+}
+
+impl PatchSite {
+    pub fn new(address: usize, replacement: &[u8]) -> Self {
+        PatchSite {
+            address,
+            expected: None,
+            replacement: replacement.to_vec(),
+        }
+    }
+
+    pub fn with_expected(mut self, expected: Option<Vec<u8>>) -> Self {
+        self.expected = expected;
+        self
+    }
+
+    /// Compare what is in memory with the expected bytes
+    fn verify(&self, current: &[u8]) -> Result<()> {
+        if let Some(expected) = &self.expected {
+            if expected.len() != self.replacement.len() {
+                return Err(anyhow!(
+                    "patch at {:x} expects {} bytes but replaces {}",
+                    self.address,
+                    expected.len(),
+                    self.replacement.len()
+                ));
+            }
+        }
+        verify_bytes(
+            self.address,
+            current,
+            self.expected.as_deref(),
+            Some(&self.replacement),
+        )
+    }
+}
+
+/// `replacement` is what patching would write, if already known
+fn verify_bytes(
+    address: usize,
+    current: &[u8],
+    expected: Option<&[u8]>,
+    replacement: Option<&[u8]>,
+) -> Result<()> {
+    let expected = match expected {
+        Some(e) => e,
+        None => {
+            event!(
+                Level::WARN,
+                "No original bytes declared for the patch at {:x}, patching unverified",
+                address
+            );
+            return Ok(());
+        }
+    };
+    if current == expected {
+        return Ok(());
+    }
+    if Some(current) == replacement {
+        return Err(anyhow!(
+            "{:x} is already patched (by another tool?)",
+            address
+        ));
+    }
+    Err(anyhow!(
+        "unexpected bytes at {:x}: found {}, expected {} (wrong game version or patched by another tool?)",
+        address,
+        hex(current),
+        hex(expected)
+    ))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Simple Memory Patch struct
+/// Supports on/off
+pub struct MemPatch {
+    memory: Arc<dyn GameMemory>,
+    sites: Vec<PatchSite>,
+    caves: Vec<CodeCave>,
+    /// sites, then caves
+    original_bytes: Option<Vec<Vec<u8>>>,
+}
+
+impl MemPatch {
+    pub fn new(memory: Arc<dyn GameMemory>, sites: Vec<PatchSite>) -> Self {
+        MemPatch {
+            memory,
+            sites,
+            caves: Vec::new(),
+            original_bytes: None,
+        }
+    }
+
+    /// Jumps to code caves, enabled and disabled together with the sites
+    pub fn with_caves(mut self, caves: Vec<CodeCave>) -> Self {
+        self.caves = caves;
+        self
+    }
+
+    pub fn sites(&self) -> &[PatchSite] {
+        &self.sites
+    }
+
+    pub fn caves(&self) -> &[CodeCave] {
+        &self.caves
+    }
+
+    /// (address, size) of everything enabling overwrites in the game's code
+    pub fn targets(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.sites
+            .iter()
+            .map(|s| (s.address, s.replacement.len()))
+            .chain(self.caves.iter().map(|c| (c.address, c.length)))
+    }
+
+    pub fn enable(&mut self) -> Result<()> {
+        if self.is_enabled() {
+            //already enabled
+            return Ok(());
+        }
+        //verify and backup every site before writing anything
+        let mut backups = Vec::with_capacity(self.sites.len() + self.caves.len());
+        for (address, size) in self.targets() {
+            backups.push(self.memory.read_vec(address, size)?);
+        }
+        for (site, current) in self.sites.iter().zip(&backups) {
+            site.verify(current)?;
+        }
+        for (cave, current) in self.caves.iter().zip(&backups[self.sites.len()..]) {
+            cave.check()?;
+            let jump = cave.cave_address().map(|a| cave.site_bytes(a));
+            verify_bytes(
+                cave.address,
+                current,
+                cave.expected.as_deref(),
+                jump.as_deref(),
+            )?;
+        }
+        //caves are written before anything jumps to them
+        let cave_jumps = self
+            .caves
+            .iter_mut()
+            .zip(&backups[self.sites.len()..])
+            .map(|(cave, original)| cave.prepare(&self.memory, original))
+            .collect::<Result<Vec<_>>>();
+        let cave_jumps = match cave_jumps {
+            Ok(jumps) => jumps,
+            Err(e) => {
+                self.release_caves();
+                return Err(e);
+            }
+        };
+
+        let writes: Vec<(usize, &[u8])> = self
+            .sites
+            .iter()
+            .map(|s| (s.address, s.replacement.as_slice()))
+            .chain(
+                self.caves
+                    .iter()
+                    .zip(&cave_jumps)
+                    .map(|(c, jump)| (c.address, jump.as_slice())),
+            )
+            .collect();
+        if let Err(e) = self.memory.patch_code(&writes) {
+            //revert what is already written, the caves can go once nothing jumps to them
+            if self
+                .memory
+                .patch_code(&self.backup_writes(&backups))
+                .is_ok()
+            {
+                self.release_caves();
+            }
+            return Err(e);
+        }
+        self.original_bytes = Some(backups);
+        Ok(())
+    }
+
+    pub fn disable(&mut self) -> Result<()> {
+        let original_bytes = match &self.original_bytes {
+            Some(b) => b,
+            None => return Ok(()), //already disabled
+        };
+        //rewriting the caves as they are makes patch_code wait until no thread is inside one,
+        //so they can be rewritten or freed afterwards
+        let caves = self
+            .caves
+            .iter()
+            .map(|c| c.read_cave(self.memory.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        let mut writes = self.backup_writes(original_bytes);
+        writes.extend(
+            caves
+                .iter()
+                .flatten()
+                .map(|(address, bytes)| (*address, bytes.as_slice())),
+        );
+        if let Err(e) = self.memory.patch_code(&writes) {
+            event!(
+                Level::ERROR,
+                "Failed to revert a patch at {:x}: {}",
+                self.targets().next().map_or(0, |(address, _)| address),
+                e
+            );
+            return Err(e);
+        }
+        self.original_bytes = None;
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.original_bytes.is_some()
+    }
+
+    /// What enabling overwrote, in `targets` order
+    pub fn original_bytes(&self) -> Option<&[Vec<u8>]> {
+        self.original_bytes.as_deref()
+    }
+
+    pub fn switch(&mut self, on_off: bool) -> Result<()> {
+        if on_off {
+            return self.enable();
+        }
+        self.disable()
+    }
+
+    fn backup_writes<'a>(&self, backups: &'a [Vec<u8>]) -> Vec<(usize, &'a [u8])> {
+        self.targets()
+            .zip(backups)
+            .map(|((address, _), b)| (address, b.as_slice()))
+            .collect()
+    }
+
+    fn release_caves(&mut self) {
+        for cave in &mut self.caves {
+            cave.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{MockMemory, Protection, Region};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const CODE: usize = 0x40_0000;
+    const ORIGINAL: [u8; 8] = [0x55, 0x8B, 0xEC, 0x83, 0xEC, 0x08, 0x90, 0xC3];
+
+    /// `MockMemory` whose writes to `fail_at` fail, only the first one if `once`
+    struct FailingMemory {
+        inner: MockMemory,
+        fail_at: usize,
+        once: bool,
+        failed: AtomicBool,
+    }
+
+    impl FailingMemory {
+        fn new(fail_at: usize, once: bool) -> Self {
+            FailingMemory {
+                inner: code_memory(),
+                fail_at,
+                once,
+                failed: AtomicBool::new(false),
+            }
+        }
+    }
+
+    impl GameMemory for FailingMemory {
+        fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<()> {
+            self.inner.read_bytes(address, buffer)
+        }
+
+        fn write_bytes(&self, address: usize, bytes: &[u8]) -> Result<()> {
+            if address == self.fail_at && !(self.once && self.failed.swap(true, Ordering::SeqCst)) {
+                return Err(anyhow!("write to {:x} failed", address));
+            }
+            self.inner.write_bytes(address, bytes)
+        }
+
+        fn query(&self, address: usize) -> Result<Region> {
+            self.inner.query(address)
+        }
+
+        fn protect(
+            &self,
+            address: usize,
+            size: usize,
+            protection: Protection,
+        ) -> Result<Protection> {
+            self.inner.protect(address, size, protection)
+        }
+
+        fn allocate(
+            &self,
+            address: Option<usize>,
+            size: usize,
+            protection: Protection,
+        ) -> Result<usize> {
+            self.inner.allocate(address, size, protection)
+        }
+
+        fn free(&self, address: usize) -> Result<()> {
+            self.inner.free(address)
+        }
+    }
+
+    fn code_memory() -> MockMemory {
+        let memory = MockMemory::new();
+        memory.map_bytes(CODE, &ORIGINAL, Protection::EXECUTE_READ);
+        memory
+    }
+
+    fn code(memory: &dyn GameMemory) -> Vec<u8> {
+        memory.read_vec(CODE, ORIGINAL.len()).unwrap()
+    }
+
+    #[test]
+    fn enable_and_disable() {
+        let memory = Arc::new(code_memory());
+        let mut patch = MemPatch::new(
+            memory.clone(),
+            vec![
+                PatchSite::new(CODE, &[0xC3]).with_expected(Some(vec![0x55])),
+                PatchSite::new(CODE + 3, &[0x90, 0x90, 0x90]),
+            ],
+        );
+        assert_eq!(
+            patch.targets().collect::<Vec<_>>(),
+            vec![(CODE, 1), (CODE + 3, 3)]
+        );
+        assert!(!patch.is_enabled());
+        assert!(patch.original_bytes().is_none());
+
+        patch.enable().unwrap();
+        assert!(patch.is_enabled());
+        assert_eq!(
+            code(memory.as_ref()),
+            [0xC3, 0x8B, 0xEC, 0x90, 0x90, 0x90, 0x90, 0xC3]
+        );
+        assert_eq!(
+            patch.original_bytes().unwrap(),
+            &[vec![0x55], vec![0x83, 0xEC, 0x08]]
+        );
+        //the code stays executable and not writable
+        assert_eq!(
+            memory.query(CODE).unwrap().protection,
+            Protection::EXECUTE_READ
+        );
+        //enabling twice does not back up the patched bytes
+        patch.switch(true).unwrap();
+        patch.switch(false).unwrap();
+        assert!(!patch.is_enabled());
+        assert_eq!(code(memory.as_ref()), ORIGINAL);
+        patch.disable().unwrap();
+        assert_eq!(code(memory.as_ref()), ORIGINAL);
+    }
+
+    #[test]
+    fn mismatch_writes_nothing() {
+        let memory = Arc::new(code_memory());
+        let mut patch = MemPatch::new(
+            memory.clone(),
+            vec![
+                PatchSite::new(CODE, &[0xC3]),
+                PatchSite::new(CODE + 1, &[0x90]).with_expected(Some(vec![0x00])),
+            ],
+        );
+        let err = patch.enable().unwrap_err();
+        assert!(err.to_string().contains("unexpected bytes"), "{}", err);
+        assert!(!patch.is_enabled());
+        assert_eq!(code(memory.as_ref()), ORIGINAL);
+
+        //an unreadable site fails before anything is written too
+        let mut patch = MemPatch::new(
+            memory.clone(),
+            vec![
+                PatchSite::new(CODE, &[0xC3]),
+                PatchSite::new(CODE + 0x1000, &[0x90]),
+            ],
+        );
+        assert!(patch.enable().is_err());
+        assert_eq!(code(memory.as_ref()), ORIGINAL);
+    }
+
+    #[test]
+    fn failed_write_is_rolled_back() {
+        let memory = Arc::new(FailingMemory::new(CODE + 4, false));
+        let mut patch = MemPatch::new(
+            memory.clone(),
+            vec![
+                PatchSite::new(CODE, &[0xC3]),
+                PatchSite::new(CODE + 4, &[0x90]),
+            ],
+        );
+        assert!(patch.enable().is_err());
+        assert!(!patch.is_enabled());
+        assert_eq!(code(memory.as_ref()), ORIGINAL);
+    }
+
+    #[test]
+    fn verify_site() {
+        let site = PatchSite::new(CODE, &[0x90, 0x90]).with_expected(Some(vec![0x55, 0x8B]));
+        site.verify(&[0x55, 0x8B]).unwrap();
+
+        let error = site.verify(&[0x90, 0x90]).unwrap_err().to_string();
+        assert!(error.contains("already patched"), "{}", error);
+
+        let error = site.verify(&[0x55, 0x00]).unwrap_err().to_string();
+        assert!(error.contains("found 55 00, expected 55 8B"), "{}", error);
+
+        //nothing declared, anything goes
+        PatchSite::new(CODE, &[0x90]).verify(&[0xCC]).unwrap();
+
+        let error = PatchSite::new(CODE, &[0x90])
+            .with_expected(Some(vec![0x55, 0x8B]))
+            .verify(&[0x55])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("expects 2 bytes but replaces 1"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn verify_raw_bytes() {
+        verify_bytes(CODE, &[1, 2], Some(&[1, 2]), None).unwrap();
+        verify_bytes(CODE, &[1, 2], None, Some(&[1, 2])).unwrap();
+        //the replacement is unknown, e.g. for a cave jump
+        let error = verify_bytes(CODE, &[3, 4], Some(&[1, 2]), None).unwrap_err();
+        assert!(error.to_string().contains("unexpected bytes at 400000"));
+        let error = verify_bytes(CODE, &[3, 4], Some(&[1, 2]), Some(&[3, 4])).unwrap_err();
+        assert!(error.to_string().contains("400000 is already patched"));
+    }
+
+    /// Nop `length` bytes at `address` through a cave
+    fn nop_cave(address: usize, length: usize) -> CodeCave {
+        CodeCave::new(address, length, &[0x90])
+    }
+
+    #[test]
+    fn caves_are_freed_when_enabling_fails() {
+        //the second cave is allocated in the second free 64k block after the code
+        let memory = Arc::new(FailingMemory::new(CODE + 0x2_0000, false));
+        let mut patch = MemPatch::new(memory.clone(), Vec::new())
+            .with_caves(vec![nop_cave(CODE, 5), nop_cave(CODE + 5, 5)]);
+        assert!(patch.enable().is_err());
+        assert!(patch.caves().iter().all(|c| c.cave_address().is_none()));
+        assert!(!memory.query(CODE + 0x1_0000).unwrap().committed);
+        assert_eq!(code(memory.as_ref()), ORIGINAL);
+
+        //the jump fails to go in, the caves go once it is reverted
+        let memory = Arc::new(FailingMemory::new(CODE, true));
+        let mut patch =
+            MemPatch::new(memory.clone(), Vec::new()).with_caves(vec![nop_cave(CODE, 5)]);
+        assert!(patch.enable().is_err());
+        assert!(patch.caves()[0].cave_address().is_none());
+        assert!(!memory.query(CODE + 0x1_0000).unwrap().committed);
+
+        //enabled fine afterwards
+        patch.enable().unwrap();
+        let cave = patch.caves()[0].cave_address().unwrap();
+        assert_eq!(
+            code(memory.as_ref())[..5],
+            patch.caves()[0].site_bytes(cave)[..]
+        );
+        patch.disable().unwrap();
+        assert_eq!(code(memory.as_ref()), ORIGINAL);
+    }
+}
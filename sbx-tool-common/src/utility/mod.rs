@@ -0,0 +1,9 @@
+pub mod cheat_table;
+pub mod code_cave;
+pub mod disasm;
+pub mod event_bus;
+pub mod hook;
+pub mod mempatch;
+pub mod patch_def;
+pub mod patch_registry;
+pub mod pe;
@@ -0,0 +1,205 @@
+//! Patch definitions as written in the patch file, resolved against the offsets by
+//! `PatchRegistry`.
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+struct PatchFile {
+    #[serde(default, rename = "patch")]
+    patches: Vec<PatchDef>,
+}
+
+/// A patch as written in the patch file
+#[derive(Debug, Clone, Deserialize)]
+pub struct PatchDef {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "default_category")]
+    pub category: String,
+    /// Patches enabled along with this one
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// Patches that can't be enabled together with this one
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+    /// Scene names the patch is applied in, every scene if empty.
+    /// An enabled patch is reverted outside of them and reapplied when one is entered.
+    #[serde(default)]
+    pub scenes: Vec<String>,
+    #[serde(default, rename = "site")]
+    pub sites: Vec<SiteDef>,
+    #[serde(default, rename = "cave")]
+    pub caves: Vec<CaveDef>,
+}
+
+fn default_category() -> String {
+    "Misc".to_owned()
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SiteDef {
+    pub offset: OffsetRef,
+    /// Hex bytes, or `asm`
+    #[serde(default)]
+    pub replacement: Option<String>,
+    /// Assembled for the site, padded with nops to the length of `expected`
+    #[serde(default)]
+    pub asm: Option<String>,
+    /// Hex bytes, defaults to the build's `original_bytes` of a named offset
+    #[serde(default)]
+    pub expected: Option<String>,
+}
+
+/// Jump from `offset` to custom code, see `CodeCave`
+#[derive(Debug, Clone, Deserialize)]
+pub struct CaveDef {
+    pub offset: OffsetRef,
+    /// Bytes the jump overwrites, whole instructions
+    pub length: usize,
+    /// Hex bytes, or `asm`
+    #[serde(default)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub asm: Option<String>,
+    #[serde(default)]
+    pub original: OriginalCode,
+    /// Hex bytes, defaults to the build's `original_bytes` of a named offset
+    #[serde(default)]
+    pub expected: Option<String>,
+    #[serde(default)]
+    pub rel32: Vec<Rel32Def>,
+}
+
+/// A rel32 operand in cave code pointing into the game
+#[derive(Debug, Clone, Deserialize)]
+pub struct Rel32Def {
+    /// Offset of the operand in `code`
+    pub at: usize,
+    pub target: OffsetRef,
+}
+
+/// An offset from the offset table by name, or a plain RVA
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OffsetRef {
+    Name(String),
+    Rva(usize),
+}
+
+/// Parse patch definitions from a patch file
+pub fn parse_patch_file(s: &str) -> Result<Vec<PatchDef>> {
+    let file: PatchFile = toml::from_str(s)?;
+    Ok(file.patches)
+}
+
+/// Where the instructions the jump overwrites run in the cave
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OriginalCode {
+    /// Before the cave code
+    Before,
+    /// After the cave code, e.g. to clamp a value the original stores
+    #[default]
+    After,
+    /// Not at all, the cave code replaces them
+    Omit,
+}
+
+/// Space separated hex bytes, e.g. `"8B 45 08"`
+pub fn parse_hex_bytes(hex: &str) -> Result<Vec<u8>> {
+    hex.split_whitespace()
+        .map(|b| u8::from_str_radix(b, 16).map_err(|_| anyhow!("invalid byte {:?}", b)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let patches = parse_patch_file(
+            r#"
+            [[patch]]
+            name = "Sites"
+            requires = ["Cave"]
+            scenes = ["BATTLE"]
+
+            [[patch.site]]
+            offset = "hpcap_1_offset"
+            replacement = "90 90"
+
+            [[patch.site]]
+            offset = 0x1234
+            asm = "nop"
+            expected = "8B 45"
+
+            [[patch]]
+            name = "Cave"
+            category = "Battle"
+
+            [[patch.cave]]
+            offset = "excap_1_offset"
+            length = 7
+            code = "E8 00 00 00 00"
+            original = "omit"
+
+            [[patch.cave.rel32]]
+            at = 1
+            target = 0xBF2A2
+            "#,
+        )
+        .unwrap();
+        assert_eq!(patches.len(), 2);
+
+        let sites = &patches[0];
+        assert_eq!(sites.category, "Misc");
+        assert_eq!(sites.description, "");
+        assert_eq!(sites.requires, ["Cave"]);
+        assert_eq!(sites.scenes, ["BATTLE"]);
+        assert!(sites.conflicts.is_empty() && sites.caves.is_empty());
+        assert!(matches!(&sites.sites[0].offset, OffsetRef::Name(n) if n == "hpcap_1_offset"));
+        assert_eq!(sites.sites[0].replacement.as_deref(), Some("90 90"));
+        assert!(matches!(sites.sites[1].offset, OffsetRef::Rva(0x1234)));
+        assert_eq!(sites.sites[1].asm.as_deref(), Some("nop"));
+        assert_eq!(sites.sites[1].expected.as_deref(), Some("8B 45"));
+
+        let cave = &patches[1].caves[0];
+        assert_eq!(cave.length, 7);
+        assert_eq!(cave.original, OriginalCode::Omit);
+        assert_eq!(cave.rel32.len(), 1);
+        assert_eq!(cave.rel32[0].at, 1);
+        assert!(matches!(cave.rel32[0].target, OffsetRef::Rva(0xbf2a2)));
+    }
+
+    #[test]
+    fn defaults_and_errors() {
+        assert!(parse_patch_file("").unwrap().is_empty());
+        let patches = parse_patch_file(
+            r#"
+            [[patch]]
+            name = "x"
+            [[patch.cave]]
+            offset = 1
+            length = 5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(patches[0].caves[0].original, OriginalCode::After);
+
+        //no name
+        assert!(parse_patch_file("[[patch]]\ndescription = \"x\"").is_err());
+        assert!(parse_patch_file(
+            "[[patch]]\nname = \"x\"\n[[patch.cave]]\noffset = 1\nlength = 5\noriginal = \"sometimes\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn hex_bytes() {
+        assert_eq!(parse_hex_bytes("8B 45 08").unwrap(), vec![0x8b, 0x45, 0x08]);
+        assert_eq!(parse_hex_bytes("").unwrap(), Vec::<u8>::new());
+        assert!(parse_hex_bytes("8B 4G").is_err());
+    }
+}
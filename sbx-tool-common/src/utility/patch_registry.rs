@@ -0,0 +1,759 @@
+use crate::asm::{self, Assembled};
+use crate::memory::GameMemory;
+use crate::scene::{Scene, SceneGate};
+use crate::utility::cheat_table::{self, CHEAT_TABLE_FILE_NAME};
+use crate::utility::code_cave::CodeCave;
+use crate::utility::mempatch::{MemPatch, PatchSite};
+use crate::utility::patch_def::parse_hex_bytes;
+pub use crate::utility::patch_def::{
+    parse_patch_file, CaveDef, OffsetRef, OriginalCode, PatchDef, Rel32Def, SiteDef,
+};
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{event, Level};
+
+/// Patches shipped with the tool.
+const EMBEDDED_PATCHES: &str = include_str!("../../data/patches.toml");
+
+/// Named offsets of the running game build, `sbx_offset::OffsetTable` in the dll
+pub trait OffsetLookup {
+    /// RVA of a named offset
+    fn offset(&self, name: &str) -> Option<usize>;
+    /// Bytes at a named offset in the unpatched game, if the build declares them
+    fn original_bytes(&self, name: &str) -> Result<Option<Vec<u8>>>;
+}
+
+/// File name of the user-supplied patch file, looked up next to the dll.
+pub const PATCH_FILE_NAME: &str = "sbx-patches.toml";
+
+/// Address of the game module in `asm`, e.g. `call sbx+0xBF2A2`.
+/// Offset names can be used as well.
+pub const GAME_MODULE_SYMBOL: &str = "sbx";
+
+/// State of a patch for display
+#[derive(Debug, Clone)]
+pub struct PatchInfo {
+    pub name: String,
+    pub description: String,
+    pub category: String,
+    /// Switched on by the user
+    pub enabled: bool,
+    /// Written to the game, false while an enabled patch waits for its scene
+    pub applied: bool,
+    pub requires: Vec<String>,
+    pub conflicts: Vec<String>,
+    pub scenes: Vec<Scene>,
+}
+
+struct RegisteredPatch {
+    def: PatchDef,
+    patch: MemPatch,
+    gate: SceneGate,
+    enabled: bool,
+}
+
+/// Named `MemPatch`es built from patch definitions, kept in definition order
+pub struct PatchRegistry {
+    patches: Vec<RegisteredPatch>,
+    /// Last scene given to `set_scene`
+    scene: Option<Scene>,
+}
+
+impl PatchRegistry {
+    /// Embedded patches, then `PATCH_FILE_NAME` and the byte patches of
+    /// `CHEAT_TABLE_FILE_NAME` in `override_dir` if they exist.
+    /// A patch with the same name as an earlier one replaces it.
+    pub fn load(
+        memory: Arc<dyn GameMemory>,
+        module_address: usize,
+        offsets: &dyn OffsetLookup,
+        override_dir: Option<&Path>,
+    ) -> Result<Self> {
+        let mut defs = parse_patch_file(EMBEDDED_PATCHES)?;
+        if let Some(dir) = override_dir {
+            let path = dir.join(PATCH_FILE_NAME);
+            if path.exists() {
+                event!(Level::INFO, "Loading patches from {:?}", path);
+                let s = std::fs::read_to_string(&path)?;
+                merge_defs(&mut defs, parse_patch_file(&s)?);
+            }
+            let path = dir.join(CHEAT_TABLE_FILE_NAME);
+            if path.exists() {
+                event!(Level::INFO, "Importing patches from {:?}", path);
+                let import = cheat_table::import_cheat_table(&std::fs::read_to_string(&path)?)?;
+                for issue in &import.issues {
+                    event!(Level::WARN, "{:?}: {}", path, issue);
+                }
+                merge_defs(&mut defs, import.patches);
+            }
+        }
+        Self::from_defs(memory, module_address, offsets, defs)
+    }
+
+    pub fn from_defs(
+        memory: Arc<dyn GameMemory>,
+        module_address: usize,
+        offsets: &dyn OffsetLookup,
+        defs: Vec<PatchDef>,
+    ) -> Result<Self> {
+        let mut patches: Vec<RegisteredPatch> = Vec::with_capacity(defs.len());
+        for def in defs {
+            if patches.iter().any(|p| p.def.name == def.name) {
+                return Err(anyhow!("patch '{}' is defined twice", def.name));
+            }
+            if def.sites.is_empty() && def.caves.is_empty() {
+                return Err(anyhow!("patch '{}' has no site or cave", def.name));
+            }
+            let sites = def
+                .sites
+                .iter()
+                .map(|s| resolve_site(s, module_address, offsets))
+                .collect::<Result<Vec<_>>>()
+                .map_err(|e| anyhow!("patch '{}': {}", def.name, e))?;
+            let caves = def
+                .caves
+                .iter()
+                .map(|c| resolve_cave(c, module_address, offsets))
+                .collect::<Result<Vec<_>>>()
+                .map_err(|e| anyhow!("patch '{}': {}", def.name, e))?;
+            let gate = SceneGate::from_names(&def.scenes)
+                .map_err(|e| anyhow!("patch '{}': {}", def.name, e))?;
+            patches.push(RegisteredPatch {
+                patch: MemPatch::new(memory.clone(), sites).with_caves(caves),
+                def,
+                gate,
+                enabled: false,
+            });
+        }
+
+        let registry = PatchRegistry {
+            patches,
+            scene: None,
+        };
+        registry.validate()?;
+        Ok(registry)
+    }
+
+    /// Referenced patches exist and `requires` has no cycle
+    fn validate(&self) -> Result<()> {
+        for p in &self.patches {
+            for name in p.def.requires.iter().chain(&p.def.conflicts) {
+                if self.find(name).is_none() {
+                    return Err(anyhow!(
+                        "patch '{}' refers to unknown patch '{}'",
+                        p.def.name,
+                        name
+                    ));
+                }
+            }
+            self.requirements_of(&p.def.name)?;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.patches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    /// In order of first appearance
+    pub fn categories(&self) -> Vec<String> {
+        let mut categories: Vec<String> = Vec::new();
+        for p in &self.patches {
+            if !categories.contains(&p.def.category) {
+                categories.push(p.def.category.clone());
+            }
+        }
+        categories
+    }
+
+    pub fn patches(&self) -> Vec<PatchInfo> {
+        self.patches
+            .iter()
+            .map(|p| PatchInfo {
+                name: p.def.name.clone(),
+                description: p.def.description.clone(),
+                category: p.def.category.clone(),
+                enabled: p.enabled,
+                applied: p.patch.is_enabled(),
+                requires: p.def.requires.clone(),
+                conflicts: p.def.conflicts.clone(),
+                scenes: p.gate.scenes().to_vec(),
+            })
+            .collect()
+    }
+
+    /// Switched on by the user, applied or waiting for its scene
+    pub fn is_enabled(&self, name: &str) -> Option<bool> {
+        self.find(name).map(|p| p.enabled)
+    }
+
+    pub fn mem_patch(&self, name: &str) -> Option<&MemPatch> {
+        self.find(name).map(|p| &p.patch)
+    }
+
+    /// Enable a patch and what it requires, applied now if the current scene allows it.
+    /// Nothing stays enabled if one of them fails.
+    pub fn enable(&mut self, name: &str) -> Result<()> {
+        let order: Vec<String> = self
+            .requirements_of(name)?
+            .into_iter()
+            .filter(|n| self.is_enabled(n) == Some(false))
+            .collect();
+        for n in &order {
+            self.check_conflicts(n, &order)?;
+        }
+
+        for (i, n) in order.iter().enumerate() {
+            let apply = self.allowed(n)?;
+            let p = self.get_mut(n)?;
+            p.enabled = true;
+            if !apply {
+                event!(Level::INFO, "Patch '{}' enabled, waiting for its scene", n);
+                continue;
+            }
+            if let Err(e) = p.patch.enable() {
+                for done in order[..=i].iter().rev() {
+                    let p = self.get_mut(done)?;
+                    p.enabled = false;
+                    let _ = p.patch.disable();
+                }
+                return Err(anyhow!("patch '{}': {}", n, e));
+            }
+            event!(Level::INFO, "Patch '{}' enabled", n);
+        }
+        Ok(())
+    }
+
+    /// Disable a patch, patches requiring it are disabled first
+    pub fn disable(&mut self, name: &str) -> Result<()> {
+        self.get_mut(name)?;
+        let mut order = Vec::new();
+        self.collect_dependents(name, &mut order);
+        for n in order {
+            let p = self.get_mut(&n)?;
+            if !p.enabled {
+                continue;
+            }
+            p.patch
+                .disable()
+                .map_err(|e| anyhow!("patch '{}': {}", n, e))?;
+            p.enabled = false;
+            event!(Level::INFO, "Patch '{}' disabled", n);
+        }
+        Ok(())
+    }
+
+    pub fn switch(&mut self, name: &str, on_off: bool) -> Result<()> {
+        if on_off {
+            return self.enable(name);
+        }
+        self.disable(name)
+    }
+
+    /// Disable every patch in reverse definition order.
+    /// Keeps going on errors and returns the first one.
+    pub fn disable_all(&mut self) -> Result<()> {
+        let mut result = Ok(());
+        for p in self.patches.iter_mut().rev() {
+            p.enabled = false;
+            if !p.patch.is_enabled() {
+                continue;
+            }
+            match p.patch.disable() {
+                Ok(()) => event!(Level::INFO, "Patch '{}' disabled", p.def.name),
+                Err(e) => {
+                    event!(
+                        Level::ERROR,
+                        "Failed to disable patch '{}': {}",
+                        p.def.name,
+                        e
+                    );
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Apply and revert the enabled patches for `scene`, see `PatchDef::scenes`.
+    /// Keeps going on errors and returns the first one.
+    pub fn set_scene(&mut self, scene: Option<Scene>) -> Result<()> {
+        self.scene = scene;
+        let mut result = Ok(());
+        let mut record = |name: &str, e: anyhow::Error| {
+            event!(Level::ERROR, "Patch '{}': {}", name, e);
+            if result.is_ok() {
+                result = Err(anyhow!("patch '{}': {}", name, e));
+            }
+        };
+
+        //reverse definition order like disable_all
+        for i in (0..self.patches.len()).rev() {
+            let name = self.patches[i].def.name.clone();
+            if !self.patches[i].patch.is_enabled() || self.allowed(&name)? {
+                continue;
+            }
+            match self.patches[i].patch.disable() {
+                Ok(()) => event!(Level::INFO, "Patch '{}' reverted outside its scene", name),
+                Err(e) => record(&name, e),
+            }
+        }
+        for i in 0..self.patches.len() {
+            let name = self.patches[i].def.name.clone();
+            if !self.patches[i].enabled || !self.allowed(&name)? {
+                continue;
+            }
+            for n in self.requirements_of(&name)? {
+                let p = self.get_mut(&n)?;
+                if p.patch.is_enabled() {
+                    continue;
+                }
+                match p.patch.enable() {
+                    Ok(()) => event!(Level::INFO, "Patch '{}' applied in its scene", n),
+                    Err(e) => record(&n, e),
+                }
+            }
+        }
+        result
+    }
+
+    /// The current scene allows `name` and everything it requires
+    fn allowed(&self, name: &str) -> Result<bool> {
+        for n in self.requirements_of(name)? {
+            if !self.get(&n)?.gate.allows(self.scene) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// `name` and everything it requires, requirements first
+    fn requirements_of(&self, name: &str) -> Result<Vec<String>> {
+        let mut order = Vec::new();
+        let mut visiting = Vec::new();
+        self.visit_requirements(name, &mut visiting, &mut order)?;
+        Ok(order)
+    }
+
+    fn visit_requirements(
+        &self,
+        name: &str,
+        visiting: &mut Vec<String>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        if order.iter().any(|n| n == name) {
+            return Ok(());
+        }
+        if visiting.iter().any(|n| n == name) {
+            return Err(anyhow!(
+                "patches require each other: {} -> {}",
+                visiting.join(" -> "),
+                name
+            ));
+        }
+        let patch = self
+            .find(name)
+            .ok_or_else(|| anyhow!("unknown patch '{}'", name))?;
+        visiting.push(name.to_owned());
+        for required in &patch.def.requires {
+            self.visit_requirements(required, visiting, order)?;
+        }
+        visiting.pop();
+        order.push(name.to_owned());
+        Ok(())
+    }
+
+    /// Patches requiring `name`(transitively) first, then `name`
+    fn collect_dependents(&self, name: &str, order: &mut Vec<String>) {
+        if order.iter().any(|n| n == name) {
+            return;
+        }
+        for p in &self.patches {
+            if p.def.requires.iter().any(|r| r == name) {
+                self.collect_dependents(&p.def.name, order);
+            }
+        }
+        order.push(name.to_owned());
+    }
+
+    /// `name` against the enabled patches and the ones about to be enabled
+    fn check_conflicts(&self, name: &str, enabling: &[String]) -> Result<()> {
+        let patch = self
+            .find(name)
+            .ok_or_else(|| anyhow!("unknown patch '{}'", name))?;
+        for other in &self.patches {
+            if other.def.name == name {
+                continue;
+            }
+            let active = other.enabled || enabling.contains(&other.def.name);
+            let conflicting = patch.def.conflicts.contains(&other.def.name)
+                || other.def.conflicts.iter().any(|c| c == name);
+            if active && conflicting {
+                return Err(anyhow!(
+                    "patch '{}' conflicts with '{}'",
+                    name,
+                    other.def.name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Option<&RegisteredPatch> {
+        self.patches.iter().find(|p| p.def.name == name)
+    }
+
+    fn get(&self, name: &str) -> Result<&RegisteredPatch> {
+        self.find(name)
+            .ok_or_else(|| anyhow!("unknown patch '{}'", name))
+    }
+
+    fn get_mut(&mut self, name: &str) -> Result<&mut RegisteredPatch> {
+        self.patches
+            .iter_mut()
+            .find(|p| p.def.name == name)
+            .ok_or_else(|| anyhow!("unknown patch '{}'", name))
+    }
+}
+
+fn merge_defs(defs: &mut Vec<PatchDef>, new_defs: Vec<PatchDef>) {
+    for def in new_defs {
+        match defs.iter_mut().find(|d| d.name == def.name) {
+            Some(d) => *d = def,
+            None => defs.push(def),
+        }
+    }
+}
+
+fn resolve_site(
+    site: &SiteDef,
+    module_address: usize,
+    offsets: &dyn OffsetLookup,
+) -> Result<PatchSite> {
+    let (offset, expected) = resolve_offset(&site.offset, site.expected.as_deref(), offsets)?;
+    let address = module_address + offset;
+    let code = site_code(
+        site.replacement.as_deref(),
+        site.asm.as_deref(),
+        module_address,
+        offsets,
+    )?;
+    let mut replacement = code.at(address);
+    if site.asm.is_some() {
+        if let Some(expected) = &expected {
+            if replacement.len() < expected.len() {
+                replacement.resize(expected.len(), 0x90);
+            }
+        }
+    }
+    Ok(PatchSite::new(address, &replacement).with_expected(expected))
+}
+
+fn resolve_cave(
+    cave: &CaveDef,
+    module_address: usize,
+    offsets: &dyn OffsetLookup,
+) -> Result<CodeCave> {
+    let code = site_code(
+        cave.code.as_deref(),
+        cave.asm.as_deref(),
+        module_address,
+        offsets,
+    )?;
+    let (offset, expected) = resolve_offset(&cave.offset, cave.expected.as_deref(), offsets)?;
+    let mut resolved = CodeCave::new(module_address + offset, cave.length, &code.bytes)
+        .with_expected(expected)
+        .with_original(cave.original);
+    for (at, target) in code.rel32 {
+        resolved = resolved.with_rel32(at, target);
+    }
+    for rel32 in &cave.rel32 {
+        let (target, _) = resolve_offset(&rel32.target, None, offsets)?;
+        resolved = resolved.with_rel32(rel32.at, module_address + target);
+    }
+    resolved.check()?;
+    Ok(resolved)
+}
+
+/// Hex bytes or assembled `asm`, one of them
+fn site_code(
+    hex: Option<&str>,
+    asm: Option<&str>,
+    module_address: usize,
+    offsets: &dyn OffsetLookup,
+) -> Result<Assembled> {
+    match (hex, asm) {
+        (Some(hex), None) => Ok(Assembled {
+            bytes: parse_hex_bytes(hex)?,
+            rel32: Vec::new(),
+        }),
+        (None, Some(source)) => {
+            let symbols = |name: &str| match name {
+                GAME_MODULE_SYMBOL => Some(module_address),
+                _ => offsets.offset(name).map(|rva| module_address + rva),
+            };
+            asm::assemble(source, &symbols)
+        }
+        _ => Err(anyhow!("give either hex bytes or asm")),
+    }
+}
+
+/// RVA and expected bytes, `expected` or the build's `original_bytes` for a named offset
+fn resolve_offset(
+    offset: &OffsetRef,
+    expected: Option<&str>,
+    offsets: &dyn OffsetLookup,
+) -> Result<(usize, Option<Vec<u8>>)> {
+    let (rva, original) = match offset {
+        OffsetRef::Name(name) => {
+            let rva = offsets
+                .offset(name)
+                .ok_or_else(|| anyhow!("unknown offset '{}'", name))?;
+            (rva, offsets.original_bytes(name)?)
+        }
+        OffsetRef::Rva(rva) => (*rva, None),
+    };
+    let expected = match expected {
+        Some(hex) => Some(parse_hex_bytes(hex)?),
+        None => original,
+    };
+    Ok((rva, expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{GameMemoryExt, MockMemory, Protection};
+    use std::collections::HashMap;
+
+    const MODULE: usize = 0x40_0000;
+    const CODE: usize = MODULE + 0x1000;
+    const ORIGINAL: [u8; 16] = [
+        0x55, 0x8B, 0xEC, 0x90, 0x8B, 0x45, 0x08, 0x90, 0x33, 0xC0, 0x90, 0x90, 0x5D, 0xC3, 0x90,
+        0x90,
+    ];
+
+    const PATCHES: &str = r#"
+        [[patch]]
+        name = "base"
+        [[patch.site]]
+        offset = "base_offset"
+        replacement = "C3 90"
+
+        [[patch]]
+        name = "dependent"
+        requires = ["base"]
+        [[patch.site]]
+        offset = 0x1004
+        asm = "xor eax, eax"
+        expected = "8B 45 08"
+
+        [[patch]]
+        name = "rival"
+        conflicts = ["base"]
+        [[patch.site]]
+        offset = 0x1008
+        replacement = "31 C0"
+
+        [[patch]]
+        name = "broken"
+        requires = ["base"]
+        [[patch.site]]
+        offset = 0x100C
+        replacement = "90"
+        expected = "CC"
+
+        [[patch]]
+        name = "battle only"
+        scenes = ["BATTLE"]
+        [[patch.site]]
+        offset = 0x100E
+        replacement = "CC"
+
+        [[patch]]
+        name = "needs battle"
+        requires = ["battle only"]
+        [[patch.site]]
+        offset = 0x100F
+        replacement = "CC"
+    "#;
+
+    #[derive(Default)]
+    struct FakeOffsets {
+        offsets: HashMap<&'static str, (usize, &'static str)>,
+    }
+
+    impl OffsetLookup for FakeOffsets {
+        fn offset(&self, name: &str) -> Option<usize> {
+            self.offsets.get(name).map(|(rva, _)| *rva)
+        }
+
+        fn original_bytes(&self, name: &str) -> Result<Option<Vec<u8>>> {
+            self.offsets
+                .get(name)
+                .map(|(_, hex)| parse_hex_bytes(hex))
+                .transpose()
+        }
+    }
+
+    fn offsets() -> FakeOffsets {
+        FakeOffsets {
+            offsets: [("base_offset", (0x1000, "55 8B"))].into_iter().collect(),
+        }
+    }
+
+    fn registry_from(defs: &str) -> Result<(Arc<MockMemory>, PatchRegistry)> {
+        let memory = Arc::new(MockMemory::new());
+        memory.map_bytes(CODE, &ORIGINAL, Protection::EXECUTE_READ);
+        let registry =
+            PatchRegistry::from_defs(memory.clone(), MODULE, &offsets(), parse_patch_file(defs)?)?;
+        Ok((memory, registry))
+    }
+
+    fn registry() -> (Arc<MockMemory>, PatchRegistry) {
+        registry_from(PATCHES).unwrap()
+    }
+
+    fn code(memory: &MockMemory) -> Vec<u8> {
+        memory.read_vec(CODE, ORIGINAL.len()).unwrap()
+    }
+
+    fn enabled(registry: &PatchRegistry) -> Vec<String> {
+        registry
+            .patches()
+            .into_iter()
+            .filter(|p| p.enabled)
+            .map(|p| p.name)
+            .collect()
+    }
+
+    #[test]
+    fn requires_are_enabled_first_and_disabled_last() {
+        let (memory, mut registry) = registry();
+        assert_eq!(registry.len(), 6);
+        assert_eq!(registry.categories(), ["Misc"]);
+
+        registry.enable("dependent").unwrap();
+        assert_eq!(enabled(&registry), ["base", "dependent"]);
+        let code = code(&memory);
+        assert_eq!(code[..2], [0xC3, 0x90]);
+        //assembled and padded to the expected length
+        assert_eq!(code[4..7], [0x31, 0xC0, 0x90]);
+
+        //disabling a requirement takes its dependents with it
+        registry.disable("base").unwrap();
+        assert!(enabled(&registry).is_empty());
+        assert_eq!(self::code(&memory), ORIGINAL);
+        assert!(registry.enable("unknown").is_err());
+    }
+
+    #[test]
+    fn conflicts_are_refused() {
+        let (memory, mut registry) = registry();
+        registry.enable("base").unwrap();
+        let error = registry.enable("rival").unwrap_err().to_string();
+        assert!(error.contains("conflicts with 'base'"), "{}", error);
+        assert_eq!(enabled(&registry), ["base"]);
+
+        //declared on either side, also for patches enabled as a requirement
+        registry.disable("base").unwrap();
+        registry.switch("rival", true).unwrap();
+        assert!(registry.enable("dependent").is_err());
+        assert_eq!(enabled(&registry), ["rival"]);
+        assert_eq!(code(&memory)[..8], ORIGINAL[..8]);
+    }
+
+    #[test]
+    fn failed_patch_rolls_back_its_requirements() {
+        let (memory, mut registry) = registry();
+        let error = registry.enable("broken").unwrap_err().to_string();
+        assert!(error.starts_with("patch 'broken'"), "{}", error);
+        assert!(enabled(&registry).is_empty());
+        assert!(registry.patches().iter().all(|p| !p.applied));
+        assert_eq!(code(&memory), ORIGINAL);
+
+        //a requirement enabled before is left alone
+        registry.enable("base").unwrap();
+        assert!(registry.enable("broken").is_err());
+        assert_eq!(enabled(&registry), ["base"]);
+    }
+
+    #[test]
+    fn scene_gated_patches() {
+        let (memory, mut registry) = registry();
+        let applied = |registry: &PatchRegistry, name: &str| {
+            registry
+                .patches()
+                .into_iter()
+                .find(|p| p.name == name)
+                .unwrap()
+                .applied
+        };
+
+        //the requirement's scene holds back the dependent too
+        registry.enable("needs battle").unwrap();
+        assert_eq!(enabled(&registry), ["battle only", "needs battle"]);
+        assert!(!applied(&registry, "battle only"));
+        assert!(!applied(&registry, "needs battle"));
+        assert_eq!(code(&memory), ORIGINAL);
+
+        registry.set_scene(Some(Scene::Battle)).unwrap();
+        assert!(applied(&registry, "battle only"));
+        assert!(applied(&registry, "needs battle"));
+        assert_eq!(code(&memory)[14..], [0xCC, 0xCC]);
+
+        registry.set_scene(Some(Scene::Config)).unwrap();
+        assert_eq!(enabled(&registry), ["battle only", "needs battle"]);
+        assert_eq!(code(&memory), ORIGINAL);
+
+        //switched off outside its scene, not applied when it comes back
+        registry.disable("battle only").unwrap();
+        registry.set_scene(Some(Scene::Battle)).unwrap();
+        assert!(enabled(&registry).is_empty());
+        assert_eq!(code(&memory), ORIGINAL);
+        assert_eq!(registry.patches()[4].scenes, [Scene::Battle]);
+    }
+
+    #[test]
+    fn disable_all() {
+        let (memory, mut registry) = registry();
+        registry.enable("dependent").unwrap();
+        registry.enable("battle only").unwrap();
+        registry.disable_all().unwrap();
+        assert!(enabled(&registry).is_empty());
+        assert_eq!(code(&memory), ORIGINAL);
+    }
+
+    #[test]
+    fn invalid_definitions() {
+        let error = |defs: &str| registry_from(defs).err().unwrap().to_string();
+        let site = "[[patch.site]]\noffset = 0x1000\nreplacement = \"90\"\n";
+
+        let twice = format!(
+            "[[patch]]\nname = \"a\"\n{0}[[patch]]\nname = \"a\"\n{0}",
+            site
+        );
+        assert!(error(&twice).contains("defined twice"));
+        assert!(error("[[patch]]\nname = \"a\"\n").contains("no site or cave"));
+        let unknown = format!("[[patch]]\nname = \"a\"\nrequires = [\"b\"]\n{}", site);
+        assert!(error(&unknown).contains("unknown patch 'b'"));
+        let cycle = format!(
+            "[[patch]]\nname = \"a\"\nrequires = [\"b\"]\n{0}\
+             [[patch]]\nname = \"b\"\nrequires = [\"a\"]\n{0}",
+            site
+        );
+        assert!(error(&cycle).contains("require each other"));
+        let scene = format!("[[patch]]\nname = \"a\"\nscenes = [\"NOWHERE\"]\n{}", site);
+        assert!(error(&scene).contains("unknown scene"));
+        let offset =
+            "[[patch]]\nname = \"a\"\n[[patch.site]]\noffset = \"nowhere\"\nreplacement = \"90\"\n";
+        assert!(error(offset).contains("unknown offset 'nowhere'"));
+    }
+}
@@ -0,0 +1,894 @@
+use anyhow::{anyhow, Result};
+
+//Pure rust PE32 parser, works on a file on disk or a module mapped in memory.
+//https://docs.microsoft.com/en-us/windows/win32/debug/pe-format
+
+const IMAGE_DOS_SIGNATURE: u16 = 0x5a4d; //MZ
+const IMAGE_NT_SIGNATURE: u32 = 0x4550; //PE\0\0
+const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x10b;
+
+pub const IMAGE_DIRECTORY_ENTRY_EXPORT: usize = 0;
+pub const IMAGE_DIRECTORY_ENTRY_IMPORT: usize = 1;
+pub const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+pub const IMAGE_DIRECTORY_ENTRY_TLS: usize = 9;
+pub const IMAGE_DIRECTORY_ENTRY_IAT: usize = 12;
+
+pub const IMAGE_REL_BASED_ABSOLUTE: u8 = 0;
+pub const IMAGE_REL_BASED_HIGHLOW: u8 = 3;
+
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x20000000;
+pub const IMAGE_SCN_MEM_READ: u32 = 0x40000000;
+pub const IMAGE_SCN_MEM_WRITE: u32 = 0x80000000;
+
+/// How the bytes are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// raw file, sections are at their PointerToRawData
+    File,
+    /// loaded by the windows loader, rva == offset
+    Mapped,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileHeader {
+    pub machine: u16,
+    pub number_of_sections: u16,
+    pub time_date_stamp: u32,
+    pub pointer_to_symbol_table: u32,
+    pub number_of_symbols: u32,
+    pub size_of_optional_header: u16,
+    pub characteristics: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DataDirectory {
+    pub virtual_address: u32,
+    pub size: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct OptionalHeader {
+    pub magic: u16,
+    pub address_of_entry_point: u32,
+    pub base_of_code: u32,
+    pub image_base: u32,
+    pub section_alignment: u32,
+    pub file_alignment: u32,
+    pub size_of_image: u32,
+    pub size_of_headers: u32,
+    pub checksum: u32,
+    pub subsystem: u16,
+    pub dll_characteristics: u16,
+    pub data_directories: Vec<DataDirectory>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Section {
+    pub name: String,
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub size_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub characteristics: u32,
+}
+
+impl Section {
+    pub fn contains_rva(&self, rva: u32) -> bool {
+        let size = std::cmp::max(self.virtual_size, self.size_of_raw_data);
+        match self.virtual_address.checked_add(size) {
+            Some(end) => rva >= self.virtual_address && rva < end,
+            //a corrupt section that wraps around the address space contains nothing
+            None => false,
+        }
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.characteristics & IMAGE_SCN_MEM_EXECUTE != 0
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedFunction {
+    /// None if imported by ordinal
+    pub name: Option<String>,
+    pub ordinal: Option<u16>,
+    pub hint: u16,
+    /// rva of this function's IAT slot
+    pub iat_rva: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedModule {
+    pub name: String,
+    pub functions: Vec<ImportedFunction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExportedFunction {
+    pub name: Option<String>,
+    pub ordinal: u16,
+    pub rva: u32,
+    /// "dll.symbol" if the export is forwarded
+    pub forwarder: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Exports {
+    pub name: String,
+    pub ordinal_base: u32,
+    pub functions: Vec<ExportedFunction>,
+}
+
+/// Addresses are VAs(relative to the image base in the header)
+#[derive(Debug, Clone)]
+pub struct TlsDirectory {
+    pub start_address_of_raw_data: u32,
+    pub end_address_of_raw_data: u32,
+    pub address_of_index: u32,
+    pub address_of_callbacks: u32,
+    pub callbacks: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Relocation {
+    /// IMAGE_REL_BASED_*
+    pub kind: u8,
+    pub rva: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct RelocationBlock {
+    pub page_rva: u32,
+    pub relocations: Vec<Relocation>,
+}
+
+/// Identifies a build of an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeFingerprint {
+    pub time_date_stamp: u32,
+    pub checksum: u32,
+    pub size_of_image: u32,
+}
+
+pub struct PeImage<'a> {
+    data: &'a [u8],
+    layout: Layout,
+    pub file_header: FileHeader,
+    pub optional_header: OptionalHeader,
+    pub sections: Vec<Section>,
+}
+
+impl<'a> PeImage<'a> {
+    /// Parse a PE file read from disk
+    pub fn parse_file(data: &'a [u8]) -> Result<Self> {
+        Self::parse(data, Layout::File)
+    }
+
+    /// Parse a copy of a loaded module
+    pub fn parse_mapped(data: &'a [u8]) -> Result<Self> {
+        Self::parse(data, Layout::Mapped)
+    }
+
+    /// Parse a module loaded in this process
+    ///
+    /// # Safety
+    /// `module_address` has to be the base of a module mapped in this process
+    pub unsafe fn from_module(module_address: usize) -> Result<PeImage<'static>> {
+        //read SizeOfImage first to know how large the image is
+        let nt = *((module_address + 0x3c) as *const u32) as usize;
+        let size_of_image = *((module_address + nt + 4 + 20 + 56) as *const u32) as usize;
+        let data = std::slice::from_raw_parts(module_address as *const u8, size_of_image);
+        PeImage::parse_mapped(data)
+    }
+
+    pub fn parse(data: &'a [u8], layout: Layout) -> Result<Self> {
+        let reader = Reader(data);
+        if reader.u16(0)? != IMAGE_DOS_SIGNATURE {
+            return Err(anyhow!("invalid dos signature"));
+        }
+        let nt = reader.u32(0x3c)? as usize;
+        if reader.u32(nt)? != IMAGE_NT_SIGNATURE {
+            return Err(anyhow!("invalid nt signature"));
+        }
+        //nt is inside data from here on, the header offsets below are far from overflowing
+
+        let fh = nt + 4;
+        let file_header = FileHeader {
+            machine: reader.u16(fh)?,
+            number_of_sections: reader.u16(fh + 2)?,
+            time_date_stamp: reader.u32(fh + 4)?,
+            pointer_to_symbol_table: reader.u32(fh + 8)?,
+            number_of_symbols: reader.u32(fh + 12)?,
+            size_of_optional_header: reader.u16(fh + 16)?,
+            characteristics: reader.u16(fh + 18)?,
+        };
+
+        let oh = fh + 20;
+        let magic = reader.u16(oh)?;
+        if magic != IMAGE_NT_OPTIONAL_HDR32_MAGIC {
+            return Err(anyhow!(
+                "not a PE32 image (optional header magic {:x})",
+                magic
+            ));
+        }
+        let number_of_rva_and_sizes = reader.u32(oh + 92)? as usize;
+        let data_directories = (0..std::cmp::min(number_of_rva_and_sizes, 16))
+            .map(|i| {
+                Ok(DataDirectory {
+                    virtual_address: reader.u32(oh + 96 + i * 8)?,
+                    size: reader.u32(oh + 100 + i * 8)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let optional_header = OptionalHeader {
+            magic,
+            address_of_entry_point: reader.u32(oh + 16)?,
+            base_of_code: reader.u32(oh + 20)?,
+            image_base: reader.u32(oh + 28)?,
+            section_alignment: reader.u32(oh + 32)?,
+            file_alignment: reader.u32(oh + 36)?,
+            size_of_image: reader.u32(oh + 56)?,
+            size_of_headers: reader.u32(oh + 60)?,
+            checksum: reader.u32(oh + 64)?,
+            subsystem: reader.u16(oh + 68)?,
+            dll_characteristics: reader.u16(oh + 70)?,
+            data_directories,
+        };
+
+        let sh = oh + file_header.size_of_optional_header as usize;
+        let sections = (0..file_header.number_of_sections as usize)
+            .map(|i| {
+                let s = sh + i * 40;
+                let name = reader.bytes(s, 8)?;
+                let name_len = name.iter().position(|&c| c == 0).unwrap_or(8);
+                Ok(Section {
+                    name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+                    virtual_size: reader.u32(s + 8)?,
+                    virtual_address: reader.u32(s + 12)?,
+                    size_of_raw_data: reader.u32(s + 16)?,
+                    pointer_to_raw_data: reader.u32(s + 20)?,
+                    characteristics: reader.u32(s + 36)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(PeImage {
+            data,
+            layout,
+            file_header,
+            optional_header,
+            sections,
+        })
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn fingerprint(&self) -> PeFingerprint {
+        PeFingerprint {
+            time_date_stamp: self.file_header.time_date_stamp,
+            checksum: self.optional_header.checksum,
+            size_of_image: self.optional_header.size_of_image,
+        }
+    }
+
+    /// PE checksum of the file as computed by `CheckSumMappedFile`.
+    /// Only meaningful for `Layout::File`.
+    pub fn compute_checksum(&self) -> Result<u32> {
+        let checksum_offset = (Reader(self.data).u32(0x3c)? as usize)
+            .checked_add(4 + 20 + 64)
+            .ok_or_else(|| anyhow!("checksum offset overflows"))?;
+        let mut sum: u64 = 0;
+        for (i, chunk) in self.data.chunks(2).enumerate() {
+            if i * 2 == checksum_offset || i * 2 == checksum_offset + 2 {
+                continue;
+            }
+            let word = match chunk {
+                [lo, hi] => u16::from_le_bytes([*lo, *hi]),
+                [lo] => *lo as u16,
+                _ => 0,
+            };
+            sum += word as u64;
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+        sum = (sum & 0xffff) + (sum >> 16);
+        u32::try_from(self.data.len())
+            .ok()
+            .and_then(|len| len.checked_add(sum as u32))
+            .ok_or_else(|| anyhow!("image is too large for a checksum"))
+    }
+
+    pub fn data_directory(&self, index: usize) -> Option<DataDirectory> {
+        self.optional_header
+            .data_directories
+            .get(index)
+            .copied()
+            .filter(|d| d.virtual_address != 0)
+    }
+
+    pub fn section_by_name(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    pub fn section_by_rva(&self, rva: u32) -> Option<&Section> {
+        self.sections.iter().find(|s| s.contains_rva(rva))
+    }
+
+    pub fn section_data(&self, section: &Section) -> Result<&'a [u8]> {
+        let (offset, size) = match self.layout {
+            Layout::File => (section.pointer_to_raw_data, section.size_of_raw_data),
+            Layout::Mapped => (section.virtual_address, section.virtual_size),
+        };
+        Reader(self.data).bytes(offset as usize, size as usize)
+    }
+
+    pub fn rva_to_offset(&self, rva: u32) -> Result<usize> {
+        if self.layout == Layout::Mapped || rva < self.optional_header.size_of_headers {
+            return Ok(rva as usize);
+        }
+        let section = self
+            .section_by_rva(rva)
+            .ok_or_else(|| anyhow!("rva {:x} is not in any section", rva))?;
+        let offset = rva - section.virtual_address;
+        //past the raw data is zero filled by the loader, the file has nothing there
+        if offset >= section.size_of_raw_data {
+            return Err(anyhow!("rva {:x} has no data in the file", rva));
+        }
+        offset
+            .checked_add(section.pointer_to_raw_data)
+            .map(|offset| offset as usize)
+            .ok_or_else(|| anyhow!("rva {:x} maps outside the file", rva))
+    }
+
+    /// `va` relative to the image base in the header
+    pub fn va_to_rva(&self, va: u32) -> Result<u32> {
+        va.checked_sub(self.optional_header.image_base)
+            .ok_or_else(|| anyhow!("va {:x} is below the image base", va))
+    }
+
+    pub fn read_u16_at_rva(&self, rva: u32) -> Result<u16> {
+        Reader(self.data).u16(self.rva_to_offset(rva)?)
+    }
+
+    pub fn read_u32_at_rva(&self, rva: u32) -> Result<u32> {
+        Reader(self.data).u32(self.rva_to_offset(rva)?)
+    }
+
+    pub fn read_bytes_at_rva(&self, rva: u32, size: usize) -> Result<&'a [u8]> {
+        Reader(self.data).bytes(self.rva_to_offset(rva)?, size)
+    }
+
+    pub fn read_cstr_at_rva(&self, rva: u32) -> Result<String> {
+        Reader(self.data).cstr(self.rva_to_offset(rva)?)
+    }
+
+    /// `count` entries of `entry_size` bytes, e.g. an export address table
+    fn read_table_at_rva(&self, rva: u32, count: u32, entry_size: u32) -> Result<&'a [u8]> {
+        let size = count
+            .checked_mul(entry_size)
+            .ok_or_else(|| anyhow!("table of {} entries is too large", count))?;
+        if size == 0 {
+            return Ok(&[]);
+        }
+        self.read_bytes_at_rva(rva, size as usize)
+    }
+
+    pub fn imports(&self) -> Result<Vec<ImportedModule>> {
+        let dir = match self.data_directory(IMAGE_DIRECTORY_ENTRY_IMPORT) {
+            Some(d) => d,
+            None => return Ok(Vec::new()),
+        };
+        let mut modules = Vec::new();
+        //IMAGE_IMPORT_DESCRIPTOR array terminated by a zeroed entry
+        let mut descriptor = dir.virtual_address;
+        loop {
+            let original_first_thunk = self.read_u32_at_rva(descriptor)?;
+            let name_rva = self.read_u32_at_rva(rva_add(descriptor, 12)?)?;
+            let first_thunk = self.read_u32_at_rva(rva_add(descriptor, 16)?)?;
+            if name_rva == 0 && first_thunk == 0 {
+                break;
+            }
+            //bound/mapped images overwrite FirstThunk, prefer the lookup table
+            let lookup = if original_first_thunk != 0 {
+                original_first_thunk
+            } else {
+                first_thunk
+            };
+
+            let mut functions = Vec::new();
+            let (mut lookup_rva, mut iat_rva) = (lookup, first_thunk);
+            loop {
+                let thunk = self.read_u32_at_rva(lookup_rva)?;
+                if thunk == 0 {
+                    break;
+                }
+                let function = if thunk & 0x80000000 != 0 {
+                    ImportedFunction {
+                        name: None,
+                        ordinal: Some(thunk as u16),
+                        hint: 0,
+                        iat_rva,
+                    }
+                } else {
+                    ImportedFunction {
+                        name: Some(self.read_cstr_at_rva(rva_add(thunk, 2)?)?),
+                        ordinal: None,
+                        hint: self.read_u16_at_rva(thunk)?,
+                        iat_rva,
+                    }
+                };
+                functions.push(function);
+                lookup_rva = rva_add(lookup_rva, 4)?;
+                iat_rva = rva_add(iat_rva, 4)?;
+            }
+            modules.push(ImportedModule {
+                name: self.read_cstr_at_rva(name_rva)?,
+                functions,
+            });
+            descriptor = rva_add(descriptor, 20)?;
+        }
+        Ok(modules)
+    }
+
+    pub fn exports(&self) -> Result<Option<Exports>> {
+        let dir = match self.data_directory(IMAGE_DIRECTORY_ENTRY_EXPORT) {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+        let d = dir.virtual_address;
+        let name = self.read_cstr_at_rva(self.read_u32_at_rva(rva_add(d, 12)?)?)?;
+        let ordinal_base = self.read_u32_at_rva(rva_add(d, 16)?)?;
+        let number_of_functions = self.read_u32_at_rva(rva_add(d, 20)?)?;
+        let number_of_names = self.read_u32_at_rva(rva_add(d, 24)?)?;
+        let address_of_functions = self.read_u32_at_rva(rva_add(d, 28)?)?;
+        let address_of_names = self.read_u32_at_rva(rva_add(d, 32)?)?;
+        let address_of_name_ordinals = self.read_u32_at_rva(rva_add(d, 36)?)?;
+
+        //the tables are read whole so a corrupt count fails here, before anything is allocated for it
+        let functions_table =
+            self.read_table_at_rva(address_of_functions, number_of_functions, 4)?;
+        let names_table = self.read_table_at_rva(address_of_names, number_of_names, 4)?;
+        let name_ordinals_table =
+            self.read_table_at_rva(address_of_name_ordinals, number_of_names, 2)?;
+
+        let mut names = vec![None; number_of_functions as usize];
+        for (name_rva, index) in names_table
+            .chunks_exact(4)
+            .zip(name_ordinals_table.chunks_exact(2))
+        {
+            let index = u16::from_le_bytes(index.try_into().unwrap()) as usize;
+            let name_rva = u32::from_le_bytes(name_rva.try_into().unwrap());
+            if let Some(slot) = names.get_mut(index) {
+                *slot = Some(self.read_cstr_at_rva(name_rva)?);
+            }
+        }
+
+        let mut functions = Vec::new();
+        for (i, (name, rva)) in names
+            .into_iter()
+            .zip(functions_table.chunks_exact(4))
+            .enumerate()
+        {
+            let rva = u32::from_le_bytes(rva.try_into().unwrap());
+            if rva == 0 {
+                continue;
+            }
+            //an rva inside the export directory points to a forwarder string
+            let forwarder = if rva >= d && rva - d < dir.size {
+                Some(self.read_cstr_at_rva(rva)?)
+            } else {
+                None
+            };
+            functions.push(ExportedFunction {
+                name,
+                ordinal: ordinal_base.wrapping_add(i as u32) as u16,
+                rva,
+                forwarder,
+            });
+        }
+        Ok(Some(Exports {
+            name,
+            ordinal_base,
+            functions,
+        }))
+    }
+
+    pub fn export_by_name(&self, symbol: &str) -> Result<Option<ExportedFunction>> {
+        Ok(self.exports()?.and_then(|e| {
+            e.functions
+                .into_iter()
+                .find(|f| f.name.as_deref() == Some(symbol))
+        }))
+    }
+
+    pub fn tls(&self) -> Result<Option<TlsDirectory>> {
+        let dir = match self.data_directory(IMAGE_DIRECTORY_ENTRY_TLS) {
+            Some(d) => d,
+            None => return Ok(None),
+        };
+        let d = dir.virtual_address;
+        let address_of_callbacks = self.read_u32_at_rva(rva_add(d, 12)?)?;
+        let mut callbacks = Vec::new();
+        if address_of_callbacks != 0 {
+            let mut callback_rva = self.va_to_rva(address_of_callbacks)?;
+            loop {
+                let callback = self.read_u32_at_rva(callback_rva)?;
+                if callback == 0 {
+                    break;
+                }
+                callbacks.push(callback);
+                callback_rva = rva_add(callback_rva, 4)?;
+            }
+        }
+        Ok(Some(TlsDirectory {
+            start_address_of_raw_data: self.read_u32_at_rva(d)?,
+            end_address_of_raw_data: self.read_u32_at_rva(rva_add(d, 4)?)?,
+            address_of_index: self.read_u32_at_rva(rva_add(d, 8)?)?,
+            address_of_callbacks,
+            callbacks,
+        }))
+    }
+
+    pub fn relocations(&self) -> Result<Vec<RelocationBlock>> {
+        let dir = match self.data_directory(IMAGE_DIRECTORY_ENTRY_BASERELOC) {
+            Some(d) => d,
+            None => return Ok(Vec::new()),
+        };
+        let mut blocks = Vec::new();
+        let end = rva_add(dir.virtual_address, dir.size)?;
+        let mut block = dir.virtual_address;
+        while rva_add(block, 8)? <= end {
+            let page_rva = self.read_u32_at_rva(block)?;
+            let block_size = self.read_u32_at_rva(block + 4)?;
+            if block_size < 8 {
+                break;
+            }
+            let block_end = rva_add(block, block_size)?;
+            if block_end > end {
+                return Err(anyhow!(
+                    "relocation block at {:x} ends past the relocation directory",
+                    block
+                ));
+            }
+            let entries = self.read_bytes_at_rva(block + 8, (block_size - 8) as usize)?;
+            let relocations = entries
+                .chunks_exact(2)
+                .map(|entry| {
+                    let entry = u16::from_le_bytes(entry.try_into().unwrap());
+                    Ok(Relocation {
+                        kind: (entry >> 12) as u8,
+                        rva: rva_add(page_rva, (entry & 0xfff) as u32)?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            blocks.push(RelocationBlock {
+                page_rva,
+                relocations,
+            });
+            block = block_end;
+        }
+        Ok(blocks)
+    }
+}
+
+/// `rva + offset`, an error where a corrupt header would wrap around
+fn rva_add(rva: u32, offset: u32) -> Result<u32> {
+    rva.checked_add(offset)
+        .ok_or_else(|| anyhow!("rva {:x}+{:x} overflows", rva, offset))
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&self, offset: usize, size: usize) -> Result<&'a [u8]> {
+        offset
+            .checked_add(size)
+            .and_then(|end| self.0.get(offset..end))
+            .ok_or_else(|| anyhow!("read {:x}+{:x} is out of bounds", offset, size))
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(
+            self.bytes(offset, 2)?.try_into().unwrap(),
+        ))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(
+            self.bytes(offset, 4)?.try_into().unwrap(),
+        ))
+    }
+
+    fn cstr(&self, offset: usize) -> Result<String> {
+        let rest = self
+            .0
+            .get(offset..)
+            .ok_or_else(|| anyhow!("string at {:x} is out of bounds", offset))?;
+        let len = rest
+            .iter()
+            .position(|&c| c == 0)
+            .ok_or_else(|| anyhow!("unterminated string at {:x}", offset))?;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //tests/fixtures/tiny.s
+    const TINY: &[u8] = include_bytes!("../../tests/fixtures/tiny.dll");
+
+    /// Copy of the fixture laid out like the loader maps it
+    fn map(file: &[u8]) -> Vec<u8> {
+        let image = PeImage::parse_file(file).unwrap();
+        let mut mapped = vec![0; image.optional_header.size_of_image as usize];
+        let headers = image.optional_header.size_of_headers as usize;
+        mapped[..headers].copy_from_slice(&file[..headers]);
+        for section in &image.sections {
+            let data = image.section_data(section).unwrap();
+            let at = section.virtual_address as usize;
+            mapped[at..at + data.len()].copy_from_slice(data);
+        }
+        mapped
+    }
+
+    fn patched(at: usize, value: u32) -> Vec<u8> {
+        let mut data = TINY.to_vec();
+        data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+        data
+    }
+
+    fn data_directory_offset(index: usize) -> usize {
+        0x78 + 4 + 20 + 96 + index * 8
+    }
+
+    #[test]
+    fn headers() {
+        let image = PeImage::parse_file(TINY).unwrap();
+        assert_eq!(image.file_header.machine, 0x14c);
+        assert_eq!(image.file_header.time_date_stamp, 0xb5fc90f8);
+        assert_eq!(image.optional_header.image_base, 0x10000000);
+        assert_eq!(image.optional_header.address_of_entry_point, 0x1000);
+        assert_eq!(image.optional_header.size_of_image, 0x7000);
+        assert_eq!(
+            image.fingerprint(),
+            PeFingerprint {
+                time_date_stamp: 0xb5fc90f8,
+                checksum: 0xcd41,
+                size_of_image: 0x7000,
+            }
+        );
+        //written by the linker
+        assert_eq!(image.compute_checksum().unwrap(), 0xcd41);
+
+        let names = image
+            .sections
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [".text", ".rdata", ".data", ".CRT", ".tls", ".reloc"]
+        );
+        let text = image.section_by_name(".text").unwrap();
+        assert!(text.is_executable());
+        assert!(!image.section_by_name(".data").unwrap().is_executable());
+    }
+
+    #[test]
+    fn rvas() {
+        let image = PeImage::parse_file(TINY).unwrap();
+        assert_eq!(image.section_by_rva(0x1011).unwrap().name, ".text");
+        assert_eq!(image.section_by_rva(0x3004).unwrap().name, ".data");
+        assert!(image.section_by_rva(0x7000).is_none());
+        assert_eq!(image.rva_to_offset(0x78).unwrap(), 0x78);
+        assert_eq!(image.rva_to_offset(0x1011).unwrap(), 0x411);
+        //read_counter: mov eax, [counter]
+        assert_eq!(
+            image.read_bytes_at_rva(0x1011, 5).unwrap(),
+            [0xa1, 0x00, 0x30, 0x00, 0x10]
+        );
+        assert_eq!(image.read_u32_at_rva(0x3000).unwrap(), 0x12345678);
+        assert_eq!(image.va_to_rva(0x10003000).unwrap(), 0x3000);
+        assert!(image.va_to_rva(0x3000).is_err());
+        //zero filled past the raw data
+        assert!(image.rva_to_offset(0x3200).is_err());
+        assert!(image.rva_to_offset(0x8000).is_err());
+    }
+
+    #[test]
+    fn imports() {
+        let image = PeImage::parse_file(TINY).unwrap();
+        let imports = image.imports().unwrap();
+        assert_eq!(imports.len(), 1);
+        assert_eq!(imports[0].name, "kernel32.dll");
+        let functions = &imports[0].functions;
+        assert_eq!(functions.len(), 2);
+        assert_eq!(functions[0].name.as_deref(), Some("GetTickCount"));
+        assert_eq!(functions[0].ordinal, None);
+        assert_eq!(functions[0].iat_rva, 0x20f8);
+        assert_eq!(functions[1].name, None);
+        assert_eq!(functions[1].ordinal, Some(1000));
+        assert_eq!(functions[1].iat_rva, 0x20fc);
+    }
+
+    #[test]
+    fn exports() {
+        let image = PeImage::parse_file(TINY).unwrap();
+        let exports = image.exports().unwrap().unwrap();
+        assert_eq!(exports.name, "tiny.dll");
+        assert_eq!(exports.ordinal_base, 1);
+        let functions = exports
+            .functions
+            .iter()
+            .map(|f| {
+                (
+                    f.name.as_deref().unwrap(),
+                    f.ordinal,
+                    f.rva,
+                    f.forwarder.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            functions,
+            [
+                ("add", 1, 0x1008, None),
+                ("counter", 2, 0x3000, None),
+                ("read_counter", 3, 0x1011, None),
+                ("tick", 4, 0x20ab, Some("kernel32.GetTickCount")),
+            ]
+        );
+        assert_eq!(image.export_by_name("add").unwrap().unwrap().rva, 0x1008);
+        assert!(image.export_by_name("sub").unwrap().is_none());
+    }
+
+    #[test]
+    fn relocations_and_tls() {
+        let image = PeImage::parse_file(TINY).unwrap();
+        let relocations = image
+            .relocations()
+            .unwrap()
+            .into_iter()
+            .flat_map(|b| b.relocations)
+            .collect::<Vec<_>>();
+        let highlow = relocations
+            .iter()
+            .filter(|r| r.kind == IMAGE_REL_BASED_HIGHLOW)
+            .map(|r| r.rva)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            highlow,
+            [0x1012, 0x1018, 0x1020, 0x1026, 0x2000, 0x2004, 0x2008, 0x200c, 0x4000]
+        );
+        //padding of the last block
+        assert_eq!(relocations.last().unwrap().kind, IMAGE_REL_BASED_ABSOLUTE);
+
+        let tls = image.tls().unwrap().unwrap();
+        assert_eq!(tls.address_of_index, 0x10003004);
+        assert_eq!(tls.callbacks, [0x1000102b]);
+    }
+
+    #[test]
+    fn mapped_layout() {
+        let mapped = map(TINY);
+        let image = PeImage::parse_mapped(&mapped).unwrap();
+        assert_eq!(image.layout(), Layout::Mapped);
+        assert_eq!(image.rva_to_offset(0x1011).unwrap(), 0x1011);
+        assert_eq!(image.read_u32_at_rva(0x3000).unwrap(), 0x12345678);
+        assert_eq!(image.imports().unwrap()[0].functions.len(), 2);
+        assert_eq!(image.exports().unwrap().unwrap().functions.len(), 4);
+        assert_eq!(image.tls().unwrap().unwrap().callbacks, [0x1000102b]);
+        let text = image.section_by_name(".text").unwrap();
+        assert_eq!(&image.section_data(text).unwrap()[..5], [0xb8, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bad_headers() {
+        let err = |data: &[u8]| PeImage::parse_file(data).err().unwrap().to_string();
+        assert!(err(&[]).contains("out of bounds"));
+        assert!(err(b"ZM").contains("dos signature"));
+        assert!(err(&patched(0x3c, 0xffff_fffe)).contains("out of bounds"));
+        assert!(err(&patched(0x3c, 0x40)).contains("nt signature"));
+        //PE32+
+        let mut pe64 = TINY.to_vec();
+        pe64[0x78 + 24] = 0x0b;
+        pe64[0x78 + 25] = 0x02;
+        assert!(err(&pe64).contains("not a PE32"));
+    }
+
+    #[test]
+    fn truncated_images_fail_without_panicking() {
+        let relocations_end = PeImage::parse_file(TINY)
+            .unwrap()
+            .rva_to_offset(0x6000)
+            .unwrap()
+            + 0x2c;
+        for len in 0..TINY.len() {
+            let data = &TINY[..len];
+            let image = match PeImage::parse_file(data) {
+                Ok(image) => image,
+                Err(_) => continue,
+            };
+            //whatever the headers describe past the end can't be read
+            let _ = image.imports();
+            let _ = image.exports();
+            let _ = image.tls();
+            let _ = image.relocations();
+            let _ = image.compute_checksum();
+            if len < relocations_end {
+                assert!(image.relocations().is_err(), "{:x}", len);
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_directories() {
+        //export directory at the end of the address space
+        let data = patched(
+            data_directory_offset(IMAGE_DIRECTORY_ENTRY_EXPORT),
+            0xffff_fff0,
+        );
+        assert!(PeImage::parse_file(&data).unwrap().exports().is_err());
+
+        //relocation directory wrapping around
+        let data = patched(
+            data_directory_offset(IMAGE_DIRECTORY_ENTRY_BASERELOC) + 4,
+            0xffff_ffff,
+        );
+        assert!(PeImage::parse_file(&data).unwrap().relocations().is_err());
+
+        //relocation block larger than the directory
+        let image = PeImage::parse_file(TINY).unwrap();
+        let reloc = image.rva_to_offset(0x6000).unwrap();
+        let data = patched(reloc + 4, 0x1000);
+        assert!(PeImage::parse_file(&data).unwrap().relocations().is_err());
+
+        //a count that would allocate gigabytes
+        let export = image
+            .rva_to_offset(
+                image
+                    .data_directory(IMAGE_DIRECTORY_ENTRY_EXPORT)
+                    .unwrap()
+                    .virtual_address,
+            )
+            .unwrap();
+        let data = patched(export + 20, 0xffff_ffff);
+        assert!(PeImage::parse_file(&data).unwrap().exports().is_err());
+        let data = patched(export + 24, 0x4000_0000);
+        assert!(PeImage::parse_file(&data).unwrap().exports().is_err());
+
+        //import lookup table without terminator at the end of the file
+        let import = image
+            .rva_to_offset(
+                image
+                    .data_directory(IMAGE_DIRECTORY_ENTRY_IMPORT)
+                    .unwrap()
+                    .virtual_address,
+            )
+            .unwrap();
+        let data = patched(import, 0xffff_fffc);
+        assert!(PeImage::parse_file(&data).unwrap().imports().is_err());
+    }
+
+    #[test]
+    fn sections_near_the_end_of_the_address_space() {
+        let section = Section {
+            name: ".wrap".to_string(),
+            virtual_size: 0x2000,
+            virtual_address: 0xffff_f000,
+            size_of_raw_data: 0x200,
+            pointer_to_raw_data: 0xffff_ff00,
+            characteristics: 0,
+        };
+        assert!(!section.contains_rva(0xffff_f800));
+        assert!(!section.contains_rva(0x800));
+
+        let mut image = PeImage::parse_file(TINY).unwrap();
+        image.sections[0].pointer_to_raw_data = 0xffff_ff00;
+        assert!(image.rva_to_offset(0x1100).is_err());
+        assert!(image.read_u32_at_rva(0x1000).is_err());
+    }
+}
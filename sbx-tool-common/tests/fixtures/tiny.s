@@ -0,0 +1,57 @@
+# Source of tiny.dll, the PE32 fixture of the pe parser tests.
+# Built with llvm-mc, llvm-dlltool and lld:
+#   llvm-mc -triple i686-pc-windows-msvc -filetype=obj tiny.s -o tiny.obj
+#   llvm-dlltool -k -m i386 -d kernel32.def -l kernel32.lib
+#   lld-link /dll /entry:DllMain@12 /def:tiny.def /release /Brepro /nodefaultlib /machine:x86 \
+#       /safeseh:no /out:tiny.dll tiny.obj kernel32.lib
+    .text
+    .globl _DllMain@12
+_DllMain@12:
+    movl $1, %eax
+    retl $12
+
+    .globl _add
+_add:
+    movl 4(%esp), %eax
+    addl 8(%esp), %eax
+    retl
+
+    .globl _read_counter
+_read_counter:
+    movl _counter, %eax
+    calll *__imp__GetTickCount@0
+    pushl $0
+    calll *__imp__Sleep@4
+    addl _counter, %eax
+    retl
+
+_tls_callback:
+    retl $12
+
+    .data
+    .globl _counter
+_counter:
+    .long 0x12345678
+
+_tls_index:
+    .long 0
+
+    .section .tls$,"dw"
+_tls_start:
+    .long 0
+_tls_end:
+
+    .section .CRT$XLB,"dr"
+_tls_callbacks:
+    .long _tls_callback
+    .long 0
+
+    .section .rdata,"dr"
+    .globl __tls_used
+__tls_used:
+    .long _tls_start
+    .long _tls_end
+    .long _tls_index
+    .long _tls_callbacks
+    .long 0
+    .long 0
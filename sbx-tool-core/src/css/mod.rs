@@ -1,12 +1,36 @@
-pub mod css_context;
+use crate::memory::{GameMemory, GameMemoryExt};
+use crate::utility::hook::HookStats;
 use anyhow::Result;
-pub use css_context::CSSContext;
 use detour::RawDetour;
 use ilhook::x86::{CallbackOption, HookFlags, HookPoint, HookType, Hooker, Registers};
+pub use sbx_tool_common::css::CSSContext;
 use std::lazy::SyncOnceCell;
 use tracing::{event, Level};
 
 pub static CSSInitContextConstantsDetour: SyncOnceCell<RawDetour> = SyncOnceCell::new();
+pub static CSS_INIT_CONTEXT_CONSTANTS_HOOK_STATS: HookStats = HookStats::new();
+
+/// Address of the vs cpu `CSSContext`, None while not in the character select screen.
+/// `context_ptr_address` is module address + `vs_cpu_css_context_offset`
+pub fn css_context_address(
+    memory: &dyn GameMemory,
+    context_ptr_address: usize,
+) -> Result<Option<usize>> {
+    match memory.read_ptr(context_ptr_address)? {
+        0 => Ok(None),
+        address => Ok(Some(address)),
+    }
+}
+
+pub fn read_css_context(
+    memory: &dyn GameMemory,
+    context_ptr_address: usize,
+) -> Result<Option<CSSContext>> {
+    match css_context_address(memory, context_ptr_address)? {
+        Some(address) => Ok(Some(memory.read(address)?)),
+        None => Ok(None),
+    }
+}
 
 pub fn init_css_detours(module_address: usize) -> Result<()> {
     css_init_context_constants_detour(module_address)?;
@@ -16,8 +40,10 @@ pub fn init_css_detours(module_address: usize) -> Result<()> {
 fn css_init_context_constants_detour(module_address: usize) -> Result<()> {
     let detour = unsafe {
         RawDetour::new(
-            (module_address + sbx_offset::css::VS_CPU_CSS_INIT_CONTEXT_CONSTANTS_OFFSET)
-                as *const (),
+            (module_address
+                + sbx_offset::get()
+                    .css
+                    .vs_cpu_css_init_context_constants_offset) as *const (),
             __hook__css_init_context_constants_detour as *const (),
         )
     }?;
@@ -47,6 +73,7 @@ extern "fastcall" fn __hook__css_init_context_constants_detour(
     cpu_party_hp: usize,
     cpu_party_ex: usize,
 ) -> u8 {
+    CSS_INIT_CONTEXT_CONSTANTS_HOOK_STATS.hit();
     let trampoline = match CSSInitContextConstantsDetour.get() {
         Some(d) => {
             let t: FnCSSInitContextConstants = unsafe { std::mem::transmute(d.trampoline()) };
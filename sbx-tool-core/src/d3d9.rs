@@ -175,9 +175,16 @@ fn get_d3d_device(window_handle: HWND) -> Result<(*mut IDirect3D9, *mut IDirect3
         dummy_d3d_device_ptr as usize
     );
 
-    //todo maybe release later with
-    //unsafe { d3d.as_ref().unwrap().Release() };
-    // unsafe { dummy_d3d_device_ptr.as_ref().unwrap().Release() };
-
     Ok((d3d, dummy_d3d_device_ptr))
 }
+
+/// Release the dummy device and Direct3D9 made by `get_directx`
+pub unsafe fn release_directx(d3d: *mut IDirect3D9, device: *mut IDirect3DDevice9) {
+    if let Some(device) = device.as_ref() {
+        device.Release();
+    }
+    if let Some(d3d) = d3d.as_ref() {
+        d3d.Release();
+    }
+    event!(Level::DEBUG, "Released the dummy device");
+}
@@ -5,14 +5,19 @@
 pub mod battle;
 pub mod css;
 pub mod d3d9;
+pub mod memory;
+pub mod reflect;
+pub mod scene;
+pub mod script;
 pub mod utility;
+pub use sbx_tool_common::{asm, config, hotkey, input, style};
 use anyhow::Result;
 use ilhook::x86::{CallbackOption, HookFlags, HookPoint, HookType, Hooker, Registers};
 use nameof::name_of;
-use phf::{phf_map, Map};
 use std::lazy::SyncOnceCell;
 use std::sync::atomic::{AtomicU32, Ordering};
 use tracing::{event, Level};
+use utility::hook::HookStats;
 use winapi::shared::minwindef::{DWORD, LPVOID};
 use winapi::shared::windef::HWND;
 use winapi::um::fileapi::CreateFileA;
@@ -21,8 +26,14 @@ use winapi::um::winnt::{HANDLE, LPCSTR};
 use winapi::um::winuser::{PeekMessageA, LPMSG, MSG};
 use winapi_mon_core::fileapi::CreateFileADetour;
 
+pub static MAIN_LOOP_INNER_HOOK_STATS: HookStats = HookStats::new();
+pub static GAME_LOOP_INNER_HOOK_STATS: HookStats = HookStats::new();
+pub static UI_LOOP_INNER_HOOK_STATS: HookStats = HookStats::new();
+pub static CREATE_FILE_A_HOOK_STATS: HookStats = HookStats::new();
+
 pub fn init_main_loop_inner_hook(module_address: usize) -> Result<Hooker> {
-    let main_loop_inner_address = module_address as usize + sbx_offset::MAIN_LOOP_INNER_OFFSET;
+    let main_loop_inner_address =
+        module_address as usize + sbx_offset::get().main_loop_inner_offset;
 
     event!(
         Level::INFO,
@@ -41,6 +52,7 @@ pub fn init_main_loop_inner_hook(module_address: usize) -> Result<Hooker> {
 
 /// sbx main message loop
 extern "cdecl" fn __hook__main_loop_inner(regs: *mut Registers, _: usize) {
+    MAIN_LOOP_INNER_HOOK_STATS.hit();
     //https://docs.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-peekmessagea
     /* MSG
        hwnd: HWND,
@@ -94,7 +106,8 @@ extern "cdecl" fn __hook__main_loop_inner(regs: *mut Registers, _: usize) {
 }
 
 pub fn init_game_loop_inner_hook(module_address: usize) -> Result<Hooker> {
-    let game_loop_inner_address = module_address as usize + sbx_offset::GAME_LOOP_INNER_OFFSET;
+    let game_loop_inner_address =
+        module_address as usize + sbx_offset::get().game_loop_inner_offset;
 
     event!(
         Level::INFO,
@@ -113,6 +126,7 @@ pub fn init_game_loop_inner_hook(module_address: usize) -> Result<Hooker> {
 
 /// sbx main message loop
 extern "cdecl" fn __hook__game_loop_inner(regs: *mut Registers, _: usize) {
+    GAME_LOOP_INNER_HOOK_STATS.hit();
     let mut msg: MSG = MSG::default();
     let result = unsafe { PeekMessageA(&mut msg, 0 as HWND, 0, 0, 0) };
     if result != 0 {
@@ -157,30 +171,13 @@ extern "cdecl" fn __hook__game_loop_inner(regs: *mut Registers, _: usize) {
 
 static UI_MAIN_LOOP_SWITCH_FLAG_ADDRESS: SyncOnceCell<usize> = SyncOnceCell::new();
 static UI_MAIN_LOOP_FIRST_SWITCH_CASE_BEFORE: AtomicU32 = AtomicU32::new(77777);
-pub static UI_MAIN_LOOP_FIRST_SWITCH_CASE_NAME_MAP: Map<u32, &'static str> = phf_map! {
-    23u32 => "CONFIG",
-    24u32 => "SAVE_LOAD",
-    26u32 => "ESCAPE",
-    95u32 => "BRAVE_MODE_SSS",
-    96u32 => "BRAVE_MODE_CSS",
-    97u32 => "VS_CPU_MODE_CSS",
-    98u32 => "VS_CPU_MODE_SSS",
-    99u32 => "BATTLE",
-};
-
-fn get_ui_main_loop_first_switch_case_name(case: u32) -> &'static str {
-    match UI_MAIN_LOOP_FIRST_SWITCH_CASE_NAME_MAP.get(&case) {
-        Some(n) => n,
-        None => "Unknown",
-    }
-}
 
 pub fn init_ui_loop_inner_hook(module_address: usize) -> Result<Hooker> {
+    //hook can be re-created by HookManager
     UI_MAIN_LOOP_SWITCH_FLAG_ADDRESS
-        .set(module_address + sbx_offset::UI_LOOP_SWITCH_FLAG_OFFSET)
-        .unwrap(); //lazy to handler the error, todo
+        .get_or_init(|| module_address + sbx_offset::get().ui_loop_switch_flag_offset);
 
-    let ui_loop_inner_address = module_address as usize + sbx_offset::UI_LOOP_INNER_OFFSET;
+    let ui_loop_inner_address = module_address as usize + sbx_offset::get().ui_loop_inner_offset;
 
     event!(
         Level::INFO,
@@ -199,6 +196,7 @@ pub fn init_ui_loop_inner_hook(module_address: usize) -> Result<Hooker> {
 
 /// sbx main message loop
 extern "cdecl" fn __hook__ui_loop_inner(regs: *mut Registers, _: usize) {
+    UI_LOOP_INNER_HOOK_STATS.hit();
     let flag_address = *UI_MAIN_LOOP_SWITCH_FLAG_ADDRESS.get().unwrap(); //already initialized by init hook function
     let case = unsafe { *(flag_address as *const u32) };
     let prev_case = UI_MAIN_LOOP_FIRST_SWITCH_CASE_BEFORE.load(Ordering::Relaxed);
@@ -211,9 +209,10 @@ extern "cdecl" fn __hook__ui_loop_inner(regs: *mut Registers, _: usize) {
     event!(
         Level::INFO,
         "[UI Main Loop] Switch Case: {}({})",
-        get_ui_main_loop_first_switch_case_name(case),
+        scene::Scene::from_case(case).name(),
         case
     );
+    scene::update_scene(case);
 }
 
 pub extern "system" fn __hook__CreateFileA(
@@ -225,6 +224,7 @@ pub extern "system" fn __hook__CreateFileA(
     dwFlagsAndAttributes: DWORD,
     hTemplateFile: HANDLE,
 ) -> HANDLE {
+    CREATE_FILE_A_HOOK_STATS.hit();
     let file_name = unsafe { std::ffi::CStr::from_ptr(lpFileName) };
 
     let creation_disposition = match dwCreationDisposition {
@@ -0,0 +1,231 @@
+use super::{GameMemory, Protection, Region};
+use anyhow::{anyhow, Result};
+use nameof::name_of;
+use winapi::shared::minwindef::{DWORD, FALSE, LPCVOID, LPVOID};
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::memoryapi::{
+    ReadProcessMemory, VirtualAllocEx, VirtualFreeEx, VirtualProtectEx, VirtualQueryEx,
+    WriteProcessMemory,
+};
+use winapi::um::processthreadsapi::OpenProcess;
+use winapi::um::tlhelp32::{
+    CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, MODULEENTRY32W, TH32CS_SNAPMODULE,
+    TH32CS_SNAPMODULE32,
+};
+use winapi::um::winnt::{
+    HANDLE, MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_RELEASE, MEM_RESERVE,
+    PROCESS_QUERY_INFORMATION, PROCESS_VM_OPERATION, PROCESS_VM_READ, PROCESS_VM_WRITE,
+};
+
+/// Memory of another process, for running the tool as an external trainer
+pub struct ExternalProcessMemory {
+    pid: u32,
+    handle: HANDLE,
+}
+
+//HANDLE is a process handle, which can be used from any thread
+unsafe impl Send for ExternalProcessMemory {}
+unsafe impl Sync for ExternalProcessMemory {}
+
+impl ExternalProcessMemory {
+    pub fn open(pid: u32) -> Result<Self> {
+        Self::open_with_access(pid, 0)
+    }
+
+    /// `extra_access` is added to the rights reading and writing needs,
+    /// e.g. PROCESS_CREATE_THREAD for an injector
+    pub fn open_with_access(pid: u32, extra_access: DWORD) -> Result<Self> {
+        let handle = unsafe {
+            OpenProcess(
+                PROCESS_VM_READ
+                    | PROCESS_VM_WRITE
+                    | PROCESS_VM_OPERATION
+                    | PROCESS_QUERY_INFORMATION
+                    | extra_access,
+                FALSE,
+                pid,
+            )
+        };
+        if handle.is_null() {
+            return Err(anyhow!("{} failed for pid {}", name_of!(OpenProcess), pid));
+        }
+        Ok(ExternalProcessMemory { pid, handle })
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    pub fn handle(&self) -> HANDLE {
+        self.handle
+    }
+
+    /// Base address of a module loaded in the process
+    pub fn module_address(&self, module: &str) -> Result<usize> {
+        let snapshot =
+            unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32, self.pid) };
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(anyhow!(
+                "{} failed for pid {}",
+                name_of!(CreateToolhelp32Snapshot),
+                self.pid
+            ));
+        }
+        let mut entry: MODULEENTRY32W = unsafe { std::mem::zeroed() };
+        entry.dwSize = std::mem::size_of::<MODULEENTRY32W>() as DWORD;
+        let mut found = None;
+        let mut ok = unsafe { Module32FirstW(snapshot, &mut entry) };
+        while ok != 0 {
+            let len = entry
+                .szModule
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(entry.szModule.len());
+            let name = String::from_utf16_lossy(&entry.szModule[..len]);
+            if name.eq_ignore_ascii_case(module) {
+                found = Some(entry.modBaseAddr as usize);
+                break;
+            }
+            ok = unsafe { Module32NextW(snapshot, &mut entry) };
+        }
+        unsafe { CloseHandle(snapshot) };
+        found.ok_or_else(|| anyhow!("module {} not found in pid {}", module, self.pid))
+    }
+}
+
+impl Drop for ExternalProcessMemory {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.handle) };
+    }
+}
+
+impl GameMemory for ExternalProcessMemory {
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<()> {
+        let mut read = 0;
+        let ok = unsafe {
+            ReadProcessMemory(
+                self.handle,
+                address as LPCVOID,
+                buffer.as_mut_ptr() as LPVOID,
+                buffer.len(),
+                &mut read,
+            )
+        };
+        if ok == 0 || read != buffer.len() {
+            return Err(anyhow!(
+                "{} failed at {:x}",
+                name_of!(ReadProcessMemory),
+                address
+            ));
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&self, address: usize, bytes: &[u8]) -> Result<()> {
+        let mut written = 0;
+        let ok = unsafe {
+            WriteProcessMemory(
+                self.handle,
+                address as LPVOID,
+                bytes.as_ptr() as LPCVOID,
+                bytes.len(),
+                &mut written,
+            )
+        };
+        if ok == 0 || written != bytes.len() {
+            return Err(anyhow!(
+                "{} failed at {:x}",
+                name_of!(WriteProcessMemory),
+                address
+            ));
+        }
+        Ok(())
+    }
+
+    fn query(&self, address: usize) -> Result<Region> {
+        let mut info: MEMORY_BASIC_INFORMATION = unsafe { std::mem::zeroed() };
+        let size = unsafe {
+            VirtualQueryEx(
+                self.handle,
+                address as LPCVOID,
+                &mut info,
+                std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            )
+        };
+        if size == 0 {
+            return Err(anyhow!(
+                "{} failed at {:x}",
+                name_of!(VirtualQueryEx),
+                address
+            ));
+        }
+        Ok(Region {
+            base: info.BaseAddress as usize,
+            size: info.RegionSize,
+            committed: info.State == MEM_COMMIT,
+            protection: Protection(info.Protect),
+        })
+    }
+
+    fn protect(&self, address: usize, size: usize, protection: Protection) -> Result<Protection> {
+        let mut old_protect = DWORD::default();
+        let ok = unsafe {
+            VirtualProtectEx(
+                self.handle,
+                address as LPVOID,
+                size,
+                protection.0,
+                &mut old_protect,
+            )
+        };
+        if ok == 0 {
+            return Err(anyhow!(
+                "{} failed at {:x}",
+                name_of!(VirtualProtectEx),
+                address
+            ));
+        }
+        Ok(Protection(old_protect))
+    }
+
+    fn allocate(
+        &self,
+        address: Option<usize>,
+        size: usize,
+        protection: Protection,
+    ) -> Result<usize> {
+        let allocated = unsafe {
+            VirtualAllocEx(
+                self.handle,
+                address.unwrap_or(0) as LPVOID,
+                size,
+                MEM_COMMIT | MEM_RESERVE,
+                protection.0,
+            )
+        } as usize;
+        if allocated == 0 {
+            return Err(anyhow!(
+                "{} failed for {:x} bytes at {:x?}",
+                name_of!(VirtualAllocEx),
+                size,
+                address
+            ));
+        }
+        Ok(allocated)
+    }
+
+    fn free(&self, address: usize) -> Result<()> {
+        if unsafe { VirtualFreeEx(self.handle, address as LPVOID, 0, MEM_RELEASE) } == 0 {
+            return Err(anyhow!(
+                "{} failed at {:x}",
+                name_of!(VirtualFreeEx),
+                address
+            ));
+        }
+        Ok(())
+    }
+
+    fn patch_code(&self, patches: &[(usize, &[u8])]) -> Result<()> {
+        super::threads::patch_code_suspended(self, self.handle, self.pid, None, patches)
+    }
+}
@@ -0,0 +1,107 @@
+use super::{null_check, GameMemory, Protection, Region};
+use anyhow::{anyhow, Result};
+use nameof::name_of;
+use winapi::shared::minwindef::{DWORD, LPVOID};
+use winapi::um::memoryapi::{VirtualAlloc, VirtualFree, VirtualProtect, VirtualQuery};
+use winapi::um::processthreadsapi::{GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId};
+use winapi::um::winnt::{MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_RELEASE, MEM_RESERVE};
+
+/// Memory of the process the dll is injected into.
+/// Reads and writes are plain pointer accesses, so the caller has to know the address is valid.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InProcessMemory;
+
+impl GameMemory for InProcessMemory {
+    fn read_bytes(&self, address: usize, buffer: &mut [u8]) -> Result<()> {
+        null_check(address)?;
+        unsafe {
+            std::ptr::copy_nonoverlapping(address as *const u8, buffer.as_mut_ptr(), buffer.len())
+        };
+        Ok(())
+    }
+
+    fn write_bytes(&self, address: usize, bytes: &[u8]) -> Result<()> {
+        null_check(address)?;
+        unsafe { std::ptr::copy_nonoverlapping(bytes.as_ptr(), address as *mut u8, bytes.len()) };
+        Ok(())
+    }
+
+    fn query(&self, address: usize) -> Result<Region> {
+        let mut info: MEMORY_BASIC_INFORMATION = unsafe { std::mem::zeroed() };
+        let size = unsafe {
+            VirtualQuery(
+                address as LPVOID,
+                &mut info,
+                std::mem::size_of::<MEMORY_BASIC_INFORMATION>(),
+            )
+        };
+        if size == 0 {
+            return Err(anyhow!(
+                "{} failed at {:x}",
+                name_of!(VirtualQuery),
+                address
+            ));
+        }
+        Ok(Region {
+            base: info.BaseAddress as usize,
+            size: info.RegionSize,
+            committed: info.State == MEM_COMMIT,
+            protection: Protection(info.Protect),
+        })
+    }
+
+    fn protect(&self, address: usize, size: usize, protection: Protection) -> Result<Protection> {
+        let mut old_protect = DWORD::default();
+        if unsafe { VirtualProtect(address as LPVOID, size, protection.0, &mut old_protect) } == 0 {
+            return Err(anyhow!(
+                "{} failed at {:x}",
+                name_of!(VirtualProtect),
+                address
+            ));
+        }
+        Ok(Protection(old_protect))
+    }
+
+    fn allocate(
+        &self,
+        address: Option<usize>,
+        size: usize,
+        protection: Protection,
+    ) -> Result<usize> {
+        let allocated = unsafe {
+            VirtualAlloc(
+                address.unwrap_or(0) as LPVOID,
+                size,
+                MEM_COMMIT | MEM_RESERVE,
+                protection.0,
+            )
+        } as usize;
+        if allocated == 0 {
+            return Err(anyhow!(
+                "{} failed for {:x} bytes at {:x?}",
+                name_of!(VirtualAlloc),
+                size,
+                address
+            ));
+        }
+        Ok(allocated)
+    }
+
+    fn free(&self, address: usize) -> Result<()> {
+        if unsafe { VirtualFree(address as LPVOID, 0, MEM_RELEASE) } == 0 {
+            return Err(anyhow!("{} failed at {:x}", name_of!(VirtualFree), address));
+        }
+        Ok(())
+    }
+
+    fn patch_code(&self, patches: &[(usize, &[u8])]) -> Result<()> {
+        //keep running on this thread, it is the one writing
+        super::threads::patch_code_suspended(
+            self,
+            unsafe { GetCurrentProcess() },
+            unsafe { GetCurrentProcessId() },
+            Some(unsafe { GetCurrentThreadId() }),
+            patches,
+        )
+    }
+}
@@ -0,0 +1,6 @@
+pub mod external;
+pub mod local;
+mod threads;
+pub use external::ExternalProcessMemory;
+pub use local::InProcessMemory;
+pub use sbx_tool_common::memory::*;
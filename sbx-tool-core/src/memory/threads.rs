@@ -0,0 +1,170 @@
+use super::{GameMemory, Protection};
+use anyhow::{anyhow, Result};
+use nameof::name_of;
+use winapi::shared::minwindef::{DWORD, FALSE, LPCVOID};
+use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+use winapi::um::processthreadsapi::{
+    FlushInstructionCache, GetThreadContext, OpenThread, ResumeThread, SuspendThread,
+};
+use winapi::um::tlhelp32::{
+    CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32,
+};
+use winapi::um::winnt::{
+    CONTEXT, CONTEXT_CONTROL, HANDLE, THREAD_GET_CONTEXT, THREAD_SUSPEND_RESUME,
+};
+
+//How often to retry when a thread is stopped inside a patched range
+const MAX_ATTEMPTS: usize = 20;
+
+/// Threads of a process, resumed on drop.
+/// Nothing may allocate while they are suspended, a suspended thread can hold the heap lock.
+struct SuspendedThreads {
+    handles: Vec<HANDLE>,
+}
+
+impl SuspendedThreads {
+    /// Suspend every thread of `pid` except `skip_tid`.
+    /// Threads created after the snapshot keep running.
+    fn suspend(pid: u32, skip_tid: Option<u32>) -> Result<Self> {
+        let mut handles = Vec::with_capacity(64);
+        for tid in thread_ids(pid)? {
+            if Some(tid) == skip_tid {
+                continue;
+            }
+            let handle =
+                unsafe { OpenThread(THREAD_SUSPEND_RESUME | THREAD_GET_CONTEXT, FALSE, tid) };
+            if !handle.is_null() {
+                handles.push(handle);
+            }
+        }
+        //from here on no allocation
+        let mut suspended = SuspendedThreads {
+            handles: Vec::with_capacity(handles.len()),
+        };
+        for handle in handles {
+            if unsafe { SuspendThread(handle) } == DWORD::MAX {
+                //exited in the meantime
+                unsafe { CloseHandle(handle) };
+                continue;
+            }
+            suspended.handles.push(handle);
+        }
+        Ok(suspended)
+    }
+
+    /// True if a thread is about to execute an instruction in one of the ranges.
+    /// A thread whose context can't be read counts as inside.
+    fn any_inside(&self, patches: &[(usize, &[u8])]) -> bool {
+        self.handles.iter().any(|handle| {
+            let mut context: CONTEXT = unsafe { std::mem::zeroed() };
+            context.ContextFlags = CONTEXT_CONTROL;
+            if unsafe { GetThreadContext(*handle, &mut context) } == 0 {
+                return true;
+            }
+            #[cfg(target_arch = "x86")]
+            let ip = context.Eip as usize;
+            #[cfg(target_arch = "x86_64")]
+            let ip = context.Rip as usize;
+            patches
+                .iter()
+                .any(|(address, bytes)| ip >= *address && ip < address + bytes.len())
+        })
+    }
+}
+
+impl Drop for SuspendedThreads {
+    fn drop(&mut self) {
+        for handle in &self.handles {
+            unsafe {
+                ResumeThread(*handle);
+                CloseHandle(*handle);
+            }
+        }
+    }
+}
+
+fn thread_ids(pid: u32) -> Result<Vec<u32>> {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) };
+    if snapshot == INVALID_HANDLE_VALUE {
+        return Err(anyhow!("{} failed", name_of!(CreateToolhelp32Snapshot)));
+    }
+    let mut entry: THREADENTRY32 = unsafe { std::mem::zeroed() };
+    entry.dwSize = std::mem::size_of::<THREADENTRY32>() as DWORD;
+    let mut ids = Vec::new();
+    let mut ok = unsafe { Thread32First(snapshot, &mut entry) };
+    while ok != 0 {
+        if entry.th32OwnerProcessID == pid {
+            ids.push(entry.th32ThreadID);
+        }
+        ok = unsafe { Thread32Next(snapshot, &mut entry) };
+    }
+    unsafe { CloseHandle(snapshot) };
+    Ok(ids)
+}
+
+/// `GameMemory::patch_code` for the windows backends.
+/// Pages are made writable before and restored after the threads are suspended,
+/// so only the plain writes happen while the game is stopped.
+pub(crate) fn patch_code_suspended<M: GameMemory + ?Sized>(
+    memory: &M,
+    process: HANDLE,
+    pid: u32,
+    skip_tid: Option<u32>,
+    patches: &[(usize, &[u8])],
+) -> Result<()> {
+    let mut old_protections = Vec::with_capacity(patches.len());
+    for (address, bytes) in patches {
+        match memory.protect(*address, bytes.len(), Protection::EXECUTE_READWRITE) {
+            Ok(p) => old_protections.push(p),
+            Err(e) => {
+                restore_protections(memory, patches, &old_protections);
+                return Err(e);
+            }
+        }
+    }
+
+    let result = write_suspended(memory, pid, skip_tid, patches);
+
+    restore_protections(memory, patches, &old_protections);
+    for (address, bytes) in patches {
+        unsafe { FlushInstructionCache(process, *address as LPCVOID, bytes.len()) };
+    }
+    result
+}
+
+fn write_suspended<M: GameMemory + ?Sized>(
+    memory: &M,
+    pid: u32,
+    skip_tid: Option<u32>,
+    patches: &[(usize, &[u8])],
+) -> Result<()> {
+    for _ in 0..MAX_ATTEMPTS {
+        let threads = SuspendedThreads::suspend(pid, skip_tid)?;
+        if threads.any_inside(patches) {
+            //let it run out of the range
+            drop(threads);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            continue;
+        }
+        for (address, bytes) in patches {
+            memory.write_bytes(*address, bytes)?;
+        }
+        return Ok(());
+    }
+    Err(anyhow!(
+        "a thread stayed inside the patched code for {} attempts",
+        MAX_ATTEMPTS
+    ))
+}
+
+fn restore_protections<M: GameMemory + ?Sized>(
+    memory: &M,
+    patches: &[(usize, &[u8])],
+    old_protections: &[Protection],
+) {
+    //reversed, sites on the same page saw the protection the previous one set
+    for (i, protection) in old_protections.iter().enumerate().rev() {
+        let (address, bytes) = patches[i];
+        let _ = memory.protect(address, bytes.len(), *protection);
+    }
+}
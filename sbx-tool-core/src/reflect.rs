@@ -0,0 +1,28 @@
+//! Where the described game structures live, the descriptions are in `sbx_tool_common::reflect`.
+use crate::battle::BattleContext;
+use crate::css::CSSContext;
+use crate::memory::PointerPath;
+use sbx_offset::OffsetTable;
+pub use sbx_tool_common::reflect::*;
+
+/// Where a structure lives in the game
+pub struct InspectorRoot {
+    pub name: &'static str,
+    pub info: &'static StructInfo,
+    pub path: PointerPath,
+}
+
+pub fn inspector_roots(offsets: &OffsetTable) -> Vec<InspectorRoot> {
+    vec![
+        InspectorRoot {
+            name: "Battle Context",
+            info: BattleContext::info(),
+            path: PointerPath::module(offsets.battle.battle_context_offset, &[]),
+        },
+        InspectorRoot {
+            name: "CSS Context",
+            info: CSSContext::info(),
+            path: PointerPath::module(offsets.css.vs_cpu_css_context_offset, &[0]),
+        },
+    ]
+}
@@ -0,0 +1,33 @@
+use crate::utility::event_bus::EventBus;
+use parking_lot::{const_mutex, Mutex};
+pub use sbx_tool_common::scene::*;
+use std::lazy::SyncOnceCell;
+
+static SCENE_TRACKER: Mutex<SceneTracker> = const_mutex(SceneTracker::new());
+static SCENE_EVENTS: SyncOnceCell<EventBus<SceneEvent>> = SyncOnceCell::new();
+
+/// Events published by the ui loop hook
+pub fn scene_events() -> &'static EventBus<SceneEvent> {
+    SCENE_EVENTS.get_or_init(EventBus::new)
+}
+
+pub fn current_scene() -> Option<Scene> {
+    SCENE_TRACKER.lock().current()
+}
+
+pub fn is_in_scene(scene: Scene) -> bool {
+    current_scene() == Some(scene)
+}
+
+/// Oldest first
+pub fn scene_history() -> Vec<SceneTransition> {
+    SCENE_TRACKER.lock().history().to_vec()
+}
+
+/// Called from the ui loop hook with the switch case
+pub(crate) fn update_scene(case: u32) {
+    let events = SCENE_TRACKER.lock().update(Scene::from_case(case));
+    for e in &events {
+        scene_events().publish(e);
+    }
+}
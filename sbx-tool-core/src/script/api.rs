@@ -0,0 +1,375 @@
+//! Functions the scripts can call.
+//!   log(text), warn(text), print(text), debug(value)
+//!   read_u8/u16/u32/i32/f32/ptr(address), write_u8/u16/u32/i32/f32/ptr(address, value), module_base()
+//!   battle_context(), set_battle_context(field, value), css_context(), set_css_context(field, value)
+//!   battle(), set_hp/set_ex/set_rush_count/set_score(side, value)
+//!   scene(), ui_case(), battle_state(), battle_case()
+//!   patches(), patch_enabled(name), enable_patch(name), disable_patch(name)
+//! Sides are "player" and "cpu". Something that isn't there, like `battle()` outside a battle, is ().
+use super::{ConsoleLevel, ScriptContext, Shared};
+use crate::battle::{BattleAccess, BattleContext, PlayerSnapshot, Side};
+use crate::css::{css_context_address, CSSContext};
+use crate::memory::viewer::write_protected;
+use crate::memory::{GameMemory, GameMemoryExt, Interpretation};
+use crate::reflect::{FieldInfo, FieldKind, GameStruct, StructInfo};
+use crate::scene::{current_scene, Scene};
+use parking_lot::Mutex;
+use rhai::{
+    Array, Dynamic, Engine, EvalAltResult, ImmutableString, Map, NativeCallContext, FLOAT, INT,
+};
+use std::fmt::Display;
+use std::sync::Arc;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+fn script_error(e: impl Display) -> Box<EvalAltResult> {
+    e.to_string().into()
+}
+
+pub(super) fn side_name(side: Side) -> &'static str {
+    match side {
+        Side::Player => "player",
+        Side::CPU => "cpu",
+    }
+}
+
+fn parse_side(name: &str) -> ScriptResult<Side> {
+    match name {
+        "player" => Ok(Side::Player),
+        "cpu" => Ok(Side::CPU),
+        _ => Err(script_error(format!(
+            "'{}' is not a side, use \"player\" or \"cpu\"",
+            name
+        ))),
+    }
+}
+
+fn to_address(address: INT) -> ScriptResult<usize> {
+    usize::try_from(address).map_err(|_| script_error(format!("{:x} is not an address", address)))
+}
+
+fn to_int<T: TryFrom<INT>>(value: INT) -> ScriptResult<T> {
+    T::try_from(value).map_err(|_| script_error(format!("{} is out of range", value)))
+}
+
+/// `bytes` as a script value, floats for f32 and integers for the rest
+fn to_dynamic(interpretation: Interpretation, bytes: &[u8]) -> Dynamic {
+    match interpretation {
+        Interpretation::F32 => {
+            let mut b = [0u8; 4];
+            b.copy_from_slice(&bytes[..4]);
+            Dynamic::from_float(f32::from_le_bytes(b) as FLOAT)
+        }
+        Interpretation::I32 => {
+            let mut b = [0u8; 4];
+            b.copy_from_slice(&bytes[..4]);
+            Dynamic::from_int(i32::from_le_bytes(b) as INT)
+        }
+        _ => {
+            let mut b = [0u8; 8];
+            let size = interpretation.size();
+            b[..size].copy_from_slice(&bytes[..size]);
+            Dynamic::from_int(u64::from_le_bytes(b) as INT)
+        }
+    }
+}
+
+/// Bytes of a script value, with the same range checks as a value typed into the memory viewer
+fn to_bytes(interpretation: Interpretation, value: &Dynamic) -> ScriptResult<Vec<u8>> {
+    let text = if let Ok(v) = value.as_int() {
+        match interpretation {
+            Interpretation::Pointer => format!("0x{:x}", v),
+            _ => v.to_string(),
+        }
+    } else if let Ok(v) = value.as_float() {
+        if interpretation != Interpretation::F32 {
+            return Err(script_error(format!(
+                "{} takes an integer, not {}",
+                interpretation.name(),
+                v
+            )));
+        }
+        v.to_string()
+    } else {
+        return Err(script_error(format!(
+            "{} is not a number",
+            value.type_name()
+        )));
+    };
+    interpretation.parse(&text).map_err(script_error)
+}
+
+fn read_value(
+    memory: &dyn GameMemory,
+    address: INT,
+    interpretation: Interpretation,
+) -> ScriptResult<Dynamic> {
+    let bytes = memory
+        .read_vec(to_address(address)?, interpretation.size())
+        .map_err(script_error)?;
+    Ok(to_dynamic(interpretation, &bytes))
+}
+
+fn write_value(
+    memory: &dyn GameMemory,
+    address: INT,
+    interpretation: Interpretation,
+    value: &Dynamic,
+) -> ScriptResult<()> {
+    let bytes = to_bytes(interpretation, value)?;
+    write_protected(memory, to_address(address)?, &bytes).map_err(script_error)
+}
+
+fn field_interpretation(field: &FieldInfo) -> Interpretation {
+    match field.kind {
+        FieldKind::Value(interpretation) => interpretation,
+        FieldKind::Pointer(_) => Interpretation::Pointer,
+    }
+}
+
+/// Field name -> value, `unk_*` fields included
+fn read_struct(memory: &dyn GameMemory, info: &StructInfo, address: usize) -> ScriptResult<Map> {
+    let bytes = memory.read_vec(address, info.size).map_err(script_error)?;
+    let mut map = Map::new();
+    for field in &info.fields {
+        let value = to_dynamic(field_interpretation(field), &bytes[field.offset..]);
+        map.insert(field.name.into(), value);
+    }
+    Ok(map)
+}
+
+fn write_field(
+    memory: &dyn GameMemory,
+    info: &StructInfo,
+    address: usize,
+    name: &str,
+    value: &Dynamic,
+) -> ScriptResult<()> {
+    let field = info
+        .fields
+        .iter()
+        .find(|f| f.name == name)
+        .ok_or_else(|| script_error(format!("{} has no field {}", info.name, name)))?;
+    let bytes = to_bytes(field_interpretation(field), value)?;
+    write_protected(memory, address + field.offset, &bytes).map_err(script_error)
+}
+
+fn player_map(player: &PlayerSnapshot) -> Map {
+    let mut map = Map::new();
+    map.insert("hp".into(), (player.current_hp as INT).into());
+    map.insert("initial_hp".into(), (player.initial_hp as INT).into());
+    map.insert("ex".into(), (player.current_ex as INT).into());
+    map.insert("max_ex".into(), (player.max_ex as INT).into());
+    map.insert("rush_count".into(), (player.rush_count as INT).into());
+    map.insert("score".into(), (player.score as INT).into());
+    map
+}
+
+fn battle_map(context: &ScriptContext) -> ScriptResult<Dynamic> {
+    let battle = BattleAccess::new(&*context.memory, context.battle_addresses);
+    if !battle.is_in_battle() {
+        return Ok(Dynamic::UNIT);
+    }
+    let snapshot = battle.snapshot().map_err(script_error)?;
+    let mut map = Map::new();
+    map.insert("state".into(), snapshot.state.name().into());
+    map.insert("player".into(), player_map(&snapshot.player).into());
+    map.insert("cpu".into(), player_map(&snapshot.cpu).into());
+    Ok(map.into())
+}
+
+/// None while not in the character select screen
+fn css_address(context: &ScriptContext) -> ScriptResult<Option<usize>> {
+    if current_scene() != Some(Scene::VsCpuModeCSS) {
+        return Ok(None);
+    }
+    css_context_address(&*context.memory, context.css_context_ptr_address).map_err(script_error)
+}
+
+fn known_patch(shared: &Mutex<Shared>, name: &str) -> ScriptResult<bool> {
+    shared
+        .lock()
+        .patches
+        .get(name)
+        .copied()
+        .ok_or_else(|| script_error(format!("unknown patch '{}'", name)))
+}
+
+fn switch_patch(shared: &Mutex<Shared>, name: &str, on_off: bool) -> ScriptResult<()> {
+    known_patch(shared, name)?;
+    let mut shared = shared.lock();
+    //seen by patch_enabled right away, switched after the tick
+    shared.patches.insert(name.to_owned(), on_off);
+    shared.patch_requests.push((name.to_owned(), on_off));
+    Ok(())
+}
+
+/// The calling script, None for the console
+fn script_name(ctx: &NativeCallContext) -> Option<String> {
+    if let Some(source) = ctx.source() {
+        return Some(source.to_owned());
+    }
+    //callbacks are tagged with the script name, see `call`
+    ctx.tag().filter(|t| t.is_string()).map(|t| t.to_string())
+}
+
+fn log_line(shared: &Mutex<Shared>, level: ConsoleLevel, source: Option<String>, text: &str) {
+    let text = match source {
+        Some(source) => format!("{}: {}", source, text),
+        None => text.to_owned(),
+    };
+    shared.lock().log(level, text);
+}
+
+pub(super) fn register(engine: &mut Engine, context: ScriptContext, shared: Arc<Mutex<Shared>>) {
+    //logging
+    let s = shared.clone();
+    engine.on_print(move |text| log_line(&s, ConsoleLevel::Output, None, text));
+    let s = shared.clone();
+    engine.on_debug(move |text, source, _| {
+        log_line(&s, ConsoleLevel::Info, source.map(str::to_owned), text)
+    });
+    let s = shared.clone();
+    engine.register_fn("log", move |ctx: NativeCallContext, text: &str| {
+        log_line(&s, ConsoleLevel::Info, script_name(&ctx), text)
+    });
+    let s = shared.clone();
+    engine.register_fn("warn", move |ctx: NativeCallContext, text: &str| {
+        log_line(&s, ConsoleLevel::Warn, script_name(&ctx), text)
+    });
+
+    //raw memory
+    let module_address = context.module_address;
+    engine.register_fn("module_base", move || module_address as INT);
+    for (name, interpretation) in [
+        ("u8", Interpretation::U8),
+        ("u16", Interpretation::U16),
+        ("u32", Interpretation::U32),
+        ("i32", Interpretation::I32),
+        ("f32", Interpretation::F32),
+        ("ptr", Interpretation::Pointer),
+    ] {
+        let memory = context.memory.clone();
+        engine.register_fn(format!("read_{}", name), move |address: INT| {
+            read_value(&*memory, address, interpretation)
+        });
+        let memory = context.memory.clone();
+        engine.register_fn(
+            format!("write_{}", name),
+            move |address: INT, value: Dynamic| {
+                write_value(&*memory, address, interpretation, &value)
+            },
+        );
+    }
+
+    //game structures
+    let c = context.clone();
+    engine.register_fn("battle_context", move || {
+        read_struct(
+            &*c.memory,
+            BattleContext::info(),
+            c.battle_addresses.context,
+        )
+    });
+    let c = context.clone();
+    engine.register_fn("set_battle_context", move |field: &str, value: Dynamic| {
+        write_field(
+            &*c.memory,
+            BattleContext::info(),
+            c.battle_addresses.context,
+            field,
+            &value,
+        )
+    });
+    let c = context.clone();
+    engine.register_fn("css_context", move || -> ScriptResult<Dynamic> {
+        match css_address(&c)? {
+            Some(address) => Ok(read_struct(&*c.memory, CSSContext::info(), address)?.into()),
+            None => Ok(Dynamic::UNIT),
+        }
+    });
+    let c = context.clone();
+    engine.register_fn("set_css_context", move |field: &str, value: Dynamic| {
+        let address =
+            css_address(&c)?.ok_or_else(|| script_error("not in the character select screen"))?;
+        write_field(&*c.memory, CSSContext::info(), address, field, &value)
+    });
+
+    //battle
+    let c = context.clone();
+    engine.register_fn("battle", move || battle_map(&c));
+    let c = context.clone();
+    engine.register_fn("set_hp", move |side: &str, value: INT| {
+        BattleAccess::new(&*c.memory, c.battle_addresses)
+            .set_hp(parse_side(side)?, to_int(value)?)
+            .map_err(script_error)
+    });
+    let c = context.clone();
+    engine.register_fn("set_ex", move |side: &str, value: INT| {
+        BattleAccess::new(&*c.memory, c.battle_addresses)
+            .set_ex(parse_side(side)?, to_int(value)?)
+            .map_err(script_error)
+    });
+    let c = context.clone();
+    engine.register_fn("set_rush_count", move |side: &str, value: INT| {
+        BattleAccess::new(&*c.memory, c.battle_addresses)
+            .set_rush_count(parse_side(side)?, to_int(value)?)
+            .map_err(script_error)
+    });
+    let c = context.clone();
+    engine.register_fn("set_score", move |side: &str, value: INT| {
+        BattleAccess::new(&*c.memory, c.battle_addresses)
+            .set_score(parse_side(side)?, to_int(value)?)
+            .map_err(script_error)
+    });
+
+    //switch cases
+    engine.register_fn("scene", || match current_scene() {
+        Some(scene) => Dynamic::from(scene.name()),
+        None => Dynamic::UNIT,
+    });
+    engine.register_fn("ui_case", || match current_scene() {
+        Some(scene) => Dynamic::from_int(scene.case() as INT),
+        None => Dynamic::UNIT,
+    });
+    let c = context.clone();
+    engine.register_fn("battle_state", move || -> ScriptResult<Dynamic> {
+        if current_scene() != Some(Scene::Battle) {
+            return Ok(Dynamic::UNIT);
+        }
+        let state = BattleAccess::new(&*c.memory, c.battle_addresses)
+            .state()
+            .map_err(script_error)?;
+        Ok(state.name().into())
+    });
+    let c = context;
+    engine.register_fn("battle_case", move || -> ScriptResult<Dynamic> {
+        if current_scene() != Some(Scene::Battle) {
+            return Ok(Dynamic::UNIT);
+        }
+        let state = BattleAccess::new(&*c.memory, c.battle_addresses)
+            .state()
+            .map_err(script_error)?;
+        Ok(Dynamic::from_int(state.case() as INT))
+    });
+
+    //patches
+    let s = shared.clone();
+    engine.register_fn("patches", move || -> Array {
+        s.lock()
+            .patches
+            .keys()
+            .map(|name| Dynamic::from(ImmutableString::from(name.as_str())))
+            .collect()
+    });
+    let s = shared.clone();
+    engine.register_fn("patch_enabled", move |name: &str| known_patch(&s, name));
+    let s = shared.clone();
+    engine.register_fn("enable_patch", move |name: &str| {
+        switch_patch(&s, name, true)
+    });
+    let s = shared;
+    engine.register_fn("disable_patch", move |name: &str| {
+        switch_patch(&s, name, false)
+    });
+}
@@ -0,0 +1,402 @@
+//! Rhai scripts from the `scripts` folder next to the dll.
+//! Every `.rhai` file there is a script. Its top level runs when it is loaded,
+//! after that the tool calls the callbacks it defines, on the game thread:
+//! `on_load()`, `on_frame()`, `on_scene_change(from, to)`, `on_battle_start()`,
+//! `on_round_start()` and `on_battle_end(winner)`.
+//! Script functions can't see top level variables, `this` is a map kept per script
+//! for state between callbacks from `on_load` on. What scripts can call is in `api`.
+mod api;
+
+use crate::battle::{battle_events, BattleAddresses, BattleEvent};
+use crate::memory::GameMemory;
+use crate::scene::{scene_events, Scene, SceneEvent};
+use crate::utility::event_bus::SubscriptionId;
+use parking_lot::Mutex;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{event, Level};
+
+/// Directory of the scripts, looked up next to the dll
+pub const SCRIPT_DIR_NAME: &str = "scripts";
+
+const SCRIPT_EXTENSION: &str = "rhai";
+
+/// Operations one call may take, stops an endless loop before it hangs the game
+const MAX_OPERATIONS: u64 = 1_000_000;
+const MAX_CALL_LEVELS: usize = 32;
+
+/// Lines kept in the console
+const CONSOLE_LENGTH: usize = 500;
+
+/// Events kept until the next tick, the oldest are dropped if nothing ticks
+const EVENT_QUEUE_LENGTH: usize = 64;
+
+/// How often the script directory is checked for changed files
+const RELOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where the scripts reach the game
+#[derive(Clone)]
+pub struct ScriptContext {
+    pub memory: Arc<dyn GameMemory>,
+    pub module_address: usize,
+    pub battle_addresses: BattleAddresses,
+    /// module address + `vs_cpu_css_context_offset`
+    pub css_context_ptr_address: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleLevel {
+    /// Typed into the console
+    Input,
+    /// `print` and console results
+    Output,
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsoleLine {
+    pub level: ConsoleLevel,
+    pub text: String,
+}
+
+/// State the functions registered for the scripts share with the manager
+#[derive(Default)]
+struct Shared {
+    console: VecDeque<ConsoleLine>,
+    /// Patch name -> enabled, refreshed every tick
+    patches: BTreeMap<String, bool>,
+    /// Patch switches asked for by the scripts, returned by the tick
+    patch_requests: Vec<(String, bool)>,
+}
+
+impl Shared {
+    /// Add a console line, logged as well
+    fn log(&mut self, level: ConsoleLevel, text: String) {
+        match level {
+            ConsoleLevel::Input => {}
+            ConsoleLevel::Warn => event!(Level::WARN, "[script] {}", text),
+            ConsoleLevel::Error => event!(Level::ERROR, "[script] {}", text),
+            _ => event!(Level::INFO, "[script] {}", text),
+        }
+        if self.console.len() == CONSOLE_LENGTH {
+            self.console.pop_front();
+        }
+        self.console.push_back(ConsoleLine { level, text });
+    }
+}
+
+/// Published by the hooks, handled on the next tick
+enum ScriptEvent {
+    Scene(SceneEvent),
+    Battle(BattleEvent),
+}
+
+fn push_event(events: &Mutex<VecDeque<ScriptEvent>>, event: ScriptEvent) {
+    let mut events = events.lock();
+    if events.len() == EVENT_QUEUE_LENGTH {
+        events.pop_front();
+    }
+    events.push_back(event);
+}
+
+pub struct Script {
+    /// File name without the extension
+    pub name: String,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    /// None if it doesn't compile
+    ast: Option<AST>,
+    /// `this` of the callbacks
+    state: Dynamic,
+    /// Unchecked in the Scripts tab
+    pub enabled: bool,
+    /// Why the script stopped, cleared when it is reloaded
+    pub error: Option<String>,
+}
+
+impl Script {
+    pub fn is_running(&self) -> bool {
+        self.enabled && self.ast.is_some() && self.error.is_none()
+    }
+}
+
+/// Loads the scripts, reloads them when their file changes and calls their callbacks
+pub struct ScriptManager {
+    engine: Engine,
+    /// None if the dll has no directory
+    dir: Option<PathBuf>,
+    scripts: Vec<Script>,
+    shared: Arc<Mutex<Shared>>,
+    events: Arc<Mutex<VecDeque<ScriptEvent>>>,
+    subscriptions: [SubscriptionId; 2],
+    /// Variables of the console input
+    console_scope: Scope<'static>,
+    last_scan: Instant,
+    /// Left by the last scene exit, the `from` of `on_scene_change`
+    exited_scene: Option<Scene>,
+}
+
+impl ScriptManager {
+    /// Loads the scripts in `SCRIPT_DIR_NAME` in `dll_directory`
+    pub fn new(context: ScriptContext, dll_directory: Option<&Path>) -> Self {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(MAX_CALL_LEVELS);
+        api::register(&mut engine, context, shared.clone());
+
+        let events = Arc::new(Mutex::new(VecDeque::new()));
+        let scene_queue = events.clone();
+        let battle_queue = events.clone();
+        let subscriptions = [
+            scene_events().subscribe(move |e| push_event(&scene_queue, ScriptEvent::Scene(*e))),
+            battle_events().subscribe(move |e| push_event(&battle_queue, ScriptEvent::Battle(*e))),
+        ];
+
+        let mut manager = ScriptManager {
+            engine,
+            dir: dll_directory.map(|d| d.join(SCRIPT_DIR_NAME)),
+            scripts: Vec::new(),
+            shared,
+            events,
+            subscriptions,
+            console_scope: Scope::new(),
+            last_scan: Instant::now(),
+            exited_scene: None,
+        };
+        manager.scan();
+        manager
+    }
+
+    pub fn dir(&self) -> Option<&Path> {
+        self.dir.as_deref()
+    }
+
+    /// By file name
+    pub fn scripts(&self) -> &[Script] {
+        &self.scripts
+    }
+
+    /// Re-enabling a script that failed runs it again
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        if let Some(script) = self.scripts.iter_mut().find(|s| s.name == name) {
+            script.enabled = enabled;
+            if enabled && script.ast.is_some() {
+                script.error = None;
+            }
+        }
+    }
+
+    /// Load every script again, changed or not
+    pub fn reload_all(&mut self) {
+        for script in &mut self.scripts {
+            script.modified = None;
+        }
+        self.scan();
+    }
+
+    pub fn console(&self) -> Vec<ConsoleLine> {
+        self.shared.lock().console.iter().cloned().collect()
+    }
+
+    pub fn clear_console(&mut self) {
+        self.shared.lock().console.clear();
+    }
+
+    /// Run a line typed into the console, its variables are kept for the next lines
+    pub fn eval(&mut self, input: &str) {
+        self.shared
+            .lock()
+            .log(ConsoleLevel::Input, format!("> {}", input));
+        let result = self
+            .engine
+            .eval_with_scope::<Dynamic>(&mut self.console_scope, input);
+        let mut shared = self.shared.lock();
+        match result {
+            Ok(value) if value.is_unit() => {}
+            Ok(value) => shared.log(ConsoleLevel::Output, format!("{:?}", value)),
+            Err(e) => shared.log(ConsoleLevel::Error, e.to_string()),
+        }
+    }
+
+    /// Called every frame on the game thread. Reloads changed scripts, calls the callbacks
+    /// of what happened since the last tick and `on_frame`.
+    /// `patches` is patch name -> enabled, returns the patch switches the scripts asked for.
+    pub fn tick(&mut self, patches: BTreeMap<String, bool>) -> Vec<(String, bool)> {
+        self.shared.lock().patches = patches;
+        if self.last_scan.elapsed() >= RELOAD_INTERVAL {
+            self.scan();
+        }
+
+        let events = std::mem::take(&mut *self.events.lock());
+        for e in events {
+            match e {
+                ScriptEvent::Scene(SceneEvent::Exit(scene)) => self.exited_scene = Some(scene),
+                ScriptEvent::Scene(SceneEvent::Enter(scene)) => {
+                    let from = match self.exited_scene.take() {
+                        Some(from) => Dynamic::from(from.name()),
+                        None => Dynamic::UNIT,
+                    };
+                    self.call_all("on_scene_change", vec![from, Dynamic::from(scene.name())]);
+                }
+                ScriptEvent::Battle(BattleEvent::BattleStarted) => {
+                    self.call_all("on_battle_start", Vec::new())
+                }
+                ScriptEvent::Battle(BattleEvent::RoundStart) => {
+                    self.call_all("on_round_start", Vec::new())
+                }
+                ScriptEvent::Battle(BattleEvent::BattleEnded { winner }) => {
+                    let winner = match winner {
+                        Some(side) => Dynamic::from(api::side_name(side)),
+                        None => Dynamic::UNIT,
+                    };
+                    self.call_all("on_battle_end", vec![winner]);
+                }
+                ScriptEvent::Battle(_) => {}
+            }
+        }
+        self.call_all("on_frame", Vec::new());
+
+        std::mem::take(&mut self.shared.lock().patch_requests)
+    }
+
+    fn call_all(&mut self, name: &str, args: Vec<Dynamic>) {
+        for script in &mut self.scripts {
+            call(&self.engine, &self.shared, script, name, args.clone());
+        }
+    }
+
+    /// Load new and changed scripts, drop removed ones
+    fn scan(&mut self) {
+        self.last_scan = Instant::now();
+        let files = match &self.dir {
+            Some(dir) => script_files(dir),
+            None => Vec::new(),
+        };
+        self.scripts
+            .retain(|s| files.iter().any(|(path, _)| *path == s.path));
+        for (path, modified) in files {
+            let index = match self.scripts.iter().position(|s| s.path == path) {
+                Some(i) if self.scripts[i].modified == modified && modified.is_some() => continue,
+                Some(i) => i,
+                None => {
+                    let name = path
+                        .file_stem()
+                        .map(|s| s.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    self.scripts.push(Script {
+                        name,
+                        path: path.clone(),
+                        modified: None,
+                        ast: None,
+                        state: Dynamic::UNIT,
+                        enabled: true,
+                        error: None,
+                    });
+                    self.scripts.len() - 1
+                }
+            };
+            let script = &mut self.scripts[index];
+            script.modified = modified;
+            load(&self.engine, &self.shared, script);
+        }
+        self.scripts.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+}
+
+impl Drop for ScriptManager {
+    fn drop(&mut self) {
+        scene_events().unsubscribe(self.subscriptions[0]);
+        battle_events().unsubscribe(self.subscriptions[1]);
+    }
+}
+
+/// `.rhai` files in `dir` with their modified time
+fn script_files(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        //no directory, no scripts
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some(SCRIPT_EXTENSION))
+        .map(|p| {
+            let modified = std::fs::metadata(&p).and_then(|m| m.modified()).ok();
+            (p, modified)
+        })
+        .collect()
+}
+
+/// Compile `script`, run its top level and `on_load`.
+/// The top level has no `this`, `run_ast` of rhai 1.12 can't bind it, `on_load` is the first
+/// code that sees the script's state.
+fn load(engine: &Engine, shared: &Mutex<Shared>, script: &mut Script) {
+    script.ast = None;
+    script.error = None;
+    script.state = Dynamic::from_map(Map::new());
+    let mut ast = match engine.compile_file(script.path.clone()) {
+        Ok(ast) => ast,
+        Err(e) => {
+            fail(shared, script, format!("failed to compile: {}", e));
+            return;
+        }
+    };
+    ast.set_source(script.name.as_str());
+    if let Err(e) = engine.run_ast_with_scope(&mut Scope::new(), &ast) {
+        fail(shared, script, e.to_string());
+        return;
+    }
+    script.ast = Some(ast);
+    shared
+        .lock()
+        .log(ConsoleLevel::Info, format!("Loaded {}", script.name));
+    call(engine, shared, script, "on_load", Vec::new());
+}
+
+/// Call the callback `name` if the script is running and defines it
+fn call(
+    engine: &Engine,
+    shared: &Mutex<Shared>,
+    script: &mut Script,
+    name: &str,
+    args: Vec<Dynamic>,
+) {
+    if !script.is_running() {
+        return;
+    }
+    let ast = match &script.ast {
+        Some(ast) => ast,
+        None => return,
+    };
+    if !ast
+        .iter_functions()
+        .any(|f| f.name == name && f.params.len() == args.len())
+    {
+        return;
+    }
+    //the top level already ran at load
+    let options = CallFnOptions::new()
+        .eval_ast(false)
+        .with_tag(script.name.clone())
+        .bind_this_ptr(&mut script.state);
+    let result =
+        engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), ast, name, args);
+    if let Err(e) = result {
+        fail(shared, script, format!("{}: {}", name, e));
+    }
+}
+
+/// Stop `script` until it is reloaded or enabled again
+fn fail(shared: &Mutex<Shared>, script: &mut Script, error: String) {
+    shared
+        .lock()
+        .log(ConsoleLevel::Error, format!("{}: {}", script.name, error));
+    script.error = Some(error);
+}
@@ -0,0 +1,62 @@
+use crate::memory::InProcessMemory;
+use crate::utility::disasm;
+use anyhow::Result;
+use detour::RawDetour;
+use ilhook::x86::{HookPoint, Hooker};
+pub use sbx_tool_common::utility::hook::*;
+
+/// ilhook hook, `HookPoint::unhook` consumes the hook
+pub struct IlHook(pub Hooker);
+
+pub struct IlHookPoint(HookPoint);
+
+//HookPoint holds raw pointers, only touched through HookManager
+unsafe impl Send for IlHookPoint {}
+
+impl OneShotHook for IlHook {
+    type Installed = IlHookPoint;
+
+    fn install(self) -> Result<IlHookPoint> {
+        Ok(IlHookPoint(unsafe { self.0.hook() }?))
+    }
+
+    fn uninstall(installed: IlHookPoint) -> Result<()> {
+        unsafe { installed.0.unhook() }?;
+        Ok(())
+    }
+}
+
+/// ilhook inline hook at `address`, `factory` makes a new `Hooker` on every enable
+pub fn inline_hook<F>(address: usize, factory: F) -> InlineHook<IlHook>
+where
+    F: Fn() -> Result<Hooker> + Send + 'static,
+{
+    InlineHook::new(address, move || {
+        //ilhook moves whole instructions from the site, a wrong offset would split one
+        disasm::check_hook_site(&InProcessMemory, address)?;
+        Ok(IlHook(factory()?))
+    })
+}
+
+/// Detours live in `SyncOnceCell`s since the hook functions need their trampolines
+pub struct StaticDetour(pub &'static RawDetour);
+
+impl HookControl for StaticDetour {
+    fn kind(&self) -> HookKind {
+        HookKind::Detour
+    }
+
+    fn enable(&mut self) -> Result<()> {
+        unsafe { self.0.enable() }?;
+        Ok(())
+    }
+
+    fn disable(&mut self) -> Result<()> {
+        unsafe { self.0.disable() }?;
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.0.is_enabled()
+    }
+}
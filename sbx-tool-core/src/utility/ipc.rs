@@ -0,0 +1,73 @@
+use anyhow::{anyhow, Result};
+use nameof::name_of;
+use std::time::Duration;
+use winapi::shared::minwindef::{FALSE, TRUE};
+use winapi::shared::winerror::WAIT_TIMEOUT;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::processthreadsapi::GetCurrentProcessId;
+use winapi::um::synchapi::{CreateEventW, OpenEventW, SetEvent, WaitForSingleObject};
+use winapi::um::winbase::WAIT_OBJECT_0;
+use winapi::um::winnt::{EVENT_MODIFY_STATE, HANDLE};
+
+//Contract between sbx-injector and sbx-tool-dll
+
+/// Export the injector calls through `CreateRemoteThread` to unload the dll.
+/// Has the `LPTHREAD_START_ROUTINE` signature.
+pub const UNLOAD_EXPORT: &str = "sbx_tool_unload";
+
+/// Named event the dll sets once the hooks are installed
+pub fn ready_event_name(pid: u32) -> String {
+    format!("Local\\sbx-tool-ready-{}", pid)
+}
+
+fn to_wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(std::iter::once(0)).collect()
+}
+
+/// Created by the injector before injecting
+pub struct ReadyEvent {
+    handle: HANDLE,
+}
+
+impl ReadyEvent {
+    pub fn create(pid: u32) -> Result<Self> {
+        let name = to_wide(&ready_event_name(pid));
+        //manual reset, so a signal before `wait` is not lost
+        let handle = unsafe { CreateEventW(std::ptr::null_mut(), TRUE, FALSE, name.as_ptr()) };
+        if handle.is_null() {
+            return Err(anyhow!("{} failed for pid {}", name_of!(CreateEventW), pid));
+        }
+        Ok(ReadyEvent { handle })
+    }
+
+    /// Returns false on timeout
+    pub fn wait(&self, timeout: Duration) -> Result<bool> {
+        match unsafe { WaitForSingleObject(self.handle, timeout.as_millis() as u32) } {
+            WAIT_OBJECT_0 => Ok(true),
+            WAIT_TIMEOUT => Ok(false),
+            _ => Err(anyhow!("{} failed", name_of!(WaitForSingleObject))),
+        }
+    }
+}
+
+impl Drop for ReadyEvent {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.handle) };
+    }
+}
+
+/// Called by the dll.
+/// Returns false if no injector is waiting(injected by something else).
+pub fn signal_ready() -> Result<bool> {
+    let name = to_wide(&ready_event_name(unsafe { GetCurrentProcessId() }));
+    let handle = unsafe { OpenEventW(EVENT_MODIFY_STATE, FALSE, name.as_ptr()) };
+    if handle.is_null() {
+        return Ok(false);
+    }
+    let ok = unsafe { SetEvent(handle) };
+    unsafe { CloseHandle(handle) };
+    if ok == 0 {
+        return Err(anyhow!("{} failed", name_of!(SetEvent)));
+    }
+    Ok(true)
+}
@@ -1,6 +1,9 @@
-//pub mod hook;
-pub mod mempatch;
-//pub mod hook;
+pub mod hook;
+pub mod ipc;
+pub mod scanner;
+pub use sbx_tool_common::utility::{
+    cheat_table, code_cave, disasm, event_bus, mempatch, patch_registry, pe,
+};
 use std::ffi::CString;
 
 use anyhow::{anyhow, Result};
@@ -55,6 +58,18 @@ pub fn get_module_handle(module: &str) -> Result<HINSTANCE> {
     Ok(handle)
 }
 
+/// Get the full path of the module file(exe or dll)
+pub fn get_module_file_name(module: HINSTANCE) -> Result<std::path::PathBuf> {
+    use std::os::windows::ffi::OsStringExt;
+    use winapi::um::libloaderapi::GetModuleFileNameW;
+    let mut buffer = vec![0u16; 1024];
+    let len = unsafe { GetModuleFileNameW(module, buffer.as_mut_ptr(), buffer.len() as u32) };
+    if len == 0 || len as usize >= buffer.len() {
+        return Err(anyhow!("{} failed!", name_of!(GetModuleFileNameW)));
+    }
+    Ok(std::ffi::OsString::from_wide(&buffer[..len as usize]).into())
+}
+
 /// not working iirc
 pub fn log_last_error() {
     use winapi::shared::winerror::ERROR_RESOURCE_LANG_NOT_FOUND;
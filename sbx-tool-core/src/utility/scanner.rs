@@ -0,0 +1,56 @@
+use super::pe::PeImage;
+use anyhow::{anyhow, Result};
+use sbx_offset::signature::Signature;
+use sbx_offset::OffsetTable;
+use tracing::{event, Level};
+
+/// rva and bytes of the module's .text section
+pub unsafe fn module_text_section(module_address: usize) -> Result<(usize, &'static [u8])> {
+    let image = PeImage::from_module(module_address)?;
+    let text = image
+        .section_by_name(".text")
+        .ok_or_else(|| anyhow!(".text section not found"))?;
+    Ok((text.virtual_address as usize, image.section_data(text)?))
+}
+
+/// Replace offsets in the table with the ones found by their signatures.
+/// Offsets whose signature fails keep the value from the table.
+pub fn resolve_signatures(module_address: usize, table: &mut OffsetTable) -> Result<()> {
+    let (text_rva, text) = unsafe { module_text_section(module_address) }?;
+    let signatures = table.signatures.clone();
+    for (name, def) in &signatures {
+        let resolved =
+            Signature::from_def(def).and_then(|s| s.resolve(text, text_rva, module_address));
+        let offset = match table.offset_mut(name) {
+            Some(o) => o,
+            None => {
+                event!(Level::WARN, "Signature for unknown offset '{}'", name);
+                continue;
+            }
+        };
+        match resolved {
+            Ok(rva) => {
+                if *offset != rva {
+                    event!(
+                        Level::INFO,
+                        "{}: {:x} -> {:x} (signature)",
+                        name,
+                        *offset,
+                        rva
+                    );
+                }
+                *offset = rva;
+            }
+            Err(e) => {
+                event!(
+                    Level::WARN,
+                    "{}: {}, keep {:x} from the offset table",
+                    name,
+                    e,
+                    *offset
+                );
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,25 +1,49 @@
 #![feature(once_cell)]
 #![allow(non_snake_case)]
 #![allow(non_upper_case_globals)]
+mod style;
+
 use anyhow::{anyhow, Result};
 use detour::RawDetour;
 use effbool::EffBool;
-use ilhook::x86::HookPoint;
 use imgui::Ui;
 use imgui_dx9_renderer::Renderer;
 use imgui_impl_win32_rs::Win32Impl;
 use lazy_static::lazy_static;
 use nameof::{name_of, name_of_type};
-use parking_lot::Mutex;
+use parking_lot::{const_mutex, Mutex};
+use sbx_offset::Selection;
 use sbx_tool_core::__hook__CreateFileA;
-use sbx_tool_core::battle::BattleContext;
-use sbx_tool_core::css::{CSSContext, CSSInitContextConstantsDetour};
-use sbx_tool_core::utility::mempatch::MemPatch;
-use std::collections::HashMap;
+use sbx_tool_core::battle::{
+    BattleAccess, BattleAddresses, BattleEvent, BattleSnapshot, Freezer, Side,
+};
+use sbx_tool_core::config::{
+    self, Config, ConfigLoad, FreezeSettings, Profile, UiLayout, LOG_LEVELS,
+};
+use sbx_tool_core::css::{CSSInitContextConstantsDetour, CSS_INIT_CONTEXT_CONSTANTS_HOOK_STATS};
+use sbx_tool_core::hotkey::{Action, Gauge, HotkeyDispatcher, HotkeyEvent, Hotkeys, Modifiers};
+use sbx_tool_core::input::{self, InputMessage, InputMode};
+use sbx_tool_core::memory::viewer::write_protected;
+use sbx_tool_core::memory::{
+    GameMemory, InProcessMemory, Interpretation, MemoryView, PointerPath, WatchEntry,
+};
+use sbx_tool_core::reflect::{self, StructInfo, StructInspector};
+use sbx_tool_core::scene::{self, Scene, SceneEvent, SceneGate};
+use sbx_tool_core::script::{ConsoleLevel, ScriptContext, ScriptManager};
+use sbx_tool_core::style::{parse_style_file, StylePreset, StyleRegistry};
+use sbx_tool_core::utility::hook::{
+    inline_hook, DetourFns, HookKind, HookManager, HookStats, StaticDetour,
+};
+use sbx_tool_core::utility::patch_registry::{PatchRegistry, GAME_MODULE_SYMBOL};
+use sbx_tool_core::utility::{cheat_table, disasm};
 use std::lazy::SyncOnceCell;
-use std::sync::atomic::AtomicUsize;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tracing::{event, Level};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, Registry};
 use winapi::shared::d3d9::{
     Direct3DCreate9, IDirect3D9, IDirect3DDevice9, D3DADAPTER_DEFAULT,
     D3DCREATE_SOFTWARE_VERTEXPROCESSING, D3D_SDK_VERSION, LPDIRECT3DDEVICE9,
@@ -32,10 +56,19 @@ use winapi::{
     },
     shared::windef::HWND,
     um::consoleapi::AllocConsole,
-    um::libloaderapi::DisableThreadLibraryCalls,
+    um::libloaderapi::{DisableThreadLibraryCalls, FreeLibraryAndExitThread},
     um::libloaderapi::{GetModuleHandleA, GetProcAddress},
+    um::synchapi::Sleep,
+    um::winbase::INFINITE,
     um::wincon::FreeConsole,
     um::winnt::{DLL_PROCESS_ATTACH, DLL_PROCESS_DETACH},
+    um::winuser::{
+        GetAsyncKeyState, GetRawInputData, MessageBoxW, HRAWINPUT, MB_ICONERROR, MB_OK, RAWINPUT,
+        RAWINPUTHEADER, RID_INPUT, RIM_TYPEKEYBOARD, RI_KEY_BREAK, VK_CONTROL, VK_MENU, VK_SHIFT,
+        WM_CHAR, WM_DEADCHAR, WM_INPUT, WM_KEYDOWN, WM_KEYUP, WM_KILLFOCUS, WM_LBUTTONUP,
+        WM_MBUTTONUP, WM_MOUSEFIRST, WM_MOUSELAST, WM_RBUTTONUP, WM_SYSKEYDOWN, WM_SYSKEYUP,
+        WM_XBUTTONUP,
+    },
 };
 
 //directx detours
@@ -43,6 +76,15 @@ static EndSceneDetour: SyncOnceCell<RawDetour> = SyncOnceCell::new();
 static ResetDetour: SyncOnceCell<RawDetour> = SyncOnceCell::new();
 static Direct3DDevicePointer: SyncOnceCell<usize> = SyncOnceCell::new();
 static WndProcDetour: SyncOnceCell<RawDetour> = SyncOnceCell::new();
+static END_SCENE_HOOK_STATS: HookStats = HookStats::new();
+static RESET_HOOK_STATS: HookStats = HookStats::new();
+static WND_PROC_HOOK_STATS: HookStats = HookStats::new();
+static DLL_MODULE: SyncOnceCell<usize> = SyncOnceCell::new();
+static UNLOADING: AtomicBool = AtomicBool::new(false);
+/// Entered by the ui loop hook, applied on the next frame
+static PENDING_SCENE: Mutex<Option<Scene>> = const_mutex(None);
+//changes the log level at runtime, debug builds only
+static LOG_LEVEL_HANDLE: SyncOnceCell<reload::Handle<LevelFilter, Registry>> = SyncOnceCell::new();
 
 struct Context {
     renderer: Option<Renderer>,
@@ -56,6 +98,7 @@ unsafe impl Send for Context {}
 lazy_static! {
     static ref TWINKLE_MAIN_WINDOW_HWND: AtomicUsize = AtomicUsize::new(0);
     static ref GraphicContext: Arc<Mutex<Option<Context>>> = Arc::new(Mutex::new(None));
+    static ref LAST_BATTLE_EVENT: Mutex<Option<BattleEvent>> = Mutex::new(None);
 }
 
 type FnReset = extern "stdcall" fn(*mut IDirect3DDevice9, *mut D3DPRESENT_PARAMETERS) -> HRESULT;
@@ -70,6 +113,7 @@ extern "stdcall" fn __hook__wnd_proc(
     wparam: WPARAM,
     lparam: LPARAM,
 ) -> LRESULT {
+    WND_PROC_HOOK_STATS.hit();
     //  event!(Level::ERROR, "WndProc called");
 
     let d = match WndProcDetour.get() {
@@ -80,22 +124,187 @@ extern "stdcall" fn __hook__wnd_proc(
         }
     };
 
+    //hotkeys, Ctrl+End to unload by default
+    match msg {
+        WM_KEYDOWN | WM_SYSKEYDOWN => on_key_down(wparam as u32),
+        WM_KEYUP | WM_SYSKEYUP => on_key_up(wparam as u32),
+        //a game registering raw keyboard input with RIDEV_NOLEGACY gets no WM_KEYDOWN,
+        //a press seen both ways fires once
+        WM_INPUT => match raw_keyboard_key(lparam) {
+            Some((key, true)) => on_key_down(key),
+            Some((key, false)) => on_key_up(key),
+            None => {}
+        },
+        //releases while unfocused are not seen
+        WM_KILLFOCUS => {
+            if let Some(ui_state) = GUI_CONTEXT.lock().as_mut() {
+                ui_state.hotkey_dispatcher.reset();
+            }
+        }
+        _ => {}
+    }
+
     //call imgui's WndProc
-    if let Err(e) =
-        unsafe { imgui_impl_win32_rs::imgui_win32_window_proc(hwnd, msg, wparam, lparam) }
-    {
-        event!(Level::ERROR, "Imgui win32 wproc returned the error: {}", e);
-    };
+    let captured =
+        match unsafe { imgui_impl_win32_rs::imgui_win32_window_proc(hwnd, msg, wparam, lparam) } {
+            Ok(captured) => captured,
+            Err(e) => {
+                event!(Level::ERROR, "Imgui win32 wproc returned the error: {}", e);
+                false
+            }
+        };
+    if !should_forward(msg, captured) {
+        return 0;
+    }
 
     //call original wndproc
     let trampoline: FnWndProc = unsafe { std::mem::transmute(d.trampoline()) };
     trampoline(hwnd, msg, wparam, lparam)
 }
 
+/// Does the game get the message, see `InputMode`.
+/// `captured` is imgui wanting it.
+fn should_forward(msg: UINT, captured: bool) -> bool {
+    let message = match msg {
+        WM_LBUTTONUP | WM_RBUTTONUP | WM_MBUTTONUP | WM_XBUTTONUP => InputMessage::MouseRelease,
+        WM_MOUSEFIRST..=WM_MOUSELAST => InputMessage::Mouse,
+        WM_KEYUP => InputMessage::KeyRelease,
+        WM_KEYDOWN | WM_CHAR | WM_DEADCHAR => InputMessage::Key,
+        //system keys always reach the game, Alt+F4 must work
+        _ => return true,
+    };
+    if UNLOADING.load(Ordering::SeqCst) {
+        return true;
+    }
+    let (mode, ui_shown) = match GUI_CONTEXT.lock().as_ref() {
+        Some(ui_state) => (
+            ui_state.profile.input_mode,
+            !ui_state.hide_ui && !ui_state.layout.collapsed,
+        ),
+        None => return true,
+    };
+    input::forward_to_game(mode, message, captured, ui_shown)
+}
+
+/// (virtual key, is down) of a WM_INPUT keyboard message
+fn raw_keyboard_key(lparam: LPARAM) -> Option<(u32, bool)> {
+    let mut input: RAWINPUT = unsafe { std::mem::zeroed() };
+    let mut size = std::mem::size_of::<RAWINPUT>() as UINT;
+    let read = unsafe {
+        GetRawInputData(
+            lparam as HRAWINPUT,
+            RID_INPUT,
+            &mut input as *mut RAWINPUT as LPVOID,
+            &mut size,
+            std::mem::size_of::<RAWINPUTHEADER>() as UINT,
+        )
+    };
+    if read == UINT::MAX || input.header.dwType != RIM_TYPEKEYBOARD {
+        return None;
+    }
+    let keyboard = unsafe { input.data.keyboard() };
+    //0xff is a fake key of escape sequences
+    if keyboard.VKey == 0xff {
+        return None;
+    }
+    Some((
+        keyboard.VKey as u32,
+        keyboard.Flags as u32 & RI_KEY_BREAK as u32 == 0,
+    ))
+}
+
+fn is_key_held(key: i32) -> bool {
+    unsafe { GetAsyncKeyState(key) < 0 }
+}
+
+/// Run the hotkey of a key, or bind it if the Hotkeys tab is waiting for one
+fn on_key_down(key: u32) {
+    //no hotkeys once unloading started, unload() is about to take the GUIContext
+    if UNLOADING.load(Ordering::SeqCst) {
+        return;
+    }
+    //async, the thread's key state isn't updated without legacy key messages
+    let modifiers = Modifiers {
+        ctrl: is_key_held(VK_CONTROL),
+        shift: is_key_held(VK_SHIFT),
+        alt: is_key_held(VK_MENU),
+    };
+    let mut ui_state = GUI_CONTEXT.lock();
+    let ui_state = match ui_state.as_mut() {
+        Some(s) => s,
+        None => return,
+    };
+    let event = ui_state
+        .hotkey_dispatcher
+        .key_down(&ui_state.hotkeys, key, modifiers);
+    match event {
+        //typing in the tool window, only chords with Ctrl or Alt
+        Some(HotkeyEvent::Triggered(_))
+            if ui_state.want_text_input && !modifiers.ctrl && !modifiers.alt => {}
+        Some(HotkeyEvent::Triggered(action)) => run_hotkey(ui_state, action),
+        Some(HotkeyEvent::Captured(action, chord)) => {
+            let label = action.label();
+            ui_state.hotkeys_tab.status = Some(match ui_state.hotkeys.bind(action, chord) {
+                Ok(()) => {
+                    ui_state.profile.hotkeys = ui_state.hotkeys.to_config();
+                    format!("{} -> {}", chord, label)
+                }
+                Err(e) => e.to_string(),
+            });
+        }
+        Some(HotkeyEvent::CaptureCancelled) | None => {}
+    }
+}
+
+fn on_key_up(key: u32) {
+    if let Some(ui_state) = GUI_CONTEXT.lock().as_mut() {
+        ui_state.hotkey_dispatcher.key_up(key);
+    }
+}
+
+fn run_hotkey(ui_state: &mut GUIContext, action: Action) {
+    event!(Level::DEBUG, "Hotkey {}", action.name());
+    match action {
+        Action::ToggleUi => ui_state.hide_ui = !ui_state.hide_ui,
+        Action::ToggleFreeze(side, gauge) => {
+            let enable = !freeze_flag(ui_state, side, gauge).get();
+            set_freeze(ui_state, side, gauge, enable, None);
+        }
+        Action::Refill(side, gauge) => {
+            let battle = BattleAccess::new(&*ui_state.memory, ui_state.battle_addresses);
+            match battle.snapshot() {
+                Ok(snapshot) => {
+                    let player = snapshot.side(side);
+                    let value = match gauge {
+                        Gauge::Hp => player.initial_hp as i32,
+                        Gauge::Ex => player.max_ex as i32,
+                    };
+                    change_gauge(ui_state, side, gauge, value);
+                }
+                Err(e) => event!(Level::WARN, "Can't refill, not in battle: {}", e),
+            }
+        }
+        Action::TogglePatch(name) => match ui_state.patches.is_enabled(&name) {
+            Some(enabled) => log_error(ui_state.patches.switch(&name, !enabled)),
+            None => event!(Level::WARN, "Hotkey for an unknown patch '{}'", name),
+        },
+        Action::SaveBattleState => {
+            let battle = BattleAccess::new(&*ui_state.memory, ui_state.battle_addresses);
+            match battle.snapshot() {
+                Ok(snapshot) => ui_state.saved_battle = Some(snapshot),
+                Err(e) => event!(Level::WARN, "Can't save the battle state: {}", e),
+            }
+        }
+        Action::LoadBattleState => load_battle_state(ui_state),
+        Action::Eject => request_unload(),
+    }
+}
+
 extern "stdcall" fn __hook__IDirect3DDevice9_Reset(
     this: *mut IDirect3DDevice9,
     params: *mut D3DPRESENT_PARAMETERS,
 ) -> HRESULT {
+    RESET_HOOK_STATS.hit();
     event!(Level::INFO, "DirectX Reset");
     let trampoline = match ResetDetour.get() {
         Some(detour) => {
@@ -119,6 +328,7 @@ extern "stdcall" fn __hook__IDirect3DDevice9_Reset(
 }
 
 extern "stdcall" fn __hook__IDirect3DDevice9_EndScene(this: *mut IDirect3DDevice9) -> HRESULT {
+    END_SCENE_HOOK_STATS.hit();
     // event!(Level::DEBUG, "EndScene hook called {:x}", this as usize);
 
     //get trampoline
@@ -230,6 +440,17 @@ extern "stdcall" fn __hook__IDirect3DDevice9_EndScene(this: *mut IDirect3DDevice
                 return trampoline(this);
             }
 
+            //losing the wndproc hook means losing the ui input, keep it pinned
+            if let Some(ui_state) = GUI_CONTEXT.lock().as_mut() {
+                let wndproc_detour = WndProcDetour.get().unwrap();
+                log_error(
+                    ui_state
+                        .hooks
+                        .add("WndProc", wndproc_detour, Some(&WND_PROC_HOOK_STATS))
+                        .and_then(|_| ui_state.hooks.pin("WndProc")),
+                );
+            }
+
             event!(Level::INFO, "WndProc hooked!");
         } //context.is_none() scope ends here
 
@@ -249,6 +470,15 @@ extern "stdcall" fn __hook__IDirect3DDevice9_EndScene(this: *mut IDirect3DDevice
         if let Err(e) = context.renderer.as_mut().unwrap().render(ui.render()) {
             event!(Level::ERROR, "Failed to draw a frame: {}", e);
         }
+
+        //the frame borrows the imgui context, a new style waits for it to end
+        let pending_style = GUI_CONTEXT
+            .lock()
+            .as_mut()
+            .and_then(|ui_state| ui_state.pending_style.take());
+        if let Some(pending_style) = pending_style {
+            set_imgui_style(&mut context.imgui_context, pending_style);
+        }
     } //mutex scope ends
 
     // call trampoline(original EndScene)
@@ -266,19 +496,184 @@ extern "stdcall" fn __hook__IDirect3DDevice9_EndScene(this: *mut IDirect3DDevice
 struct GUIContext {
     message_sender: std::sync::mpsc::Sender<ChannelMessage>,
     pub hide_ui: bool,
-    main_loop_hook: Arc<HookPoint>, //or Vec<HookPoint>
-    game_loop_hook: Arc<HookPoint>,
-    battle_loop_hook: Arc<HookPoint>,
-    ui_loop_hook: Arc<HookPoint>,
+    hooks: HookManager,
     do_freeze_player_current_hp: EffBool,
     do_freeze_player_current_ex: EffBool,
     do_freeze_cpu_current_hp: EffBool,
     do_freeze_cpu_current_ex: EffBool,
-    mem_patches: HashMap<MemPatchName, MemPatch>,
+    patches: PatchRegistry,
+    freeze_thread: Option<std::thread::JoinHandle<()>>,
+    /// What the freeze thread holds the gauges at, saved with the profile
+    frozen: Arc<Mutex<FreezeSettings>>,
+    memory: Arc<dyn GameMemory>,
     css_context_address: usize,
-    battle_context_address: usize,
+    battle_addresses: BattleAddresses,
+    module_address: usize,
+    code_view: CodeView,
+    memory_tab: MemoryTab,
+    watches: Vec<WatchEntry>,
+    structs_tab: StructsTab,
+    config: Config,
+    /// None if there is nowhere to save it
+    config_path: Option<PathBuf>,
+    /// The applied profile, `config` only changes on save
+    profile: Profile,
+    config_tab: ConfigTab,
+    /// Window placement of the last frame
+    layout: UiLayout,
+    /// Move the window to `layout` next frame
+    apply_layout: bool,
+    styles: StyleRegistry,
+    style_tab: StyleTab,
+    /// imgui's style before any preset, presets only list changes to it
+    default_style: imgui::Style,
+    /// Style and font scale for the next frame
+    pending_style: Option<(imgui::Style, f32)>,
+    hotkeys: Hotkeys,
+    hotkey_dispatcher: HotkeyDispatcher,
+    hotkeys_tab: HotkeysTab,
+    /// imgui had a text input focused last frame
+    want_text_input: bool,
+    /// For the load battle state hotkey
+    saved_battle: Option<BattleSnapshot>,
+    scripts: ScriptManager,
+    scripts_tab: ScriptsTab,
+}
+
+/// State of the Scripts tab
+#[derive(Default)]
+struct ScriptsTab {
+    /// Console input line
+    input: String,
+}
+
+enum ScriptAction {
+    Eval(String),
+    SetEnabled(String, bool),
+    Reload,
+    ClearConsole,
+}
+
+/// State of the Hotkeys tab
+#[derive(Default)]
+struct HotkeysTab {
+    /// Result of the last binding
+    status: Option<String>,
+}
+
+/// State of the Style tab
+#[derive(Default)]
+struct StyleTab {
+    /// in `StyleRegistry::names()`
+    selected: usize,
+    name_input: String,
+    /// Result of the last save, load or paste
+    status: Option<String>,
+}
+
+enum StyleAction {
+    Apply(String),
+    Save(StylePreset),
+    Reload,
+    Paste(StylePreset),
+}
+
+/// State of the Config tab
+#[derive(Default)]
+struct ConfigTab {
+    /// in `Config::profile_names()`
+    selected: usize,
+    new_profile: String,
+    /// Result of the last save or reload
+    status: Option<String>,
+    /// Problems in the config file
+    issues: Vec<String>,
+}
+
+enum ConfigAction {
+    Apply(String),
+    Save(String),
+    Reload,
 }
 
+/// State of the Structs tab
+#[derive(Default)]
+struct StructsTab {
+    /// in `reflect::game_structs()`
+    selected: usize,
+    path_input: String,
+    path: Option<PointerPath>,
+    error: Option<String>,
+    inspector: StructInspector,
+}
+
+/// State of the Memory tab
+struct MemoryTab {
+    path_input: String,
+    view: MemoryView,
+    error: Option<String>,
+    edit_as: Interpretation,
+    edit_input: String,
+    edit_error: Option<String>,
+}
+
+impl MemoryTab {
+    fn new() -> Self {
+        MemoryTab {
+            path_input: String::new(),
+            view: MemoryView::new(PointerPath::module(0, &[]), MEMORY_VIEW_SIZE),
+            error: None,
+            edit_as: Interpretation::U32,
+            edit_input: String::new(),
+            edit_error: None,
+        }
+    }
+
+    fn go(&mut self, path: PointerPath) {
+        self.path_input = path.to_string();
+        self.view.set_path(path);
+        self.error = None;
+    }
+}
+
+/// State of the Code tab
+#[derive(Default)]
+struct CodeView {
+    address_input: String,
+    address: Option<usize>,
+    error: Option<String>,
+}
+
+impl CodeView {
+    fn go(&mut self, address: usize) {
+        self.address = Some(address);
+        self.address_input = format!("0x{:x}", address);
+        self.error = None;
+    }
+}
+
+/// Bytes before the address shown in the Code tab
+const CODE_VIEW_BEFORE: usize = 0x40;
+/// Instructions from the address on
+const CODE_VIEW_LINES: usize = 40;
+/// ilhook's jmp at a hook site
+const HOOK_JMP_SIZE: usize = 5;
+const HOOK_COLOR: [f32; 4] = [1.0, 0.85, 0.2, 1.0];
+const PATCH_COLOR: [f32; 4] = [1.0, 0.45, 0.45, 1.0];
+
+/// Bytes shown in the Memory tab
+const MEMORY_VIEW_SIZE: usize = 0x100;
+const MEMORY_VIEW_COLUMNS: usize = 16;
+const CHANGED_COLOR: [f32; 4] = [1.0, 0.35, 0.35, 1.0];
+const UNREADABLE_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const UNKNOWN_FIELD_COLOR: [f32; 4] = [0.65, 0.65, 0.65, 1.0];
+/// How deep pointer fields can be opened, pointers may form a cycle
+const MAX_STRUCT_DEPTH: usize = 8;
+
+/// Scripts tab console
+const SCRIPT_WARN_COLOR: [f32; 4] = [1.0, 0.85, 0.2, 1.0];
+const SCRIPT_ERROR_COLOR: [f32; 4] = [1.0, 0.45, 0.45, 1.0];
+
 //we use mutex and taka care
 unsafe impl Send for GUIContext {}
 
@@ -288,51 +683,100 @@ lazy_static! {
 
 fn imgui_ui_loop(ui: Ui) -> Ui {
     use imgui::{
-        ColorEdit, ColorPicker, Condition, ImColor32, StyleColor, TabBar, TabItem, Window,
+        ChildWindow, CollapsingHeader, ColorEdit, ColorPicker, Condition, ImColor32, Selectable,
+        StyleColor, TabBar, TabItem, Window,
     };
     let mut ui_state = GUI_CONTEXT.lock();
-    let ui_state = ui_state.as_mut().unwrap();
+    let ui_state = match ui_state.as_mut() {
+        Some(s) => s,
+        None => return ui, //unloading
+    };
+    ui_state.want_text_input = ui.io().want_text_input;
+    //scripts run with the window hidden too
+    if !UNLOADING.load(Ordering::SeqCst) {
+        if let Some(scene) = PENDING_SCENE.lock().take() {
+            apply_scene(ui_state, Some(scene));
+        }
+        tick_scripts(ui_state);
+    }
+    //hidden by the toggle ui hotkey
+    if ui_state.hide_ui {
+        return ui;
+    }
     let message_sender = &ui_state.message_sender;
-    let mem_patches = &mut ui_state.mem_patches;
+    let patches = &mut ui_state.patches;
+    let mut patch_switches = Vec::new();
+    let hooks = &mut ui_state.hooks;
+    let mut hook_switches = Vec::new();
+
+    let memory = ui_state.memory.clone();
+    let module_address = ui_state.module_address;
+
+    let current_scene = scene::current_scene();
 
     //battle related
-    let battle_context: *mut BattleContext =
-        unsafe { std::mem::transmute(ui_state.battle_context_address) };
-    let player = unsafe { (*battle_context).player1_ptr };
-    let player_subparams = unsafe { (*battle_context).player1_sub_param_ptr };
-    let cpu = unsafe { (*battle_context).player2_ptr };
-    let cpu_subparams = unsafe { (*battle_context).player2_sub_param_ptr };
+    let battle = BattleAccess::new(&*memory, ui_state.battle_addresses);
 
     //css related
-    let css_context: *mut CSSContext =
-        unsafe { std::mem::transmute(*(ui_state.css_context_address as *mut usize)) };
-
-    //todo maybe need to lock ui
-    let css_disable_cost_patch = mem_patches.get(&MemPatchName::CSSDisableCost).unwrap();
-    let mut is_enable_css_disable_cost_patch = css_disable_cost_patch.is_enabled();
-    let hp_cap_disable_patch = mem_patches.get(&MemPatchName::HPCapDisable).unwrap();
-    let mut is_enable_hp_cap_disable_patch = hp_cap_disable_patch.is_enabled();
-    let ex_cap_disable_patch = mem_patches.get(&MemPatchName::ExCapDisable).unwrap();
-    let mut is_enable_ex_cap_disable_patch = ex_cap_disable_patch.is_enabled();
-    Window::new("SBX Tool")
-        .size([200.0, 400.0], Condition::Once)
+    let css_context_address = if current_scene == Some(Scene::VsCpuModeCSS) {
+        sbx_tool_core::css::css_context_address(&*memory, ui_state.css_context_address)
+            .unwrap_or(None)
+    } else {
+        None
+    };
+
+    let mut config_action = None;
+    let mut style_action = None;
+    let mut hotkey_action = None;
+    let mut script_action = None;
+
+    let layout = ui_state.layout;
+    let layout_condition = if std::mem::take(&mut ui_state.apply_layout) {
+        Condition::Always
+    } else {
+        Condition::Once
+    };
+    let mut window = Window::new("SBX Tool")
+        .size(layout.window_size, layout_condition)
+        .collapsed(layout.collapsed, layout_condition);
+    if let Some(position) = layout.window_position {
+        window = window.position(position, layout_condition);
+    }
+    let shown = window
         .build(&ui, || {
+            ui_state.layout = UiLayout {
+                window_size: ui.window_size(),
+                window_position: Some(ui.window_pos()),
+                collapsed: false,
+            };
             TabBar::new("tab").build(&ui, || {
                 TabItem::new("Status").build(&ui, || {
                     ui.bullet_text(format!("{} frames", ui.frame_count()));
                     ui.bullet_text(format!("{:.8} fps", ui.io().framerate));
+                    if let Some(scene) = current_scene {
+                        ui.bullet_text(format!("Scene {}", scene.name()));
+                    }
+                    if let Some(e) = *LAST_BATTLE_EVENT.lock() {
+                        ui.bullet_text(format!("Last battle event {:?}", e));
+                    }
+                    if CollapsingHeader::new("Scene History").build(&ui) {
+                        for t in scene::scene_history().iter().rev() {
+                            let from = t.from.map_or("-", |s| s.name());
+                            ui.text(format!(
+                                "{:>6.1}s ago {} -> {}",
+                                t.at.elapsed().as_secs_f32(),
+                                from,
+                                t.to.name()
+                            ));
+                        }
+                    }
                 });
                 TabItem::new("CSS").build(&ui, || {
-                    if css_context as usize == 0{
+                    if css_context_address.is_none(){
                         ui.text("Only available in vs-cpu character select screen.");
                         return;
                     }
-                    ui.checkbox("Ignore Party Cost", &mut is_enable_css_disable_cost_patch);
-                    if ui.is_item_hovered() {
-                        ui.tooltip_text(
-                            "Ignore the party cost limit by disabling character cost addition.",
-                        );
-                    }
+                    ui.text("Ignore Party Cost is in the Patches tab.");
 
                     ui.new_line();
                     ui.text("You should be able to choose more than 5 characters for a party, since max character limit is already 'patched'.");
@@ -348,16 +792,25 @@ fn imgui_ui_loop(ui: Ui) -> Ui {
                     ui.text(format!("player subparams {:x}",player_subparams as usize));
                     ui.text(format!("cpu subparams {:x}",cpu_subparams as usize));
                     */
-                    if player as usize==0 || cpu as usize ==0 || player_subparams as usize  ==0 || cpu_subparams as usize ==0{
-                       // not in battle
-                       // return to avoid crash
-                       ui.text("Only available while battle.");
-                       return;
+                    if current_scene != Some(Scene::Battle){
+                        ui.text("Only available while battle.");
+                        return;
                     }
-                    ui.text(format!("Player {:x}",player as usize));
+                    let snapshot = match battle.snapshot(){
+                        Ok(s) => s,
+                        Err(e) => {
+                            // not in battle
+                            // return to avoid crash
+                            ui.text("Only available while battle.");
+                            ui.text_disabled(format!("({})",e));
+                            return;
+                        }
+                    };
+                    ui.text(format!("State {}",snapshot.state.name()));
+                    ui.text(format!("Player {:x}",battle.player_address(Side::Player).unwrap_or(0)));
 
                     //Player HP
-                    let mut player_current_hp=unsafe{ (*player).current_hp}as i32;
+                    let mut player_current_hp=snapshot.player.current_hp as i32;
                     let changed=  ui.input_int("Player HP",&mut player_current_hp ).step(500).step_fast(2000).build();
 
                     ui.same_line();
@@ -371,11 +824,11 @@ fn imgui_ui_loop(ui: Ui) -> Ui {
                     let (is_changed,val)= ui_state.do_freeze_player_current_hp.set_and_is_changed(do_freeze_player_hp);
                     if is_changed {
                     //send freeze player hp message
-                        message_sender.send(ChannelMessage::FreezePlayerHP{enable:val}).unwrap();
+                        message_sender.send(ChannelMessage::FreezePlayerHP{enable:val,value:None}).unwrap();
                     }
 
                     //Player Ex
-                    let mut player_current_ex=unsafe{ (*player_subparams).current_ex};
+                    let mut player_current_ex=snapshot.player.current_ex;
                     let changed= ui.input_int("Player Ex",&mut player_current_ex).step(30).step_fast(100).build();
                     ui.same_line();
                     let mut do_freeze_player_ex=  ui_state.do_freeze_player_current_ex.get();
@@ -389,26 +842,26 @@ fn imgui_ui_loop(ui: Ui) -> Ui {
                     let (is_changed,val)= ui_state.do_freeze_player_current_ex.set_and_is_changed(do_freeze_player_ex);
                     if is_changed {
                     //send freeze player ex message
-                        message_sender.send(ChannelMessage::FreezePlayerEx{enable:val}).unwrap();
+                        message_sender.send(ChannelMessage::FreezePlayerEx{enable:val,value:None}).unwrap();
                     }
 
 
                     //Player Rush Count
-                    let mut player_rush_count=unsafe{ (*battle_context).player1_rush_count} as i32;
+                    let mut player_rush_count=snapshot.player.rush_count as i32;
                     if ui.input_int("Player Rush Count",&mut player_rush_count).step_fast(5).build(){
-                    unsafe{ (*battle_context).player1_rush_count=player_rush_count as u32};
+                        log_error(battle.set_rush_count(Side::Player,player_rush_count as u32));
                     }
 
                     //Player Score
-                    let mut player_score=unsafe{(*battle_context).player1_score} as i32;
+                    let mut player_score=snapshot.player.score as i32;
                     if ui.input_int("Player Score",&mut player_score).step(10000).step_fast(100000).build(){
-                    unsafe{ (*battle_context).player1_score=player_score as u32};
+                        log_error(battle.set_score(Side::Player,player_score as u32));
                     }
 
                     //CPU
-                    ui.text(format!("CPU {:x}",cpu as usize));
+                    ui.text(format!("CPU {:x}",battle.player_address(Side::CPU).unwrap_or(0)));
                     //CPU HP
-                    let mut cpu_current_hp=unsafe{ (*cpu).current_hp}as i32;
+                    let mut cpu_current_hp=snapshot.cpu.current_hp as i32;
                     let changed= ui.input_int("CPU HP", &mut cpu_current_hp).step(500).step_fast(2000).build();
                     ui.same_line();
                     let mut do_freeze_cpu_hp=  ui_state.do_freeze_cpu_current_hp.get();
@@ -423,12 +876,12 @@ fn imgui_ui_loop(ui: Ui) -> Ui {
                     let (is_changed,val)= ui_state.do_freeze_cpu_current_hp.set_and_is_changed(do_freeze_cpu_hp);
                     if is_changed {
                     //send freeze cpu hp message
-                        message_sender.send(ChannelMessage::FreezeCPUHP{enable:val}).unwrap();
+                        message_sender.send(ChannelMessage::FreezeCPUHP{enable:val,value:None}).unwrap();
                     }
 
 
                     //CPU Ex
-                    let mut cpu_current_ex=unsafe{ (*cpu_subparams).current_ex};
+                    let mut cpu_current_ex=snapshot.cpu.current_ex;
                     let changed= ui.input_int("CPU Ex",&mut cpu_current_ex).step(30).step_fast(100).build();
                     ui.same_line();
                     let mut do_freeze_cpu_ex=  ui_state.do_freeze_cpu_current_ex.get();
@@ -441,34 +894,643 @@ fn imgui_ui_loop(ui: Ui) -> Ui {
                     let (is_changed,val)= ui_state.do_freeze_cpu_current_ex.set_and_is_changed(do_freeze_cpu_ex);
                     if is_changed {
                     //send freeze cpu ex message
-                        message_sender.send(ChannelMessage::FreezeCPUEx{enable:val}).unwrap();
+                        message_sender.send(ChannelMessage::FreezeCPUEx{enable:val,value:None}).unwrap();
                     }
 
-                    let mut cpu_rush_count=unsafe{ (*battle_context).player2_rush_count} as i32;
+                    let mut cpu_rush_count=snapshot.cpu.rush_count as i32;
                     if ui.input_int("CPU Rush Count",&mut cpu_rush_count).step_fast(5).build(){
-                        unsafe{ (*battle_context).player2_rush_count=cpu_rush_count as u32};
+                        log_error(battle.set_rush_count(Side::CPU,cpu_rush_count as u32));
                     }
 
                     //CPU Score
-                    let mut cpu_score=unsafe{(*battle_context).player2_score} as i32;
+                    let mut cpu_score=snapshot.cpu.score as i32;
                     if ui.input_int("CPU Score",&mut cpu_score).step(10000).step_fast(100000).build(){
-                        unsafe{ (*battle_context).player2_score=cpu_score as u32};
+                        log_error(battle.set_score(Side::CPU,cpu_score as u32));
+                    }
+
+                    ui.separator();
+                    if ui.button("Save State"){
+                        ui_state.saved_battle = Some(snapshot);
+                    }
+                    if ui.is_item_hovered(){
+                        ui.tooltip_text("Remember hp, ex, rush counts and scores of both sides.");
+                    }
+                    ui.same_line();
+                    if ui.button("Load State"){
+                        hotkey_action = Some(Action::LoadBattleState);
                     }
 
-                    ui.checkbox("Disable HP Cap", &mut is_enable_hp_cap_disable_patch);
-                    ui.checkbox("Disable Ex Cap", &mut is_enable_ex_cap_disable_patch);
+                });
+                TabItem::new("Patches").build(&ui, || {
+                    let infos = patches.patches();
+                    for category in patches.categories() {
+                        if !CollapsingHeader::new(&category).default_open(true).build(&ui) {
+                            continue;
+                        }
+                        for patch in infos.iter().filter(|p| p.category == category) {
+                            let mut enabled = patch.enabled;
+                            if ui.checkbox(&patch.name, &mut enabled) {
+                                patch_switches.push((patch.name.clone(), enabled));
+                            }
+                            if ui.is_item_hovered() {
+                                let mut tooltip = patch.description.clone();
+                                if !patch.requires.is_empty() {
+                                    tooltip.push_str(&format!("\nRequires: {}", patch.requires.join(", ")));
+                                }
+                                if !patch.conflicts.is_empty() {
+                                    tooltip.push_str(&format!("\nConflicts with: {}", patch.conflicts.join(", ")));
+                                }
+                                if !patch.scenes.is_empty() {
+                                    tooltip.push_str(&format!("\nOnly applied in: {}", scene_names(&patch.scenes)));
+                                }
+                                ui.tooltip_text(tooltip);
+                            }
+                            if patch.enabled && !patch.applied {
+                                ui.same_line();
+                                ui.text_disabled("(waiting for scene)");
+                            }
+                        }
+                    }
+                });
+                TabItem::new("Hooks").build(&ui, || {
+                    let now = std::time::SystemTime::now();
+                    for hook in hooks.hooks() {
+                        let kind = match hook.kind {
+                            HookKind::Inline => "inline",
+                            HookKind::Detour => "detour",
+                        };
+                        if hook.pinned {
+                            ui.text(format!("{} ({})", hook.name, kind));
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text("Required by the tool, can not be disabled.");
+                            }
+                        } else {
+                            let mut enabled = hook.enabled;
+                            if ui.checkbox(format!("{} ({})", hook.name, kind), &mut enabled) {
+                                hook_switches.push((hook.name.clone(), enabled));
+                            }
+                            if !hook.scenes.is_empty() && ui.is_item_hovered() {
+                                ui.tooltip_text(format!("Only installed in: {}", scene_names(&hook.scenes)));
+                            }
+                            if hook.enabled && !hook.installed {
+                                ui.same_line();
+                                ui.text_disabled("(waiting for scene)");
+                            }
+                        }
+                        if let Some(count) = hook.call_count {
+                            let last_hit = match hook.last_hit.and_then(|t| now.duration_since(t).ok()) {
+                                Some(d) => format!("{:.1}s ago", d.as_secs_f32()),
+                                None => "never".to_owned(),
+                            };
+                            ui.same_line();
+                            ui.text_disabled(format!("{} calls, last {}", count, last_hit));
+                        }
+                    }
+                });
+                TabItem::new("Memory").build(&ui, || {
+                    let tab = &mut ui_state.memory_tab;
+                    let entered = ui
+                        .input_text("Path", &mut tab.path_input)
+                        .hint("sbx.exe+438B28, 10, 4")
+                        .enter_returns_true(true)
+                        .build();
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text("Address or Cheat Engine style pointer path, numbers are hex.\nThe last offset is added without dereferencing.");
+                    }
+                    ui.same_line();
+                    if ui.button("Go") || entered {
+                        match tab.path_input.parse::<PointerPath>() {
+                            Ok(path) => tab.go(path),
+                            Err(e) => tab.error = Some(e.to_string()),
+                        }
+                    }
+                    for (label, step) in [("-100", -0x100), ("-10", -0x10), ("+10", 0x10), ("+100", 0x100)] {
+                        if ui.small_button(label) {
+                            tab.view.scroll += step;
+                            tab.view.selected = None;
+                        }
+                        ui.same_line();
+                    }
+                    if tab.view.scroll < 0 {
+                        ui.text(format!("-{:X}", -tab.view.scroll));
+                    } else {
+                        ui.text(format!("+{:X}", tab.view.scroll));
+                    }
+                    if let Some(e) = &tab.error {
+                        ui.text_disabled(format!("({})", e));
+                    }
+
+                    //re-read every frame
+                    if let Err(e) = tab.view.refresh(&*memory, module_address) {
+                        ui.text_disabled(format!("({})", e));
+                    }
+                    if let Some(address) = tab.view.address() {
+                        let bytes = tab.view.bytes().to_vec();
+                        let byte_width = ui.calc_text_size("FF")[0];
+                        for (row, chunk) in bytes.chunks(MEMORY_VIEW_COLUMNS).enumerate() {
+                            ui.text(format!("{:08X}", address + row * MEMORY_VIEW_COLUMNS));
+                            for (column, byte) in chunk.iter().enumerate() {
+                                let i = row * MEMORY_VIEW_COLUMNS + column;
+                                ui.same_line();
+                                let _color = match byte {
+                                    None => Some(ui.push_style_color(StyleColor::Text, UNREADABLE_COLOR)),
+                                    Some(_) if tab.view.is_changed(i) => Some(ui.push_style_color(StyleColor::Text, CHANGED_COLOR)),
+                                    Some(_) => None,
+                                };
+                                let label = match byte {
+                                    Some(b) => format!("{:02X}##byte{}", b, i),
+                                    None => format!("??##byte{}", i),
+                                };
+                                if Selectable::new(label)
+                                    .selected(tab.view.selected == Some(i))
+                                    .size([byte_width, 0.0])
+                                    .build(&ui)
+                                {
+                                    tab.view.selected = Some(i);
+                                    tab.edit_error = None;
+                                }
+                            }
+                            let ascii: String = chunk
+                                .iter()
+                                .map(|b| match b {
+                                    Some(b) if b.is_ascii_graphic() || *b == b' ' => *b as char,
+                                    _ => '.',
+                                })
+                                .collect();
+                            ui.same_line();
+                            ui.text(ascii);
+                        }
+                    }
+
+                    if let Some(selected) = tab.view.selected_address() {
+                        ui.separator();
+                        ui.text(format!("Selected {:08X}", selected));
+                        for interpretation in Interpretation::ALL {
+                            ui.radio_button(interpretation.name(), &mut tab.edit_as, interpretation);
+                            ui.same_line();
+                            let bytes = match tab.view.selection(interpretation.size()) {
+                                Some(b) => b,
+                                None => {
+                                    ui.text_disabled("-");
+                                    continue;
+                                }
+                            };
+                            ui.text(interpretation.format(&bytes));
+                            if interpretation == Interpretation::Pointer {
+                                let mut pointer = [0u8; std::mem::size_of::<usize>()];
+                                pointer.copy_from_slice(&bytes);
+                                let pointer = usize::from_le_bytes(pointer);
+                                ui.same_line();
+                                if ui.small_button("Follow") {
+                                    tab.go(PointerPath::absolute(pointer, &[]));
+                                }
+                            }
+                        }
+                        let entered = ui
+                            .input_text("Value", &mut tab.edit_input)
+                            .enter_returns_true(true)
+                            .build();
+                        ui.same_line();
+                        if ui.button("Write") || entered {
+                            let result = tab
+                                .edit_as
+                                .parse(&tab.edit_input)
+                                .and_then(|bytes| write_protected(&*memory, selected, &bytes));
+                            tab.edit_error = result.err().map(|e| e.to_string());
+                        }
+                        if ui.is_item_hovered() {
+                            ui.tooltip_text("Written as the selected type. Code is written with the game threads suspended.");
+                        }
+                        if let Some(e) = &tab.edit_error {
+                            ui.text_disabled(format!("({})", e));
+                        }
+                    }
+
+                    if !ui_state.watches.is_empty() {
+                        ui.separator();
+                        if CollapsingHeader::new("Watches").default_open(true).build(&ui) {
+                            for (i, watch) in ui_state.watches.iter().enumerate() {
+                                if ui.small_button(format!("View##watch{}", i)) {
+                                    tab.go(watch.path.clone());
+                                }
+                                ui.same_line();
+                                let value = watch
+                                    .read_display(&*memory, module_address)
+                                    .unwrap_or_else(|e| format!("({})", e));
+                                ui.text(format!("{}: {}", watch.name, value));
+                            }
+                        }
+                    }
+                });
+                TabItem::new("Structs").build(&ui, || {
+                    let tab = &mut ui_state.structs_tab;
+                    let structs = reflect::game_structs();
+                    for (i, root) in reflect::inspector_roots(sbx_offset::get()).into_iter().enumerate() {
+                        if i > 0 {
+                            ui.same_line();
+                        }
+                        if ui.small_button(root.name) {
+                            tab.selected = structs.iter().position(|s| s.name == root.info.name).unwrap_or(0);
+                            tab.path_input = root.path.to_string();
+                            tab.path = Some(root.path);
+                            tab.error = None;
+                        }
+                    }
+                    let names: Vec<&str> = structs.iter().map(|s| s.name).collect();
+                    ui.combo_simple_string("Struct", &mut tab.selected, &names);
+                    let entered = ui
+                        .input_text("Path", &mut tab.path_input)
+                        .hint("sbx.exe+438B28, 0")
+                        .enter_returns_true(true)
+                        .build();
+                    ui.same_line();
+                    if ui.button("Inspect") || entered {
+                        match tab.path_input.parse::<PointerPath>() {
+                            Ok(path) => {
+                                tab.path = Some(path);
+                                tab.error = None;
+                            }
+                            Err(e) => tab.error = Some(e.to_string()),
+                        }
+                    }
+                    if let Some(e) = &tab.error {
+                        ui.text_disabled(format!("({})", e));
+                    }
+
+                    let (path, info) = match (&tab.path, structs.get(tab.selected)) {
+                        (Some(path), Some(info)) => (path, *info),
+                        _ => return,
+                    };
+                    ui.separator();
+                    match path.resolve(&*memory, module_address) {
+                        Ok(address) => {
+                            ui.text(format!("{} at {:08X} ({:X} bytes)", info.name, address, info.size));
+                            draw_struct(&ui, &mut tab.inspector, &*memory, info, address, 0);
+                        }
+                        Err(e) => ui.text_disabled(format!("({})", e)),
+                    }
+                });
+                TabItem::new("Code").build(&ui, || {
+                    let code_view = &mut ui_state.code_view;
+                    let entered = ui
+                        .input_text("Address", &mut code_view.address_input)
+                        .hint("sbx+0x1234")
+                        .enter_returns_true(true)
+                        .build();
+                    ui.same_line();
+                    if ui.button("Go") || entered {
+                        let symbols = |name: &str| match name {
+                            GAME_MODULE_SYMBOL => Some(module_address),
+                            _ => None,
+                        };
+                        match sbx_tool_core::asm::evaluate(&code_view.address_input, &symbols) {
+                            Ok(address) => code_view.go(address),
+                            Err(e) => code_view.error = Some(e.to_string()),
+                        }
+                    }
+                    if let Some(e) = &code_view.error {
+                        ui.text_disabled(format!("({})", e));
+                    }
+
+                    let hook_sites: Vec<(String, usize, bool)> = hooks
+                        .hooks()
+                        .into_iter()
+                        .filter_map(|h| h.address.map(|a| (h.name, a, h.installed)))
+                        .collect();
+                    let infos = patches.patches();
+                    let mut patched = Vec::new();
+                    for info in infos.iter().filter(|p| p.applied) {
+                        if let Some(patch) = patches.mem_patch(&info.name) {
+                            patched.extend(patch.targets());
+                        }
+                    }
+
+                    if CollapsingHeader::new("Hook Sites").build(&ui) {
+                        for (name, address, _) in &hook_sites {
+                            if ui.small_button(format!("{} {:x}", name, address)) {
+                                code_view.go(*address);
+                            }
+                        }
+                    }
+                    if CollapsingHeader::new("Patch Sites").build(&ui) {
+                        for info in &infos {
+                            let patch = match patches.mem_patch(&info.name) {
+                                Some(p) => p,
+                                None => continue,
+                            };
+                            for (address, _) in patch.targets() {
+                                if ui.small_button(format!("{} {:x}", info.name, address)) {
+                                    code_view.go(address);
+                                }
+                            }
+                        }
+                    }
 
+                    if let Some(address) = code_view.address {
+                        ui.separator();
+                        match disasm::disassemble_around(&*memory, address, CODE_VIEW_BEFORE, CODE_VIEW_LINES) {
+                            Ok(lines) => {
+                                for line in lines {
+                                    let marker = if line.address == address { ">" } else { " " };
+                                    let text = format!("{}{}", marker, code_line_text(&line));
+                                    if hook_sites.iter().any(|(_, a, enabled)| *enabled && line.overlaps(*a, HOOK_JMP_SIZE)) {
+                                        ui.text_colored(HOOK_COLOR, text);
+                                    } else if patched.iter().any(|(a, size)| line.overlaps(*a, *size)) {
+                                        ui.text_colored(PATCH_COLOR, text);
+                                    } else if line.invalid {
+                                        ui.text_disabled(text);
+                                    } else {
+                                        ui.text(text);
+                                    }
+                                }
+                            }
+                            Err(e) => ui.text_disabled(format!("({})", e)),
+                        }
+                    }
 
+                    ui.separator();
+                    if !CollapsingHeader::new("Original vs Patched").build(&ui) {
+                        return;
+                    }
+                    for info in &infos {
+                        let patch = match patches.mem_patch(&info.name) {
+                            Some(p) => p,
+                            None => continue,
+                        };
+                        if !CollapsingHeader::new(format!("{}##code", info.name)).build(&ui) {
+                            continue;
+                        }
+                        let code = match disasm::patch_code(&*memory, patch) {
+                            Ok(c) => c,
+                            Err(e) => {
+                                ui.text_disabled(format!("({})", e));
+                                continue;
+                            }
+                        };
+                        for site in code {
+                            ui.text(format!("{:x}", site.address));
+                            ui.columns(2, "original vs patched", true);
+                            for line in &site.original {
+                                ui.text(code_line_text(line));
+                            }
+                            ui.next_column();
+                            if site.patched.is_empty() {
+                                ui.text_disabled("(jmp to the cave once enabled)");
+                            }
+                            for line in &site.patched {
+                                ui.text_colored(PATCH_COLOR, code_line_text(line));
+                            }
+                            ui.columns(1, "", false);
+                            if let Some(cave) = &site.cave {
+                                ui.text("Cave");
+                                for line in cave {
+                                    ui.text(code_line_text(line));
+                                }
+                            }
+                        }
+                    }
+                });
+                TabItem::new("Config").build(&ui, || {
+                    let tab = &mut ui_state.config_tab;
+                    match &ui_state.config_path {
+                        Some(path) => ui.text(path.display().to_string()),
+                        None => ui.text_disabled("(no place for the config file, changes are not saved)"),
+                    }
+                    ui.text(format!("Profile {}", ui_state.config.profile));
+
+                    let names = ui_state.config.profile_names();
+                    let name_refs: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+                    ui.combo_simple_string("Profiles", &mut tab.selected, &name_refs);
+                    if let Some(name) = names.get(tab.selected) {
+                        if ui.button("Apply") {
+                            config_action = Some(ConfigAction::Apply(name.clone()));
+                        }
+                        if ui.is_item_hovered() {
+                            ui.tooltip_text("Switch the patches, freezes, hotkeys, style, log level and window to the profile.");
+                        }
+                        ui.same_line();
+                        if ui.button("Save") {
+                            config_action = Some(ConfigAction::Save(name.clone()));
+                        }
+                        if ui.is_item_hovered() {
+                            ui.tooltip_text("Overwrite the profile with the current patches, freezes, hotkeys, style, log level and window.");
+                        }
+                    }
+                    ui.same_line();
+                    if ui.button("Reload") {
+                        config_action = Some(ConfigAction::Reload);
+                    }
+
+                    ui.input_text("##new profile", &mut tab.new_profile)
+                        .hint("new profile")
+                        .build();
+                    ui.same_line();
+                    if ui.button("Save As") && !tab.new_profile.trim().is_empty() {
+                        config_action = Some(ConfigAction::Save(tab.new_profile.trim().to_owned()));
+                        tab.new_profile.clear();
+                    }
+
+                    let mut level = LOG_LEVELS
+                        .iter()
+                        .position(|l| *l == ui_state.profile.log_level)
+                        .unwrap_or(0);
+                    if ui.combo_simple_string("Log Level", &mut level, &LOG_LEVELS) {
+                        ui_state.profile.log_level = LOG_LEVELS[level].to_owned();
+                        set_log_level(&ui_state.profile.log_level);
+                    }
+                    if !cfg!(debug_assertions) && ui.is_item_hovered() {
+                        ui.tooltip_text("Release builds don't log.");
+                    }
+
+                    let mut mode = InputMode::ALL
+                        .iter()
+                        .position(|m| *m == ui_state.profile.input_mode)
+                        .unwrap_or(0);
+                    let mode_names = InputMode::ALL.map(InputMode::name);
+                    if ui.combo_simple_string("Game Input", &mut mode, &mode_names) {
+                        ui_state.profile.input_mode = InputMode::ALL[mode];
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text("What the game gets while this window is shown.\nCapture: what the window doesn't use. UI Focus: nothing. Passthrough: everything.");
+                    }
+
+                    if let Some(status) = &tab.status {
+                        ui.text_disabled(status);
+                    }
+                    if !tab.issues.is_empty() && CollapsingHeader::new("Config Issues").build(&ui) {
+                        for issue in &tab.issues {
+                            ui.text_wrapped(issue);
+                        }
+                    }
                 });
                 TabItem::new("Style").build(&ui, || {
-                    if ui.button("Save Style[TODO]"){
+                    let tab = &mut ui_state.style_tab;
+                    let names = ui_state.styles.names();
+                    let name_refs: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+                    ui.combo_simple_string("Preset", &mut tab.selected, &name_refs);
+                    ui.same_line();
+                    if ui.button("Apply") {
+                        if let Some(name) = names.get(tab.selected) {
+                            style_action = Some(StyleAction::Apply(name.clone()));
+                        }
+                    }
+
+                    ui.input_text("##style name", &mut tab.name_input)
+                        .hint("style name")
+                        .build();
+                    ui.same_line();
+                    if ui.button("Save Style") && !tab.name_input.trim().is_empty() {
+                        let preset = style::from_imgui_style(tab.name_input.trim(), &ui.clone_style(), ui.io().font_global_scale);
+                        style_action = Some(StyleAction::Save(preset));
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text(format!("Save the style below as a preset in {} next to the dll.", sbx_tool_core::style::STYLE_FILE_NAME));
+                    }
+                    ui.same_line();
+                    if ui.button("Load Style") {
+                        style_action = Some(StyleAction::Reload);
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text(format!("Read {} again, for presets edited by hand.", sbx_tool_core::style::STYLE_FILE_NAME));
                     }
-                    if ui.button("Load Style[TODO]"){
+
+                    if ui.button("Copy") {
+                        let preset = style::from_imgui_style(&ui_state.profile.style, &ui.clone_style(), ui.io().font_global_scale);
+                        match preset.to_toml() {
+                            Ok(toml) => ui.set_clipboard_text(toml),
+                            Err(e) => tab.status = Some(format!("Failed to copy: {}", e)),
+                        }
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text("Copy the style as TOML, to share it.");
+                    }
+                    ui.same_line();
+                    if ui.button("Paste") {
+                        match parse_style_file(&ui.clipboard_text().unwrap_or_default()) {
+                            Ok(mut presets) if !presets.is_empty() => {
+                                style_action = Some(StyleAction::Paste(presets.remove(0)));
+                            }
+                            Ok(_) => tab.status = Some("No [[style]] in the clipboard".to_owned()),
+                            Err(e) => tab.status = Some(format!("Not a style: {}", e)),
+                        }
                     }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text("Use a copied style until the tool is unloaded, save it to keep it.");
+                    }
+                    if let Some(status) = &tab.status {
+                        ui.text_disabled(status);
+                    }
+
                     ui.spacing();
                     ui.show_default_style_editor();
                 });
+                TabItem::new("Hotkeys").build(&ui, || {
+                    ui.text_disabled("Click a binding and press the keys, Escape cancels.");
+                    let capturing = ui_state.hotkey_dispatcher.capturing().cloned();
+                    let mut actions = Action::fixed();
+                    actions.extend(patches.patches().into_iter().map(|p| Action::TogglePatch(p.name)));
+                    ui.columns(2, "hotkeys", false);
+                    for action in actions {
+                        let name = action.name();
+                        ui.text(action.label());
+                        ui.next_column();
+                        let chord = ui_state.hotkeys.chord(&action);
+                        let binding = if capturing.as_ref() == Some(&action) {
+                            "press the keys...".to_owned()
+                        } else {
+                            chord.map(|c| c.to_string()).unwrap_or_else(|| "-".to_owned())
+                        };
+                        if ui.button(format!("{}##{}", binding, name)) {
+                            ui_state.hotkey_dispatcher.capture(action.clone());
+                            ui_state.hotkeys_tab.status = None;
+                        }
+                        if chord.is_some() {
+                            ui.same_line();
+                            if ui.small_button(format!("x##{}", name)) {
+                                ui_state.hotkeys.unbind(&action);
+                                ui_state.profile.hotkeys = ui_state.hotkeys.to_config();
+                            }
+                        }
+                        ui.next_column();
+                    }
+                    ui.columns(1, "", false);
+                    if let Some(status) = &ui_state.hotkeys_tab.status {
+                        ui.text_disabled(status);
+                    }
+                    ui.text_disabled("Save the profile in the Config tab to keep the bindings.");
+                });
+                TabItem::new("Scripts").build(&ui, || {
+                    let scripts = &ui_state.scripts;
+                    match scripts.dir() {
+                        Some(dir) => ui.text(dir.display().to_string()),
+                        None => ui.text_disabled("(no scripts folder, the dll has no directory)"),
+                    }
+                    if ui.button("Reload") {
+                        script_action = Some(ScriptAction::Reload);
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text("Load every script again. A changed file is reloaded on its own.");
+                    }
+                    ui.same_line();
+                    if ui.button("Clear") {
+                        script_action = Some(ScriptAction::ClearConsole);
+                    }
+                    if scripts.scripts().is_empty() {
+                        ui.text_disabled("No .rhai files");
+                    }
+                    for script in scripts.scripts() {
+                        let mut enabled = script.enabled;
+                        if ui.checkbox(&script.name, &mut enabled) {
+                            script_action = Some(ScriptAction::SetEnabled(script.name.clone(), enabled));
+                        }
+                        if let Some(error) = &script.error {
+                            ui.same_line();
+                            ui.text_colored(SCRIPT_ERROR_COLOR, "(stopped)");
+                            if ui.is_item_hovered() {
+                                ui.tooltip_text(error);
+                            }
+                        }
+                    }
+
+                    ui.separator();
+                    let tab = &mut ui_state.scripts_tab;
+                    let input_height = ui.frame_height_with_spacing();
+                    ChildWindow::new("console")
+                        .size([0.0, -input_height])
+                        .border(true)
+                        .build(&ui, || {
+                            for line in scripts.console() {
+                                match line.level {
+                                    ConsoleLevel::Input => ui.text_disabled(&line.text),
+                                    ConsoleLevel::Warn => ui.text_colored(SCRIPT_WARN_COLOR, &line.text),
+                                    ConsoleLevel::Error => ui.text_colored(SCRIPT_ERROR_COLOR, &line.text),
+                                    ConsoleLevel::Output | ConsoleLevel::Info => ui.text_wrapped(&line.text),
+                                }
+                            }
+                            //follow new lines unless scrolled up
+                            if ui.scroll_y() >= ui.scroll_max_y() {
+                                ui.set_scroll_here_y_with_ratio(1.0);
+                            }
+                        });
+                    let entered = ui
+                        .input_text("##script input", &mut tab.input)
+                        .hint("battle().cpu.hp")
+                        .enter_returns_true(true)
+                        .build();
+                    ui.same_line();
+                    if (ui.button("Run") || entered) && !tab.input.trim().is_empty() {
+                        script_action = Some(ScriptAction::Eval(std::mem::take(&mut tab.input)));
+                    }
+                });
                 TabItem::new("Information").build(&ui, || {
+                    if ui.button("Unload") {
+                        request_unload();
+                    }
+                    if ui.is_item_hovered() {
+                        let chord = ui_state.hotkeys.chord(&Action::Eject);
+                        ui.tooltip_text(format!("Revert patches, remove hooks and eject the dll. ({})", chord.map(|c| c.to_string()).unwrap_or_else(|| "no hotkey".to_owned())));
+                    }
+                    ui.separator();
                     ui.text("Created by d42ejh");
                     ui.text("https://github.com/d42ejh/sbx-tool-dll");
                     ui.text("SBX tool I made for fun");
@@ -477,55 +1539,535 @@ fn imgui_ui_loop(ui: Ui) -> Ui {
                 });
             });
         });
+    if shown.is_none() {
+        ui_state.layout.collapsed = true;
+    }
+
+    //structs not shown this frame start over next time
+    ui_state.structs_tab.inspector.end_frame();
+
+    //enable/disable hooks
+    for (name, on_off) in hook_switches {
+        log_error(hooks.switch(&name, on_off));
+    }
 
     //enable/disable mem patches
-    let css_disable_cost_patch = mem_patches.get_mut(&MemPatchName::CSSDisableCost).unwrap();
-    css_disable_cost_patch.switch(is_enable_css_disable_cost_patch);
-    let hp_cap_disable_patch = mem_patches.get_mut(&MemPatchName::HPCapDisable).unwrap();
-    hp_cap_disable_patch.switch(is_enable_hp_cap_disable_patch);
-    let ex_cap_disable_patch = mem_patches.get_mut(&MemPatchName::ExCapDisable).unwrap();
-    ex_cap_disable_patch.switch(is_enable_ex_cap_disable_patch);
+    for (name, on_off) in patch_switches {
+        log_error(patches.switch(&name, on_off));
+    }
+
+    if let Some(action) = hotkey_action {
+        run_hotkey(ui_state, action);
+    }
+
+    match script_action {
+        Some(ScriptAction::Eval(input)) => ui_state.scripts.eval(&input),
+        Some(ScriptAction::SetEnabled(name, enabled)) => {
+            ui_state.scripts.set_enabled(&name, enabled)
+        }
+        Some(ScriptAction::Reload) => ui_state.scripts.reload_all(),
+        Some(ScriptAction::ClearConsole) => ui_state.scripts.clear_console(),
+        None => {}
+    }
+
+    match config_action {
+        Some(ConfigAction::Apply(name)) => {
+            let profile = ui_state.config.profiles.get(&name).cloned();
+            if let Some(profile) = profile {
+                ui_state.config.profile = name;
+                apply_profile(ui_state, &profile);
+            }
+        }
+        Some(ConfigAction::Save(name)) => {
+            let result = save_profile(ui_state, &name);
+            ui_state.config_tab.status = Some(match result {
+                Ok(()) => format!("Saved '{}'", name),
+                Err(e) => format!("Failed to save: {}", e),
+            });
+        }
+        Some(ConfigAction::Reload) => reload_config(ui_state),
+        None => {}
+    }
+
+    match style_action {
+        Some(StyleAction::Apply(name)) => apply_style(ui_state, &name),
+        Some(StyleAction::Save(preset)) => {
+            let name = preset.name.clone();
+            match ui_state.styles.save(preset) {
+                Ok(()) => {
+                    apply_style(ui_state, &name);
+                    ui_state.style_tab.status = Some(format!("Saved '{}'", name));
+                }
+                Err(e) => ui_state.style_tab.status = Some(format!("Failed to save: {}", e)),
+            }
+        }
+        Some(StyleAction::Reload) => match ui_state.styles.reload() {
+            Ok(()) => {
+                let name = ui_state.profile.style.clone();
+                apply_style(ui_state, &name);
+                ui_state.style_tab.status = Some("Loaded".to_owned());
+            }
+            Err(e) => ui_state.style_tab.status = Some(format!("Failed to load: {}", e)),
+        },
+        Some(StyleAction::Paste(preset)) => {
+            let name = preset.name.clone();
+            ui_state.styles.insert(preset);
+            apply_style(ui_state, &name);
+            ui_state.style_tab.status = Some(format!("Pasted '{}'", name));
+        }
+        None => {}
+    }
 
     ui
 }
 
-#[derive(Eq, PartialEq, Hash, Clone, Copy)]
-enum MemPatchName {
-    CSSDisableCost,
-    HPCapDisable,
-    ExCapDisable,
+/// Run the scripts for this frame and the patch switches they asked for
+fn tick_scripts(ui_state: &mut GUIContext) {
+    let patches = ui_state
+        .patches
+        .patches()
+        .into_iter()
+        .map(|p| (p.name, p.enabled))
+        .collect();
+    for (name, on_off) in ui_state.scripts.tick(patches) {
+        log_error(ui_state.patches.switch(&name, on_off));
+    }
+}
+
+/// Switch to the style preset `name` from the next frame
+fn apply_style(ui_state: &mut GUIContext, name: &str) {
+    let preset = match ui_state.styles.get(name) {
+        Some(p) => p,
+        None => {
+            event!(Level::WARN, "Unknown style '{}'", name);
+            return;
+        }
+    };
+    let (imgui_style, issues) = style::to_imgui_style(preset, &ui_state.default_style);
+    for issue in &issues {
+        event!(Level::WARN, "Style '{}': {}", name, issue);
+    }
+    ui_state.pending_style = Some((imgui_style, preset.font_scale as f32));
+    ui_state.profile.style = name.to_owned();
+    let names = ui_state.styles.names();
+    ui_state.style_tab.selected = names.iter().position(|n| n == name).unwrap_or(0);
+}
+
+fn set_imgui_style(context: &mut imgui::Context, (style, font_scale): (imgui::Style, f32)) {
+    *context.style_mut() = style;
+    context.io_mut().font_global_scale = font_scale;
+}
+
+/// Switch to `profile`, the patches not in it are disabled
+fn apply_profile(ui_state: &mut GUIContext, profile: &Profile) {
+    event!(
+        Level::INFO,
+        "Applying profile '{}'",
+        ui_state.config.profile
+    );
+    set_log_level(&profile.log_level);
+    apply_style(ui_state, &profile.style);
+
+    //disable first, what is enabled may conflict with the profile's patches
+    for info in ui_state.patches.patches() {
+        if info.enabled && !profile.patches.contains(&info.name) {
+            log_error(ui_state.patches.disable(&info.name));
+        }
+    }
+    for name in &profile.patches {
+        match ui_state.patches.is_enabled(name) {
+            Some(false) => log_error(ui_state.patches.enable(name)),
+            Some(true) => {}
+            None => event!(Level::WARN, "Profile has an unknown patch '{}'", name),
+        }
+    }
+
+    let freeze = profile.freeze;
+    let hp = |value: Option<u32>| value.map(|v| v as i32);
+    set_freeze(ui_state, Side::Player, Gauge::Hp, freeze.player_hp, hp(freeze.player_hp_value));
+    set_freeze(ui_state, Side::Player, Gauge::Ex, freeze.player_ex, freeze.player_ex_value);
+    set_freeze(ui_state, Side::CPU, Gauge::Hp, freeze.cpu_hp, hp(freeze.cpu_hp_value));
+    set_freeze(ui_state, Side::CPU, Gauge::Ex, freeze.cpu_ex, freeze.cpu_ex_value);
+
+    //checked when the config was read
+    ui_state.hotkeys = Hotkeys::from_config(&profile.hotkeys).0;
+    ui_state.hotkey_dispatcher.cancel_capture();
+
+    ui_state.layout = profile.ui;
+    ui_state.apply_layout = true;
+    ui_state.profile = profile.clone();
+}
+
+/// Checkbox state of a freeze in the Battle tab
+fn freeze_flag(ui_state: &mut GUIContext, side: Side, gauge: Gauge) -> &mut EffBool {
+    match (side, gauge) {
+        (Side::Player, Gauge::Hp) => &mut ui_state.do_freeze_player_current_hp,
+        (Side::Player, Gauge::Ex) => &mut ui_state.do_freeze_player_current_ex,
+        (Side::CPU, Gauge::Hp) => &mut ui_state.do_freeze_cpu_current_hp,
+        (Side::CPU, Gauge::Ex) => &mut ui_state.do_freeze_cpu_current_ex,
+    }
+}
+
+/// Switch a freeze like its checkbox does, held at `value` or the current value if None
+fn set_freeze(
+    ui_state: &mut GUIContext,
+    side: Side,
+    gauge: Gauge,
+    enable: bool,
+    value: Option<i32>,
+) {
+    let (is_changed, _) = freeze_flag(ui_state, side, gauge).set_and_is_changed(enable);
+    if !is_changed && value.is_none() {
+        return;
+    }
+    let message = match (side, gauge) {
+        (Side::Player, Gauge::Hp) => ChannelMessage::FreezePlayerHP {
+            enable,
+            value: value.map(|v| v as u32),
+        },
+        (Side::Player, Gauge::Ex) => ChannelMessage::FreezePlayerEx { enable, value },
+        (Side::CPU, Gauge::Hp) => ChannelMessage::FreezeCPUHP {
+            enable,
+            value: value.map(|v| v as u32),
+        },
+        (Side::CPU, Gauge::Ex) => ChannelMessage::FreezeCPUEx { enable, value },
+    };
+    ui_state.message_sender.send(message).unwrap();
+}
+
+/// Set hp or ex through the freeze thread, a frozen gauge stays at the new value
+fn change_gauge(ui_state: &GUIContext, side: Side, gauge: Gauge, value: i32) {
+    let message = match (side, gauge) {
+        (Side::Player, Gauge::Hp) => ChannelMessage::ChangePlayerHP {
+            value: value as u32,
+        },
+        (Side::Player, Gauge::Ex) => ChannelMessage::ChangePlayerEx { value },
+        (Side::CPU, Gauge::Hp) => ChannelMessage::ChangeCPUHP {
+            value: value as u32,
+        },
+        (Side::CPU, Gauge::Ex) => ChannelMessage::ChangeCPUEx { value },
+    };
+    ui_state.message_sender.send(message).unwrap();
+}
+
+/// Write back the hp, ex, rush counts and scores of the saved battle state
+fn load_battle_state(ui_state: &mut GUIContext) {
+    let saved = match ui_state.saved_battle {
+        Some(s) => s,
+        None => {
+            event!(Level::WARN, "No battle state saved");
+            return;
+        }
+    };
+    let battle = BattleAccess::new(&*ui_state.memory, ui_state.battle_addresses);
+    if let Err(e) = battle.validate() {
+        event!(Level::WARN, "Can't load the battle state: {}", e);
+        return;
+    }
+    for side in [Side::Player, Side::CPU] {
+        let player = saved.side(side);
+        change_gauge(ui_state, side, Gauge::Hp, player.current_hp as i32);
+        change_gauge(ui_state, side, Gauge::Ex, player.current_ex);
+        log_error(battle.set_rush_count(side, player.rush_count));
+        log_error(battle.set_score(side, player.score));
+    }
+}
+
+/// Store the current state as profile `name`, make it the active one and write the config
+fn save_profile(ui_state: &mut GUIContext, name: &str) -> Result<()> {
+    let mut profile = ui_state.profile.clone();
+    profile.patches = ui_state
+        .patches
+        .patches()
+        .into_iter()
+        .filter(|p| p.enabled)
+        .map(|p| p.name)
+        .collect();
+    //the checkboxes may be ahead of the freeze thread
+    profile.freeze = FreezeSettings {
+        player_hp: ui_state.do_freeze_player_current_hp.get(),
+        player_ex: ui_state.do_freeze_player_current_ex.get(),
+        cpu_hp: ui_state.do_freeze_cpu_current_hp.get(),
+        cpu_ex: ui_state.do_freeze_cpu_current_ex.get(),
+        ..*ui_state.frozen.lock()
+    };
+    profile.ui = ui_state.layout;
+
+    ui_state.config.profile = name.to_owned();
+    ui_state
+        .config
+        .profiles
+        .insert(name.to_owned(), profile.clone());
+    ui_state.profile = profile;
+    let names = ui_state.config.profile_names();
+    ui_state.config_tab.selected = names.iter().position(|n| n == name).unwrap_or(0);
+
+    let path = ui_state
+        .config_path
+        .as_ref()
+        .ok_or_else(|| anyhow!("no place for the config file"))?;
+    ui_state.config.save(path)?;
+    event!(Level::INFO, "Saved profile '{}' to {:?}", name, path);
+    Ok(())
+}
+
+/// Read the config file again, the applied profile stays until another is applied
+fn reload_config(ui_state: &mut GUIContext) {
+    let path = match &ui_state.config_path {
+        Some(p) => p.clone(),
+        None => return,
+    };
+    let tab = &mut ui_state.config_tab;
+    match Config::load(&path) {
+        Ok(load) => {
+            for issue in &load.issues {
+                event!(Level::WARN, "{:?}: {}", path, issue);
+            }
+            tab.issues = load.issues;
+            tab.status = Some(format!("Reloaded, {} issues", tab.issues.len()));
+            tab.selected = load
+                .config
+                .profile_names()
+                .iter()
+                .position(|n| *n == load.config.profile)
+                .unwrap_or(0);
+            ui_state.config = load.config;
+        }
+        Err(e) => tab.status = Some(format!("Failed to reload: {}", e)),
+    }
+}
+
+/// No-op without the debug console logger
+fn set_log_level(level: &str) {
+    let handle = match LOG_LEVEL_HANDLE.get() {
+        Some(h) => h,
+        None => return,
+    };
+    match level.parse::<LevelFilter>() {
+        Ok(filter) => {
+            if let Err(e) = handle.modify(|f| *f = filter) {
+                event!(Level::ERROR, "Failed to change the log level: {}", e);
+            }
+        }
+        Err(e) => event!(Level::WARN, "Unknown log level '{}': {}", level, e),
+    }
+}
+
+/// Fields of `info` at `address`, pointer fields open as tree nodes
+fn draw_struct(
+    ui: &Ui,
+    inspector: &mut StructInspector,
+    memory: &dyn GameMemory,
+    info: &'static StructInfo,
+    address: usize,
+    depth: usize,
+) {
+    use imgui::{StyleColor, TreeNode};
+    for value in inspector.read(memory, info, address) {
+        let field = value.info;
+        let text = format!(
+            "+{:02X} {:<26} {:<24} {}",
+            field.offset,
+            field.name,
+            field.type_name(),
+            value.display()
+        );
+        let color = if value.changed {
+            Some(CHANGED_COLOR)
+        } else if !field.is_known() {
+            Some(UNKNOWN_FIELD_COLOR)
+        } else {
+            None
+        };
+        let color = color.map(|c| ui.push_style_color(StyleColor::Text, c));
+        match value.pointer() {
+            Some((pointer, target)) if depth < MAX_STRUCT_DEPTH => {
+                let node =
+                    TreeNode::new(format!("{}##{:x}+{:x}", text, address, field.offset)).push(ui);
+                //the fields it points to have their own colors
+                drop(color);
+                if ui.is_item_hovered() && !field.comment.is_empty() {
+                    ui.tooltip_text(&field.comment);
+                }
+                if let Some(_node) = node {
+                    draw_struct(ui, inspector, memory, target, pointer, depth + 1);
+                }
+            }
+            _ => {
+                ui.text(text);
+                drop(color);
+                if ui.is_item_hovered() && !field.comment.is_empty() {
+                    ui.tooltip_text(&field.comment);
+                }
+            }
+        }
+    }
+}
+
+/// `address  bytes  instruction`
+fn code_line_text(line: &disasm::Line) -> String {
+    let bytes = line
+        .bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!("{:08X}  {:<24} {}", line.address, bytes, line.text)
+}
+
+fn log_error(result: anyhow::Result<()>) {
+    if let Err(e) = result {
+        event!(Level::ERROR, "{}", e);
+    }
 }
 
 #[derive(Debug)]
 enum ChannelMessage {
     ChangePlayerHP { value: u32 },
     ChangePlayerEx { value: i32 },
-    FreezePlayerHP { enable: bool },
-    FreezePlayerEx { enable: bool },
+    /// Freeze at `value`, or at the current value if None
+    FreezePlayerHP { enable: bool, value: Option<u32> },
+    FreezePlayerEx { enable: bool, value: Option<i32> },
     ChangeCPUHP { value: u32 },
     ChangeCPUEx { value: i32 },
-    FreezeCPUHP { enable: bool },
-    FreezeCPUEx { enable: bool },
+    FreezeCPUHP { enable: bool, value: Option<u32> },
+    FreezeCPUEx { enable: bool, value: Option<i32> },
+    Shutdown,
+    /// Stop for good without leaving the thread's code, it is unmapped by an external FreeLibrary.
+    /// Acknowledged through the sender
+    Park(std::sync::mpsc::Sender<()>),
 }
 
-fn attached_main() -> anyhow::Result<()> {
+/// Unload on a new thread, the caller may be one of the hooks being removed
+fn request_unload() {
+    if UNLOADING.swap(true, Ordering::SeqCst) {
+        return; //already unloading
+    }
+    std::thread::spawn(unload);
+}
+
+fn unload() {
+    event!(Level::INFO, "Unloading...");
+
+    //the hooks and the ui see no GUIContext from here on
+    let ui_state = GUI_CONTEXT.lock().take();
+    if let Some(mut ui_state) = ui_state {
+        //stop the freeze thread
+        let _ = ui_state.message_sender.send(ChannelMessage::Shutdown);
+        if let Some(thread) = ui_state.freeze_thread.take() {
+            if thread.join().is_err() {
+                event!(Level::ERROR, "Freeze thread panicked");
+            }
+        }
+        //scripts unsubscribe from the scene and battle events
+        drop(ui_state.scripts);
+        log_error(ui_state.patches.disable_all());
+        log_error(ui_state.hooks.disable_all());
+    }
+
+    //let hook calls already running return before the code is gone
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    //release imgui, the renderer holds d3d resources
+    drop(GraphicContext.lock().take());
+
+    event!(Level::INFO, "Bye");
+    if cfg!(debug_assertions) {
+        unsafe { FreeConsole() };
+    }
+
+    if let Some(dll_module) = DLL_MODULE.get() {
+        unsafe { FreeLibraryAndExitThread(*dll_module as HINSTANCE, 0) };
+    }
+}
+
+/// Revert everything when unloaded by an external FreeLibrary, under the loader lock.
+/// Threads exiting wait for the loader lock, so the freeze thread can't be joined.
+/// It is parked in a wait that never returns instead, it would crash once our code is gone.
+fn detach() {
+    let mut ui_state = match GUI_CONTEXT.try_lock_for(std::time::Duration::from_millis(500)) {
+        Some(ui_state) => ui_state,
+        None => {
+            event!(Level::ERROR, "GUIContext is locked, left patches and hooks");
+            return;
+        }
+    };
+    let ui_state = match ui_state.as_mut() {
+        Some(s) => s,
+        None => return, //not attached yet
+    };
+    let (parked_sender, parked) = std::sync::mpsc::channel();
+    let _ = ui_state
+        .message_sender
+        .send(ChannelMessage::Park(parked_sender));
+    if parked.recv_timeout(std::time::Duration::from_millis(500)).is_err() {
+        event!(Level::ERROR, "Freeze thread did not stop");
+    }
+    //the same thread suspending path as a normal unload, waits until no thread is inside a patch
+    log_error(ui_state.patches.disable_all());
+    log_error(ui_state.hooks.disable_all());
+}
+
+/// Apply or revert the scene gated patches and hooks
+fn apply_scene(ui_state: &mut GUIContext, scene: Option<Scene>) {
+    log_error(ui_state.patches.set_scene(scene));
+    log_error(ui_state.hooks.set_scene(scene));
+}
+
+/// `BATTLE, VS_CPU_MODE_CSS`
+fn scene_names(scenes: &[Scene]) -> String {
+    scenes.iter().map(|s| s.name()).collect::<Vec<_>>().join(", ")
+}
+
+fn attached_main(dll_module: usize) -> anyhow::Result<()> {
+    let _ = DLL_MODULE.set(dll_module);
     //disable log for release
     if cfg!(debug_assertions) {
         unsafe { AllocConsole() };
         ansi_term::enable_ansi_support().unwrap();
 
         // let file_appender = tracing_appender::rolling::never("tmp", "sbx.log"); //uncommnet this to use file log
-        tracing_subscriber::fmt()
-            // .with_writer(file_appender) //uncommnet this to use file log
-            .pretty()
-            .with_thread_ids(true)
-            .with_thread_names(true)
-            // enable everything
-            .with_max_level(tracing::Level::TRACE)
+        // enable everything until the config sets the level
+        let (level, level_handle) = reload::Layer::new(LevelFilter::TRACE);
+        tracing_subscriber::registry()
+            .with(level)
+            .with(
+                tracing_subscriber::fmt::layer()
+                    // .with_writer(file_appender) //uncommnet this to use file log
+                    .pretty()
+                    .with_thread_ids(true)
+                    .with_thread_names(true),
+            )
             // sets this to be the default, global collector for this application.
             .init();
+        let _ = LOG_LEVEL_HANDLE.set(level_handle);
     }
 
+    //pick the offset table of the running game build, refuse unknown builds
+    init_offset_table(dll_module)?;
+
+    //a broken config only warns
+    let config_path = config::config_path(dll_directory(dll_module).as_deref());
+    let config_load = match &config_path {
+        Some(path) => {
+            let load = Config::load(path).unwrap_or_else(|e| ConfigLoad {
+                config: Config::default(),
+                issues: vec![e.to_string()],
+            });
+            for issue in &load.issues {
+                event!(Level::WARN, "{:?}: {}", path, issue);
+            }
+            load
+        }
+        None => ConfigLoad {
+            config: Config::default(),
+            issues: Vec::new(),
+        },
+    };
+    let profile = config_load.config.active();
+    set_log_level(&profile.log_level);
+    event!(Level::INFO, "Profile '{}'", config_load.config.profile);
+
     //winapi stuffs
 
     /*
@@ -537,17 +2079,31 @@ fn attached_main() -> anyhow::Result<()> {
             let detour = detour.read().unwrap();
             unsafe { detour.enable() };
     */
+    let mut hooks = HookManager::new();
+
     let detour = winapi_mon_core::fileapi::hook_CreateFileA(Some(__hook__CreateFileA), true)?;
+    let (enable_detour, disable_detour, state_detour) = (detour.clone(), detour.clone(), detour);
+    hooks.add(
+        "CreateFileA",
+        DetourFns {
+            enable: move || Ok(unsafe { enable_detour.read().unwrap().enable() }?),
+            disable: move || Ok(unsafe { disable_detour.read().unwrap().disable() }?),
+            is_enabled: move || state_detour.read().unwrap().is_enabled(),
+        },
+        Some(&sbx_tool_core::CREATE_FILE_A_HOOK_STATS),
+    )?;
 
     event!(Level::INFO, "Initialized the logger!");
 
     //hook directx functions
     //get original directx function address
     //get directx
-    let (d3d, d3d_device) = sbx_tool_core::d3d9::get_directx()?;
+    let (d3d, d3d_device) = get_directx_when_ready()?;
 
     let end_scene_fn_address = sbx_tool_core::d3d9::get_vtable_value(d3d_device, 42);
     let reset_fn_address = sbx_tool_core::d3d9::get_vtable_value(d3d_device, 16);
+    //the vtable is all we need from the dummy device
+    unsafe { sbx_tool_core::d3d9::release_directx(d3d, d3d_device) };
 
     event!(
         Level::INFO,
@@ -573,6 +2129,11 @@ fn attached_main() -> anyhow::Result<()> {
             e
         )));
     }
+    hooks.add(
+        "IDirect3DDevice9::Reset",
+        StaticDetour(ResetDetour.get().unwrap()),
+        Some(&RESET_HOOK_STATS),
+    )?;
 
     //hook endscene
     event!(
@@ -598,6 +2159,13 @@ fn attached_main() -> anyhow::Result<()> {
             e
         )));
     }
+    //the ui is drawn from EndScene
+    hooks.add(
+        "IDirect3DDevice9::EndScene",
+        StaticDetour(EndSceneDetour.get().unwrap()),
+        Some(&END_SCENE_HOOK_STATS),
+    )?;
+    hooks.pin("IDirect3DDevice9::EndScene")?;
 
     //wait for device pointer gets initialized
     loop {
@@ -614,107 +2182,108 @@ fn attached_main() -> anyhow::Result<()> {
 
     event!(Level::INFO, "Initializing inline hooks");
     let module_address = unsafe { GetModuleHandleA(std::ptr::null()) } as usize;
-
-    let hook = sbx_tool_core::init_main_loop_inner_hook(module_address)?;
-    let main_loop_hookpoint = Arc::new(unsafe { hook.hook() }?);
-
-    let hook = sbx_tool_core::init_game_loop_inner_hook(module_address)?;
-    let game_loop_hookpoint = Arc::new(unsafe { hook.hook() }?);
-
-    let hook = sbx_tool_core::battle::init_battle_loop_inner_hook(module_address)?;
-    let battle_loop_hookpoint = Arc::new(unsafe { hook.hook() }?);
-
-    let hook = sbx_tool_core::init_ui_loop_inner_hook(module_address)?;
-    let ui_loop_hookpoint = Arc::new(unsafe { hook.hook() }?);
-
-    event!(Level::INFO, "Initializing MemPatches");
-    let mut mempatch_map = HashMap::new();
-
-    //character cost patch
-    let patch = MemPatch::new(&[(
-        module_address + sbx_offset::css::ADD_CHARACTER_COST_TO_PARTY_COST_OFFSET,
-        &[0x90, 0x90, 0x90, 0x90],
-    )]);
-    mempatch_map.insert(MemPatchName::CSSDisableCost, patch);
-
-    //hp cap patch
-    let patch = MemPatch::new(&[
-        (
-            module_address + sbx_offset::battle::HPCAP_1_OFFSET,
-            &[0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90],
-        ),
-        (
-            module_address + sbx_offset::battle::HPCAP_2_OFFSET,
-            &[0x90, 0x90, 0x90, 0x90, 0x90],
-        ),
-    ]);
-    mempatch_map.insert(MemPatchName::HPCapDisable, patch);
-
-    //ex cap patch
-    let patch = MemPatch::new(&[
-        (
-            module_address + sbx_offset::battle::EXCAP_1_OFFSET,
-            &[0x90, 0x90, 0x90, 0x90, 0x90, 0x90, 0x90],
-        ),
-        (
-            module_address + sbx_offset::battle::EXCAP_2_OFFSET,
-            &[0x90, 0x90, 0x90],
+    let offsets = sbx_offset::get();
+
+    hooks.add_enabled(
+        "Main Loop",
+        inline_hook(module_address + offsets.main_loop_inner_offset, move || {
+            sbx_tool_core::init_main_loop_inner_hook(module_address)
+        }),
+        Some(&sbx_tool_core::MAIN_LOOP_INNER_HOOK_STATS),
+    )?;
+
+    hooks.add_enabled(
+        "Game Loop",
+        inline_hook(module_address + offsets.game_loop_inner_offset, move || {
+            sbx_tool_core::init_game_loop_inner_hook(module_address)
+        }),
+        Some(&sbx_tool_core::GAME_LOOP_INNER_HOOK_STATS),
+    )?;
+
+    sbx_tool_core::battle::battle_events().subscribe(|e| {
+        *LAST_BATTLE_EVENT.lock() = Some(*e);
+    });
+    hooks.add_enabled(
+        "Battle Loop",
+        inline_hook(
+            module_address + offsets.battle.battle_main_loop_first_switch_offset,
+            move || sbx_tool_core::battle::init_battle_loop_inner_hook(module_address),
         ),
-    ]);
-    mempatch_map.insert(MemPatchName::ExCapDisable, patch);
+        Some(&sbx_tool_core::battle::BATTLE_LOOP_INNER_HOOK_STATS),
+    )?;
+
+    hooks.add_enabled(
+        "UI Loop",
+        inline_hook(module_address + offsets.ui_loop_inner_offset, move || {
+            sbx_tool_core::init_ui_loop_inner_hook(module_address)
+        }),
+        Some(&sbx_tool_core::UI_LOOP_INNER_HOOK_STATS),
+    )?;
+
+    let memory: Arc<dyn GameMemory> = Arc::new(InProcessMemory);
+
+    event!(Level::INFO, "Loading patches");
+    let patches = PatchRegistry::load(
+        memory.clone(),
+        module_address,
+        sbx_offset::get(),
+        dll_directory(dll_module).as_deref(),
+    )?;
+    event!(Level::INFO, "{} patches loaded", patches.len());
+    let watches = match dll_directory(dll_module) {
+        Some(dir) => cheat_table::load_watches(&dir).unwrap_or_else(|e| {
+            event!(Level::WARN, "Failed to load watches: {}", e);
+            Vec::new()
+        }),
+        None => Vec::new(),
+    };
 
     event!(Level::INFO, "Initializing SBX contexts");
     //CSS stuffs
-    let css_context_address = module_address + sbx_offset::css::VS_CPU_CSS_CONTEXT_OFFSET;
+    let css_context_address = module_address + sbx_offset::get().css.vs_cpu_css_context_offset;
 
     sbx_tool_core::css::init_css_detours(module_address)?;
-    let d = CSSInitContextConstantsDetour.get().unwrap();
     event!(Level::INFO, "CSS detours initialized");
-    unsafe { d.enable() }?;
+    hooks.add_enabled(
+        "CSS Init Context Constants",
+        StaticDetour(CSSInitContextConstantsDetour.get().unwrap()),
+        Some(&CSS_INIT_CONTEXT_CONSTANTS_HOOK_STATS),
+    )?;
+    //the party constants only matter on the character select screens
+    hooks.gate(
+        "CSS Init Context Constants",
+        SceneGate::new(&[Scene::VsCpuModeCSS, Scene::BraveModeCSS]),
+    )?;
     //battle context
-    let battle_context_address = module_address + sbx_offset::battle::BATTLE_CONTEXT_OFFSET;
+    let battle_addresses = sbx_tool_core::battle::battle_addresses(module_address);
 
     //create channel
     let (sender, receiver) = std::sync::mpsc::channel::<ChannelMessage>();
 
     //spawn receiver thread
-    std::thread::spawn(move || {
-        let mut do_freeze_player_hp = false;
-        let mut freeeze_player_hp_value = 0x77777777;
-        let mut do_freeze_player_ex = false;
-        let mut freeeze_player_ex_value = 300;
-
-        let mut do_freeze_cpu_hp = false;
-        let mut freeeze_cpu_hp_value = 0x77777777;
-        let mut do_freeze_cpu_ex = false;
-        let mut freeeze_cpu_ex_value = 300;
+    let thread_memory = memory.clone();
+    let frozen = Arc::new(Mutex::new(FreezeSettings::default()));
+    let thread_frozen = frozen.clone();
+    let freeze_thread = std::thread::spawn(move || {
+        let battle = BattleAccess::new(&*thread_memory, battle_addresses);
+        let mut freezer = Freezer::new();
 
         loop {
-            // probably better let these out of the loop.
-            // but got complex(for me!) ce, so I leave these as is.
-            // smart compiler should do optimizations about this.
-            let battle_context: *mut BattleContext =
-                unsafe { std::mem::transmute(battle_context_address) };
-            let player = unsafe { (*battle_context).player1_ptr };
-            let player_subparams = unsafe { (*battle_context).player1_sub_param_ptr };
-            let cpu = unsafe { (*battle_context).player2_ptr };
-            let cpu_subparams = unsafe { (*battle_context).player2_sub_param_ptr };
-
-            let is_in_battle = || {
-                if player as usize == 0
-                    || cpu as usize == 0
-                    || player_subparams as usize == 0
-                    || cpu_subparams as usize == 0
-                {
-                    return false;
-                }
-                return true;
+            //the battle pointers are only trusted while the ui loop is in the battle scene
+            let snapshot = if scene::is_in_scene(Scene::Battle) {
+                battle.snapshot().ok()
+            } else {
+                None
             };
-
-            let is_in_battle = is_in_battle();
+            let is_in_battle = snapshot.is_some();
 
             //receive message and do action depend on the message
-            if let Ok(msg) = receiver.recv_timeout(std::time::Duration::from_millis(10)) {
+            let msg = match receiver.recv_timeout(std::time::Duration::from_millis(10)) {
+                Ok(msg) => Some(msg),
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => None,
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            if let Some(msg) = msg {
                 event!(
                     Level::DEBUG,
                     "Received Message {:?}, is_in_battle {}",
@@ -725,146 +2294,147 @@ fn attached_main() -> anyhow::Result<()> {
                 match msg {
                     ChannelMessage::ChangePlayerHP { value } => {
                         if is_in_battle {
-                            //      event!(Level::DEBUG, "Change player hp");
-                            unsafe {
-                                (*player).current_hp = value;
-                                (*player).graphic_hp_end = value;
-                            };
+                            log_error(battle.set_hp(Side::Player, value));
+                            //a frozen gauge is frozen at the new value
+                            freezer.hp_changed(Side::Player, value);
                         }
                     }
                     ChannelMessage::ChangePlayerEx { value } => {
                         if is_in_battle {
-                            //      event!(Level::DEBUG, "Change player ex");
-                            unsafe {
-                                (*player_subparams).current_ex = value;
-                                (*player_subparams).graphic_ex_end = std::cmp::max(value, 0);
-                                //avoid crash
-                            };
+                            log_error(battle.set_ex(Side::Player, value));
+                            freezer.ex_changed(Side::Player, value);
                         }
                     }
-                    ChannelMessage::FreezePlayerHP { enable } => {
-                        do_freeze_player_hp = enable;
-                        if is_in_battle {
-                            freeeze_player_hp_value = unsafe { (*player).current_hp };
-                        }
+                    ChannelMessage::FreezePlayerHP { enable, value } => {
+                        freezer.freeze_hp(Side::Player, enable, value, snapshot.as_ref());
                     }
-                    ChannelMessage::FreezePlayerEx { enable } => {
-                        do_freeze_player_ex = enable;
-                        if is_in_battle {
-                            freeeze_player_ex_value = unsafe { (*player_subparams).current_ex };
-                        }
+                    ChannelMessage::FreezePlayerEx { enable, value } => {
+                        freezer.freeze_ex(Side::Player, enable, value, snapshot.as_ref());
                     }
                     //CPU
                     ChannelMessage::ChangeCPUHP { value } => {
                         if is_in_battle {
-                            //     event!(Level::DEBUG, "Change cpu hp");
-                            unsafe {
-                                (*cpu).current_hp = value;
-                                (*cpu).graphic_hp_end = value;
-                            };
+                            log_error(battle.set_hp(Side::CPU, value));
+                            freezer.hp_changed(Side::CPU, value);
                         }
                     }
                     ChannelMessage::ChangeCPUEx { value } => {
                         if is_in_battle {
-                            //      event!(Level::DEBUG, "Change cpu ex");
-                            unsafe {
-                                (*cpu_subparams).current_ex = value;
-                                (*cpu_subparams).graphic_ex_end = std::cmp::max(value, 0);
-                            };
+                            log_error(battle.set_ex(Side::CPU, value));
+                            freezer.ex_changed(Side::CPU, value);
                         }
                     }
-                    ChannelMessage::FreezeCPUHP { enable } => {
-                        do_freeze_cpu_hp = enable;
-                        if is_in_battle {
-                            freeeze_cpu_hp_value = unsafe { (*cpu).current_hp };
-                        }
+                    ChannelMessage::FreezeCPUHP { enable, value } => {
+                        freezer.freeze_hp(Side::CPU, enable, value, snapshot.as_ref());
                     }
-                    ChannelMessage::FreezeCPUEx { enable } => {
-                        do_freeze_cpu_ex = enable;
-                        if is_in_battle {
-                            freeeze_cpu_ex_value = unsafe { (*cpu_subparams).current_ex };
+                    ChannelMessage::FreezeCPUEx { enable, value } => {
+                        freezer.freeze_ex(Side::CPU, enable, value, snapshot.as_ref());
+                    }
+                    ChannelMessage::Shutdown => break,
+                    ChannelMessage::Park(parked) => {
+                        let _ = parked.send(());
+                        //Sleep(INFINITE) never returns, the process exit ends the thread
+                        loop {
+                            unsafe { Sleep(INFINITE) };
                         }
                     }
                 }
+                *thread_frozen.lock() = freezer.settings();
             };
 
             //avoid crash with invalid pointers
             if !is_in_battle {
                 continue;
             }
-
-            //do stuffs
-            //player freeze
-            if do_freeze_player_hp {
-                /*
-                event!(
-                    Level::DEBUG,
-                    "Freeze Player HP: {}",
-                    freeeze_player_hp_value
-                );
-                */
-                unsafe {
-                    (*player).current_hp = freeeze_player_hp_value;
-                    (*player).graphic_hp_end = freeeze_player_hp_value;
-                };
-            }
-
-            if do_freeze_player_ex {
-                /*
-                event!(
-                    Level::DEBUG,
-                    "Freeze Player Ex: {}",
-                    freeeze_player_ex_value
-                );
-                */
-                unsafe {
-                    (*player_subparams).current_ex = freeeze_player_ex_value;
-                    (*player_subparams).graphic_ex_end = std::cmp::max(freeeze_player_ex_value, 0);
-                };
-            }
-
-            //cpu freeze
-            if do_freeze_cpu_hp {
-                //                event!(Level::DEBUG, "Freeze CPU HP: {}", freeeze_cpu_hp_value);
-                unsafe {
-                    (*cpu).current_hp = freeeze_cpu_hp_value;
-                    (*cpu).graphic_hp_end = freeeze_cpu_hp_value;
-                };
-            }
-
-            if do_freeze_cpu_ex {
-                //  event!(Level::DEBUG, "Freeze CPU Ex: {}", freeeze_cpu_ex_value);
-                unsafe {
-                    (*cpu_subparams).current_ex = freeeze_cpu_ex_value;
-                    (*cpu_subparams).graphic_ex_end = std::cmp::max(freeeze_cpu_ex_value, 0);
-                };
-            }
+            freezer.apply(&battle);
         }
     });
 
+    let scripts = ScriptManager::new(
+        ScriptContext {
+            memory: memory.clone(),
+            module_address,
+            battle_addresses,
+            css_context_ptr_address: css_context_address,
+        },
+        dll_directory(dll_module).as_deref(),
+    );
+
+    //imgui's default style is the base of the style presets
+    let mut imgui = imgui::Context::create();
+    let styles = StyleRegistry::load(dll_directory(dll_module).as_deref()).or_else(|e| {
+        event!(Level::WARN, "Failed to load styles: {}", e);
+        StyleRegistry::load(None)
+    })?;
+
     //init gui context before imgui
     event!(Level::INFO, "Initializing GUIContext");
     {
         *GUI_CONTEXT.lock() = Some(GUIContext {
             message_sender: sender,
             hide_ui: false,
-            mem_patches: mempatch_map,
-            main_loop_hook: main_loop_hookpoint,
-            game_loop_hook: game_loop_hookpoint,
-            ui_loop_hook: ui_loop_hookpoint,
-            battle_loop_hook: battle_loop_hookpoint,
+            patches: patches,
+            freeze_thread: Some(freeze_thread),
+            frozen,
+            memory: memory,
+            hooks: hooks,
             css_context_address: css_context_address,
-            battle_context_address: battle_context_address,
+            battle_addresses: battle_addresses,
+            module_address: module_address,
+            code_view: CodeView::default(),
+            memory_tab: MemoryTab::new(),
+            structs_tab: StructsTab::default(),
+            watches: watches,
             do_freeze_player_current_hp: EffBool::default(),
             do_freeze_player_current_ex: EffBool::default(),
             do_freeze_cpu_current_hp: EffBool::default(),
             do_freeze_cpu_current_ex: EffBool::default(),
+            config_tab: ConfigTab {
+                selected: config_load
+                    .config
+                    .profile_names()
+                    .iter()
+                    .position(|n| *n == config_load.config.profile)
+                    .unwrap_or(0),
+                issues: config_load.issues,
+                ..ConfigTab::default()
+            },
+            config: config_load.config,
+            config_path: config_path,
+            profile: profile.clone(),
+            layout: profile.ui,
+            apply_layout: true,
+            styles: styles,
+            style_tab: StyleTab::default(),
+            default_style: *imgui.style(),
+            pending_style: None,
+            hotkeys: Hotkeys::default(),
+            hotkey_dispatcher: HotkeyDispatcher::default(),
+            hotkeys_tab: HotkeysTab::default(),
+            want_text_input: false,
+            saved_battle: None,
+            scripts: scripts,
+            scripts_tab: ScriptsTab::default(),
         });
+        //scene gated patches and hooks follow the ui loop from here on.
+        //the hook runs in the middle of the game's loop, patching waits for the frame
+        scene::scene_events().subscribe(|e| {
+            if let SceneEvent::Enter(scene) = e {
+                *PENDING_SCENE.lock() = Some(*scene);
+            }
+        });
+        //freezes are sent to the freeze thread, it is running already
+        let mut ui_state = GUI_CONTEXT.lock();
+        let ui_state = ui_state.as_mut().unwrap();
+        apply_scene(ui_state, scene::current_scene());
+        apply_profile(ui_state, &profile);
+        if let Some(pending_style) = ui_state.pending_style.take() {
+            set_imgui_style(&mut imgui, pending_style);
+        }
     }
 
     //imgui stuffs
     event!(Level::INFO, "Setting up imgui stuffs...");
-    let imgui = imgui::Context::create();
 
     {
         *GraphicContext.lock() = Some(Context {
@@ -876,20 +2446,124 @@ fn attached_main() -> anyhow::Result<()> {
 
     event!(Level::INFO, "All done!");
 
+    //let sbx-injector know we are in
+    match sbx_tool_core::utility::ipc::signal_ready() {
+        Ok(true) => event!(Level::INFO, "Signaled the injector"),
+        Ok(false) => {}
+        Err(e) => event!(Level::ERROR, "Failed to signal the injector: {}", e),
+    }
+
     // no need to do this. unsafe { FreeConsole() };
 
     Ok(())
 }
 
+/// Injected into a suspended game the window does not exist yet, wait for it
+fn get_directx_when_ready() -> anyhow::Result<(*mut IDirect3D9, *mut IDirect3DDevice9)> {
+    const WINDOW_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+    let start = std::time::Instant::now();
+    loop {
+        match sbx_tool_core::d3d9::get_directx() {
+            Ok(directx) => return Ok(directx),
+            Err(e) if start.elapsed() > WINDOW_TIMEOUT => return Err(e),
+            Err(_) => std::thread::sleep(std::time::Duration::from_millis(500)),
+        }
+    }
+}
+
+/// Where the user files(offsets, patches) are looked up.
+/// None if the dll's file name can't be read.
+fn dll_directory(dll_module: usize) -> Option<std::path::PathBuf> {
+    let dll_path = sbx_tool_core::utility::get_module_file_name(dll_module as HINSTANCE).ok()?;
+    dll_path.parent().map(|p| p.to_path_buf())
+}
+
+fn init_offset_table(dll_module: usize) -> anyhow::Result<()> {
+    let exe_path = std::env::current_exe()?;
+    let fingerprint = sbx_offset::BuildFingerprint::from_file(&exe_path)?;
+    event!(Level::INFO, "Game executable {:?}", exe_path);
+
+    let mut registry = sbx_offset::OffsetRegistry::embedded()?;
+    if let Some(dir) = dll_directory(dll_module) {
+        let override_path = dir.join(sbx_offset::OVERRIDE_FILE_NAME);
+        if override_path.exists() {
+            event!(Level::INFO, "Loading offsets from {:?}", override_path);
+            registry.load_override_file(&override_path)?;
+        }
+    }
+
+    let mut table = match registry.select(&fingerprint) {
+        Some(Selection::Matched(t)) => t.clone(),
+        Some(Selection::Unverified(t)) => {
+            event!(
+                Level::WARN,
+                "Game build is not verified, using the {} offsets. Add this to {} once it is confirmed: {}",
+                t.name,
+                sbx_offset::OVERRIDE_FILE_NAME,
+                fingerprint
+            );
+            t.clone()
+        }
+        None => {
+            return Err(anyhow!(
+                "Unknown game build, refused to attach. Add this to {} to register it: {}",
+                sbx_offset::OVERRIDE_FILE_NAME,
+                fingerprint
+            ));
+        }
+    };
+    event!(Level::INFO, "Game build: {}", table.name);
+
+    let module_address = unsafe { GetModuleHandleA(std::ptr::null()) } as usize;
+    sbx_tool_core::utility::scanner::resolve_signatures(module_address, &mut table)?;
+    sbx_offset::init(table)
+}
+
+/// Release builds don't log and panic=abort would take the game down with a panic,
+/// so an attach error is shown and the dll stays loaded doing nothing
+fn report_attach_error(error: &anyhow::Error) {
+    event!(Level::ERROR, "Failed to attach: {:#}", error);
+    let text = sbx_tool_core::utility::win32_wstring(&format!("{:#}", error));
+    let caption = sbx_tool_core::utility::win32_wstring("sbx-tool");
+    unsafe {
+        MessageBoxW(
+            std::ptr::null_mut(),
+            text.as_ptr(),
+            caption.as_ptr(),
+            MB_OK | MB_ICONERROR,
+        )
+    };
+}
+
+/// Called by sbx-injector's eject through CreateRemoteThread
+#[no_mangle]
+extern "system" fn sbx_tool_unload(_: LPVOID) -> DWORD {
+    event!(Level::INFO, "Unload requested from outside");
+    request_unload();
+    0
+}
+
 #[no_mangle]
 #[allow(non_snake_case)]
-extern "system" fn DllMain(dll_module: HINSTANCE, call_reason: DWORD, _: LPVOID) -> BOOL {
+extern "system" fn DllMain(dll_module: HINSTANCE, call_reason: DWORD, reserved: LPVOID) -> BOOL {
     match call_reason {
         DLL_PROCESS_ATTACH => {
             unsafe { DisableThreadLibraryCalls(dll_module) };
-            std::thread::spawn(|| attached_main().unwrap()); //need to spawn a new thread to create directx device
+            let dll_module = dll_module as usize;
+            //need to spawn a new thread to create directx device
+            std::thread::spawn(move || {
+                if let Err(e) = attached_main(dll_module) {
+                    report_attach_error(&e);
+                }
+            });
+        }
+        DLL_PROCESS_DETACH => {
+            //lpReserved is null when unloaded by FreeLibrary, non null when the process is exiting
+            if reserved.is_null() && !UNLOADING.swap(true, Ordering::SeqCst) {
+                //ejected from outside, see `detach`
+                detach();
+            }
         }
-        DLL_PROCESS_DETACH => (),
         _ => (),
     }
     TRUE
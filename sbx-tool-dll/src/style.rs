@@ -0,0 +1,172 @@
+//! `StylePreset` to `imgui::Style` and back
+use imgui::{Style, StyleColor};
+use sbx_tool_core::style::{StyleBase, StylePreset, StyleValue};
+
+/// Types of the `imgui::Style` fields a preset can set
+trait StyleField {
+    fn value(&self) -> StyleValue;
+    /// false if `value` is of another type
+    fn set(&mut self, value: StyleValue) -> bool;
+}
+
+impl StyleField for f32 {
+    fn value(&self) -> StyleValue {
+        StyleValue::Float(round(*self))
+    }
+
+    fn set(&mut self, value: StyleValue) -> bool {
+        match value {
+            StyleValue::Float(v) => *self = v as f32,
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl StyleField for [f32; 2] {
+    fn value(&self) -> StyleValue {
+        StyleValue::Vec2([round(self[0]), round(self[1])])
+    }
+
+    fn set(&mut self, value: StyleValue) -> bool {
+        match value {
+            StyleValue::Vec2([x, y]) => *self = [x as f32, y as f32],
+            _ => return false,
+        }
+        true
+    }
+}
+
+impl StyleField for bool {
+    fn value(&self) -> StyleValue {
+        StyleValue::Bool(*self)
+    }
+
+    fn set(&mut self, value: StyleValue) -> bool {
+        match value {
+            StyleValue::Bool(v) => *self = v,
+            _ => return false,
+        }
+        true
+    }
+}
+
+//f32 noise like 0.05999999865889549 is hard to edit by hand
+fn round(v: f32) -> f64 {
+    (v as f64 * 1000.0).round() / 1000.0
+}
+
+macro_rules! style_fields {
+    ($($field:ident),* $(,)?) => {
+        fn field_values(style: &Style) -> Vec<(&'static str, StyleValue)> {
+            vec![$((stringify!($field), style.$field.value())),*]
+        }
+
+        /// None for an unknown field, Some(false) for a value of the wrong type
+        fn set_field(style: &mut Style, name: &str, value: StyleValue) -> Option<bool> {
+            match name {
+                $(stringify!($field) => Some(style.$field.set(value)),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+style_fields! {
+    alpha,
+    window_padding,
+    window_rounding,
+    window_border_size,
+    window_min_size,
+    window_title_align,
+    child_rounding,
+    child_border_size,
+    popup_rounding,
+    popup_border_size,
+    frame_padding,
+    frame_rounding,
+    frame_border_size,
+    item_spacing,
+    item_inner_spacing,
+    cell_padding,
+    touch_extra_padding,
+    indent_spacing,
+    columns_min_spacing,
+    scrollbar_size,
+    scrollbar_rounding,
+    grab_min_size,
+    grab_rounding,
+    log_slider_deadzone,
+    tab_rounding,
+    tab_border_size,
+    tab_min_width_for_close_button,
+    button_text_align,
+    selectable_text_align,
+    display_window_padding,
+    display_safe_area_padding,
+    mouse_cursor_scale,
+    anti_aliased_lines,
+    anti_aliased_fill,
+    curve_tessellation_tol,
+}
+
+fn color_name(color: StyleColor) -> String {
+    format!("{:?}", color)
+}
+
+/// `preset` over imgui's `default` style, with what couldn't be set
+pub fn to_imgui_style(preset: &StylePreset, default: &Style) -> (Style, Vec<String>) {
+    let mut style = *default;
+    match preset.base {
+        StyleBase::Dark => style.use_dark_colors(),
+        StyleBase::Light => style.use_light_colors(),
+        StyleBase::Classic => style.use_classic_colors(),
+    };
+    let mut issues = Vec::new();
+    for (name, value) in &preset.vars {
+        match set_field(&mut style, name, *value) {
+            Some(true) => {}
+            Some(false) => issues.push(format!("{} has a value of the wrong type", name)),
+            None => issues.push(format!("unknown style field {}", name)),
+        }
+    }
+    for (name, rgba) in &preset.colors {
+        match StyleColor::VARIANTS
+            .iter()
+            .find(|c| color_name(**c) == *name)
+        {
+            Some(color) => {
+                style.colors[*color as usize] = [
+                    rgba[0] as f32,
+                    rgba[1] as f32,
+                    rgba[2] as f32,
+                    rgba[3] as f32,
+                ]
+            }
+            None => issues.push(format!("unknown style color {}", name)),
+        }
+    }
+    (style, issues)
+}
+
+/// Every field and color of `style`, for saving what the style editor made
+pub fn from_imgui_style(name: &str, style: &Style, font_scale: f32) -> StylePreset {
+    let mut preset = StylePreset::new(name, StyleBase::Dark);
+    preset.font_scale = round(font_scale);
+    for (field, value) in field_values(style) {
+        preset.vars.insert(field.to_owned(), value);
+    }
+    for color in StyleColor::VARIANTS {
+        let rgba = style.colors[color as usize];
+        preset.colors.insert(
+            color_name(color),
+            [
+                round(rgba[0]),
+                round(rgba[1]),
+                round(rgba[2]),
+                round(rgba[3]),
+            ],
+        );
+    }
+    preset
+}